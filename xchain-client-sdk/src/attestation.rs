@@ -0,0 +1,547 @@
+use crypto::curve25519::{curve25519, curve25519_base};
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use serde::{Deserialize, Serialize};
+
+use crate::{config, wallet};
+use xchain_node_sdk::{canonical, errors::*, ocall, protos::xendorser};
+
+// 背书通道的远程认证: enclave发送临时的x25519公钥和自己的quote，背书服务返回它的临时公钥、
+// 背书地址和quote，双方的quote的REPORTDATA分别绑定了nonce和两边的公钥，互相校验之后用ECDH的结果
+// 推导会话密钥。之后的每个背书请求的RequestData换成AuthenticatedData信封: 会话id、新的随机nonce、
+// 原来的RequestData和MAC，MAC覆盖请求的哈希(请求名、链名、手续费交易、nonce和数据)。
+// 背书服务的ResponseData也是信封，MAC覆盖对应请求的哈希、背书服务的nonce和返回的内容，
+// 只对这一个请求有效，不能重放到其他请求上；校验通过之后还原ResponseData。
+// quote的生成和校验(EPID/IAS或者DCAP)依赖运行环境，由调用方实现QuoteProvider和QuoteVerifier。
+// 会话绑定了背书服务的实例，failoverEndpoints需要是同一个服务的接入点，否则MAC校验失败。
+// 只作用于complianceCheckEndorseServiceAddr，extraEndorsers是其他机构的服务，不经过认证的通道。
+
+pub const ATTEST_REQUEST: &str = "Attest";
+
+const KEY_LEN: usize = 32;
+
+/// 认证请求，二进制字段都是hex编码
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttestRequest {
+    pub nonce: String,
+    pub public_key: String,
+    pub quote: String,
+}
+
+/// 背书服务返回的ResponseData
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttestResponse {
+    pub public_key: String,
+    /// 之后合规检查签名使用的地址
+    pub endorser_address: String,
+    pub quote: String,
+}
+
+/// 认证通道里RequestData和ResponseData的信封，二进制字段都是hex编码
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthenticatedData {
+    pub session_id: String,
+    pub nonce: String,
+    pub data: String,
+    pub mac: String,
+}
+
+/// 已经发出的认证请求，校验对应的返回
+#[derive(Debug, Clone)]
+pub struct PendingRequest {
+    request_hash: Vec<u8>,
+}
+
+/// quote里的enclave度量值，hex编码
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Identity {
+    pub mr_enclave: String,
+    pub mr_signer: String,
+}
+
+/// 生成本enclave的quote，report_data放在REPORTDATA的开头
+pub trait QuoteProvider {
+    fn quote(&self, report_data: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// 校验对方的quote，REPORTDATA的开头必须是report_data，返回quote里的度量值
+pub trait QuoteVerifier {
+    fn verify(&self, quote: &[u8], report_data: &[u8]) -> Result<Identity>;
+}
+
+fn sha256(parts: &[&[u8]]) -> Vec<u8> {
+    xchain_crypto::hash::hash::sha256(&parts.concat())
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::new(Sha256::new(), key);
+    mac.input(data);
+    mac.result().code().to_vec()
+}
+
+fn client_report_data(nonce: &[u8], client_key: &[u8]) -> Vec<u8> {
+    sha256(&[&b"xuper-attest-client"[..], nonce, client_key])
+}
+
+fn endorser_report_data(
+    nonce: &[u8],
+    client_key: &[u8],
+    endorser_key: &[u8],
+    endorser_address: &str,
+) -> Vec<u8> {
+    sha256(&[
+        &b"xuper-attest-endorser"[..],
+        nonce,
+        client_key,
+        endorser_key,
+        endorser_address.as_bytes(),
+    ])
+}
+
+fn random_key() -> Result<[u8; KEY_LEN]> {
+    let mut key = [0u8; KEY_LEN];
+    key.copy_from_slice(&wallet::random_bytes(KEY_LEN)?);
+    Ok(key)
+}
+
+/// 请求的哈希，nonce和data是信封里解码之后的值
+fn request_hash(req: &xendorser::EndorserRequest, nonce: &[u8], data: &[u8]) -> Vec<u8> {
+    sha256(&[
+        &b"request\n"[..],
+        req.RequestName.as_bytes(),
+        &b"\n"[..],
+        req.BcName.as_bytes(),
+        &b"\n"[..],
+        hex::encode(req.get_Fee().get_txid()).as_bytes(),
+        &b"\n"[..],
+        hex::encode(nonce).as_bytes(),
+        &b"\n"[..],
+        data,
+    ])
+}
+
+fn response_data(
+    request_hash: &[u8],
+    resp: &xendorser::EndorserResponse,
+    nonce: &[u8],
+    data: &[u8],
+) -> Vec<u8> {
+    [
+        &b"response\n"[..],
+        hex::encode(request_hash).as_bytes(),
+        &b"\n"[..],
+        hex::encode(nonce).as_bytes(),
+        &b"\n"[..],
+        resp.ResponseName.as_bytes(),
+        &b"\n"[..],
+        resp.EndorserAddress.as_bytes(),
+        &b"\n"[..],
+        hex::encode(resp.get_EndorserSign().get_Sign()).as_bytes(),
+        &b"\n"[..],
+        data,
+    ]
+    .concat()
+}
+
+fn decode_key(s: &str) -> Result<Vec<u8>> {
+    let key = hex::decode(s)?;
+    if key.len() != KEY_LEN {
+        return Err(Error::new(
+            ErrorKind::AttestationFailed,
+            "invalid public key length",
+        ));
+    }
+    Ok(key)
+}
+
+fn is_allowed(identity: &Identity, allowed: &config::AttestationConfig) -> Result<()> {
+    if allowed.mr_enclaves.is_empty() && allowed.mr_signers.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidArguments,
+            "attestation allowlist is empty",
+        ));
+    }
+    let contains = |list: &[String], v: &str| {
+        list.is_empty() || list.iter().any(|m| m.eq_ignore_ascii_case(v))
+    };
+    if !contains(&allowed.mr_enclaves, &identity.mr_enclave)
+        || !contains(&allowed.mr_signers, &identity.mr_signer)
+    {
+        return Err(Error::new(
+            ErrorKind::AttestationFailed,
+            format!("endorser enclave is not allowed: {:?}", identity),
+        ));
+    }
+    Ok(())
+}
+
+/// 进行中的握手，保存临时私钥
+pub struct Handshake {
+    secret: [u8; KEY_LEN],
+    nonce: [u8; KEY_LEN],
+    public_key: [u8; KEY_LEN],
+}
+
+impl Handshake {
+    pub fn new(provider: &dyn QuoteProvider) -> Result<(Self, AttestRequest)> {
+        let secret = random_key()?;
+        let nonce = random_key()?;
+        let public_key = curve25519_base(&secret);
+        let quote = provider.quote(&client_report_data(&nonce, &public_key))?;
+        let req = AttestRequest {
+            nonce: hex::encode(&nonce),
+            public_key: hex::encode(&public_key),
+            quote: hex::encode(&quote),
+        };
+        Ok((
+            Handshake {
+                secret: secret,
+                nonce: nonce,
+                public_key: public_key,
+            },
+            req,
+        ))
+    }
+
+    /// 校验背书服务的quote和度量值，推导会话密钥
+    pub fn finish(
+        self,
+        resp: &AttestResponse,
+        verifier: &dyn QuoteVerifier,
+        allowed: &config::AttestationConfig,
+    ) -> Result<AttestedChannel> {
+        let endorser_key = decode_key(&resp.public_key)?;
+        let report_data = endorser_report_data(
+            &self.nonce,
+            &self.public_key,
+            &endorser_key,
+            &resp.endorser_address,
+        );
+        let identity = verifier.verify(&hex::decode(&resp.quote)?, &report_data)?;
+        is_allowed(&identity, allowed)?;
+
+        let shared = curve25519(&self.secret, &endorser_key);
+        if shared.iter().all(|b| *b == 0) {
+            return Err(Error::new(
+                ErrorKind::AttestationFailed,
+                "invalid endorser public key",
+            ));
+        }
+        let transcript = [&self.nonce[..], &self.public_key[..], &endorser_key[..]].concat();
+        Ok(AttestedChannel {
+            session_id: hex::encode(sha256(&[&transcript[..]])),
+            identity: identity,
+            endorser_address: resp.endorser_address.to_owned(),
+            key: hmac_sha256(
+                &shared,
+                &[&b"xuper-attest-session"[..], &transcript[..]].concat(),
+            ),
+        })
+    }
+}
+
+/// 认证过的背书通道，见Session::set_attestation
+#[derive(Clone)]
+pub struct AttestedChannel {
+    pub session_id: String,
+    pub identity: Identity,
+    pub endorser_address: String,
+    key: Vec<u8>,
+}
+
+impl std::fmt::Debug for AttestedChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("AttestedChannel")
+            .field("session_id", &self.session_id)
+            .field("identity", &self.identity)
+            .field("endorser_address", &self.endorser_address)
+            .finish()
+    }
+}
+
+impl AttestedChannel {
+    /// 把RequestData换成带MAC的信封，每个请求使用新的nonce
+    pub fn authenticate_request(
+        &self,
+        req: &mut xendorser::EndorserRequest,
+    ) -> Result<PendingRequest> {
+        let nonce = wallet::random_bytes(KEY_LEN)?;
+        let hash = request_hash(req, &nonce, &req.RequestData);
+        let envelope = AuthenticatedData {
+            session_id: self.session_id.to_owned(),
+            nonce: hex::encode(&nonce),
+            data: hex::encode(&req.RequestData),
+            mac: hex::encode(hmac_sha256(&self.key, &hash)),
+        };
+        req.set_RequestData(canonical::to_canonical_json(&envelope)?);
+        Ok(PendingRequest { request_hash: hash })
+    }
+
+    /// 校验返回的信封和MAC并还原ResponseData，签名的地址必须是认证时的地址
+    pub fn verify_response(
+        &self,
+        resp: &mut xendorser::EndorserResponse,
+        pending: &PendingRequest,
+    ) -> Result<()> {
+        let unauthenticated = || {
+            Error::new(
+                ErrorKind::AttestationFailed,
+                "endorser response is not authenticated",
+            )
+        };
+        let envelope: AuthenticatedData =
+            serde_json::from_slice(&resp.ResponseData).map_err(|_| unauthenticated())?;
+        let nonce = hex::decode(&envelope.nonce).map_err(|_| unauthenticated())?;
+        let data = hex::decode(&envelope.data).map_err(|_| unauthenticated())?;
+        let mac = hex::decode(&envelope.mac).map_err(|_| unauthenticated())?;
+        let expected = hmac_sha256(
+            &self.key,
+            &response_data(&pending.request_hash, resp, &nonce, &data),
+        );
+        if envelope.session_id != self.session_id
+            || nonce.len() != KEY_LEN
+            || !crypto::util::fixed_time_eq(&mac, &expected)
+        {
+            return Err(unauthenticated());
+        }
+        if !resp.EndorserAddress.is_empty() && resp.EndorserAddress != self.endorser_address {
+            return Err(Error::new(
+                ErrorKind::AttestationFailed,
+                format!("unexpected endorser address {}", resp.EndorserAddress),
+            ));
+        }
+        resp.set_ResponseData(data);
+        Ok(())
+    }
+}
+
+/// 和complianceCheckEndorseServiceAddr的背书服务握手，config里必须配置attestation
+pub fn attest(
+    config: &config::CommConfig,
+    provider: &dyn QuoteProvider,
+    verifier: &dyn QuoteVerifier,
+) -> Result<AttestedChannel> {
+    let allowed = config
+        .compliance_check
+        .attestation
+        .as_ref()
+        .ok_or(Error::new(
+            ErrorKind::InvalidArguments,
+            "attestation is not configured",
+        ))?;
+    let (handshake, attest_req) = Handshake::new(provider)?;
+    let mut req = xendorser::EndorserRequest::new();
+    req.set_RequestName(String::from(ATTEST_REQUEST));
+    req.set_RequestData(canonical::to_canonical_json(&attest_req)?);
+    let addr = &config
+        .compliance_check
+        .compliance_check_endorse_service_addr;
+    let resp = if ocall::has_endorser(addr) {
+        ocall::ocall_xchain_endorser_call_by(addr, req)
+    } else {
+        ocall::ocall_xchain_endorser_call(req)
+    }?;
+    let attest_resp: AttestResponse = serde_json::from_slice(&resp.ResponseData)?;
+    handshake.finish(&attest_resp, verifier, allowed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 测试用的quote: mr_enclave || mr_signer || report_data
+    struct MockQuote(Identity);
+
+    impl QuoteProvider for MockQuote {
+        fn quote(&self, report_data: &[u8]) -> Result<Vec<u8>> {
+            Ok([
+                &hex::decode(&self.0.mr_enclave)?[..],
+                &hex::decode(&self.0.mr_signer)?[..],
+                report_data,
+            ]
+            .concat())
+        }
+    }
+
+    impl QuoteVerifier for MockQuote {
+        fn verify(&self, quote: &[u8], report_data: &[u8]) -> Result<Identity> {
+            if quote.len() != 96 || &quote[64..] != report_data {
+                return Err(Error::from(ErrorKind::AttestationFailed));
+            }
+            Ok(Identity {
+                mr_enclave: hex::encode(&quote[..32]),
+                mr_signer: hex::encode(&quote[32..64]),
+            })
+        }
+    }
+
+    fn identity(b: u8) -> Identity {
+        Identity {
+            mr_enclave: hex::encode([b; 32]),
+            mr_signer: hex::encode([b + 1; 32]),
+        }
+    }
+
+    // 背书服务这一侧的握手，返回响应和会话密钥
+    fn endorser_side(req: &AttestRequest, enclave: &MockQuote) -> (AttestResponse, Vec<u8>) {
+        let nonce = hex::decode(&req.nonce).unwrap();
+        let client_key = hex::decode(&req.public_key).unwrap();
+        enclave
+            .verify(
+                &hex::decode(&req.quote).unwrap(),
+                &client_report_data(&nonce, &client_key),
+            )
+            .unwrap();
+        let secret = random_key().unwrap();
+        let public_key = curve25519_base(&secret);
+        let address = String::from("TeyyPLpp9L7QAcxHangtcHTu7HUZ6iydY");
+        let report_data = endorser_report_data(&nonce, &client_key, &public_key, &address);
+        let shared = curve25519(&secret, &client_key);
+        let transcript = [&nonce[..], &client_key[..], &public_key[..]].concat();
+        let key = hmac_sha256(
+            &shared,
+            &[&b"xuper-attest-session"[..], &transcript[..]].concat(),
+        );
+        (
+            AttestResponse {
+                public_key: hex::encode(&public_key),
+                endorser_address: address,
+                quote: hex::encode(enclave.quote(&report_data).unwrap()),
+            },
+            key,
+        )
+    }
+
+    // 背书服务这一侧对返回的认证
+    fn respond(
+        key: &[u8],
+        session_id: &str,
+        request_hash: &[u8],
+        resp: &xendorser::EndorserResponse,
+        data: &[u8],
+    ) -> xendorser::EndorserResponse {
+        let nonce = random_key().unwrap();
+        let envelope = AuthenticatedData {
+            session_id: session_id.to_owned(),
+            nonce: hex::encode(&nonce),
+            data: hex::encode(data),
+            mac: hex::encode(hmac_sha256(
+                key,
+                &response_data(request_hash, resp, &nonce, data),
+            )),
+        };
+        let mut r = resp.clone();
+        r.set_ResponseData(serde_json::to_vec(&envelope).unwrap());
+        r
+    }
+
+    fn compliance_request() -> xendorser::EndorserRequest {
+        let mut req = xendorser::EndorserRequest::new();
+        req.set_RequestName(String::from("ComplianceCheck"));
+        req.set_RequestData(b"{}".to_vec());
+        req
+    }
+
+    fn allowed(id: &Identity) -> config::AttestationConfig {
+        config::AttestationConfig {
+            mr_enclaves: vec![id.mr_enclave.to_uppercase()],
+            mr_signers: vec![],
+        }
+    }
+
+    #[test]
+    fn test_handshake() {
+        let client = MockQuote(identity(1));
+        let endorser = MockQuote(identity(7));
+        let (handshake, req) = Handshake::new(&client).unwrap();
+        let (resp, key) = endorser_side(&req, &client);
+        let channel = handshake
+            .finish(&resp, &endorser, &allowed(&endorser.0))
+            .unwrap();
+        assert_eq!(channel.key, key);
+        assert_eq!(channel.identity, endorser.0);
+
+        let mut req = compliance_request();
+        let pending = channel.authenticate_request(&mut req).unwrap();
+        // 背书服务校验请求的MAC
+        let envelope: AuthenticatedData = serde_json::from_slice(&req.RequestData).unwrap();
+        assert_eq!(envelope.session_id, channel.session_id);
+        let nonce = hex::decode(&envelope.nonce).unwrap();
+        let data = hex::decode(&envelope.data).unwrap();
+        assert_eq!(data, b"{}".to_vec());
+        let hash = request_hash(&req, &nonce, &data);
+        assert_eq!(
+            hex::decode(&envelope.mac).unwrap(),
+            hmac_sha256(&key, &hash)
+        );
+        // 同样的请求每次的nonce不同
+        let mut other = compliance_request();
+        let other_pending = channel.authenticate_request(&mut other).unwrap();
+        assert_ne!(other.RequestData, req.RequestData);
+
+        let mut resp = xendorser::EndorserResponse::new();
+        resp.set_EndorserAddress(channel.endorser_address.to_owned());
+        resp.set_ResponseData(b"ok".to_vec());
+        assert_eq!(
+            channel
+                .verify_response(&mut resp.clone(), &pending)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::AttestationFailed
+        );
+        let authenticated = respond(&key, &channel.session_id, &hash, &resp, b"ok");
+        let mut r = authenticated.clone();
+        assert_eq!(channel.verify_response(&mut r, &pending).is_ok(), true);
+        assert_eq!(r.ResponseData, b"ok".to_vec());
+        // 重放到其他请求上
+        assert_eq!(
+            channel
+                .verify_response(&mut authenticated.clone(), &other_pending)
+                .is_err(),
+            true
+        );
+        // 篡改返回的数据
+        let mut envelope: AuthenticatedData =
+            serde_json::from_slice(&authenticated.ResponseData).unwrap();
+        envelope.data = hex::encode(b"tampered");
+        let mut tampered = authenticated.clone();
+        tampered.set_ResponseData(serde_json::to_vec(&envelope).unwrap());
+        assert_eq!(
+            channel.verify_response(&mut tampered, &pending).is_err(),
+            true
+        );
+    }
+
+    #[test]
+    fn test_handshake_rejected() {
+        let client = MockQuote(identity(1));
+        let endorser = MockQuote(identity(7));
+
+        // 度量值不在白名单里
+        let (handshake, req) = Handshake::new(&client).unwrap();
+        let (resp, _) = endorser_side(&req, &client);
+        let err = handshake
+            .finish(&resp, &endorser, &allowed(&identity(9)))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AttestationFailed);
+
+        // 替换了背书地址，和quote绑定的不一致
+        let (handshake, req) = Handshake::new(&client).unwrap();
+        let (mut resp, _) = endorser_side(&req, &client);
+        resp.endorser_address = String::from("dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN");
+        let err = handshake
+            .finish(&resp, &endorser, &allowed(&endorser.0))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AttestationFailed);
+
+        // 白名单为空
+        let (handshake, req) = Handshake::new(&client).unwrap();
+        let (resp, _) = endorser_side(&req, &client);
+        let err = handshake
+            .finish(&resp, &endorser, &Default::default())
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidArguments);
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+use std::ops::AddAssign;
+
+use xchain_node_sdk::{errors::*, ocall, protos::xchain};
+
+/// 一个区块的出块奖励
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockAward {
+    pub height: i64,
+    /// hex编码
+    pub blockid: String,
+    pub proposer: String,
+    /// 是否在主干上，分叉上的奖励不应该入账
+    pub on_trunk: bool,
+    /// 地址 -> 奖励金额
+    pub rewards: BTreeMap<String, num_bigint::BigInt>,
+    pub total: num_bigint::BigInt,
+}
+
+/// 是否是出块奖励(coinbase)交易
+pub fn is_award_tx(tx: &xchain::Transaction) -> bool {
+    tx.coinbase
+}
+
+/// 解析区块里面所有的出块奖励
+pub fn decode_block_award(block: &xchain::Block) -> Result<BlockAward> {
+    if block.status == xchain::Block_EBlockStatus::NOEXIST || block.block.is_none() {
+        return Err(Error::from(ErrorKind::ChainRPCError));
+    }
+    let internal = block.get_block();
+    let mut rewards = BTreeMap::new();
+    let mut total: num_bigint::BigInt = num_traits::Zero::zero();
+    for tx in internal
+        .get_transactions()
+        .iter()
+        .filter(|tx| is_award_tx(tx))
+    {
+        for output in tx.get_tx_outputs().iter() {
+            let to = String::from_utf8(output.to_addr.clone())
+                .map_err(|_| Error::from(ErrorKind::ParseError))?;
+            let amount =
+                num_bigint::BigInt::from_bytes_be(num_bigint::Sign::Plus, output.get_amount());
+            total.add_assign(&amount);
+            rewards
+                .entry(to)
+                .or_insert_with(num_traits::Zero::zero)
+                .add_assign(amount);
+        }
+    }
+    Ok(BlockAward {
+        height: internal.height,
+        blockid: hex::encode(&internal.blockid),
+        proposer: String::from_utf8(internal.proposer.clone())
+            .map_err(|_| Error::from(ErrorKind::ParseError))?,
+        on_trunk: block.status == xchain::Block_EBlockStatus::TRUNK,
+        rewards: rewards,
+        total: total,
+    })
+}
+
+/// 查询高度为height的区块的出块奖励
+pub fn query_block_award(height: i64) -> Result<BlockAward> {
+    let block = ocall::ocall_xchain_get_block_by_height(height)?;
+    decode_block_award(&block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_traits::FromPrimitive;
+
+    fn output(to: &str, amount: u8) -> xchain::TxOutput {
+        let mut o = xchain::TxOutput::new();
+        o.set_to_addr(to.as_bytes().to_vec());
+        o.set_amount(vec![amount]);
+        o
+    }
+
+    #[test]
+    fn test_decode_block_award() {
+        let mut award = xchain::Transaction::new();
+        award.set_coinbase(true);
+        award.mut_tx_outputs().push(output("miner", 10));
+        award.mut_tx_outputs().push(output("miner", 5));
+        let mut transfer = xchain::Transaction::new();
+        transfer.mut_tx_outputs().push(output("alice", 100));
+
+        let mut block = xchain::Block::new();
+        block.set_status(xchain::Block_EBlockStatus::TRUNK);
+        let internal = block.mut_block();
+        internal.set_height(7);
+        internal.set_blockid(vec![0xab]);
+        internal.set_proposer(b"miner".to_vec());
+        internal.mut_transactions().push(award);
+        internal.mut_transactions().push(transfer);
+
+        let res = decode_block_award(&block).unwrap();
+        assert_eq!(res.height, 7);
+        assert_eq!(res.blockid, "ab");
+        assert_eq!(res.proposer, "miner");
+        assert_eq!(res.on_trunk, true);
+        assert_eq!(res.rewards.len(), 1);
+        assert_eq!(
+            res.rewards["miner"],
+            num_bigint::BigInt::from_i64(15).unwrap()
+        );
+        assert_eq!(res.total, num_bigint::BigInt::from_i64(15).unwrap());
+
+        block.set_status(xchain::Block_EBlockStatus::NOEXIST);
+        assert_eq!(decode_block_award(&block).is_ok(), false);
+    }
+}
@@ -0,0 +1,91 @@
+use num_bigint::BigInt;
+
+use crate::consts;
+use xchain_node_sdk::{errors::*, ocall, protos::xchain};
+
+/// 账户在一条链上的余额
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Balance {
+    pub bcname: String,
+    pub address: String,
+    /// 可以花费的余额
+    pub confirmed: BigInt,
+    /// 还在冻结(frozen_height之前)、暂时不能花费的余额
+    pub pending: BigInt,
+}
+
+impl Balance {
+    pub fn total(&self) -> BigInt {
+        &self.confirmed + &self.pending
+    }
+}
+
+/// 从节点返回的余额明细里取出bcname的余额
+pub fn decode_balance(bcname: &String, status: &xchain::AddressBalanceStatus) -> Result<Balance> {
+    let tfds = status
+        .get_tfds()
+        .iter()
+        .find(|t| &t.bcname == bcname)
+        .ok_or(Error::from(ErrorKind::ChainRPCError))?;
+    if tfds.error != xchain::XChainErrorEnum::SUCCESS {
+        return Err(Error::from(ErrorKind::ChainRPCError));
+    }
+    let mut balance = Balance {
+        bcname: bcname.to_owned(),
+        address: status.address.to_owned(),
+        confirmed: Default::default(),
+        pending: Default::default(),
+    };
+    for d in tfds.get_tfd().iter() {
+        let amount = consts::str_as_bigint(&d.balance)?;
+        if d.isFrozen {
+            balance.pending += amount;
+        } else {
+            balance.confirmed += amount;
+        }
+    }
+    Ok(balance)
+}
+
+/// 查询address在chain上面的余额
+pub fn get_balance(chain_name: &String, address: &String) -> Result<Balance> {
+    let status = ocall::ocall_xchain_get_balance_detail(address)?;
+    decode_balance(chain_name, &status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_balance() {
+        let bcname = String::from("xuper");
+        let detail = |balance: &str, frozen: bool| {
+            let mut d = xchain::TokenFrozenDetail::new();
+            d.set_balance(String::from(balance));
+            d.set_isFrozen(frozen);
+            d
+        };
+        let mut tfds = xchain::TokenFrozenDetails::new();
+        tfds.set_bcname(bcname.to_owned());
+        tfds.mut_tfd().push(detail("100", false));
+        tfds.mut_tfd().push(detail("30", true));
+        let mut status = xchain::AddressBalanceStatus::new();
+        status.set_address(String::from("alice"));
+        status.mut_tfds().push(tfds);
+
+        let b = decode_balance(&bcname, &status).unwrap();
+        assert_eq!(b.address, "alice");
+        assert_eq!(b.confirmed, BigInt::from(100));
+        assert_eq!(b.pending, BigInt::from(30));
+        assert_eq!(b.total(), BigInt::from(130));
+        assert_eq!(
+            decode_balance(&String::from("other"), &status).is_ok(),
+            false
+        );
+
+        status.mut_tfds()[0].set_error(xchain::XChainErrorEnum::CONNECT_REFUSE);
+        assert_eq!(decode_balance(&bcname, &status).is_ok(), false);
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use xchain_node_sdk::errors::*;
+use xuper_client_sdk::{balance, config, confirm, contract, transfer, transport, wallet};
+
+// 基于SDK的命令行工具，不写Rust代码也可以转账、查询和调用合约，和Go的xchain-cli用法相近。
+// 默认使用编译进SDK的conf/sdk.yaml，--config指定其他的sdk.yaml。
+// 命令行不在SGX里运行，配置的transport是ocall时改用grpc直接连接节点和背书服务。
+
+const USAGE: &str = "usage: xchain-cli [--config sdk.yaml] [--name xuper] [--keys data/keys] \
+[--fee 0] [--desc text] <command>
+
+commands:
+    account create <dir>                    generate a new account in dir
+    balance [address]                       balance of address, default the --keys account
+    transfer <to> <amount>                  transfer from the --keys account
+    query-tx <txid>                         state of a tx
+    invoke <contract> <method> [key=value]  invoke a wasm contract method";
+
+const DEFAULT_CHAIN: &str = "xuper";
+const DEFAULT_KEYS: &str = "data/keys";
+
+#[derive(Debug)]
+struct Options {
+    config: Option<String>,
+    chain_name: String,
+    keys: String,
+    fee: String,
+    desc: String,
+    command: Vec<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options> {
+    let mut opts = Options {
+        config: None,
+        chain_name: DEFAULT_CHAIN.to_owned(),
+        keys: DEFAULT_KEYS.to_owned(),
+        fee: String::from("0"),
+        desc: String::new(),
+        command: vec![],
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = match arg.as_str() {
+            "--config" | "--name" | "--keys" | "--fee" | "--desc" => args
+                .next()
+                .ok_or(Error::new(
+                    ErrorKind::InvalidArguments,
+                    format!("{} needs a value", arg),
+                ))?
+                .to_owned(),
+            _ => {
+                opts.command.push(arg.to_owned());
+                continue;
+            }
+        };
+        match arg.as_str() {
+            "--config" => opts.config = Some(value),
+            "--name" => opts.chain_name = value,
+            "--keys" => opts.keys = value,
+            "--fee" => opts.fee = value,
+            _ => opts.desc = value,
+        }
+    }
+    Ok(opts)
+}
+
+/// key=value形式的合约参数
+fn parse_args(args: &[String]) -> Result<HashMap<String, Vec<u8>>> {
+    args.iter()
+        .map(|a| {
+            let mut kv = a.splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some(k), Some(v)) if !k.is_empty() => Ok((k.to_owned(), v.as_bytes().to_vec())),
+                _ => Err(Error::new(
+                    ErrorKind::InvalidArguments,
+                    format!("contract argument {} is not key=value", a),
+                )),
+            }
+        })
+        .collect()
+}
+
+fn connect(opts: &Options) -> Result<()> {
+    let mut c = match opts.config {
+        Some(ref path) => {
+            (*config::ClientConfig::from_yaml(&std::fs::read_to_string(path)?)?).clone()
+        }
+        None => config::CONFIG.read().unwrap().clone(),
+    };
+    if c.transport == config::Transport::Ocall {
+        c.transport = config::Transport::Grpc;
+    }
+    *config::CONFIG.write().unwrap() = c;
+    config::install_api_key();
+    config::install_endorsers();
+    transport::connect(&opts.chain_name)
+}
+
+fn account(opts: &Options) -> Result<wallet::Account> {
+    wallet::Account::from_key_dir(&opts.keys, "", "")
+}
+
+fn run(opts: &Options) -> Result<String> {
+    let command: Vec<&str> = opts.command.iter().map(|s| s.as_str()).collect();
+    if let ["account", "create", dir] = command[..] {
+        return Ok(wallet::Account::generate(dir, "", "")?.address);
+    }
+    match command.first() {
+        Some(&"balance") | Some(&"transfer") | Some(&"query-tx") | Some(&"invoke") => {
+            connect(opts)?
+        }
+        _ => return Err(Error::new(ErrorKind::InvalidArguments, USAGE)),
+    }
+    let chain_name = &opts.chain_name;
+    match command[..] {
+        ["balance"] | ["balance", _] => {
+            let address = match command.get(1) {
+                Some(address) => address.to_string(),
+                None => account(opts)?.address,
+            };
+            let b = balance::get_balance(chain_name, &address)?;
+            Ok(format!(
+                "confirmed: {}\npending: {}",
+                b.confirmed, b.pending
+            ))
+        }
+        ["transfer", to, amount] => transfer::transfer(
+            &account(opts)?,
+            chain_name,
+            &to.to_owned(),
+            &amount.to_owned(),
+            &opts.fee,
+            &opts.desc,
+        ),
+        ["query-tx", txid] => Ok(format!("{:?}", confirm::query_tx_state(&txid.to_owned())?)),
+        ["invoke", contract_name, method_name, ..] => {
+            let (txid, body) = contract::invoke_contract(
+                &account(opts)?,
+                chain_name,
+                &contract_name.to_owned(),
+                &method_name.to_owned(),
+                parse_args(&opts.command[3..])?,
+                &opts.fee,
+            )?;
+            Ok(format!("{}\n{}", txid, String::from_utf8_lossy(&body)))
+        }
+        _ => Err(Error::new(ErrorKind::InvalidArguments, USAGE)),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match parse_options(&args).and_then(|opts| run(&opts)) {
+        Ok(out) => println!("{}", out),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let opts = parse_options(&strings(&[
+            "--name", "hello", "transfer", "--fee", "10", "alice", "100",
+        ]))
+        .unwrap();
+        assert_eq!(opts.chain_name, "hello");
+        assert_eq!(opts.keys, DEFAULT_KEYS);
+        assert_eq!(opts.fee, "10");
+        assert_eq!(opts.config, None);
+        assert_eq!(opts.command, strings(&["transfer", "alice", "100"]));
+        assert_eq!(
+            parse_options(&strings(&["balance", "--keys"])).is_err(),
+            true
+        );
+
+        let args = parse_args(&strings(&["key=a=b", "empty="])).unwrap();
+        assert_eq!(args["key"], b"a=b".to_vec());
+        assert_eq!(args["empty"], b"".to_vec());
+        assert_eq!(parse_args(&strings(&["key"])).is_err(), true);
+        assert_eq!(parse_args(&strings(&["=v"])).is_err(), true);
+
+        // 不认识的命令在连接节点之前返回
+        let opts = parse_options(&strings(&["unknown"])).unwrap();
+        assert_eq!(run(&opts).unwrap_err().kind(), ErrorKind::InvalidArguments);
+    }
+}
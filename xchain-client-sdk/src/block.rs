@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+
+use xchain_node_sdk::{errors::*, ocall, protos::xchain};
+
+/// 按高度或者blockid查询区块
+#[derive(Debug, PartialEq, Clone)]
+pub enum BlockRef {
+    Height(i64),
+    /// hex编码
+    Id(String),
+}
+
+impl BlockRef {
+    /// 十进制数字是高度，其他的是hex编码的blockid
+    pub fn parse(height_or_hash: &str) -> Result<Self> {
+        if height_or_hash.is_empty() {
+            return Err(Error::from(ErrorKind::InvalidArguments));
+        }
+        if height_or_hash.len() < 64 && height_or_hash.bytes().all(|b| b.is_ascii_digit()) {
+            let height = height_or_hash
+                .parse()
+                .map_err(|_| Error::from(ErrorKind::InvalidArguments))?;
+            return Ok(BlockRef::Height(height));
+        }
+        hex::decode(height_or_hash)?;
+        Ok(BlockRef::Id(height_or_hash.to_lowercase()))
+    }
+}
+
+/// 解析之后的区块，hash都是hex编码
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockInfo {
+    pub blockid: String,
+    pub height: i64,
+    /// 出块的矿工地址
+    pub proposer: String,
+    /// 纳秒
+    pub timestamp: i64,
+    pub pre_hash: String,
+    /// 最新的区块为空
+    pub next_hash: String,
+    pub txids: Vec<String>,
+    /// 是否在主干上
+    pub on_trunk: bool,
+}
+
+pub fn decode_block(block: &xchain::Block) -> Result<BlockInfo> {
+    if block.status == xchain::Block_EBlockStatus::NOEXIST || block.block.is_none() {
+        return Err(Error::from(ErrorKind::ChainRPCError));
+    }
+    let internal = block.get_block();
+    Ok(BlockInfo {
+        blockid: hex::encode(&internal.blockid),
+        height: internal.height,
+        proposer: String::from_utf8(internal.proposer.clone())
+            .map_err(|_| Error::from(ErrorKind::ParseError))?,
+        timestamp: internal.timestamp,
+        pre_hash: hex::encode(&internal.pre_hash),
+        next_hash: hex::encode(&internal.next_hash),
+        txids: internal
+            .get_transactions()
+            .iter()
+            .map(|tx| hex::encode(&tx.txid))
+            .collect(),
+        on_trunk: block.status == xchain::Block_EBlockStatus::TRUNK,
+    })
+}
+
+pub fn query_block(r: &BlockRef) -> Result<BlockInfo> {
+    let block = match r {
+        BlockRef::Height(height) => ocall::ocall_xchain_get_block_by_height(*height)?,
+        BlockRef::Id(blockid) => ocall::ocall_xchain_get_block(blockid)?,
+    };
+    let info = decode_block(&block)?;
+    // 不信任宿主返回的区块是请求的那一个
+    let matched = match r {
+        BlockRef::Height(height) => info.height == *height,
+        BlockRef::Id(blockid) => info.blockid == *blockid,
+    };
+    if !matched {
+        return Err(Error::from(ErrorKind::ChainRPCError));
+    }
+    Ok(info)
+}
+
+/// 一段高度区间的出块统计
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockStats {
+    pub blocks: u64,
+    /// 矿工地址 -> 出块数
+    pub proposers: BTreeMap<String, u64>,
+    pub txs: u64,
+    /// 相邻区块的平均出块间隔，秒
+    pub avg_interval_secs: f64,
+    /// 第一个区块之后的交易数 / (最后一个区块的时间 - 第一个区块的时间)
+    pub tps: f64,
+}
+
+/// 按高度排序的区块的统计，少于两个区块时间隔和tps为0
+pub fn compute_stats(blocks: &[BlockInfo]) -> BlockStats {
+    let mut proposers = BTreeMap::new();
+    let mut txs = 0;
+    for b in blocks.iter() {
+        *proposers.entry(b.proposer.to_owned()).or_insert(0) += 1;
+        txs += b.txids.len() as u64;
+    }
+    let (mut avg_interval_secs, mut tps) = (0.0, 0.0);
+    if let (Some(first), Some(last)) = (blocks.first(), blocks.last()) {
+        let span = (last.timestamp - first.timestamp) as f64 / 1e9;
+        if blocks.len() > 1 && span > 0.0 {
+            avg_interval_secs = span / (blocks.len() - 1) as f64;
+            tps = (txs - first.txids.len() as u64) as f64 / span;
+        }
+    }
+    BlockStats {
+        blocks: blocks.len() as u64,
+        proposers: proposers,
+        txs: txs,
+        avg_interval_secs: avg_interval_secs,
+        tps: tps,
+    }
+}
+
+/// 查询[range.start, range.end)的区块并统计
+pub fn block_stats(range: std::ops::Range<i64>) -> Result<BlockStats> {
+    if range.start < 0 || range.start >= range.end {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let mut blocks = vec![];
+    for height in range {
+        blocks.push(query_block(&BlockRef::Height(height))?);
+    }
+    Ok(compute_stats(&blocks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_ref() {
+        assert_eq!(BlockRef::parse("42").unwrap(), BlockRef::Height(42));
+        let id = "AB".repeat(32);
+        assert_eq!(BlockRef::parse(&id).unwrap(), BlockRef::Id("ab".repeat(32)));
+        assert_eq!(BlockRef::parse("").is_ok(), false);
+        assert_eq!(BlockRef::parse("xyz").is_ok(), false);
+    }
+
+    #[test]
+    fn test_decode_block() {
+        let mut tx = xchain::Transaction::new();
+        tx.set_txid(vec![0x01, 0x02]);
+        let mut internal = xchain::InternalBlock::new();
+        internal.set_blockid(vec![0xbb; 2]);
+        internal.set_height(7);
+        internal.set_proposer(b"miner".to_vec());
+        internal.set_timestamp(1_600_000_000_000_000_000);
+        internal.set_pre_hash(vec![0xaa; 2]);
+        internal.set_transactions(protobuf::RepeatedField::from_vec(vec![tx]));
+        let mut block = xchain::Block::new();
+        block.set_status(xchain::Block_EBlockStatus::TRUNK);
+        block.set_block(internal);
+
+        let info = decode_block(&block).unwrap();
+        assert_eq!(info.blockid, "bbbb");
+        assert_eq!(info.pre_hash, "aaaa");
+        assert_eq!(info.next_hash, "");
+        assert_eq!(info.proposer, "miner");
+        assert_eq!(info.txids, vec![String::from("0102")]);
+        assert_eq!(info.on_trunk, true);
+
+        block.set_status(xchain::Block_EBlockStatus::NOEXIST);
+        assert_eq!(decode_block(&block).is_ok(), false);
+    }
+
+    #[test]
+    fn test_compute_stats() {
+        let block = |height: i64, proposer: &str, secs: i64, txs: usize| BlockInfo {
+            blockid: String::new(),
+            height: height,
+            proposer: String::from(proposer),
+            timestamp: secs * 1_000_000_000,
+            pre_hash: String::new(),
+            next_hash: String::new(),
+            txids: vec![String::new(); txs],
+            on_trunk: true,
+        };
+        let stats = compute_stats(&[
+            block(1, "alice", 100, 4),
+            block(2, "bob", 103, 3),
+            block(3, "alice", 106, 9),
+        ]);
+        assert_eq!(stats.blocks, 3);
+        assert_eq!(stats.proposers["alice"], 2);
+        assert_eq!(stats.proposers["bob"], 1);
+        assert_eq!(stats.txs, 16);
+        assert_eq!(stats.avg_interval_secs, 3.0);
+        assert_eq!(stats.tps, 2.0);
+
+        let stats = compute_stats(&[block(1, "alice", 100, 4)]);
+        assert_eq!(stats.tps, 0.0);
+        assert_eq!(block_stats(5..5).is_ok(), false);
+    }
+}
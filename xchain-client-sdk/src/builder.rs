@@ -0,0 +1,225 @@
+use std::ops::Sub;
+
+use crate::{consts, session, template, wallet};
+use xchain_node_sdk::{encoder, errors::*, protos::xchain};
+
+// 生成转账交易的纯函数，不读CONFIG，不访问节点和背书服务，Session生成交易时也使用这里的函数，
+// 批量签名、交易分析之类的工具可以离线生成和SDK完全一样的交易。
+// 合规检查(背书服务的auth_require和签名)不在这里，由调用方放在auth_require里。
+
+/// build_transfer_tx的参数
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TxOptions {
+    /// 发起者，找零给这个地址
+    pub initiator: String,
+    pub auth_require: Vec<String>,
+    pub desc: String,
+    /// 矿工手续费
+    pub fee: String,
+    /// 收款输出冻结到的区块高度
+    pub frozen_height: i64,
+    /// 见CommConfig::desc_compress_threshold，0表示不压缩
+    pub desc_compress_threshold: usize,
+    /// 为None时使用当前时间
+    pub timestamp: Option<i64>,
+    /// 为None时随机生成
+    pub nonce: Option<String>,
+}
+
+/// 没有签名的交易和签名用的摘要
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsignedTx {
+    pub tx: xchain::Transaction,
+    pub digest: Vec<u8>,
+}
+
+/// utxo对应的交易输入，按(ref_txid, ref_offset)排序，不依赖节点返回的顺序，
+/// 固定timestamp和nonce时同样的utxo生成同样的交易
+pub fn tx_inputs(utxos: &[xchain::Utxo]) -> Vec<xchain::TxInput> {
+    let mut tx_inputs: Vec<xchain::TxInput> = utxos
+        .iter()
+        .map(|utxo| {
+            let mut ti = xchain::TxInput::new();
+            ti.set_ref_txid(utxo.refTxid.clone());
+            ti.set_ref_offset(utxo.refOffset);
+            ti.set_from_addr(utxo.toAddr.clone());
+            ti.set_amount(utxo.amount.clone());
+            ti
+        })
+        .collect();
+    tx_inputs.sort_by(|a, b| (&a.ref_txid, a.ref_offset).cmp(&(&b.ref_txid, b.ref_offset)));
+    tx_inputs
+}
+
+/// 收款的输出，金额为0时不生成
+pub fn payment_outputs(
+    payments: &[(&str, &str)],
+    frozen_height: i64,
+) -> Result<Vec<xchain::TxOutput>> {
+    let mut tx_outputs = vec![];
+    for (to, amount) in payments.iter() {
+        let am = consts::str_as_bigint(amount)?;
+        if !to.is_empty() && am != num_traits::Zero::zero() {
+            let mut t = xchain::TxOutput::new();
+            t.set_to_addr(to.as_bytes().to_vec());
+            t.set_amount(am.to_bytes_be().1);
+            t.set_frozen_height(frozen_height);
+            tx_outputs.push(t);
+        }
+    }
+    Ok(tx_outputs)
+}
+
+/// 输入总额大于需要的金额时找零给change_addr
+pub fn change_output(
+    total: &num_bigint::BigInt,
+    total_need: &num_bigint::BigInt,
+    change_addr: &str,
+) -> Option<xchain::TxOutput> {
+    if total <= total_need {
+        return None;
+    }
+    let mut to = xchain::TxOutput::new();
+    to.set_to_addr(change_addr.as_bytes().to_vec());
+    to.set_amount(total.sub(total_need).to_bytes_be().1);
+    Some(to)
+}
+
+/// 花费inputs里全部的utxo给outputs里的(to, amount)转账，输出依次是收款、手续费、找零
+/// outputs为空时是只有desc的交易，没有手续费时inputs也可以为空
+pub fn build_transfer_tx(
+    inputs: &[xchain::Utxo],
+    outputs: &[(String, String)],
+    options: &TxOptions,
+) -> Result<UnsignedTx> {
+    if options.initiator.is_empty() {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let msg = session::Message {
+        fee: options.fee.to_owned(),
+        desc: options.desc.to_owned(),
+        initiator: options.initiator.to_owned(),
+        ..Default::default()
+    };
+    let template = template::TxTemplate::new(
+        &msg,
+        options.auth_require.clone(),
+        options.desc_compress_threshold,
+    )?;
+
+    let payments: Vec<(&str, &str)> = outputs.iter().map(|(t, a)| (&t[..], &a[..])).collect();
+    let mut tx_outputs = payment_outputs(&payments, options.frozen_height)?;
+    if let Some(ref fee_output) = template.fee_output {
+        tx_outputs.push(fee_output.clone());
+    }
+
+    let amounts: Vec<&str> = outputs.iter().map(|(_, a)| &a[..]).collect();
+    let total_need = consts::checked_sum(&amounts)? + &template.fee;
+    let mut total = num_bigint::BigInt::from(0);
+    for utxo in inputs.iter() {
+        total += num_bigint::BigInt::from_bytes_be(num_bigint::Sign::Plus, &utxo.amount);
+    }
+    if total < total_need {
+        return Err(Error::new(
+            ErrorKind::InvalidArguments,
+            format!("inputs total {} is less than {}", total, total_need),
+        ));
+    }
+    if let Some(change) = change_output(&total, &total_need, &options.initiator) {
+        tx_outputs.push(change);
+    }
+
+    let mut tx = template.to_tx();
+    tx.set_timestamp(options.timestamp.unwrap_or_else(consts::now_as_nanos));
+    tx.set_tx_inputs(protobuf::RepeatedField::from_vec(tx_inputs(inputs)));
+    tx.set_tx_outputs(protobuf::RepeatedField::from_vec(tx_outputs));
+    tx.set_nonce(match options.nonce {
+        Some(ref n) => n.to_owned(),
+        None => wallet::get_nonce()?,
+    });
+    let digest = encoder::make_tx_digest_hash(&tx)?;
+    Ok(UnsignedTx {
+        tx: tx,
+        digest: digest,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utxo(amount: u8, ref_txid: u8) -> xchain::Utxo {
+        let mut u = xchain::Utxo::new();
+        u.set_toAddr(b"alice".to_vec());
+        u.set_amount(vec![amount]);
+        u.set_refTxid(vec![ref_txid]);
+        u
+    }
+
+    fn options() -> TxOptions {
+        TxOptions {
+            initiator: String::from("alice"),
+            auth_require: vec![String::from("alice")],
+            desc: String::from("batch"),
+            fee: String::from("2"),
+            frozen_height: 10,
+            timestamp: Some(1),
+            nonce: Some(String::from("n")),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_build_transfer_tx() {
+        let inputs = vec![utxo(6, 0xbb), utxo(4, 0xaa)];
+        let outputs = vec![(String::from("bob"), String::from("3"))];
+        let unsigned = build_transfer_tx(&inputs, &outputs, &options()).unwrap();
+        let tx = &unsigned.tx;
+        assert_eq!(tx.get_tx_inputs()[0].get_ref_txid(), &[0xaa]);
+        // 收款3冻结，手续费2，找零5
+        let amounts: Vec<&[u8]> = tx.get_tx_outputs().iter().map(|o| o.get_amount()).collect();
+        assert_eq!(amounts, vec![&[3][..], &[2][..], &[5][..]]);
+        assert_eq!(tx.get_tx_outputs()[0].get_frozen_height(), 10);
+        assert_eq!(tx.get_tx_outputs()[2].get_to_addr(), b"alice");
+        assert_eq!(tx.get_desc(), b"batch");
+        assert_eq!(unsigned.digest, encoder::make_tx_digest_hash(tx).unwrap());
+
+        // 同样的参数生成同样的交易
+        let reversed: Vec<xchain::Utxo> = inputs.iter().rev().cloned().collect();
+        assert_eq!(
+            build_transfer_tx(&reversed, &outputs, &options()).unwrap(),
+            unsigned
+        );
+    }
+
+    #[test]
+    fn test_build_desc_only_tx() {
+        let unsigned = build_transfer_tx(&[utxo(10, 0xaa)], &[], &options()).unwrap();
+        // 手续费2，找零8
+        let amounts: Vec<&[u8]> = unsigned
+            .tx
+            .get_tx_outputs()
+            .iter()
+            .map(|o| o.get_amount())
+            .collect();
+        assert_eq!(amounts, vec![&[2][..], &[8][..]]);
+
+        let free = TxOptions {
+            fee: String::from("0"),
+            ..options()
+        };
+        let unsigned = build_transfer_tx(&[], &[], &free).unwrap();
+        assert_eq!(unsigned.tx.get_tx_inputs().is_empty(), true);
+        assert_eq!(unsigned.tx.get_tx_outputs().is_empty(), true);
+        assert_eq!(unsigned.tx.get_desc(), b"batch");
+    }
+
+    #[test]
+    fn test_build_transfer_tx_insufficient() {
+        let outputs = vec![(String::from("bob"), String::from("9"))];
+        let err = build_transfer_tx(&[utxo(10, 0xaa)], &outputs, &options()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidArguments);
+        assert_eq!(build_transfer_tx(&[], &outputs, &options()).is_err(), true);
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread;
+
+use crate::{session, transfer, wallet};
+use xchain_node_sdk::{errors::*, protos::xchain};
+
+// 批量提交: 预执行、合规检查、提交三步分别在三个线程里面流水线执行，
+// 第N+1笔交易预执行的同时第N笔在做合规检查。
+// 还没提交的交易选中的utxo会被记录下来，后面的交易预执行选中了相同的utxo时，
+// 等前面的交易提交之后重新预执行，避免双花。
+// 限制: utxo由节点在预执行时选择，节点看不到还没提交的交易的找零输出，所以后面的交易
+// 不能花费前面交易的找零。同一个账户的交易只有在账户里有足够多互不相交的utxo时才能流水线，
+// 否则(比如账户只有一个utxo)会等前一笔提交之后才预执行，退化成串行。
+// 需要同一个账户高吞吐时，先用transfer_to_many给自己转出多个输出，把余额拆成多个utxo，
+// 或者把交易分给多个账户。
+// run_parallel是按组并行的线程池，组内按顺序执行，Client::submit_parallel用它按账户并行。
+
+/// 一笔待提交的交易
+pub struct BulkJob {
+    pub account: wallet::Account,
+    pub msg: session::Message,
+    pub req: xchain::PreExecWithSelectUTXORequest,
+}
+
+impl BulkJob {
+    /// 转账，参数同transfer::transfer
+    pub fn transfer(
+        account: &wallet::Account,
+        chain_name: &String,
+        to: &String,
+        amount: &String,
+        fee: &String,
+        desc: &String,
+    ) -> Result<Self> {
+        let (req, msg) =
+            transfer::build_transfer_request(account, chain_name, to, amount, fee, desc)?;
+        Ok(BulkJob {
+            account: account.clone(),
+            msg: msg,
+            req: req,
+        })
+    }
+}
+
+type UtxoRef = (Vec<u8>, i32);
+
+/// 还没提交的交易占用的utxo
+type Claims = Arc<(Mutex<HashSet<UtxoRef>>, Condvar)>;
+
+/// 流水线提交交易，同一个账户的交易选中相同utxo时串行执行(见模块说明)
+pub struct BulkSubmitter {
+    chain_name: String,
+}
+
+impl BulkSubmitter {
+    pub fn new(chain_name: &String) -> Self {
+        BulkSubmitter {
+            chain_name: chain_name.to_owned(),
+        }
+    }
+
+    /// 按顺序提交，返回每笔交易的txid或者错误，某一笔失败不影响其他交易
+    pub fn submit(&self, jobs: Vec<BulkJob>) -> Vec<Result<String>> {
+        let claims: Claims = Arc::new((Mutex::new(HashSet::new()), Condvar::new()));
+
+        let (pre_exec_sender, pre_exec_receiver) = mpsc::sync_channel(1);
+        let chain_name = self.chain_name.clone();
+        let c = claims.clone();
+        let pre_exec = thread::spawn(move || {
+            for job in jobs.into_iter() {
+                let res = pre_exec_and_claim(&chain_name, &job, &c);
+                if pre_exec_sender.send((job, res)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let (endorsed_sender, endorsed_receiver) = mpsc::sync_channel(1);
+        let chain_name = self.chain_name.clone();
+        let compliance = thread::spawn(move || {
+            for (job, res) in pre_exec_receiver.iter() {
+                let (refs, tx) = match res {
+                    Ok((mut resp, refs)) => {
+                        let sess = session::Session::new(&chain_name, &job.account, &job.msg);
+                        (refs, sess.gen_complete_tx(&mut resp, None))
+                    }
+                    Err(e) => (vec![], Err(e)),
+                };
+                if endorsed_sender.send((job, refs, tx)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut results = vec![];
+        for (job, refs, tx) in endorsed_receiver.iter() {
+            let sess = session::Session::new(&self.chain_name, &job.account, &job.msg);
+            results.push(tx.and_then(|tx| sess.post_complete_tx(&tx)));
+            release(&claims, &refs);
+        }
+        let _ = pre_exec.join();
+        let _ = compliance.join();
+        results
+    }
+}
+
+fn pre_exec_and_claim(
+    chain_name: &String,
+    job: &BulkJob,
+    claims: &Claims,
+) -> Result<(xchain::PreExecWithSelectUTXOResponse, Vec<UtxoRef>)> {
+    let sess = session::Session::new(chain_name, &job.account, &job.msg);
+    loop {
+        let resp = sess.pre_exec_with_select_utxo(job.req.clone())?;
+        let refs: Vec<UtxoRef> = resp
+            .get_utxoOutput()
+            .get_utxoList()
+            .iter()
+            .map(|u| (u.refTxid.clone(), u.refOffset))
+            .collect();
+        let (lock, cvar) = &**claims;
+        let mut claimed = lock.lock().unwrap();
+        if try_claim(&mut claimed, &refs) {
+            return Ok((resp, refs));
+        }
+        // 等前面的交易提交之后重新预执行
+        let _ = cvar.wait(claimed).unwrap();
+    }
+}
+
+/// 没有冲突时占用refs
+fn try_claim(claimed: &mut HashSet<UtxoRef>, refs: &[UtxoRef]) -> bool {
+    if refs.iter().any(|r| claimed.contains(r)) {
+        return false;
+    }
+    for r in refs.iter() {
+        claimed.insert(r.clone());
+    }
+    true
+}
+
+fn release(claims: &Claims, refs: &[UtxoRef]) {
+    if refs.is_empty() {
+        return;
+    }
+    let (lock, cvar) = &**claims;
+    let mut claimed = lock.lock().unwrap();
+    for r in refs.iter() {
+        claimed.remove(r);
+    }
+    cvar.notify_all();
+}
+
+/// 最多workers个线程，每次取一组按顺序执行f，返回和下标顺序一致的结果
+/// client::Client::submit_parallel按账户分组调用
+pub(crate) fn run_parallel<F>(
+    groups: Vec<Vec<usize>>,
+    n: usize,
+    workers: usize,
+    f: F,
+) -> Vec<Result<String>>
+where
+    F: Fn(usize) -> Result<String> + Send + Sync + 'static,
+{
+    let workers = std::cmp::min(std::cmp::max(workers, 1), groups.len());
+    let queue = Arc::new(Mutex::new(groups.into_iter().collect::<VecDeque<_>>()));
+    let results: Arc<Mutex<Vec<Option<Result<String>>>>> =
+        Arc::new(Mutex::new((0..n).map(|_| None).collect()));
+    let f = Arc::new(f);
+    let handles: Vec<_> = (0..workers)
+        .map(|_| {
+            let (queue, results, f) = (queue.clone(), results.clone(), f.clone());
+            thread::spawn(move || loop {
+                let group = match queue.lock().unwrap().pop_front() {
+                    Some(g) => g,
+                    None => return,
+                };
+                for i in group {
+                    let res = f(i);
+                    results.lock().unwrap()[i] = Some(res);
+                }
+            })
+        })
+        .collect();
+    for h in handles {
+        let _ = h.join();
+    }
+    let mut results = results.lock().unwrap();
+    results
+        .iter_mut()
+        .map(|r| r.take().unwrap_or(Err(Error::from(ErrorKind::Unknown))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claim_and_release() {
+        let claims: Claims = Arc::new((Mutex::new(HashSet::new()), Condvar::new()));
+        let a = vec![(vec![1u8], 0), (vec![1u8], 1)];
+        let b = vec![(vec![1u8], 1), (vec![2u8], 0)];
+        let c = vec![(vec![2u8], 0)];
+
+        {
+            let mut claimed = claims.0.lock().unwrap();
+            assert_eq!(try_claim(&mut claimed, &a), true);
+            // 和a冲突，并且冲突时不占用
+            assert_eq!(try_claim(&mut claimed, &b), false);
+            assert_eq!(try_claim(&mut claimed, &c), true);
+        }
+        release(&claims, &a);
+        release(&claims, &c);
+        let mut claimed = claims.0.lock().unwrap();
+        assert_eq!(try_claim(&mut claimed, &b), true);
+    }
+
+    #[test]
+    fn test_run_parallel() {
+        // (正在执行的, 最多同时执行的)
+        let running = Arc::new(Mutex::new((0, 0)));
+        let r = running.clone();
+        let results = run_parallel(
+            vec![vec![0, 3], vec![1], vec![2], vec![4]],
+            5,
+            2,
+            move |i| {
+                {
+                    let mut r = r.lock().unwrap();
+                    r.0 += 1;
+                    r.1 = std::cmp::max(r.0, r.1);
+                }
+                thread::sleep(std::time::Duration::from_millis(10));
+                r.lock().unwrap().0 -= 1;
+                if i == 1 {
+                    return Err(Error::from(ErrorKind::InvalidArguments));
+                }
+                Ok(i.to_string())
+            },
+        );
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0].as_ref().unwrap(), "0");
+        assert_eq!(results[1].is_ok(), false);
+        assert_eq!(results[4].as_ref().unwrap(), "4");
+        assert_eq!(running.lock().unwrap().1 <= 2, true);
+        assert_eq!(
+            run_parallel(vec![], 0, 2, |_| Ok(String::new())).is_empty(),
+            true
+        );
+    }
+}
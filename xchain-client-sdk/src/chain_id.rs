@@ -0,0 +1,111 @@
+use crate::desc;
+use xchain_node_sdk::{errors::*, protos::xchain};
+
+// 把交易绑定到chain id(例如"xuper-test", "xuper-prod")，防止签名过的交易在测试网和生产网之间重放。
+// 交易摘要包括desc，chain id以 CHAIN_ID_MAGIC + chain_id + "\n" 的格式放在desc的最前面，签名之后不能修改。
+// 节点不检查这个前缀，绑定对校验chain id的一方(背书服务、对账、离线广播)有效，见verify。
+// 配置了chainId时Session在所有钩子之后绑定，trace和tags重新嵌入desc时会去掉绑定。
+
+/// chain id不能为空，不能包含换行，最长64字节
+pub fn is_valid_chain_id(chain_id: &str) -> bool {
+    !chain_id.is_empty() && chain_id.len() <= 64 && !chain_id.contains('\n')
+}
+
+/// 把chain id绑定到desc，替换已有的绑定
+pub fn bind(d: &[u8], chain_id: &str) -> Result<Vec<u8>> {
+    if !is_valid_chain_id(chain_id) {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let (_, rest) = desc::split_chain_id(d)?;
+    let mut res = desc::CHAIN_ID_MAGIC.to_vec();
+    res.extend_from_slice(chain_id.as_bytes());
+    res.push(b'\n');
+    res.extend_from_slice(rest);
+    Ok(res)
+}
+
+/// 取出desc绑定的chain id
+pub fn extract(d: &[u8]) -> Result<Option<String>> {
+    Ok(desc::split_chain_id(d)?.0.map(|c| c.to_owned()))
+}
+
+pub fn tx_chain_id(tx: &xchain::Transaction) -> Result<Option<String>> {
+    extract(tx.get_desc())
+}
+
+/// 签名之前把tx绑定到chain id，txid需要调用方重新计算
+pub fn bind_tx(tx: &mut xchain::Transaction, chain_id: &str) -> Result<()> {
+    let d = bind(tx.get_desc(), chain_id)?;
+    tx.set_desc(d);
+    Ok(())
+}
+
+/// 校验tx绑定的是chain_id，没有绑定或者绑定的是别的链时返回ChainIdMismatch
+pub fn verify(tx: &xchain::Transaction, chain_id: &str) -> Result<()> {
+    match tx_chain_id(tx)? {
+        Some(ref c) if c == chain_id => Ok(()),
+        Some(c) => Err(Error::new(
+            ErrorKind::ChainIdMismatch,
+            format!("tx is bound to {}, expect {}", c, chain_id),
+        )),
+        None => Err(Error::new(
+            ErrorKind::ChainIdMismatch,
+            format!("tx is not bound to {}", chain_id),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tags;
+
+    #[test]
+    fn test_bind_chain_id() {
+        let mut tx = xchain::Transaction::new();
+        tx.set_desc(b"test duanbing".to_vec());
+        bind_tx(&mut tx, "xuper-test").unwrap();
+        assert_eq!(tx_chain_id(&tx).unwrap(), Some(String::from("xuper-test")));
+        assert_eq!(desc::tx_desc(&tx).unwrap(), b"test duanbing".to_vec());
+        assert_eq!(verify(&tx, "xuper-test").is_ok(), true);
+        assert_eq!(
+            verify(&tx, "xuper-prod").unwrap_err().kind(),
+            ErrorKind::ChainIdMismatch
+        );
+
+        // 替换已有的绑定
+        bind_tx(&mut tx, "xuper-prod").unwrap();
+        assert_eq!(verify(&tx, "xuper-prod").is_ok(), true);
+        assert_eq!(desc::tx_desc(&tx).unwrap(), b"test duanbing".to_vec());
+
+        let plain = xchain::Transaction::new();
+        assert_eq!(
+            verify(&plain, "xuper-prod").unwrap_err().kind(),
+            ErrorKind::ChainIdMismatch
+        );
+        // 和标签一起使用
+        let d = tags::embed(b"test duanbing", &[String::from("payout")]).unwrap();
+        let d = bind(&d, "xuper-test").unwrap();
+        assert_eq!(extract(&d).unwrap(), Some(String::from("xuper-test")));
+        assert_eq!(tags::extract(&d).unwrap(), vec![String::from("payout")]);
+        assert_eq!(desc::decode(&d).unwrap(), b"test duanbing".to_vec());
+
+        assert_eq!(bind(b"", "").is_ok(), false);
+        assert_eq!(bind(b"", "a\nb").is_ok(), false);
+    }
+
+    #[cfg(feature = "trace-context")]
+    #[test]
+    fn test_bind_with_trace_and_tags() {
+        use crate::trace;
+
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let d = trace::embed(b"test duanbing", traceparent).unwrap();
+        let d = tags::embed(&d, &[String::from("payout")]).unwrap();
+        let d = bind(&d, "xuper-test").unwrap();
+        assert_eq!(extract(&d).unwrap(), Some(String::from("xuper-test")));
+        assert_eq!(trace::extract(&d).unwrap(), Some(String::from(traceparent)));
+        assert_eq!(tags::extract(&d).unwrap(), vec![String::from("payout")]);
+        assert_eq!(desc::decode(&d).unwrap(), b"test duanbing".to_vec());
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use xchain_node_sdk::{errors::*, ocall, protos::xchain};
+
+// 节点可能同时服务多条平行链，不再假设链名是"xuper"。
+// Chains在第一次使用时查询节点服务的链并缓存，调用方不指定链名时使用默认链。
+
+/// 一条链的基本参数
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChainInfo {
+    pub bcname: String,
+    /// 主干高度
+    pub height: i64,
+    /// hex编码
+    pub root_blockid: String,
+    /// hex编码
+    pub tip_blockid: String,
+    pub utxo_total: String,
+    pub max_block_size: i64,
+}
+
+pub fn decode_chain_info(status: &xchain::BCStatus) -> Result<ChainInfo> {
+    if status.bcname.is_empty() || status.meta.is_none() {
+        return Err(Error::from(ErrorKind::ChainRPCError));
+    }
+    let meta = status.get_meta();
+    let utxo_meta = status.get_utxoMeta();
+    Ok(ChainInfo {
+        bcname: status.bcname.to_owned(),
+        height: meta.trunk_height,
+        root_blockid: hex::encode(&meta.root_blockid),
+        tip_blockid: hex::encode(&meta.tip_blockid),
+        utxo_total: utxo_meta.utxo_total.to_owned(),
+        max_block_size: utxo_meta.max_block_size,
+    })
+}
+
+/// 查询节点服务的所有链和它们的基本参数，按链名排序
+pub fn discover_chains() -> Result<Vec<ChainInfo>> {
+    let mut names = ocall::ocall_xchain_get_block_chains()?;
+    names.sort();
+    let mut chains = vec![];
+    for name in names.iter() {
+        let status = ocall::ocall_xchain_get_block_chain_status(name)?;
+        chains.push(decode_chain_info(&status)?);
+    }
+    Ok(chains)
+}
+
+pub type Loader = Box<dyn Fn() -> Result<Vec<ChainInfo>> + Send + Sync>;
+
+pub struct Chains {
+    loader: Loader,
+    chains: RwLock<Option<BTreeMap<String, ChainInfo>>>,
+}
+
+impl Default for Chains {
+    fn default() -> Self {
+        Self::with_loader(Box::new(discover_chains))
+    }
+}
+
+impl Chains {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_loader(loader: Loader) -> Self {
+        Chains {
+            loader: loader,
+            chains: RwLock::new(None),
+        }
+    }
+
+    /// 重新查询节点，链的参数(例如高度)会变化
+    pub fn refresh(&self) -> Result<()> {
+        let chains = (self.loader)()?
+            .into_iter()
+            .map(|c| (c.bcname.to_owned(), c))
+            .collect();
+        *self.chains.write().unwrap() = Some(chains);
+        Ok(())
+    }
+
+    fn with_chains<T, F>(&self, f: F) -> Result<T>
+    where
+        F: Fn(&BTreeMap<String, ChainInfo>) -> Result<T>,
+    {
+        if let Some(ref chains) = *self.chains.read().unwrap() {
+            return f(chains);
+        }
+        self.refresh()?;
+        f(self.chains.read().unwrap().as_ref().unwrap())
+    }
+
+    pub fn names(&self) -> Result<Vec<String>> {
+        self.with_chains(|c| Ok(c.keys().cloned().collect()))
+    }
+
+    pub fn chain(&self, bcname: &str) -> Result<ChainInfo> {
+        self.with_chains(|c| {
+            c.get(bcname)
+                .cloned()
+                .ok_or(Error::from(ErrorKind::InvalidArguments))
+        })
+    }
+
+    /// 节点只服务一条链时就是这条链，否则必须指定链名
+    pub fn default_chain(&self) -> Result<String> {
+        self.with_chains(|c| {
+            if c.len() != 1 {
+                return Err(Error::from(ErrorKind::InvalidArguments));
+            }
+            Ok(c.keys().next().unwrap().to_owned())
+        })
+    }
+
+    /// 校验链名，为空时使用默认链
+    pub fn resolve(&self, bcname: &str) -> Result<String> {
+        if bcname.is_empty() {
+            return self.default_chain();
+        }
+        Ok(self.chain(bcname)?.bcname)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn info(bcname: &str, height: i64) -> ChainInfo {
+        ChainInfo {
+            bcname: String::from(bcname),
+            height: height,
+            root_blockid: String::new(),
+            tip_blockid: String::new(),
+            utxo_total: String::from("0"),
+            max_block_size: 0,
+        }
+    }
+
+    #[test]
+    fn test_decode_chain_info() {
+        let mut status = xchain::BCStatus::new();
+        status.set_bcname(String::from("hello"));
+        assert_eq!(decode_chain_info(&status).is_ok(), false);
+        status.mut_meta().set_trunk_height(10);
+        status.mut_meta().set_tip_blockid(vec![0xaa]);
+        status.mut_utxoMeta().set_utxo_total(String::from("100"));
+        let c = decode_chain_info(&status).unwrap();
+        assert_eq!(c.height, 10);
+        assert_eq!(c.tip_blockid, "aa");
+        assert_eq!(c.utxo_total, "100");
+    }
+
+    #[test]
+    fn test_chains_lazy_discovery() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let c = calls.clone();
+        let chains = Chains::with_loader(Box::new(move || {
+            c.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![info("xuper", 10), info("hello", 3)])
+        }));
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        assert_eq!(
+            chains.names().unwrap(),
+            vec![String::from("hello"), String::from("xuper")]
+        );
+        assert_eq!(chains.chain("hello").unwrap().height, 3);
+        assert_eq!(chains.resolve("xuper").unwrap(), "xuper");
+        assert_eq!(chains.resolve("other").is_ok(), false);
+        // 多条链时必须指定链名
+        assert_eq!(chains.resolve("").is_ok(), false);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        chains.refresh().unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let chains = Chains::with_loader(Box::new(|| Ok(vec![info("hello", 3)])));
+        assert_eq!(chains.resolve("").unwrap(), "hello");
+    }
+}
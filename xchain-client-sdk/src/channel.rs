@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{consts, contract, wallet};
+use xchain_node_sdk::errors::*;
+
+// 微支付通道: 链上开通道和结算，中间大量的小额支付只在链下交换签名的余额更新，
+// 不需要每笔都上链支付合规检查的手续费。
+// 通道合约为account.contract_name，需要提供open和settle两个方法，押金由合约托管。
+
+/// 链下的余额更新，由payer签名后发给payee
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct BalanceUpdate {
+    pub channel_id: String,
+    pub payer: String,
+    pub payee: String,
+    /// 单调递增，结算时以seq最大的更新为准
+    pub seq: u64,
+    /// 累计支付给payee的金额
+    pub paid: String,
+}
+
+impl BalanceUpdate {
+    /// 被签名的内容，用Account::sign_message签名
+    pub fn to_bytes(&self) -> Vec<u8> {
+        format!(
+            "{}\n{}\n{}\n{}\n{}",
+            self.channel_id, self.payer, self.payee, self.seq, self.paid
+        )
+        .into_bytes()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SignedBalanceUpdate {
+    pub update: BalanceUpdate,
+    pub public_key: String,
+    pub sign: Vec<u8>,
+}
+
+/// payer对余额更新签名
+pub fn sign_update(
+    account: &wallet::Account,
+    update: &BalanceUpdate,
+) -> Result<SignedBalanceUpdate> {
+    if update.payer != account.address {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    // 累计金额不能是负数
+    consts::checked_sum(&[&update.paid])?;
+    Ok(SignedBalanceUpdate {
+        update: update.clone(),
+        public_key: account.public_key()?,
+        sign: account.sign_message(&update.to_bytes())?,
+    })
+}
+
+/// 校验签名，并且签名的公钥属于payer
+pub fn verify_update(signed: &SignedBalanceUpdate) -> Result<()> {
+    let address = wallet::verify_message_with_public_key(
+        &signed.public_key,
+        &signed.update.to_bytes(),
+        &signed.sign,
+    )?;
+    if address != signed.update.payer {
+        return Err(Error::from(ErrorKind::CryptoError));
+    }
+    Ok(())
+}
+
+/// payee收到新的余额更新时调用: 校验签名，同一个通道，seq递增，累计金额不减少
+pub fn accept_update(
+    latest: Option<&SignedBalanceUpdate>,
+    next: &SignedBalanceUpdate,
+) -> Result<()> {
+    verify_update(next)?;
+    let paid = consts::checked_sum(&[&next.update.paid])?;
+    if let Some(latest) = latest {
+        let l = &latest.update;
+        let n = &next.update;
+        if l.channel_id != n.channel_id || l.payer != n.payer || l.payee != n.payee {
+            return Err(Error::from(ErrorKind::InvalidArguments));
+        }
+        if n.seq <= l.seq || paid < consts::str_as_bigint(&l.paid)? {
+            return Err(Error::from(ErrorKind::InvalidArguments));
+        }
+    }
+    Ok(())
+}
+
+/// 开通道，押金deposit
+pub fn open_channel(
+    account: &wallet::Account,
+    chain_name: &String,
+    channel_id: &String,
+    payee: &String,
+    deposit: &String,
+) -> Result<String> {
+    consts::str_as_bigint(deposit)?;
+    let mut args = std::collections::HashMap::new();
+    args.insert(
+        String::from("channel_id"),
+        channel_id.to_owned().into_bytes(),
+    );
+    args.insert(String::from("payee"), payee.to_owned().into_bytes());
+    args.insert(String::from("deposit"), deposit.to_owned().into_bytes());
+    contract::invoke_contract(
+        account,
+        chain_name,
+        &account.contract_name,
+        &String::from("open"),
+        args,
+        &String::new(),
+    )
+    .map(|(txid, _)| txid)
+}
+
+/// 用payer签名的最后一次余额更新结算
+pub fn settle_channel(
+    account: &wallet::Account,
+    chain_name: &String,
+    signed: &SignedBalanceUpdate,
+) -> Result<String> {
+    verify_update(signed)?;
+    let u = &signed.update;
+    let mut args = std::collections::HashMap::new();
+    args.insert(
+        String::from("channel_id"),
+        u.channel_id.to_owned().into_bytes(),
+    );
+    args.insert(String::from("payer"), u.payer.to_owned().into_bytes());
+    args.insert(String::from("payee"), u.payee.to_owned().into_bytes());
+    args.insert(String::from("seq"), u.seq.to_string().into_bytes());
+    args.insert(String::from("paid"), u.paid.to_owned().into_bytes());
+    args.insert(
+        String::from("public_key"),
+        signed.public_key.to_owned().into_bytes(),
+    );
+    args.insert(String::from("sign"), signed.sign.clone());
+    contract::invoke_contract(
+        account,
+        chain_name,
+        &account.contract_name,
+        &String::from("settle"),
+        args,
+        &String::new(),
+    )
+    .map(|(txid, _)| txid)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_sign_and_accept_update() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = wallet::Account::new(d.to_str().unwrap(), "channel", Default::default());
+
+        let mut update = BalanceUpdate {
+            channel_id: String::from("c1"),
+            payer: acc.address.to_owned(),
+            payee: String::from("dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN"),
+            seq: 1,
+            paid: String::from("10"),
+        };
+        let first = sign_update(&acc, &update).unwrap();
+        assert_eq!(accept_update(None, &first).is_ok(), true);
+
+        update.seq = 2;
+        update.paid = String::from("15");
+        let second = sign_update(&acc, &update).unwrap();
+        assert_eq!(accept_update(Some(&first), &second).is_ok(), true);
+        // 旧的更新不能覆盖新的
+        assert_eq!(accept_update(Some(&second), &first).is_ok(), false);
+
+        let mut tampered = second.clone();
+        tampered.update.paid = String::from("1");
+        assert_eq!(verify_update(&tampered).is_ok(), false);
+
+        update.seq = 3;
+        update.paid = String::from("-5");
+        assert_eq!(
+            sign_update(&acc, &update).unwrap_err().kind(),
+            ErrorKind::InvalidArguments
+        );
+        // 对手方绕过sign_update签的负数金额也不接受
+        let negative = SignedBalanceUpdate {
+            update: update.clone(),
+            public_key: acc.public_key().unwrap(),
+            sign: acc.sign_message(&update.to_bytes()).unwrap(),
+        };
+        assert_eq!(verify_update(&negative).is_ok(), true);
+        assert_eq!(accept_update(Some(&second), &negative).is_ok(), false);
+
+        // 没有前缀的签名不是余额更新的签名
+        let mut raw = second.clone();
+        raw.sign = acc.sign(&second.update.to_bytes()).unwrap();
+        assert_eq!(verify_update(&raw).is_ok(), false);
+    }
+}
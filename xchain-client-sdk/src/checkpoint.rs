@@ -0,0 +1,346 @@
+use protobuf::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::wallet;
+use xchain_crypto::hash::hash::double_sha256;
+use xchain_node_sdk::{encoder, errors::*, ocall, protos::xchain};
+
+// 轻节点校验的检查点: 已经校验过的enclave导出签名的(高度, 区块id, 出块节点集合)，
+// 新的enclave信任签名者之后直接从检查点开始校验，不需要从创世块开始。
+// 每个区块重新计算交易的txid、merkle root和blockid(和xuperchain的MakeBlockID一致)，
+// 再校验区块之间的链接(pre_hash)、出块节点属于集合以及出块节点对blockid的签名，
+// 区块的内容和出块节点签名的blockid绑定。
+// 出块节点集合不由调用方传入: 导出时取检查点之前window个区块的出块节点(这些区块同样逐块校验)，
+// 之后固定在签名的检查点里，LightVerifier不允许修改。
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub bcname: String,
+    pub height: i64,
+    /// hex编码
+    pub blockid: String,
+    /// 出块节点的地址
+    pub validators: Vec<String>,
+}
+
+impl Checkpoint {
+    /// 被签名的内容
+    pub fn to_bytes(&self) -> Vec<u8> {
+        format!(
+            "{}\n{}\n{}\n{}",
+            self.bcname,
+            self.height,
+            self.blockid,
+            self.validators.join(",")
+        )
+        .into_bytes()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SignedCheckpoint {
+    pub checkpoint: Checkpoint,
+    pub public_key: String,
+    pub sign: Vec<u8>,
+}
+
+/// 对检查点签名
+pub fn sign_checkpoint(
+    account: &wallet::Account,
+    checkpoint: &Checkpoint,
+) -> Result<SignedCheckpoint> {
+    Ok(SignedCheckpoint {
+        checkpoint: checkpoint.clone(),
+        public_key: account.public_key()?,
+        sign: account.sign(&checkpoint.to_bytes())?,
+    })
+}
+
+/// 校验签名，并且签名者在trusted_signers里面
+pub fn verify_checkpoint(signed: &SignedCheckpoint, trusted_signers: &[String]) -> Result<()> {
+    let address = wallet::verify_with_public_key(
+        &signed.public_key,
+        &signed.checkpoint.to_bytes(),
+        &signed.sign,
+    )?;
+    if !trusted_signers.contains(&address) {
+        return Err(Error::from(ErrorKind::CryptoError));
+    }
+    Ok(())
+}
+
+/// 交易txid的merkle tree的根，和xuperchain的MakeMerkleTree一致，奇数个节点时最后一个和自己配对
+pub fn merkle_root(txids: &[Vec<u8>]) -> Vec<u8> {
+    let mut level = txids.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| {
+                let mut buf = pair[0].clone();
+                buf.extend_from_slice(pair.last().unwrap());
+                double_sha256(&buf)
+            })
+            .collect();
+    }
+    level.pop().unwrap_or_default()
+}
+
+/// 由区块头计算blockid，和xuperchain的MakeBlockID一致
+pub fn make_block_id(block: &xchain::InternalBlock) -> Result<Vec<u8>> {
+    let mut buf = vec![];
+    buf.extend_from_slice(&block.version.to_le_bytes());
+    buf.extend_from_slice(&block.nonce.to_le_bytes());
+    buf.extend_from_slice(&block.tx_count.to_le_bytes());
+    buf.extend_from_slice(&block.proposer);
+    buf.extend_from_slice(&block.timestamp.to_le_bytes());
+    buf.extend_from_slice(&block.pubkey);
+    buf.extend_from_slice(&block.pre_hash);
+    buf.extend_from_slice(&block.merkle_root);
+    buf.extend_from_slice(&block.height.to_le_bytes());
+    if block.targetBits > 0 {
+        buf.extend_from_slice(&block.targetBits.to_le_bytes());
+    }
+    if block.has_Justify() {
+        let qc = block
+            .get_Justify()
+            .write_to_bytes()
+            .map_err(|e| Error::new(ErrorKind::ParseError, e))?;
+        buf.extend_from_slice(&qc);
+    }
+    Ok(double_sha256(&buf))
+}
+
+/// 校验区块的内容和出块节点的签名，返回出块节点的地址，不校验和前一个区块的链接
+pub fn verify_block(block: &xchain::InternalBlock) -> Result<String> {
+    let mut txids = vec![];
+    for tx in block.get_transactions() {
+        if encoder::make_transaction_id(tx)? != tx.txid {
+            return Err(Error::new(ErrorKind::CryptoError, "txid mismatch"));
+        }
+        txids.push(tx.txid.clone());
+    }
+    if block.tx_count as usize != txids.len() || merkle_root(&txids) != block.merkle_root {
+        return Err(Error::new(ErrorKind::CryptoError, "merkle root mismatch"));
+    }
+    if make_block_id(block)? != block.blockid {
+        return Err(Error::new(ErrorKind::CryptoError, "blockid mismatch"));
+    }
+    let proposer = String::from_utf8(block.proposer.clone())
+        .map_err(|_| Error::from(ErrorKind::ParseError))?;
+    let public_key =
+        std::str::from_utf8(&block.pubkey).map_err(|_| Error::from(ErrorKind::ParseError))?;
+    let address = wallet::verify_with_public_key(public_key, &block.blockid, &block.sign)?;
+    if address != proposer {
+        return Err(Error::from(ErrorKind::CryptoError));
+    }
+    Ok(proposer)
+}
+
+fn trunk_block(height: i64) -> Result<xchain::InternalBlock> {
+    let mut block = ocall::ocall_xchain_get_block_by_height(height)?;
+    if block.status != xchain::Block_EBlockStatus::TRUNK || block.block.is_none() {
+        return Err(Error::from(ErrorKind::ChainRPCError));
+    }
+    Ok(block.take_block())
+}
+
+/// 按顺序校验一段相连的区块，返回出块节点的集合(按首次出块的顺序)
+pub fn derive_validators(blocks: &[xchain::InternalBlock]) -> Result<Vec<String>> {
+    let mut validators: Vec<String> = vec![];
+    for (i, block) in blocks.iter().enumerate() {
+        if i > 0
+            && (block.height != blocks[i - 1].height + 1 || block.pre_hash != blocks[i - 1].blockid)
+        {
+            return Err(Error::from(ErrorKind::CryptoError));
+        }
+        let proposer = verify_block(block)?;
+        if !validators.contains(&proposer) {
+            validators.push(proposer);
+        }
+    }
+    Ok(validators)
+}
+
+/// 导出主干上高度为height的区块作为检查点
+/// 出块节点集合取高度(height - window, height]的区块的出块节点，window至少覆盖一轮出块
+pub fn export_checkpoint(
+    account: &wallet::Account,
+    bcname: &String,
+    height: i64,
+    window: i64,
+) -> Result<SignedCheckpoint> {
+    if window <= 0 || height - window + 1 < 0 {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let blocks = (height - window + 1..=height)
+        .map(trunk_block)
+        .collect::<Result<Vec<_>>>()?;
+    let validators = derive_validators(&blocks)?;
+    let last = blocks.last().unwrap();
+    let checkpoint = Checkpoint {
+        bcname: bcname.to_owned(),
+        height: last.height,
+        blockid: hex::encode(&last.blockid),
+        validators: validators,
+    };
+    sign_checkpoint(account, &checkpoint)
+}
+
+/// 从检查点开始逐块校验，出块节点集合固定为检查点里的集合
+#[derive(Debug, Clone)]
+pub struct LightVerifier {
+    bcname: String,
+    height: i64,
+    blockid: Vec<u8>,
+    validators: Vec<String>,
+}
+
+impl LightVerifier {
+    pub fn from_checkpoint(signed: &SignedCheckpoint, trusted_signers: &[String]) -> Result<Self> {
+        verify_checkpoint(signed, trusted_signers)?;
+        let c = &signed.checkpoint;
+        Ok(LightVerifier {
+            bcname: c.bcname.to_owned(),
+            height: c.height,
+            blockid: hex::decode(&c.blockid)?,
+            validators: c.validators.clone(),
+        })
+    }
+
+    pub fn bcname(&self) -> &str {
+        &self.bcname
+    }
+
+    pub fn height(&self) -> i64 {
+        self.height
+    }
+
+    pub fn blockid(&self) -> &[u8] {
+        &self.blockid
+    }
+
+    pub fn validators(&self) -> &[String] {
+        &self.validators
+    }
+
+    /// 校验紧接着的下一个区块，通过之后前进到这个区块
+    pub fn verify_next(&mut self, block: &xchain::InternalBlock) -> Result<()> {
+        if block.height != self.height + 1 || block.pre_hash != self.blockid {
+            return Err(Error::from(ErrorKind::CryptoError));
+        }
+        let proposer = verify_block(block)?;
+        if !self.validators.contains(&proposer) {
+            return Err(Error::from(ErrorKind::CryptoError));
+        }
+        self.height = block.height;
+        self.blockid = block.blockid.clone();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn tx(nonce: &str) -> xchain::Transaction {
+        let mut tx = xchain::Transaction::new();
+        tx.set_version(1);
+        tx.set_nonce(nonce.to_owned());
+        tx.set_initiator(String::from("alice"));
+        let txid = encoder::make_transaction_id(&tx).unwrap();
+        tx.set_txid(txid);
+        tx
+    }
+
+    fn signed_block(
+        acc: &wallet::Account,
+        height: i64,
+        pre_hash: &[u8],
+        txs: Vec<xchain::Transaction>,
+    ) -> xchain::InternalBlock {
+        let mut block = xchain::InternalBlock::new();
+        block.set_version(1);
+        block.set_height(height);
+        block.set_pre_hash(pre_hash.to_vec());
+        block.set_timestamp(1600000000000000000 + height);
+        block.set_proposer(acc.address.to_owned().into_bytes());
+        block.set_pubkey(acc.public_key().unwrap().into_bytes());
+        let txids: Vec<Vec<u8>> = txs.iter().map(|t| t.txid.clone()).collect();
+        block.set_tx_count(txs.len() as i32);
+        block.set_merkle_root(merkle_root(&txids));
+        block.set_transactions(protobuf::RepeatedField::from_vec(txs));
+        let blockid = make_block_id(&block).unwrap();
+        block.set_sign(acc.sign(&blockid).unwrap());
+        block.set_blockid(blockid);
+        block
+    }
+
+    #[test]
+    fn test_merkle_root() {
+        let (a, b, c) = (vec![1u8], vec![2u8], vec![3u8]);
+        assert_eq!(merkle_root(&[]), Vec::<u8>::new());
+        assert_eq!(merkle_root(&[a.clone()]), a);
+        let ab = double_sha256(&[1, 2]);
+        let cc = double_sha256(&[3, 3]);
+        assert_eq!(merkle_root(&[a, b, c]), double_sha256(&[ab, cc].concat()));
+    }
+
+    #[test]
+    fn test_light_verifier_from_checkpoint() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = wallet::Account::new(d.to_str().unwrap(), "", "");
+
+        let genesis = signed_block(&acc, 10, &[], vec![tx("a")]);
+        let block = signed_block(&acc, 11, &genesis.blockid, vec![tx("b"), tx("c"), tx("d")]);
+        // 出块节点集合由区块得出
+        let validators = derive_validators(&[genesis.clone(), block.clone()]).unwrap();
+        assert_eq!(validators, vec![acc.address.to_owned()]);
+        assert_eq!(
+            derive_validators(&[block.clone(), genesis.clone()]).is_ok(),
+            false
+        );
+
+        let checkpoint = Checkpoint {
+            bcname: String::from("xuper"),
+            height: genesis.height,
+            blockid: hex::encode(&genesis.blockid),
+            validators: validators,
+        };
+        let signed = sign_checkpoint(&acc, &checkpoint).unwrap();
+        assert_eq!(
+            LightVerifier::from_checkpoint(&signed, &[String::from("other")]).is_ok(),
+            false
+        );
+        let mut verifier =
+            LightVerifier::from_checkpoint(&signed, &[acc.address.to_owned()]).unwrap();
+
+        // 链接不上
+        let fork = signed_block(&acc, 11, &[0xcc], vec![]);
+        assert_eq!(verifier.verify_next(&fork).is_ok(), false);
+
+        // 交易内容和签名的blockid不一致
+        let mut forged = block.clone();
+        forged.mut_transactions()[1].set_desc(b"forged".to_vec());
+        assert_eq!(verifier.verify_next(&forged).is_ok(), false);
+        let mut forged = block.clone();
+        forged.mut_transactions()[1] = tx("forged");
+        assert_eq!(verifier.verify_next(&forged).is_ok(), false);
+        let mut forged = block.clone();
+        forged.set_timestamp(0);
+        assert_eq!(verifier.verify_next(&forged).is_ok(), false);
+
+        assert_eq!(verifier.verify_next(&block).is_ok(), true);
+        assert_eq!(verifier.height(), 11);
+        assert_eq!(verifier.blockid(), &block.blockid[..]);
+
+        let mut tampered = signed.clone();
+        tampered.checkpoint.height = 100;
+        assert_eq!(
+            verify_checkpoint(&tampered, &[acc.address.to_owned()]).is_ok(),
+            false
+        );
+    }
+}
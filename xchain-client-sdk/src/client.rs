@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+
+use crate::{bulk, chains, config, transfer, wallet};
+use xchain_node_sdk::errors::*;
+
+// 并行提交多个账户的转账: 按发起账户分组，同一个账户的转账在同一个worker里按顺序执行，
+// 避免预执行选中同一批utxo；不同账户的转账在最多workers个线程里并行，各自走完整的Session流程。
+// shutdown之后不再接受新的转账，还没有开始的转账返回ShuttingDown，由调用方保存之后重新提交；
+// 已经开始的转账执行完(提交或者失败)，不会留下提交了一半的交易。订阅(Subscriber)设置了
+// shutdown_signal时在两个事件之间停止，游标已经持久化，重启之后继续。
+// 每笔转账可以指定链名，用chains检查节点是否服务这条链，都没有指定时使用节点的默认链(见chains)。
+
+pub const DEFAULT_WORKERS: usize = 4;
+
+/// 一笔转账，参数同transfer::transfer
+#[derive(Debug, Clone)]
+pub struct TransferRequest {
+    /// 为空时使用Client的链
+    pub chain_name: String,
+    pub account: wallet::Account,
+    pub to: String,
+    pub amount: String,
+    pub fee: String,
+    pub desc: String,
+}
+
+/// Client::shutdown之后置位，Subscriber等长时间运行的任务检查它停止
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownSignal(Arc<AtomicBool>);
+
+impl ShutdownSignal {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// 正在执行的转账数，归零时通知shutdown
+#[derive(Default)]
+struct InFlight {
+    count: Mutex<usize>,
+    drained: Condvar,
+}
+
+/// 转账执行期间计数
+struct InFlightGuard(Arc<InFlight>);
+
+impl InFlightGuard {
+    /// 在同一个锁里检查shutdown并计数，shutdown也在这个锁里置位，
+    /// 所以shutdown返回之后不会再有新的转账开始
+    fn enter(in_flight: &Arc<InFlight>, shutdown: &ShutdownSignal) -> Result<Self> {
+        let mut count = in_flight.count.lock().unwrap();
+        if shutdown.is_set() {
+            return Err(Error::from(ErrorKind::ShuttingDown));
+        }
+        *count += 1;
+        Ok(InFlightGuard(in_flight.clone()))
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut count = self.0.count.lock().unwrap();
+        *count -= 1;
+        if *count == 0 {
+            self.0.drained.notify_all();
+        }
+    }
+}
+
+pub struct Client {
+    chain_name: String,
+    config: config::ClientConfig,
+    workers: usize,
+    shutdown: ShutdownSignal,
+    in_flight: Arc<InFlight>,
+    chains: Arc<chains::Chains>,
+}
+
+impl Client {
+    /// 使用全局CONFIG当前的配置，chain_name为空时使用节点的默认链
+    pub fn new(chain_name: &String) -> Self {
+        Client::with_config(chain_name, config::ClientConfig::global())
+    }
+
+    pub fn with_config(chain_name: &String, config: config::ClientConfig) -> Self {
+        Client {
+            chain_name: chain_name.to_owned(),
+            config: config,
+            workers: DEFAULT_WORKERS,
+            shutdown: ShutdownSignal::new(),
+            in_flight: Default::default(),
+            chains: Arc::new(chains::Chains::new()),
+        }
+    }
+
+    /// 默认在第一次转账时查询节点服务的链
+    pub fn set_chains(&mut self, chains: chains::Chains) {
+        self.chains = Arc::new(chains);
+    }
+
+    pub fn chains(&self) -> &chains::Chains {
+        &self.chains
+    }
+
+    /// 转账的链: 请求指定的链，其次是Client的链，都为空时是节点的默认链
+    fn route(&self, chain_name: &str) -> Result<String> {
+        if chain_name.is_empty() {
+            return self.chains.resolve(&self.chain_name);
+        }
+        self.chains.resolve(chain_name)
+    }
+
+    /// 最多同时执行几个账户的转账
+    pub fn set_workers(&mut self, workers: usize) {
+        self.workers = std::cmp::max(workers, 1);
+    }
+
+    /// shutdown之后返回ShuttingDown
+    pub fn transfer(&self, req: &TransferRequest) -> Result<String> {
+        let _guard = InFlightGuard::enter(&self.in_flight, &self.shutdown)?;
+        let chain_name = self.route(&req.chain_name)?;
+        transfer::transfer_with_config(
+            &self.config,
+            &req.account,
+            &chain_name,
+            &req.to,
+            &req.amount,
+            &req.fee,
+            &req.desc,
+        )
+    }
+
+    /// 并行提交，返回和requests顺序一致的txid或者错误，某一笔失败不影响其他转账
+    /// 执行期间shutdown时，还没有开始的转账返回ShuttingDown
+    pub fn submit_parallel(&self, requests: Vec<TransferRequest>) -> Vec<Result<String>> {
+        let groups = partition(&requests);
+        let client = Arc::new(Client {
+            chain_name: self.chain_name.to_owned(),
+            config: self.config.clone(),
+            workers: self.workers,
+            shutdown: self.shutdown.clone(),
+            in_flight: self.in_flight.clone(),
+            chains: self.chains.clone(),
+        });
+        let requests = Arc::new(requests);
+        bulk::run_parallel(groups, requests.len(), self.workers, move |i| {
+            client.transfer(&requests[i])
+        })
+    }
+
+    /// 传给Subscriber::set_shutdown_signal，shutdown时订阅一起停止
+    pub fn shutdown_signal(&self) -> ShutdownSignal {
+        self.shutdown.clone()
+    }
+
+    /// 不再接受新的转账，等待正在执行的转账完成
+    /// deadline之前没有全部完成时返回DeadlineExceeded，剩下的转账仍然会执行完
+    pub fn shutdown(&self, deadline: Instant) -> Result<()> {
+        let mut count = self.in_flight.count.lock().unwrap();
+        self.shutdown.set();
+        while *count > 0 {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Error::from(ErrorKind::DeadlineExceeded));
+            }
+            count = self
+                .in_flight
+                .drained
+                .wait_timeout(count, deadline - now)
+                .unwrap()
+                .0;
+        }
+        Ok(())
+    }
+}
+
+/// 按发起账户分组，组内和组之间都保持第一次出现的顺序
+fn partition(requests: &[TransferRequest]) -> Vec<Vec<usize>> {
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    let mut groups: Vec<Vec<usize>> = vec![];
+    for (i, req) in requests.iter().enumerate() {
+        let g = *index.entry(&req.account.address).or_insert_with(|| {
+            groups.push(vec![]);
+            groups.len() - 1
+        });
+        groups[g].push(i);
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn request(address: &str) -> TransferRequest {
+        TransferRequest {
+            chain_name: String::new(),
+            account: wallet::Account {
+                address: String::from(address),
+                ..Default::default()
+            },
+            to: String::from("bob"),
+            amount: String::from("1"),
+            fee: String::from("0"),
+            desc: String::new(),
+        }
+    }
+
+    fn chains(names: &'static [&'static str]) -> chains::Chains {
+        chains::Chains::with_loader(Box::new(move || {
+            Ok(names
+                .iter()
+                .map(|n| chains::ChainInfo {
+                    bcname: n.to_string(),
+                    height: 0,
+                    root_blockid: String::new(),
+                    tip_blockid: String::new(),
+                    utxo_total: String::from("0"),
+                    max_block_size: 0,
+                })
+                .collect())
+        }))
+    }
+
+    #[test]
+    fn test_route() {
+        let mut client = Client::new(&String::new());
+        client.set_chains(chains(&["hello"]));
+        // 只有一条链时不需要指定
+        assert_eq!(client.route("").unwrap(), "hello");
+        assert_eq!(client.route("xuper").is_ok(), false);
+
+        let mut client = Client::new(&String::from("xuper"));
+        client.set_chains(chains(&["hello", "xuper"]));
+        assert_eq!(client.route("").unwrap(), "xuper");
+        assert_eq!(client.route("hello").unwrap(), "hello");
+        assert_eq!(client.route("other").is_ok(), false);
+
+        let mut client = Client::new(&String::new());
+        client.set_chains(chains(&["hello", "xuper"]));
+        assert_eq!(client.route("").is_ok(), false);
+    }
+
+    #[test]
+    fn test_partition() {
+        let requests = vec![
+            request("alice"),
+            request("carol"),
+            request("alice"),
+            request("dave"),
+        ];
+        assert_eq!(partition(&requests), vec![vec![0, 2], vec![1], vec![3]]);
+        assert_eq!(partition(&[]).is_empty(), true);
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let client = Client::new(&String::from("xuper"));
+        let guard = InFlightGuard::enter(&client.in_flight, &client.shutdown).unwrap();
+        // 正在执行的转账没有完成
+        let deadline = Instant::now() + std::time::Duration::from_millis(20);
+        assert_eq!(
+            client.shutdown(deadline).unwrap_err().kind(),
+            ErrorKind::DeadlineExceeded
+        );
+        assert_eq!(client.shutdown_signal().is_set(), true);
+        // 不再接受新的转账
+        assert_eq!(
+            InFlightGuard::enter(&client.in_flight, &client.shutdown)
+                .err()
+                .map(|e| e.kind()),
+            Some(ErrorKind::ShuttingDown)
+        );
+        assert_eq!(*client.in_flight.count.lock().unwrap(), 1);
+        assert_eq!(
+            client.transfer(&request("alice")).unwrap_err().kind(),
+            ErrorKind::ShuttingDown
+        );
+        let results = client.submit_parallel(vec![request("alice"), request("carol")]);
+        assert_eq!(
+            results
+                .iter()
+                .all(|r| r.as_ref().unwrap_err().kind() == ErrorKind::ShuttingDown),
+            true
+        );
+
+        let finish = thread::spawn(move || {
+            thread::sleep(std::time::Duration::from_millis(10));
+            drop(guard);
+        });
+        let deadline = Instant::now() + std::time::Duration::from_secs(5);
+        assert_eq!(client.shutdown(deadline).is_ok(), true);
+        finish.join().unwrap();
+    }
+}
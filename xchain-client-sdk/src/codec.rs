@@ -0,0 +1,92 @@
+use protobuf::Message;
+
+use xchain_node_sdk::{canonical, encoder, errors::*, protos::xchain};
+
+// 交易的文本编码，用于提交之前审计、保存或者交给其他工具:
+// json是规范json(见canonical)，字段顺序、省略规则和golang一致，同一个交易的编码总是相同，可以直接比较；
+// hex是protobuf编码的hex，和Go SDK、xchain-cli的交易文件互通，合约参数(map)的编码顺序不固定，
+// 解码之后是同一个交易，比较交易用json或者txid。
+// 解码不校验txid，需要时调用verify_txid。
+
+/// 交易的规范json
+pub fn to_json(tx: &xchain::Transaction) -> Result<String> {
+    String::from_utf8(canonical::to_canonical_json(tx)?)
+        .map_err(|e| Error::new(ErrorKind::ParseError, e))
+}
+
+/// 解析to_json的结果，也可以解析serde_json的输出
+pub fn from_json(json: &str) -> Result<xchain::Transaction> {
+    canonical::from_canonical_json(json.as_bytes())
+}
+
+/// 交易protobuf编码的hex
+pub fn to_hex(tx: &xchain::Transaction) -> Result<String> {
+    let data = tx
+        .write_to_bytes()
+        .map_err(|e| Error::new(ErrorKind::ParseError, e))?;
+    Ok(hex::encode(data))
+}
+
+pub fn from_hex(s: &str) -> Result<xchain::Transaction> {
+    let data = hex::decode(s.trim()).map_err(|e| Error::new(ErrorKind::ParseError, e))?;
+    protobuf::parse_from_bytes(&data).map_err(|e| Error::new(ErrorKind::ParseError, e))
+}
+
+/// 交易的txid和内容一致，解码之后、提交之前确认交易没有被修改
+pub fn verify_txid(tx: &xchain::Transaction) -> Result<()> {
+    if encoder::make_transaction_id(tx)? != tx.txid {
+        return Err(Error::new(ErrorKind::InvalidArguments, "txid mismatch"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx() -> xchain::Transaction {
+        let mut tx = xchain::Transaction::new();
+        tx.set_desc(b"codec".to_vec());
+        tx.set_version(1);
+        tx.set_initiator(String::from("dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN"));
+        tx.set_timestamp(1600000000000000000);
+        tx.set_nonce(String::from("nonce"));
+        let mut output = xchain::TxOutput::new();
+        output.set_amount(vec![1, 0]);
+        output.set_to_addr(b"WwLgfAatHyKx2mCJruRaML4oVf7Chzp42".to_vec());
+        tx.mut_tx_outputs().push(output);
+        let mut r = xchain::InvokeRequest::new();
+        r.set_contract_name(String::from("counter"));
+        r.set_method_name(String::from("increase"));
+        r.mut_args().insert(String::from("key"), b"a".to_vec());
+        r.mut_args().insert(String::from("value"), b"1".to_vec());
+        tx.mut_contract_requests().push(r);
+        let txid = encoder::make_transaction_id(&tx).unwrap();
+        tx.set_txid(txid);
+        tx
+    }
+
+    #[test]
+    fn test_codec() {
+        let tx = tx();
+        let json = to_json(&tx).unwrap();
+        assert_eq!(from_json(&json).unwrap(), tx);
+        // 同一个交易的json总是相同
+        assert_eq!(to_json(&from_json(&json).unwrap()).unwrap(), json);
+        assert_eq!(json.contains(r#""desc":"Y29kZWM=""#), true);
+
+        let h = to_hex(&tx).unwrap();
+        let decoded = from_hex(&format!("{}\n", h)).unwrap();
+        assert_eq!(decoded, tx);
+        assert_eq!(verify_txid(&decoded).is_ok(), true);
+
+        let mut tampered = decoded;
+        tampered.mut_tx_outputs()[0].set_amount(vec![2, 0]);
+        assert_eq!(
+            verify_txid(&tampered).unwrap_err().kind(),
+            ErrorKind::InvalidArguments
+        );
+        assert_eq!(from_hex("zz").unwrap_err().kind(), ErrorKind::ParseError);
+        assert_eq!(from_json("{").is_err(), true);
+    }
+}
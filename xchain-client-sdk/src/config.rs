@@ -1,4 +1,26 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
+use xchain_node_sdk::errors::*;
+
+/// 交易的类别，不同类别的手续费可以付给不同的账户
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Clone, Copy)]
+pub enum TxClass {
+    #[serde(rename = "transfer")]
+    Transfer,
+    #[serde(rename = "contractInvoke")]
+    ContractInvoke,
+    #[serde(rename = "contractDeploy")]
+    ContractDeploy,
+    #[serde(rename = "governance")]
+    Governance,
+}
+
+impl Default for TxClass {
+    fn default() -> Self {
+        TxClass::Transfer
+    }
+}
 
 //TODO: handle skip
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
@@ -13,6 +35,189 @@ pub struct ComplianceCheckConfig {
     pub compliance_check_endorse_service_fee_addr: String,
     #[serde(rename = "complianceCheckEndorseServiceAddr")]
     pub compliance_check_endorse_service_addr: String,
+    /// 背书服务的公钥(json格式)，配置之后校验合规检查返回的签名，不匹配时返回EndorserSignInvalid
+    #[serde(rename = "complianceCheckEndorseServicePublicKey", default)]
+    pub compliance_check_endorse_service_public_key: Option<String>,
+    /// 按交易类别收取手续费的地址，没有配置的类别使用complianceCheckEndorseServiceFeeAddr
+    #[serde(rename = "complianceCheckEndorseServiceFeeAddrByClass", default)]
+    pub compliance_check_endorse_service_fee_addr_by_class: HashMap<TxClass, String>,
+    /// 背书服务没有返回签名时，不做合规检查直接提交交易，而不是返回EndorserSignMissing
+    #[serde(rename = "skipOnMissingEndorserSign", default)]
+    pub skip_on_missing_endorser_sign: bool,
+    /// 没有背书服务的私有部署不做合规检查: 不生成合规检查交易、不请求背书签名，auth_require里没有背书服务，
+    /// 预执行和选utxo直接访问节点，complianceCheckEndorseServiceFee不再收取
+    #[serde(rename = "skipComplianceCheck", default)]
+    pub skip_compliance_check: bool,
+    /// 背书服务手续费为0时不生成合规检查交易，合规检查请求不带Fee，真正的交易直接花费预执行选出的utxo
+    #[serde(rename = "omitZeroFeeTx", default)]
+    pub omit_zero_fee_tx: bool,
+    /// 额外的合规背书服务，按顺序在complianceCheckEndorseServiceAddr之后背书
+    #[serde(rename = "extraEndorsers", default)]
+    pub extra_endorsers: Vec<EndorserConfig>,
+    /// complianceCheckEndorseServiceAddr的备用接入点，node:endorsePort失败或者超时时按顺序切换
+    #[serde(rename = "failoverEndpoints", default)]
+    pub failover_endpoints: Vec<EndpointConfig>,
+    /// 配置之后，发给背书服务的请求必须经过远程认证的通道(attestation::attest)
+    #[serde(rename = "attestation", default)]
+    pub attestation: Option<AttestationConfig>,
+}
+
+impl ComplianceCheckConfig {
+    /// 交易类别对应的手续费地址
+    pub fn fee_addr(&self, class: TxClass) -> &String {
+        self.compliance_check_endorse_service_fee_addr_by_class
+            .get(&class)
+            .unwrap_or(&self.compliance_check_endorse_service_fee_addr)
+    }
+
+    /// 需要支付的背书服务费，skipComplianceCheck时为0
+    pub fn endorse_service_fee(&self) -> i32 {
+        if self.skip_compliance_check {
+            return 0;
+        }
+        self.compliance_check_endorse_service_fee
+    }
+
+    /// 是否需要合规检查交易，见omitZeroFeeTx
+    pub fn needs_fee_tx(&self) -> bool {
+        !self.omit_zero_fee_tx || self.compliance_check_endorse_service_fee != 0
+    }
+
+    /// 背书服务addr配置的公钥
+    pub fn endorser_public_key(&self, addr: &str) -> Option<&String> {
+        if addr == self.compliance_check_endorse_service_addr {
+            return self.compliance_check_endorse_service_public_key.as_ref();
+        }
+        self.extra_endorsers
+            .iter()
+            .find(|e| e.addr == addr)
+            .and_then(|e| e.public_key.as_ref())
+    }
+
+    /// 所有合规背书服务的地址，按auth_require里的顺序
+    pub fn endorser_addrs(&self) -> Vec<String> {
+        if self.skip_compliance_check {
+            return vec![];
+        }
+        let mut addrs = vec![self.compliance_check_endorse_service_addr.to_owned()];
+        addrs.extend(self.extra_endorsers.iter().map(|e| e.addr.to_owned()));
+        addrs
+    }
+}
+
+/// 额外的合规背书服务，见install_endorsers
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct EndorserConfig {
+    /// 背书服务的地址
+    #[serde(rename = "addr")]
+    pub addr: String,
+    #[serde(rename = "host")]
+    pub host: String,
+    #[serde(rename = "port")]
+    pub port: u16,
+    /// 背书服务的公钥(json格式)，见complianceCheckEndorseServicePublicKey
+    #[serde(rename = "publicKey", default)]
+    pub public_key: Option<String>,
+}
+
+/// 同一个背书服务的一个接入点
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct EndpointConfig {
+    #[serde(rename = "host")]
+    pub host: String,
+    #[serde(rename = "port")]
+    pub port: u16,
+}
+
+impl EndpointConfig {
+    /// "host:port"，也是ocall::set_endorser里的名字
+    pub fn key(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+/// 背书服务enclave的度量值白名单，hex编码，两个都配置时都要满足
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default)]
+pub struct AttestationConfig {
+    #[serde(rename = "mrEnclaves", default)]
+    pub mr_enclaves: Vec<String>,
+    #[serde(rename = "mrSigners", default)]
+    pub mr_signers: Vec<String>,
+}
+
+/// 托管的背书服务和节点要求的API key
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct ApiKeyConfig {
+    #[serde(rename = "header")]
+    pub header: String,
+    #[serde(rename = "value")]
+    pub value: String,
+}
+
+/// utxo太多时自动合并，见consolidate模块
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct ConsolidationConfig {
+    /// 未冻结的utxo超过多少个时合并
+    #[serde(rename = "utxoThreshold")]
+    pub utxo_threshold: usize,
+    /// 一次最多合并多少个utxo
+    #[serde(rename = "maxInputs")]
+    pub max_inputs: usize,
+    /// 只在[offPeakStartHour, offPeakEndHour)内合并，UTC小时，开始大于结束时跨过0点
+    #[serde(rename = "offPeakStartHour")]
+    pub off_peak_start_hour: u32,
+    #[serde(rename = "offPeakEndHour")]
+    pub off_peak_end_hour: u32,
+}
+
+/// 签名策略允许调用的合约方法，见policy模块
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct AllowedContract {
+    #[serde(rename = "contract")]
+    pub contract: String,
+    /// "*"表示所有方法
+    #[serde(rename = "methods")]
+    pub methods: Vec<String>,
+}
+
+/// 调用背书服务时传输层失败的重试，见session::RetryPolicy
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct RetryConfig {
+    /// 包括第一次在内最多请求几次
+    #[serde(rename = "maxAttempts")]
+    pub max_attempts: u32,
+    /// 第n次重试之前等待backoffMs * 2^(n-1)毫秒，最多maxBackoffMs毫秒
+    #[serde(rename = "backoffMs")]
+    pub backoff_ms: u64,
+    #[serde(rename = "maxBackoffMs")]
+    pub max_backoff_ms: u64,
+    /// [0, 1]，等待时间随机减少的最大比例
+    #[serde(rename = "jitter", default)]
+    pub jitter: f64,
+}
+
+/// 交易因为手续费不够被拒绝时提高手续费重新提交，见fee_bump模块
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct FeeBumpConfig {
+    /// 每次重新提交的手续费是原手续费的百分之多少，按顺序使用
+    #[serde(rename = "schedulePercent")]
+    pub schedule_percent: Vec<u32>,
+    /// 手续费上限，到达上限之后不再提高
+    #[serde(rename = "maxFee")]
+    pub max_fee: String,
+}
+
+/// 链上的名字服务合约，见names模块
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct NameServiceConfig {
+    #[serde(rename = "contract")]
+    pub contract: String,
+    /// 解析名字的方法，参数是name，返回地址
+    #[serde(rename = "method")]
+    pub method: String,
+    /// 解析结果缓存多少秒
+    #[serde(rename = "ttlSecs")]
+    pub ttl_secs: u64,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
@@ -29,6 +234,60 @@ pub struct CommConfig {
     pub min_new_chain_amount: String,
     #[serde(rename = "crypto")]
     pub crypto: String,
+    /// 签名前校验预执行返回的读集
+    #[serde(rename = "verifyReadSet", default)]
+    pub verify_read_set: bool,
+    /// 严格解析背书服务的返回，出现不认识的字段或者字段名风格不同时拒绝
+    #[serde(rename = "strictResponse", default)]
+    pub strict_response: bool,
+    /// 交易是否带上Message的desc，默认和之前一样desc为空，只有desc的交易(transfer::post_desc)总是带上
+    #[serde(rename = "descOnChain", default)]
+    pub desc_on_chain: bool,
+    /// desc超过多少字节时压缩，0表示不压缩，没有开启zstd-desc feature时不压缩
+    #[serde(rename = "descCompressThreshold", default)]
+    pub desc_compress_threshold: usize,
+    #[serde(rename = "apiKey", default)]
+    pub api_key: Option<ApiKeyConfig>,
+    /// 没有配置时不自动合并
+    #[serde(rename = "consolidation", default)]
+    pub consolidation: Option<ConsolidationConfig>,
+    /// 访问节点和背书服务的方式，见transport模块
+    #[serde(rename = "transport", default)]
+    pub transport: Transport,
+    /// 没有配置时不重试
+    #[serde(rename = "endorserRetry", default)]
+    pub endorser_retry: Option<RetryConfig>,
+    /// 没有配置时不限制调用的合约
+    #[serde(rename = "contractAllowlist", default)]
+    pub contract_allowlist: Option<Vec<AllowedContract>>,
+    /// 配置之后交易绑定到这个chain id，见chain_id模块
+    #[serde(rename = "chainId", default)]
+    pub chain_id: Option<String>,
+    /// 没有配置时手续费不够直接返回错误
+    #[serde(rename = "feeBump", default)]
+    pub fee_bump: Option<FeeBumpConfig>,
+    /// 没有配置时收款方只能是地址
+    #[serde(rename = "nameService", default)]
+    pub name_service: Option<NameServiceConfig>,
+    /// 访问node:nodePort熔断时切换到的备用节点，transport为grpc时由transport::connect连接
+    #[serde(rename = "fallbackNode", default)]
+    pub fallback_node: Option<EndpointConfig>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub enum Transport {
+    /// 在SGX里运行，请求由enclave外部初始化的ocall转发
+    #[serde(rename = "ocall")]
+    Ocall,
+    /// 不在SGX里运行，直接用gRPC连接node:nodePort和node:endorsePort
+    #[serde(rename = "grpc")]
+    Grpc,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Transport::Ocall
+    }
 }
 
 lazy_static! {
@@ -38,3 +297,432 @@ lazy_static! {
         std::sync::RwLock::new(yaml)
     };
 }
+
+/// 每次请求时读取CONFIG里的apiKey，修改CONFIG即轮换
+pub fn api_key_provider() -> std::sync::Arc<xchain_node_sdk::auth::ApiKeyProvider> {
+    std::sync::Arc::new(|| {
+        Ok(CONFIG
+            .read()
+            .unwrap()
+            .api_key
+            .as_ref()
+            .map(|k| xchain_node_sdk::auth::ApiKey {
+                header: k.header.to_owned(),
+                value: k.value.to_owned(),
+            }))
+    })
+}
+
+/// 对配置里的节点和背书服务的请求带上apiKey
+pub fn install_api_key() {
+    let (node, ports) = {
+        let c = CONFIG.read().unwrap();
+        (c.node.to_owned(), vec![c.node_port, c.endorse_port])
+    };
+    for port in ports {
+        xchain_node_sdk::auth::set_api_key_provider(
+            &xchain_node_sdk::auth::endpoint(&node, port),
+            Some(api_key_provider()),
+        );
+    }
+}
+
+/// 用gRPC连接配置里的额外背书服务和备用接入点
+pub fn install_endorsers() {
+    let c = CONFIG.read().unwrap().compliance_check.clone();
+    for e in c.extra_endorsers.iter() {
+        xchain_node_sdk::ocall::init_endorser(&e.addr, &e.host, e.port);
+    }
+    install_failover_endpoints(&c);
+}
+
+fn install_failover_endpoints(c: &ComplianceCheckConfig) {
+    for e in c.failover_endpoints.iter() {
+        xchain_node_sdk::ocall::init_endorser(&e.key(), &e.host, e.port);
+    }
+}
+
+/// 一套独立的配置，传给Session和transfer::*_with_config，同一个进程里可以同时使用多套背书服务
+/// 不传时使用全局CONFIG的快照
+#[derive(Debug, PartialEq, Clone)]
+pub struct ClientConfig(std::sync::Arc<CommConfig>);
+
+impl ClientConfig {
+    pub fn new(c: CommConfig) -> Self {
+        ClientConfig(std::sync::Arc::new(c))
+    }
+
+    /// sdk.yaml格式的配置
+    pub fn from_yaml(yaml: &str) -> Result<Self> {
+        let c: CommConfig =
+            serde_yaml::from_str(yaml).map_err(|e| Error::new(ErrorKind::ParseError, e))?;
+        Ok(ClientConfig::new(c))
+    }
+
+    /// 全局CONFIG当前的值，之后修改CONFIG不影响返回的配置
+    pub fn global() -> Self {
+        ClientConfig::new(CONFIG.read().unwrap().clone())
+    }
+
+    /// 用gRPC连接这套配置的背书服务(node:endorsePort)和额外的背书服务，
+    /// 之后使用这套配置的Session按complianceCheckEndorseServiceAddr访问，而不是ocall::init的连接
+    pub fn install_endorsers(&self) {
+        let c = &self.0;
+        xchain_node_sdk::ocall::init_endorser(
+            &c.compliance_check.compliance_check_endorse_service_addr,
+            &c.node,
+            c.endorse_port,
+        );
+        for e in c.compliance_check.extra_endorsers.iter() {
+            xchain_node_sdk::ocall::init_endorser(&e.addr, &e.host, e.port);
+        }
+        install_failover_endpoints(&c.compliance_check);
+    }
+}
+
+impl std::ops::Deref for ClientConfig {
+    type Target = CommConfig;
+
+    fn deref(&self) -> &CommConfig {
+        &self.0
+    }
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig::global()
+    }
+}
+
+/// 签名的配置包，由不可信的宿主传入enclave，payload是sdk.yaml格式的配置
+/// version和payload一起签名，enclave拒绝低于已经加载过的版本，宿主不能回滚到旧的配置
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SignedConfig {
+    pub version: u64,
+    pub payload: String,
+    /// hex编码
+    pub sign: String,
+}
+
+fn config_sign_bytes(version: u64, payload: &str) -> Vec<u8> {
+    format!("{}\n{}", version, payload).into_bytes()
+}
+
+/// 用管理员的账户签名配置，生成配置包，每次发布新配置时version递增
+pub fn sign_config(account: &crate::wallet::Account, yaml: &str, version: u64) -> Result<String> {
+    let _: CommConfig =
+        serde_yaml::from_str(yaml).map_err(|e| Error::new(ErrorKind::ParseError, e))?;
+    let signed = SignedConfig {
+        version: version,
+        payload: yaml.to_owned(),
+        sign: hex::encode(account.sign(&config_sign_bytes(version, yaml))?),
+    };
+    Ok(serde_json::to_string(&signed)?)
+}
+
+/// 用固定在enclave里的公钥(json格式)校验配置包，返回其中的版本和配置
+pub fn verify_signed_config(bundle: &str, pinned_public_key: &str) -> Result<(u64, CommConfig)> {
+    let signed: SignedConfig = serde_json::from_str(bundle)?;
+    crate::wallet::verify_with_public_key(
+        pinned_public_key,
+        &config_sign_bytes(signed.version, &signed.payload),
+        &hex::decode(&signed.sign)?,
+    )?;
+    let c =
+        serde_yaml::from_str(&signed.payload).map_err(|e| Error::new(ErrorKind::ParseError, e))?;
+    Ok((signed.version, c))
+}
+
+/// 封存的最高配置版本，payload是被签名的json字符串
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SealedConfigVersion {
+    pub payload: String,
+    pub public_key: String,
+    /// hex编码
+    pub sign: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+struct ConfigVersionState {
+    address: String,
+    version: u64,
+}
+
+/// 加载过的最高配置版本，和sequence::Sequence一样用enclave账户签名封存，由Saver写到存储(例如SGX sealed文件)
+/// 签名只防篡改，宿主删除存储之后从0开始，需要防删除时Saver要写到单调计数器之类的存储
+pub struct ConfigVersion {
+    account: crate::wallet::Account,
+    highest: std::sync::Mutex<u64>,
+    saver: crate::sequence::Saver,
+}
+
+impl ConfigVersion {
+    /// 从loader恢复，没有时从0开始
+    pub fn open(
+        account: crate::wallet::Account,
+        loader: crate::sequence::Loader,
+        saver: crate::sequence::Saver,
+    ) -> Result<Self> {
+        let highest = match loader()? {
+            Some(sealed) => {
+                let sealed: SealedConfigVersion = serde_json::from_str(&sealed)?;
+                let address = crate::wallet::verify_with_public_key(
+                    &sealed.public_key,
+                    sealed.payload.as_bytes(),
+                    &hex::decode(&sealed.sign)?,
+                )?;
+                let state: ConfigVersionState = serde_json::from_str(&sealed.payload)?;
+                if address != account.address || state.address != account.address {
+                    return Err(Error::from(ErrorKind::CryptoError));
+                }
+                state.version
+            }
+            None => 0,
+        };
+        Ok(ConfigVersion {
+            account: account,
+            highest: std::sync::Mutex::new(highest),
+            saver: saver,
+        })
+    }
+
+    pub fn highest(&self) -> u64 {
+        *self.highest.lock().unwrap()
+    }
+
+    /// version低于最高版本时返回InvalidArguments，否则持久化之后更新
+    fn advance(&self, version: u64) -> Result<()> {
+        let mut highest = self.highest.lock().unwrap();
+        if version < *highest {
+            return Err(Error::new(
+                ErrorKind::InvalidArguments,
+                format!(
+                    "config version {} is older than loaded version {}",
+                    version, *highest
+                ),
+            ));
+        }
+        if version > *highest {
+            let payload = serde_json::to_string(&ConfigVersionState {
+                address: self.account.address.to_owned(),
+                version: version,
+            })?;
+            let sealed = SealedConfigVersion {
+                sign: hex::encode(self.account.sign(payload.as_bytes())?),
+                public_key: self.account.public_key()?,
+                payload: payload,
+            };
+            (self.saver)(&serde_json::to_string(&sealed)?)?;
+            *highest = version;
+        }
+        Ok(())
+    }
+}
+
+/// 校验通过并且版本不低于versions里的最高版本之后替换CONFIG，失败时保留原来的配置
+/// 节点地址在ocall::init之后修改不会生效
+pub fn load_signed_config(
+    bundle: &str,
+    pinned_public_key: &str,
+    versions: &ConfigVersion,
+) -> Result<()> {
+    let (version, c) = verify_signed_config(bundle, pinned_public_key)?;
+    versions.advance(version)?;
+    *CONFIG.write().unwrap() = c;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fee_addr_by_class() {
+        let contents = r#"
+complianceCheckEndorseServiceFee: 400
+complianceCheckEndorseServiceFeeAddr: default_addr
+complianceCheckEndorseServiceAddr: endorser_addr
+complianceCheckEndorseServiceFeeAddrByClass:
+  contractDeploy: deploy_addr
+  governance: governance_addr
+"#;
+        let c: ComplianceCheckConfig = serde_yaml::from_str(contents).unwrap();
+        assert_eq!(c.fee_addr(TxClass::Transfer), "default_addr");
+        assert_eq!(c.fee_addr(TxClass::ContractInvoke), "default_addr");
+        assert_eq!(c.fee_addr(TxClass::ContractDeploy), "deploy_addr");
+        assert_eq!(c.fee_addr(TxClass::Governance), "governance_addr");
+        assert_eq!(c.endorser_addrs(), vec![String::from("endorser_addr")]);
+
+        let c: ComplianceCheckConfig = serde_yaml::from_str(&format!(
+            "{}extraEndorsers:\n  - addr: second_addr\n    host: 127.0.0.1\n    port: 37102\n",
+            contents
+        ))
+        .unwrap();
+        assert_eq!(c.extra_endorsers[0].port, 37102);
+        assert_eq!(c.failover_endpoints.is_empty(), true);
+        assert_eq!(
+            c.endorser_addrs(),
+            vec![String::from("endorser_addr"), String::from("second_addr")]
+        );
+    }
+
+    #[test]
+    fn test_failover_endpoints() {
+        let c: ComplianceCheckConfig = serde_yaml::from_str(
+            r#"
+complianceCheckEndorseServiceFee: 400
+complianceCheckEndorseServiceFeeAddr: default_addr
+complianceCheckEndorseServiceAddr: endorser_addr
+failoverEndpoints:
+  - host: 10.0.0.2
+    port: 37101
+  - host: 10.0.0.3
+    port: 37101
+"#,
+        )
+        .unwrap();
+        assert_eq!(c.failover_endpoints.len(), 2);
+        assert_eq!(c.failover_endpoints[1].key(), "10.0.0.3:37101");
+        assert_eq!(c.endorser_addrs(), vec![String::from("endorser_addr")]);
+    }
+
+    #[test]
+    fn test_skip_compliance_check() {
+        let c: ComplianceCheckConfig = serde_yaml::from_str(
+            r#"
+complianceCheckEndorseServiceFee: 400
+complianceCheckEndorseServiceFeeAddr: default_addr
+complianceCheckEndorseServiceAddr: endorser_addr
+skipComplianceCheck: true
+"#,
+        )
+        .unwrap();
+        assert_eq!(c.endorse_service_fee(), 0);
+        assert_eq!(c.endorser_addrs().is_empty(), true);
+    }
+
+    #[test]
+    fn test_client_config() {
+        let c = ClientConfig::from_yaml(include_str!("../conf/sdk.yaml")).unwrap();
+        let mut other = (*c).clone();
+        other.compliance_check.compliance_check_endorse_service_fee = 1;
+        let c = ClientConfig::new(other);
+        assert_eq!(c.compliance_check.compliance_check_endorse_service_fee, 1);
+        assert_ne!(c, ClientConfig::global());
+
+        let acc = crate::wallet::Account {
+            address: String::from("alice"),
+            ..Default::default()
+        };
+        let (req, msg) = crate::transfer::build_transfer_to_many_request_with_config(
+            &c,
+            &acc,
+            &String::from("xuper"),
+            &[(String::from("bob"), String::from("10"))],
+            &String::from("0"),
+            &String::new(),
+        )
+        .unwrap();
+        assert_eq!(req.totalAmount, 11);
+        assert_eq!(msg.auth_require, c.compliance_check.endorser_addrs());
+        assert_eq!(ClientConfig::from_yaml("node: [").is_ok(), false);
+    }
+
+    #[test]
+    fn test_signed_config() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = crate::wallet::Account::new(d.to_str().unwrap(), "", "");
+        let pinned = acc.public_key().unwrap();
+        let yaml = include_str!("../conf/sdk.yaml");
+
+        let bundle = sign_config(&acc, yaml, 2).unwrap();
+        let (version, c) = verify_signed_config(&bundle, &pinned).unwrap();
+        assert_eq!(version, 2);
+        assert_eq!(c, serde_yaml::from_str::<CommConfig>(yaml).unwrap());
+
+        let stored = std::sync::Arc::new(std::sync::Mutex::new(None));
+        let open = |stored: &std::sync::Arc<std::sync::Mutex<Option<String>>>| {
+            let (s1, s2) = (stored.clone(), stored.clone());
+            ConfigVersion::open(
+                acc.clone(),
+                Box::new(move || Ok(s1.lock().unwrap().clone())),
+                Box::new(move |v: &str| {
+                    *s2.lock().unwrap() = Some(v.to_owned());
+                    Ok(())
+                }),
+            )
+            .unwrap()
+        };
+        let versions = open(&stored);
+        assert_eq!(versions.highest(), 0);
+        assert_eq!(
+            load_signed_config(&bundle, &pinned, &versions).is_ok(),
+            true
+        );
+        assert_eq!(
+            load_signed_config(&bundle, &pinned, &versions).is_ok(),
+            true
+        );
+        // 重启之后仍然拒绝旧版本
+        let versions = open(&stored);
+        assert_eq!(versions.highest(), 2);
+        let old = sign_config(&acc, yaml, 1).unwrap();
+        assert_eq!(
+            load_signed_config(&old, &pinned, &versions)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidArguments
+        );
+        assert_eq!(versions.highest(), 2);
+
+        // 宿主修改了版本号
+        let mut signed: SignedConfig = serde_json::from_str(&old).unwrap();
+        signed.version = 3;
+        let tampered = serde_json::to_string(&signed).unwrap();
+        assert_eq!(verify_signed_config(&tampered, &pinned).is_ok(), false);
+
+        // 宿主修改了手续费地址
+        let mut signed: SignedConfig = serde_json::from_str(&bundle).unwrap();
+        signed.payload = signed
+            .payload
+            .replace("complianceCheckEndorseServiceFeeAddr", "x");
+        let tampered = serde_json::to_string(&signed).unwrap();
+        assert_eq!(verify_signed_config(&tampered, &pinned).is_ok(), false);
+        assert_eq!(
+            load_signed_config(&tampered, &pinned, &versions).is_ok(),
+            false
+        );
+        assert_eq!(sign_config(&acc, "node: [", 1).is_ok(), false);
+    }
+
+    #[test]
+    fn test_api_key_rotation() {
+        let c: ApiKeyConfig = serde_yaml::from_str("header: x-api-key\nvalue: k1").unwrap();
+        assert_eq!(c.value, "k1");
+
+        install_api_key();
+        let endpoint = {
+            let c = CONFIG.read().unwrap();
+            xchain_node_sdk::auth::endpoint(&c.node, c.endorse_port)
+        };
+        let headers = |k: Option<ApiKeyConfig>| {
+            CONFIG.write().unwrap().api_key = k;
+            xchain_node_sdk::auth::api_key_headers(&endpoint).unwrap()
+        };
+        assert_eq!(headers(None), vec![]);
+        assert_eq!(
+            headers(Some(c.clone())),
+            vec![(String::from("x-api-key"), String::from("k1"))]
+        );
+        let rotated = ApiKeyConfig {
+            value: String::from("k2"),
+            ..c
+        };
+        assert_eq!(
+            headers(Some(rotated)),
+            vec![(String::from("x-api-key"), String::from("k2"))]
+        );
+        CONFIG.write().unwrap().api_key = None;
+    }
+}
@@ -0,0 +1,127 @@
+use std::time::{Duration, Instant};
+
+use xchain_node_sdk::{errors::*, ocall, protos::xchain};
+
+// 提交交易之后轮询节点，等待交易上链。
+// 节点返回FAILED表示交易被丢弃，不会再上链；查询失败时继续轮询直到超时。
+// NOEXIST只表示节点上查不到: 刚提交的交易可能还没有进入交易池，或者还没有广播到查询的节点，
+// 轮询时在宽限期之内当作还在等待，宽限期过后仍然查不到才认为被丢弃。
+
+/// wait_for_tx默认的NOEXIST宽限期
+pub const DEFAULT_NOT_FOUND_GRACE: Duration = Duration::from_secs(30);
+
+/// 交易在节点上的状态
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TxState {
+    /// 还在交易池里，没有打包
+    Unconfirmed,
+    /// 已经打包，blockid是hex编码
+    Confirmed { blockid: String },
+    /// 节点上查不到(NOEXIST)，可能还没有广播过来，也可能已经被丢弃
+    NotFound,
+    /// 被节点丢弃
+    Dropped { reason: String },
+}
+
+pub fn tx_state(status: &xchain::TxStatus) -> TxState {
+    match status.status {
+        xchain::TransactionStatus::CONFIRM => TxState::Confirmed {
+            blockid: hex::encode(&status.get_tx().blockid),
+        },
+        xchain::TransactionStatus::FAILED => TxState::Dropped {
+            reason: format!("{:?}", status.status),
+        },
+        xchain::TransactionStatus::NOEXIST => TxState::NotFound,
+        _ => TxState::Unconfirmed,
+    }
+}
+
+/// 查询交易当前的状态
+pub fn query_tx_state(txid: &String) -> Result<TxState> {
+    Ok(tx_state(&ocall::ocall_xchain_query_tx(txid)?))
+}
+
+/// 每隔poll_interval查询一次，直到交易确认，返回交易所在区块的高度
+/// 交易被丢弃时返回ChainRPCError，timeout之内没有确认返回DeadlineExceeded
+/// 查不到交易的宽限期是DEFAULT_NOT_FOUND_GRACE
+pub fn wait_for_tx(txid: &String, timeout: Duration, poll_interval: Duration) -> Result<i64> {
+    wait_for_tx_with_grace(txid, timeout, poll_interval, DEFAULT_NOT_FOUND_GRACE)
+}
+
+/// 同wait_for_tx，开始轮询之后grace之内查不到交易时继续等待，超过grace仍然查不到时认为被丢弃
+pub fn wait_for_tx_with_grace(
+    txid: &String,
+    timeout: Duration,
+    poll_interval: Duration,
+    grace: Duration,
+) -> Result<i64> {
+    let start = Instant::now();
+    let deadline = start + timeout;
+    loop {
+        match query_tx_state(txid) {
+            Ok(TxState::Confirmed { blockid }) => return block_height(&blockid),
+            Ok(TxState::NotFound) if start.elapsed() < grace => {}
+            Ok(TxState::NotFound) => return Err(dropped(txid, "NOEXIST")),
+            Ok(TxState::Dropped { reason }) => return Err(dropped(txid, &reason)),
+            Ok(TxState::Unconfirmed) | Err(_) => {}
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(Error::from(ErrorKind::DeadlineExceeded));
+        }
+        std::thread::sleep(std::cmp::min(poll_interval, deadline - now));
+    }
+}
+
+fn dropped(txid: &String, reason: &str) -> Error {
+    Error::new(
+        ErrorKind::ChainRPCError,
+        format!("tx {} dropped: {}", txid, reason),
+    )
+}
+
+/// blockid(hex编码)所在的高度
+pub fn block_height(blockid: &String) -> Result<i64> {
+    if blockid.is_empty() {
+        return Err(Error::from(ErrorKind::ParseError));
+    }
+    let block = ocall::ocall_xchain_get_block(blockid)?;
+    Ok(block.get_block().height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tx_state() {
+        let mut status = xchain::TxStatus::new();
+        status.set_status(xchain::TransactionStatus::UNCONFIRM);
+        assert_eq!(tx_state(&status), TxState::Unconfirmed);
+
+        let mut tx = xchain::Transaction::new();
+        tx.set_blockid(vec![0xbb; 2]);
+        status.set_tx(tx);
+        status.set_status(xchain::TransactionStatus::CONFIRM);
+        assert_eq!(
+            tx_state(&status),
+            TxState::Confirmed {
+                blockid: String::from("bbbb")
+            }
+        );
+
+        status.set_status(xchain::TransactionStatus::FAILED);
+        assert_eq!(
+            tx_state(&status),
+            TxState::Dropped {
+                reason: String::from("FAILED")
+            }
+        );
+
+        // 查不到不等于被丢弃
+        status.set_status(xchain::TransactionStatus::NOEXIST);
+        assert_eq!(tx_state(&status), TxState::NotFound);
+        assert_eq!(block_height(&String::new()).is_ok(), false);
+    }
+}
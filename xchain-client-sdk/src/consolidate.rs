@@ -0,0 +1,207 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use num_bigint::BigInt;
+
+use crate::{config, consts, transfer, wallet};
+use xchain_node_sdk::{errors::*, ocall, protos::xchain};
+
+// 账户的utxo太多时，交易的输入变多，体积和手续费都变大。
+// Consolidator在后台定时检查，未冻结的utxo超过utxoThreshold并且在闲时窗口内时，
+// 把最小的maxInputs个utxo转给自己，合并成一个utxo。
+// 节点每次最多返回maxInputs个utxo，只在这些utxo里面挑选。
+// 合并交易和普通转账一样要付背书手续费，合并的总额不够手续费时不合并。
+
+/// hour是否在[start, end)内，start大于end时跨过0点
+pub fn in_off_peak(hour: u32, start: u32, end: u32) -> bool {
+    if start <= end {
+        hour >= start && hour < end
+    } else {
+        hour >= start || hour < end
+    }
+}
+
+/// 秒级时间戳对应的UTC小时
+pub fn utc_hour(secs: i64) -> u32 {
+    ((secs / 3600) % 24) as u32
+}
+
+/// 节点返回的utxo记录，refTxid是hex编码
+pub fn utxo_from_key(key: &xchain::UtxoKey, address: &str) -> Result<xchain::Utxo> {
+    let mut utxo = xchain::Utxo::new();
+    utxo.set_refTxid(hex::decode(&key.refTxid)?);
+    utxo.set_refOffset(
+        key.offset
+            .parse()
+            .map_err(|_| Error::from(ErrorKind::ParseError))?,
+    );
+    utxo.set_amount(consts::str_as_bigint(&key.amount)?.to_bytes_be().1);
+    utxo.set_toAddr(address.as_bytes().to_vec());
+    Ok(utxo)
+}
+
+/// 需要合并时返回要合并的utxo，从小到大
+pub fn plan(
+    record: &xchain::UtxoRecordDetail,
+    address: &str,
+    cfg: &config::ConsolidationConfig,
+) -> Result<Option<Vec<xchain::Utxo>>> {
+    let open = record.get_openUtxoRecord();
+    let count = consts::str_as_i64(&open.utxoCount)?;
+    if count <= cfg.utxo_threshold as i64 {
+        return Ok(None);
+    }
+    let mut utxos = open
+        .get_item()
+        .iter()
+        .map(|k| utxo_from_key(k, address))
+        .collect::<Result<Vec<_>>>()?;
+    utxos.sort_by_key(|u| BigInt::from_bytes_be(num_bigint::Sign::Plus, &u.amount));
+    utxos.truncate(cfg.max_inputs);
+    if utxos.len() < 2 {
+        return Ok(None);
+    }
+    Ok(Some(utxos))
+}
+
+pub struct Consolidator {
+    account: wallet::Account,
+    chain_name: String,
+    config: config::ClientConfig,
+}
+
+impl Consolidator {
+    /// 使用全局CONFIG当前的配置
+    pub fn new(account: &wallet::Account, chain_name: &String) -> Self {
+        Consolidator::with_config(account, chain_name, config::ClientConfig::global())
+    }
+
+    pub fn with_config(
+        account: &wallet::Account,
+        chain_name: &String,
+        config: config::ClientConfig,
+    ) -> Self {
+        Consolidator {
+            account: account.clone(),
+            chain_name: chain_name.to_owned(),
+            config: config,
+        }
+    }
+
+    /// 检查一次，提交了合并交易时返回txid
+    pub fn run_once(&self) -> Result<Option<String>> {
+        let cfg = match self.config.consolidation {
+            Some(ref cfg) => cfg.clone(),
+            None => return Ok(None),
+        };
+        let endorser_fee = BigInt::from(self.config.compliance_check.endorse_service_fee());
+        let hour = utc_hour(consts::now_as_secs());
+        if !in_off_peak(hour, cfg.off_peak_start_hour, cfg.off_peak_end_hour) {
+            return Ok(None);
+        }
+        let record =
+            ocall::ocall_xchain_query_utxo_record(&self.account.address, cfg.max_inputs as i64)?;
+        let utxos = match plan(&record, &self.account.address, &cfg)? {
+            Some(utxos) => utxos,
+            None => return Ok(None),
+        };
+        let total: BigInt = utxos
+            .iter()
+            .map(|u| BigInt::from_bytes_be(num_bigint::Sign::Plus, &u.amount))
+            .sum();
+        if total <= endorser_fee {
+            return Ok(None);
+        }
+        transfer::transfer_spending_with_config(
+            &self.config,
+            &self.account,
+            &self.chain_name,
+            &self.account.address,
+            &(total - endorser_fee).to_string(),
+            &String::from("0"),
+            &String::from("consolidate utxo"),
+            &utxos,
+        )
+        .map(Some)
+    }
+
+    /// 在后台线程里每隔interval检查一次，每次的结果交给callback
+    pub fn spawn<F>(self, interval: Duration, callback: F) -> ConsolidatorHandle
+    where
+        F: Fn(Result<Option<String>>) + Send + 'static,
+    {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                callback(self.run_once());
+            }
+        });
+        ConsolidatorHandle {
+            stop: stop,
+            thread: thread,
+        }
+    }
+}
+
+pub struct ConsolidatorHandle {
+    stop: mpsc::Sender<()>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl ConsolidatorHandle {
+    /// 停止后台线程，等正在进行的检查结束
+    pub fn stop(self) {
+        drop(self.stop);
+        let _ = self.thread.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(count: &str, amounts: &[&str]) -> xchain::UtxoRecordDetail {
+        let mut r = xchain::UtxoRecordDetail::new();
+        r.mut_openUtxoRecord().set_utxoCount(count.to_owned());
+        for (i, a) in amounts.iter().enumerate() {
+            let mut k = xchain::UtxoKey::new();
+            k.set_refTxid(String::from("aa"));
+            k.set_offset(i.to_string());
+            k.set_amount(a.to_string());
+            r.mut_openUtxoRecord().mut_item().push(k);
+        }
+        r
+    }
+
+    #[test]
+    fn test_consolidation_plan() {
+        assert_eq!(in_off_peak(3, 2, 5), true);
+        assert_eq!(in_off_peak(5, 2, 5), false);
+        assert_eq!(in_off_peak(23, 22, 4), true);
+        assert_eq!(in_off_peak(1, 22, 4), true);
+        assert_eq!(in_off_peak(12, 22, 4), false);
+        assert_eq!(utc_hour(3600 * 24 * 10 + 3600 * 7 + 5), 7);
+
+        let cfg = config::ConsolidationConfig {
+            utxo_threshold: 3,
+            max_inputs: 2,
+            off_peak_start_hour: 0,
+            off_peak_end_hour: 24,
+        };
+        assert_eq!(
+            plan(&record("3", &["5", "1", "3"]), "alice", &cfg).unwrap(),
+            None
+        );
+        let utxos = plan(&record("10", &["5", "1", "3"]), "alice", &cfg)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            utxos.iter().map(|u| u.refOffset).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(utxos[0].toAddr, b"alice".to_vec());
+        assert_eq!(utxos[0].refTxid, vec![0xaa]);
+        assert_eq!(plan(&record("10", &["5"]), "alice", &cfg).unwrap(), None);
+    }
+}
@@ -5,10 +5,58 @@ use num_traits;
 use num_traits::identities::Zero;
 
 use xchain_node_sdk::errors::{Error, ErrorKind, Result};
+use xchain_node_sdk::protos::xchain;
 
 #[allow(non_upper_case_globals)]
 pub const TXVersion: i32 = 1;
 
+/// 手续费输出的地址
+pub const FEE_ADDR: &str = "$";
+
+/// 交易里面付给矿工的手续费输出
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FeeOutput {
+    pub amount: num_bigint::BigInt,
+}
+
+impl FeeOutput {
+    pub fn new(amount: &str) -> Result<Self> {
+        Ok(FeeOutput {
+            amount: str_as_bigint(amount)?,
+        })
+    }
+
+    pub fn to_tx_output(&self) -> xchain::TxOutput {
+        let mut t = xchain::TxOutput::new();
+        t.set_to_addr(FEE_ADDR.as_bytes().to_vec());
+        t.set_amount(self.amount.to_bytes_be().1);
+        t
+    }
+
+    /// 是手续费输出时返回Some
+    pub fn from_tx_output(output: &xchain::TxOutput) -> Option<Self> {
+        if !Self::is_fee(output) {
+            return None;
+        }
+        Some(FeeOutput {
+            amount: num_bigint::BigInt::from_bytes_be(num_bigint::Sign::Plus, &output.amount),
+        })
+    }
+
+    pub fn is_fee(output: &xchain::TxOutput) -> bool {
+        output.to_addr == FEE_ADDR.as_bytes()
+    }
+
+    /// 交易的手续费总和
+    pub fn total(tx: &xchain::Transaction) -> num_bigint::BigInt {
+        tx.tx_outputs
+            .iter()
+            .filter_map(|o| Self::from_tx_output(o))
+            .fold(num_bigint::BigInt::zero(), |acc, f| acc + f.amount)
+    }
+}
+
 pub fn now_as_nanos() -> i64 {
     let t = std::time::SystemTime::now();
     let since_the_epoch = t
@@ -25,6 +73,14 @@ pub fn now_as_secs() -> i64 {
     since_the_epoch.as_secs() as i64
 }
 
+/// deadline已过则返回DeadlineExceeded
+pub fn check_deadline(deadline: Option<std::time::Instant>) -> Result<()> {
+    match deadline {
+        Some(d) if std::time::Instant::now() >= d => Err(Error::from(ErrorKind::DeadlineExceeded)),
+        _ => Ok(()),
+    }
+}
+
 pub fn str_as_i64(s: &str) -> Result<i64> {
     let i = s
         .parse::<i64>()
@@ -42,9 +98,83 @@ pub fn str_as_bigint(s: &str) -> Result<num_bigint::BigInt> {
     num_bigint::BigInt::from_str(s).map_err(|_| Error::from(ErrorKind::ParseError))
 }
 
+/// 非负金额之和，空字符串当作0，负数返回InvalidArguments
+/// 所有金额的加法都在这里用BigInt完成，不会溢出
+pub fn checked_sum(amounts: &[&str]) -> Result<num_bigint::BigInt> {
+    let mut total = num_bigint::BigInt::zero();
+    for a in amounts.iter() {
+        let a = str_as_bigint(a)?;
+        if a < num_bigint::BigInt::zero() {
+            return Err(Error::from(ErrorKind::InvalidArguments));
+        }
+        total += a;
+    }
+    Ok(total)
+}
+
+/// 转成预执行请求里的i64金额，超出i64范围时返回InvalidArguments
+pub fn bigint_as_i64(n: &num_bigint::BigInt) -> Result<i64> {
+    num_traits::ToPrimitive::to_i64(n).ok_or(Error::from(ErrorKind::InvalidArguments))
+}
+
 pub fn print_bytes_num(s: &Vec<u8>) {
-    println!(
-        "print_bytes_num: {:?}",
-        num_bigint::BigInt::from_bytes_be(num_bigint::Sign::Plus, s).to_str_radix(10)
+    tracing::debug!(
+        amount = %num_bigint::BigInt::from_bytes_be(num_bigint::Sign::Plus, s),
+        "print_bytes_num"
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_traits::FromPrimitive;
+
+    #[test]
+    fn test_fee_output() {
+        let fee = FeeOutput::new("300").unwrap();
+        let output = fee.to_tx_output();
+        assert_eq!(output.to_addr, b"$".to_vec());
+        assert_eq!(FeeOutput::from_tx_output(&output), Some(fee));
+
+        let mut to = xchain::TxOutput::new();
+        to.set_to_addr(b"dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN".to_vec());
+        to.set_amount(vec![1]);
+        assert_eq!(FeeOutput::is_fee(&to), false);
+
+        let mut tx = xchain::Transaction::new();
+        tx.mut_tx_outputs().push(to);
+        tx.mut_tx_outputs().push(output);
+        tx.mut_tx_outputs()
+            .push(FeeOutput::new("1").unwrap().to_tx_output());
+        assert_eq!(
+            FeeOutput::total(&tx),
+            num_bigint::BigInt::from_i64(301).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_checked_sum() {
+        let max = i64::MAX.to_string();
+        let total = checked_sum(&[&max, "0", ""]).unwrap();
+        assert_eq!(bigint_as_i64(&total).unwrap(), i64::MAX);
+
+        // 任意两项相加超过i64都能发现，和顺序无关
+        for amounts in [
+            [max.as_str(), "1", "0"],
+            ["1", "0", max.as_str()],
+            ["0", max.as_str(), max.as_str()],
+        ]
+        .iter()
+        {
+            let total = checked_sum(amounts).unwrap();
+            assert_eq!(
+                total > num_bigint::BigInt::from_i64(i64::MAX).unwrap(),
+                true
+            );
+            assert_eq!(bigint_as_i64(&total).is_ok(), false);
+        }
+
+        assert_eq!(checked_sum(&["1", "-1"]).is_ok(), false);
+        assert_eq!(checked_sum(&["1", "a"]).is_ok(), false);
+    }
+}
@@ -1,36 +1,166 @@
 use super::config;
-use crate::{session, wallet};
-use xchain_node_sdk::{errors::Result, ocall, protos};
-/// account在chain上面给to转账amount，小费是fee，留言是des, ocallc
+use crate::{consts, multisig, session, wallet};
+use xchain_node_sdk::{deadline, errors::*, ocall, protos};
+
+pub const WASM_MODULE: &str = "wasm";
+/// 系统合约的模块
+pub const KERNEL_MODULE: &str = "xkernel";
+
+/// account调用chain上合约contract_name的method_name，返回txid和合约的返回body
+/// fee为空时按预执行的gas_used付费，否则fee不能小于gas_used
 pub fn invoke_contract(
     account: &wallet::Account,
     chain_name: &String,
+    contract_name: &String,
+    method_name: &String,
+    args: std::collections::HashMap<String, Vec<u8>>,
+    fee: &String,
+) -> Result<(String, Vec<u8>)> {
+    do_invoke_contract(
+        &config::ClientConfig::global(),
+        account,
+        WASM_MODULE,
+        chain_name,
+        contract_name,
+        method_name,
+        args,
+        fee,
+        &[],
+        None,
+    )
+}
+
+/// 同invoke_contract，整个流程需要在deadline之前完成，否则放弃交易并返回DeadlineExceeded
+/// 每个RPC都以剩余的时间为超时；提交交易超时的时候交易可能已经上链，需要按txid查询
+pub fn invoke_contract_with_deadline(
+    account: &wallet::Account,
+    chain_name: &String,
+    contract_name: &String,
+    method_name: &String,
+    args: std::collections::HashMap<String, Vec<u8>>,
+    fee: &String,
+    deadline: std::time::Instant,
+) -> Result<(String, Vec<u8>)> {
+    do_invoke_contract(
+        &config::ClientConfig::global(),
+        account,
+        WASM_MODULE,
+        chain_name,
+        contract_name,
+        method_name,
+        args,
+        fee,
+        &[],
+        Some(deadline),
+    )
+}
+
+/// 合约账户的多个AK共同授权的调用，signers都是account.contract_account的AK
+/// 提交之前按链上的ACL校验签名，签名不够时返回AclNotSatisfied
+pub fn invoke_contract_multisig(
+    account: &wallet::Account,
+    signers: &[wallet::Account],
+    chain_name: &String,
+    contract_name: &String,
+    method_name: &String,
+    args: std::collections::HashMap<String, Vec<u8>>,
+    fee: &String,
+) -> Result<(String, Vec<u8>)> {
+    if account.contract_account.is_empty() || signers.is_empty() {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    do_invoke_contract(
+        &config::ClientConfig::global(),
+        account,
+        WASM_MODULE,
+        chain_name,
+        contract_name,
+        method_name,
+        args,
+        fee,
+        signers,
+        None,
+    )
+}
+
+/// 预执行之后实际支付的手续费
+pub fn invoke_fee(fee: &String, gas_used: i64) -> Result<String> {
+    if fee.is_empty() {
+        return Ok(gas_used.to_string());
+    }
+    let f = consts::str_as_bigint(fee)?;
+    if f < num_bigint::BigInt::from(gas_used) {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    Ok(f.to_string())
+}
+
+/// 合约的返回body，只有一个调用请求时取第一个
+pub fn response_body(resp: &protos::xchain::PreExecWithSelectUTXOResponse) -> Vec<u8> {
+    resp.get_response()
+        .get_responses()
+        .first()
+        .map(|r| r.body.clone())
+        .unwrap_or_default()
+}
+
+/// 调用xkernel系统合约(例如$acl)，signers不为空时由合约账户的多个AK授权，同invoke_contract_multisig
+pub fn invoke_kernel_contract(
+    account: &wallet::Account,
+    signers: &[wallet::Account],
+    chain_name: &String,
+    contract_name: &String,
+    method_name: &String,
+    args: std::collections::HashMap<String, Vec<u8>>,
+    fee: &String,
+) -> Result<(String, Vec<u8>)> {
+    if !signers.is_empty() && account.contract_account.is_empty() {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    do_invoke_contract(
+        &config::ClientConfig::global(),
+        account,
+        KERNEL_MODULE,
+        chain_name,
+        contract_name,
+        method_name,
+        args,
+        fee,
+        signers,
+        None,
+    )
+}
+
+pub(crate) fn do_invoke_contract(
+    config: &config::ClientConfig,
+    account: &wallet::Account,
+    module_name: &str,
+    chain_name: &String,
+    contract_name: &String,
     method_name: &String,
     args: std::collections::HashMap<String, Vec<u8>>,
-) -> Result<String> {
+    fee: &String,
+    signers: &[wallet::Account],
+    deadline: Option<std::time::Instant>,
+) -> Result<(String, Vec<u8>)> {
     let mut invoke_req = protos::xchain::InvokeRequest::new();
-    invoke_req.set_module_name(String::from("wasm"));
-    invoke_req.set_contract_name(account.contract_name.to_owned());
+    invoke_req.set_module_name(module_name.to_owned());
+    invoke_req.set_contract_name(contract_name.to_owned());
     invoke_req.set_method_name(method_name.to_owned());
     invoke_req.set_args(args);
     invoke_req.set_amount(String::from("0"));
 
     let invoke_requests = vec![invoke_req; 1];
     let mut auth_requires = vec![];
-    if !account.contract_account.is_empty() {
+    if !signers.is_empty() {
+        auth_requires = multisig::auth_require(&account.contract_account, signers);
+    } else if !account.contract_account.is_empty() {
         let mut s = account.contract_account.to_owned();
         s.push_str("/");
         s.push_str(account.address.to_owned().as_str());
         auth_requires.push(s);
     };
-    auth_requires.push(
-        config::CONFIG
-            .read()
-            .unwrap()
-            .compliance_check
-            .compliance_check_endorse_service_addr
-            .to_owned(),
-    );
+    auth_requires.extend(config.compliance_check.endorser_addrs());
 
     let mut invoke_rpc_request = protos::xchain::InvokeRPCRequest::new();
     invoke_rpc_request.set_bcname(chain_name.to_owned());
@@ -38,129 +168,109 @@ pub fn invoke_contract(
     invoke_rpc_request.set_initiator(account.address.to_owned());
     invoke_rpc_request.set_auth_require(protobuf::RepeatedField::from_vec(auth_requires.clone()));
 
-    let total_amount = config::CONFIG
-        .read()
-        .unwrap()
-        .compliance_check
-        .compliance_check_endorse_service_fee;
+    let endorser_fee = config.compliance_check.endorse_service_fee() as i64;
+    // 指定了fee时选出的utxo要同时够付fee
+    let total_amount =
+        consts::bigint_as_i64(&consts::checked_sum(&[fee, &endorser_fee.to_string()])?)?;
 
     let mut pre_sel_utxo_req = protos::xchain::PreExecWithSelectUTXORequest::new();
     pre_sel_utxo_req.set_bcname(chain_name.to_owned());
     pre_sel_utxo_req.set_address(account.address.to_owned());
-    pre_sel_utxo_req.set_totalAmount(total_amount as i64);
+    pre_sel_utxo_req.set_totalAmount(total_amount);
     pre_sel_utxo_req.set_request(invoke_rpc_request.clone());
 
     let msg = session::Message {
         to: Default::default(),
-        fee: Default::default(),
-        desc: String::from("call from contract"),
-        auth_require: auth_requires.clone(),
-        amount: Default::default(),
-        frozen_height: 0,
-        initiator: account.address.to_owned(),
-    };
-
-    let sess = session::Session::new(chain_name, account, &msg);
-    let mut resp = sess.pre_exec_with_select_utxo(pre_sel_utxo_req)?;
-
-    //TODO 代码优化
-    let msg = session::Message {
-        to: String::from(""),
-        fee: resp.get_response().get_gas_used().to_string(),
+        fee: fee.to_owned(),
         desc: String::from("call from contract"),
         auth_require: auth_requires,
         amount: Default::default(),
+        outputs: vec![],
         frozen_height: 0,
         initiator: account.address.to_owned(),
+        auth_require_signers: signers.to_vec(),
+        tx_class: config::TxClass::ContractInvoke,
+        timestamp: None,
+        nonce: None,
     };
-    let sess = session::Session::new(chain_name, account, &msg);
-    sess.gen_complete_tx_and_post(&mut resp)
+    // 查询ACL、预执行、合规检查和提交交易的每个RPC都以deadline剩余的时间为超时
+    deadline::scope(deadline, || {
+        let mut sess = session::Session::with_config(chain_name, account, &msg, config.clone());
+        if !signers.is_empty() {
+            sess.add_hook(std::sync::Arc::new(multisig::AclHook {
+                contract_account: account.contract_account.to_owned(),
+                acl: multisig::query_acl(&account.contract_account)?,
+            }));
+        }
+        // 手续费按gas_used计算，选出的utxo不够时重新选
+        let mut resp = sess.pre_exec_with_auto_fee(pre_sel_utxo_req)?;
+        let body = response_body(&resp);
+        let txid = sess.gen_complete_tx_and_post_with_deadline(&mut resp, deadline)?;
+        Ok((txid, body))
+    })
 }
 
+/// 只预执行chain上合约contract_name的method_name并返回合约的响应，
+/// 不选utxo、不做合规检查、不提交交易，也不付手续费，用于查询价格等只读调用
+/// 合约返回的status大于400时返回ContractCodeGT400
+/// 和之前的版本不兼容: 去掉了account参数，合约名由contract_name传入，返回合约的响应而不是InvokeRPCResponse。
+/// 原来的调用方传&account.contract_name，需要节点完整响应的用ocall::ocall_xchain_pre_exec
 pub fn query_contract(
-    account: &wallet::Account,
     chain_name: &String,
+    contract_name: &String,
     method_name: &String,
     args: std::collections::HashMap<String, Vec<u8>>,
-) -> Result<protos::xchain::InvokeRPCResponse> {
+) -> Result<protos::xchain::ContractResponse> {
     let mut invoke_req = protos::xchain::InvokeRequest::new();
-    invoke_req.set_module_name(String::from("wasm"));
-    invoke_req.set_contract_name(account.contract_name.to_owned());
+    invoke_req.set_module_name(WASM_MODULE.to_owned());
+    invoke_req.set_contract_name(contract_name.to_owned());
     invoke_req.set_method_name(method_name.to_owned());
     invoke_req.set_args(args);
-    let invoke_requests = vec![invoke_req; 1];
-    let mut auth_requires = vec![];
-
-    if !account.contract_account.is_empty() {
-        let mut s = account.contract_account.to_owned();
-        s.push_str("/");
-        s.push_str(account.address.to_owned().as_str());
-        auth_requires.push(s);
-    };
-
-    auth_requires.push(
-        config::CONFIG
-            .read()
-            .unwrap()
-            .compliance_check
-            .compliance_check_endorse_service_addr
-            .to_owned(),
-    );
 
     let mut invoke_rpc_request = protos::xchain::InvokeRPCRequest::new();
     invoke_rpc_request.set_bcname(chain_name.to_owned());
-    invoke_rpc_request.set_requests(protobuf::RepeatedField::from_vec(invoke_requests));
-    invoke_rpc_request.set_initiator(account.address.to_owned());
-    invoke_rpc_request.set_auth_require(protobuf::RepeatedField::from_vec(auth_requires.clone()));
-
-    /*
-    let msg = session::Message {
-        to: String::from(""),
-        fee: String::from("0"),
-        desc: String::from(""),
-        auth_require: auth_requires,
-        amount: Default::default(),
-        frozen_height: 0,
-        initiator: account.address.to_owned(),
-    };
+    invoke_rpc_request.set_requests(protobuf::RepeatedField::from_vec(vec![invoke_req]));
+    let resp = ocall::ocall_xchain_pre_exec(invoke_rpc_request)?;
+    first_response(resp.get_response().get_responses())
+}
 
-    let sess = session::Session::new(chain_name, account, &msg);
-    */
-    ocall::ocall_xchain_pre_exec(invoke_rpc_request)
+/// 只有一个调用请求时取第一个合约的响应，同response_body
+fn first_response(
+    responses: &[protos::xchain::ContractResponse],
+) -> Result<protos::xchain::ContractResponse> {
+    ContractError::check(responses)?;
+    responses.first().cloned().ok_or(Error::new(
+        ErrorKind::UnexpectedResponse,
+        "contract returned no response",
+    ))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::config;
+    use crate::testkit;
     use std::collections::HashMap;
-    use std::path::PathBuf;
-    use xchain_node_sdk::ocall;
+    use xchain_node_sdk::errors::ErrorKind;
+    use xchain_node_sdk::{ocall, protos};
 
     #[test]
     fn test_contract() {
-        let host = config::CONFIG.read().unwrap().node.clone();
-        let port = config::CONFIG.read().unwrap().endorse_port;
-        let bcname = String::from("xuper");
-        let res = ocall::init(&bcname, &host, port);
-        assert_eq!(res.is_ok(), true);
-
-        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        d.push("key/private.key");
-        let acc = super::wallet::Account::new(
-            d.to_str().unwrap(),
-            "counter327861",
-            "XC1111111111000000@xuper",
-        );
+        let env = testkit::TestEnv::from_env().unwrap();
+        let bcname = env.chain_name.to_owned();
+        let acc = env
+            .faucet("counter327861", "XC1111111111000000@xuper")
+            .unwrap();
 
         let mn = String::from("increase");
         let mut args = HashMap::new();
         args.insert(String::from("key"), String::from("counter").into_bytes());
 
-        let txid = super::invoke_contract(&acc, &bcname, &mn, args);
-        println!("contract txid: {:?}", txid);
+        let res =
+            super::invoke_contract(&acc, &bcname, &acc.contract_name, &mn, args, &String::new());
+        println!("contract invoke res: {:?}", res);
 
-        assert_eq!(txid.is_ok(), true);
-        let txid = txid.unwrap();
+        assert_eq!(res.is_ok(), true);
+        let (txid, body) = res.unwrap();
+        assert_eq!(body.is_empty(), false);
 
         let res = ocall::ocall_xchain_query_tx(&txid);
         assert_eq!(res.is_ok(), true);
@@ -171,30 +281,59 @@ mod tests {
 
     #[test]
     fn test_query() {
-        let host = config::CONFIG.read().unwrap().node.clone();
-        let port = config::CONFIG.read().unwrap().endorse_port;
-        let bcname = String::from("xuper");
-        let res = ocall::init(&bcname, &host, port);
-        assert_eq!(res.is_ok(), true);
-
-        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        d.push("key/private.key");
-        let acc = super::wallet::Account::new(
-            d.to_str().unwrap(),
-            "counter327861",
-            "XC1111111111000000@xuper",
-        );
+        let env = testkit::TestEnv::from_env().unwrap();
+        let bcname = env.chain_name.to_owned();
+        let acc = env
+            .faucet("counter327861", "XC1111111111000000@xuper")
+            .unwrap();
         let mn = String::from("get");
         let mut args = HashMap::new();
         args.insert(String::from("key"), String::from("counter").into_bytes());
 
-        let resp = super::query_contract(&acc, &bcname, &mn, args);
+        let resp = super::query_contract(&bcname, &acc.contract_name, &mn, args);
         assert_eq!(resp.is_ok(), true);
         println!(
             "contract query result: {}",
-            std::str::from_utf8(&resp.ok().unwrap().get_response().get_response()[0]).unwrap()
+            String::from_utf8_lossy(&resp.unwrap().body)
         );
 
         ocall::close();
     }
+
+    #[test]
+    fn test_first_response() {
+        let response = |status: i32, body: &str| {
+            let mut r = protos::xchain::ContractResponse::new();
+            r.set_status(status);
+            r.set_message(String::from("msg"));
+            r.set_body(body.as_bytes().to_vec());
+            r
+        };
+
+        let resp = super::first_response(&[response(200, "first"), response(200, "second")]);
+        assert_eq!(resp.unwrap().body, b"first".to_vec());
+
+        let resp = super::first_response(&[response(500, "failed")]);
+        assert_eq!(resp.unwrap_err().kind(), ErrorKind::ContractCodeGT400);
+        let resp = super::first_response(&[response(200, "ok"), response(403, "denied")]);
+        assert_eq!(resp.unwrap_err().kind(), ErrorKind::ContractCodeGT400);
+
+        let resp = super::first_response(&[]);
+        assert_eq!(resp.unwrap_err().kind(), ErrorKind::UnexpectedResponse);
+    }
+
+    #[test]
+    fn test_invoke_fee() {
+        assert_eq!(super::invoke_fee(&String::new(), 35).unwrap(), "35");
+        assert_eq!(super::invoke_fee(&String::from("100"), 35).unwrap(), "100");
+        assert_eq!(super::invoke_fee(&String::from("10"), 35).is_ok(), false);
+        assert_eq!(super::invoke_fee(&String::from("-1"), 0).is_ok(), false);
+
+        let mut resp = protos::xchain::PreExecWithSelectUTXOResponse::new();
+        assert_eq!(super::response_body(&resp), Vec::<u8>::new());
+        let mut r = protos::xchain::ContractResponse::new();
+        r.set_body(b"42".to_vec());
+        resp.mut_response().mut_responses().push(r);
+        assert_eq!(super::response_body(&resp), b"42".to_vec());
+    }
 }
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+use crate::{contract, multisig, wallet};
+use xchain_node_sdk::{
+    errors::*,
+    protos::xchain::{self, PermissionRule},
+};
+
+// 通过$acl系统合约创建合约账户和修改ACL，和普通合约调用一样经过预执行和合规检查。
+// 创建时指定16位数字的账户号，链上的账户名是"XC" + 账户号 + "@" + 链名。
+// 修改ACL需要满足当前的ACL，由合约账户的多个AK签名(见multisig)。
+
+pub const ACL_CONTRACT: &str = "$acl";
+pub const NEW_ACCOUNT_METHOD: &str = "NewAccount";
+pub const SET_ACCOUNT_ACL_METHOD: &str = "SetAccountAcl";
+
+const ACCOUNT_NUMBER_LEN: usize = 16;
+
+/// 账户号对应的链上账户名
+pub fn account_name(number: &str, chain_name: &str) -> Result<String> {
+    if number.len() != ACCOUNT_NUMBER_LEN || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    if chain_name.is_empty() {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    Ok(format!("XC{}@{}", number, chain_name))
+}
+
+/// 签名阈值的ACL: 签了名的AK的权重之和不小于threshold
+pub fn threshold_acl(threshold: f64, aks_weight: HashMap<String, f64>) -> Result<xchain::Acl> {
+    let total: f64 = aks_weight.values().sum();
+    if aks_weight.is_empty() || threshold <= 0.0 || threshold > total {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let mut pm = xchain::PermissionModel::new();
+    pm.set_rule(PermissionRule::SIGN_THRESHOLD);
+    pm.set_acceptValue(threshold);
+    let mut acl = xchain::Acl::new();
+    acl.set_pm(pm);
+    acl.set_aksWeight(aks_weight);
+    Ok(acl)
+}
+
+fn acl_args(name: &str, acl: &xchain::Acl) -> Result<HashMap<String, Vec<u8>>> {
+    let mut args = HashMap::new();
+    args.insert(String::from("account_name"), name.as_bytes().to_vec());
+    args.insert(String::from("acl"), serde_json::to_vec(acl)?);
+    Ok(args)
+}
+
+/// account创建账户号为number的合约账户，返回txid和链上的账户名
+/// 链上创建账户需要手续费，fee为空时按预执行的gas_used付费
+pub fn create_contract_account(
+    account: &wallet::Account,
+    chain_name: &String,
+    number: &str,
+    acl: &xchain::Acl,
+    fee: &String,
+) -> Result<(String, String)> {
+    let name = account_name(number, chain_name)?;
+    let (txid, _) = contract::invoke_kernel_contract(
+        account,
+        &[],
+        chain_name,
+        &String::from(ACL_CONTRACT),
+        &String::from(NEW_ACCOUNT_METHOD),
+        acl_args(number, acl)?,
+        fee,
+    )?;
+    Ok((txid, name))
+}
+
+/// 修改account.contract_account的ACL，signers需要满足当前的ACL
+pub fn set_acl(
+    account: &wallet::Account,
+    signers: &[wallet::Account],
+    chain_name: &String,
+    acl: &xchain::Acl,
+    fee: &String,
+) -> Result<String> {
+    if signers.is_empty() {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let (txid, _) = contract::invoke_kernel_contract(
+        account,
+        signers,
+        chain_name,
+        &String::from(ACL_CONTRACT),
+        &String::from(SET_ACCOUNT_ACL_METHOD),
+        acl_args(&account.contract_account, acl)?,
+        fee,
+    )?;
+    Ok(txid)
+}
+
+/// 查询合约账户当前的ACL
+pub fn query_acl(contract_account: &String) -> Result<xchain::Acl> {
+    multisig::query_acl(contract_account)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_name() {
+        assert_eq!(
+            account_name("1111111111111111", "xuper").unwrap(),
+            "XC1111111111111111@xuper"
+        );
+        assert_eq!(account_name("111111111111111", "xuper").is_ok(), false);
+        assert_eq!(account_name("111111111111111a", "xuper").is_ok(), false);
+        assert_eq!(account_name("1111111111111111", "").is_ok(), false);
+    }
+
+    #[test]
+    fn test_threshold_acl() {
+        let mut aks = HashMap::new();
+        aks.insert(String::from("alice"), 0.5);
+        aks.insert(String::from("bob"), 0.5);
+        assert_eq!(threshold_acl(1.5, aks.clone()).is_ok(), false);
+        assert_eq!(threshold_acl(0.0, aks.clone()).is_ok(), false);
+
+        let acl = threshold_acl(1.0, aks).unwrap();
+        let args = acl_args("1111111111111111", &acl).unwrap();
+        let decoded: xchain::Acl = serde_json::from_slice(&args["acl"]).unwrap();
+        assert_eq!(decoded, acl);
+        assert_eq!(
+            multisig::check_acl(&acl, &[String::from("alice")]).is_ok(),
+            false
+        );
+        assert_eq!(
+            multisig::check_acl(&acl, &[String::from("alice"), String::from("bob")]).is_ok(),
+            true
+        );
+    }
+}
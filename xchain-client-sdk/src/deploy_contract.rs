@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use protobuf::Message;
+
+use crate::{config, consts, contract, session, wallet};
+use xchain_node_sdk::{errors::*, ocall, protos::xchain};
+
+// 部署WASM合约: 调用xkernel的Deploy方法，参数和xchain-cli一致:
+//   account_name   部署合约的合约账户
+//   contract_name  合约名
+//   contract_code  WASM二进制
+//   contract_desc  WasmCodeDesc的protobuf编码，指定runtime(c, go)
+//   init_args      初始化参数的json，值是base64编码(Go的map[string][]byte)
+// 部署的gas比普通调用大很多，先不选utxo预执行一次得到gas_used，再按gas_used选utxo。
+
+pub const WASM_MAGIC: &[u8] = b"\0asm";
+
+/// 生成部署合约的调用请求
+pub fn build_deploy_request(
+    account: &wallet::Account,
+    contract_name: &String,
+    code: &[u8],
+    runtime: &str,
+    init_args: &HashMap<String, Vec<u8>>,
+) -> Result<xchain::InvokeRequest> {
+    if account.contract_account.is_empty()
+        || contract_name.is_empty()
+        || !code.starts_with(WASM_MAGIC)
+        || (runtime != "c" && runtime != "go")
+    {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let mut desc = xchain::WasmCodeDesc::new();
+    desc.set_runtime(runtime.to_owned());
+    let desc = desc
+        .write_to_bytes()
+        .map_err(|_| Error::from(ErrorKind::ParseError))?;
+    let init_args: HashMap<&String, String> = init_args
+        .iter()
+        .map(|(k, v)| (k, base64::encode(v)))
+        .collect();
+
+    let mut args = HashMap::new();
+    args.insert(
+        String::from("account_name"),
+        account.contract_account.to_owned().into_bytes(),
+    );
+    args.insert(
+        String::from("contract_name"),
+        contract_name.to_owned().into_bytes(),
+    );
+    args.insert(String::from("contract_code"), code.to_vec());
+    args.insert(String::from("contract_desc"), desc);
+    args.insert(String::from("init_args"), serde_json::to_vec(&init_args)?);
+
+    let mut invoke_req = xchain::InvokeRequest::new();
+    invoke_req.set_module_name(String::from("xkernel"));
+    invoke_req.set_method_name(String::from("Deploy"));
+    invoke_req.set_args(args);
+    Ok(invoke_req)
+}
+
+/// account用合约账户部署contract_name，返回txid
+/// fee为空时按预执行的gas_used付费，否则fee不能小于gas_used
+pub fn deploy_contract(
+    account: &wallet::Account,
+    chain_name: &String,
+    contract_name: &String,
+    code: &[u8],
+    runtime: &str,
+    init_args: &HashMap<String, Vec<u8>>,
+    fee: &String,
+) -> Result<String> {
+    let invoke_req = build_deploy_request(account, contract_name, code, runtime, init_args)?;
+    deploy(
+        &config::ClientConfig::global(),
+        account,
+        chain_name,
+        invoke_req,
+        fee,
+    )
+}
+
+/// 预执行部署请求得到gas_used，再选utxo、签名并提交，WASM和EVM合约共用
+pub(crate) fn deploy(
+    config: &config::ClientConfig,
+    account: &wallet::Account,
+    chain_name: &String,
+    invoke_req: xchain::InvokeRequest,
+    fee: &String,
+) -> Result<String> {
+    let mut auth_requires = vec![format!("{}/{}", account.contract_account, account.address)];
+    auth_requires.extend(config.compliance_check.endorser_addrs());
+
+    let mut invoke_rpc_request = xchain::InvokeRPCRequest::new();
+    invoke_rpc_request.set_bcname(chain_name.to_owned());
+    invoke_rpc_request.set_requests(protobuf::RepeatedField::from_vec(vec![invoke_req]));
+    invoke_rpc_request.set_initiator(account.address.to_owned());
+    invoke_rpc_request.set_auth_require(protobuf::RepeatedField::from_vec(auth_requires.clone()));
+
+    let gas_used = ocall::ocall_xchain_pre_exec(invoke_rpc_request.clone())?
+        .get_response()
+        .get_gas_used();
+    let fee = contract::invoke_fee(fee, gas_used)?;
+    let endorser_fee = config.compliance_check.endorse_service_fee() as i64;
+    let total_amount =
+        consts::bigint_as_i64(&consts::checked_sum(&[&fee, &endorser_fee.to_string()])?)?;
+
+    let mut pre_sel_utxo_req = xchain::PreExecWithSelectUTXORequest::new();
+    pre_sel_utxo_req.set_bcname(chain_name.to_owned());
+    pre_sel_utxo_req.set_address(account.address.to_owned());
+    pre_sel_utxo_req.set_totalAmount(total_amount);
+    pre_sel_utxo_req.set_request(invoke_rpc_request);
+
+    let msg = session::Message {
+        to: String::from(""),
+        fee: fee.to_owned(),
+        desc: String::from("deploy contract"),
+        auth_require: auth_requires,
+        amount: Default::default(),
+        outputs: vec![],
+        frozen_height: 0,
+        initiator: account.address.to_owned(),
+        auth_require_signers: vec![],
+        tx_class: config::TxClass::ContractDeploy,
+        timestamp: None,
+        nonce: None,
+    };
+    let sess = session::Session::with_config(chain_name, account, &msg, config.clone());
+    let mut resp = sess.pre_exec_with_select_utxo(pre_sel_utxo_req)?;
+    // 两次预执行之间状态可能变化
+    contract::invoke_fee(&fee, resp.get_response().get_gas_used())?;
+    sess.gen_complete_tx_and_post(&mut resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_deploy_request() {
+        let acc = wallet::Account {
+            address: String::from("alice"),
+            contract_account: String::from("XC1111111111000000@xuper"),
+            ..Default::default()
+        };
+        let name = String::from("counter");
+        let code = b"\0asm\x01\x00\x00\x00".to_vec();
+        let mut init_args = HashMap::new();
+        init_args.insert(String::from("creator"), b"alice".to_vec());
+
+        let req = build_deploy_request(&acc, &name, &code, "c", &init_args).unwrap();
+        assert_eq!(req.module_name, "xkernel");
+        assert_eq!(req.method_name, "Deploy");
+        let args = req.get_args();
+        assert_eq!(args["account_name"], b"XC1111111111000000@xuper".to_vec());
+        assert_eq!(args["contract_code"], code);
+        let desc: xchain::WasmCodeDesc =
+            protobuf::parse_from_bytes(&args["contract_desc"]).unwrap();
+        assert_eq!(desc.runtime, "c");
+        let init: HashMap<String, String> = serde_json::from_slice(&args["init_args"]).unwrap();
+        assert_eq!(init["creator"], "YWxpY2U=");
+
+        assert_eq!(
+            build_deploy_request(&acc, &name, b"not wasm", "c", &init_args).is_ok(),
+            false
+        );
+        assert_eq!(
+            build_deploy_request(&acc, &name, &code, "java", &init_args).is_ok(),
+            false
+        );
+        let acc = wallet::Account {
+            contract_account: String::new(),
+            ..acc
+        };
+        assert_eq!(
+            build_deploy_request(&acc, &name, &code, "c", &init_args).is_ok(),
+            false
+        );
+    }
+}
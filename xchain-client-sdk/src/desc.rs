@@ -0,0 +1,145 @@
+use xchain_node_sdk::{errors::*, protos::xchain};
+
+/// 压缩过的desc的前缀，0xff不会出现在utf8文本里面，不会和普通的desc混淆
+pub const ZSTD_MAGIC: &[u8] = b"\xffzstd:";
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// 嵌入了W3C traceparent的desc的前缀，后面紧跟TRACEPARENT_LEN字节的traceparent，见trace模块
+pub const TRACE_MAGIC: &[u8] = b"\xfftrace:";
+pub const TRACEPARENT_LEN: usize = 55;
+
+/// 绑定了chain id的desc的前缀，后面是chain id和换行，在最前面，见chain_id模块
+pub const CHAIN_ID_MAGIC: &[u8] = b"\xffchain:";
+
+/// 嵌入了标签的desc的前缀，后面是逗号分隔的标签和换行，在traceparent之后，见tags模块
+pub const TAGS_MAGIC: &[u8] = b"\xfftags:";
+
+/// 上链之前处理desc: 超过threshold字节时压缩并加上前缀，threshold为0时不压缩
+/// 压缩之后反而更大，或者没有开启zstd-desc feature时保持原样
+pub fn encode(desc: &[u8], threshold: usize) -> Result<Vec<u8>> {
+    if !cfg!(feature = "zstd-desc") || threshold == 0 || desc.len() <= threshold {
+        return Ok(desc.to_vec());
+    }
+    let compressed = compress(desc)?;
+    if compressed.len() + ZSTD_MAGIC.len() >= desc.len() {
+        return Ok(desc.to_vec());
+    }
+    let mut res = ZSTD_MAGIC.to_vec();
+    res.extend_from_slice(&compressed);
+    Ok(res)
+}
+
+/// 还原链上的desc，没有压缩前缀的原样返回，绑定的chain id、嵌入的traceparent和标签会被去掉
+/// 压缩过的desc需要开启zstd-desc feature才能还原，否则返回InvalidArguments
+pub fn decode(desc: &[u8]) -> Result<Vec<u8>> {
+    let desc = split_traceparent(desc)?.1;
+    let desc = split_tags(desc)?.1;
+    if !desc.starts_with(ZSTD_MAGIC) {
+        return Ok(desc.to_vec());
+    }
+    decompress(&desc[ZSTD_MAGIC.len()..])
+}
+
+/// 把desc分成绑定的chain id和剩下的部分
+pub fn split_chain_id(desc: &[u8]) -> Result<(Option<&str>, &[u8])> {
+    if !desc.starts_with(CHAIN_ID_MAGIC) {
+        return Ok((None, desc));
+    }
+    let rest = &desc[CHAIN_ID_MAGIC.len()..];
+    let end = rest
+        .iter()
+        .position(|b| *b == b'\n')
+        .ok_or(Error::from(ErrorKind::ParseError))?;
+    let chain_id =
+        std::str::from_utf8(&rest[..end]).map_err(|_| Error::from(ErrorKind::ParseError))?;
+    Ok((Some(chain_id), &rest[end + 1..]))
+}
+
+/// 把desc分成嵌入的traceparent和剩下的部分，绑定的chain id会被跳过
+pub fn split_traceparent(desc: &[u8]) -> Result<(Option<&[u8]>, &[u8])> {
+    let desc = split_chain_id(desc)?.1;
+    if !desc.starts_with(TRACE_MAGIC) {
+        return Ok((None, desc));
+    }
+    let rest = &desc[TRACE_MAGIC.len()..];
+    if rest.len() < TRACEPARENT_LEN {
+        return Err(Error::from(ErrorKind::ParseError));
+    }
+    Ok((Some(&rest[..TRACEPARENT_LEN]), &rest[TRACEPARENT_LEN..]))
+}
+
+/// 把去掉traceparent之后的desc分成嵌入的标签和剩下的部分
+pub fn split_tags(desc: &[u8]) -> Result<(Option<Vec<String>>, &[u8])> {
+    if !desc.starts_with(TAGS_MAGIC) {
+        return Ok((None, desc));
+    }
+    let rest = &desc[TAGS_MAGIC.len()..];
+    let end = rest
+        .iter()
+        .position(|b| *b == b'\n')
+        .ok_or(Error::from(ErrorKind::ParseError))?;
+    let tags = std::str::from_utf8(&rest[..end]).map_err(|_| Error::from(ErrorKind::ParseError))?;
+    Ok((
+        Some(tags.split(',').map(|t| t.to_owned()).collect()),
+        &rest[end + 1..],
+    ))
+}
+
+/// 查询到的交易的原始desc
+pub fn tx_desc(tx: &xchain::Transaction) -> Result<Vec<u8>> {
+    decode(tx.get_desc())
+}
+
+#[cfg(feature = "zstd-desc")]
+fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    Ok(zstd::stream::encode_all(data, ZSTD_LEVEL)?)
+}
+
+#[cfg(feature = "zstd-desc")]
+fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    Ok(zstd::stream::decode_all(data)?)
+}
+
+#[cfg(not(feature = "zstd-desc"))]
+fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    Ok(data.to_vec())
+}
+
+#[cfg(not(feature = "zstd-desc"))]
+fn decompress(_data: &[u8]) -> Result<Vec<u8>> {
+    Err(Error::new(
+        ErrorKind::InvalidArguments,
+        "compressed desc needs the zstd-desc feature",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_desc() {
+        let desc = b"test duanbing";
+        assert_eq!(encode(desc, 0).unwrap(), desc.to_vec());
+        assert_eq!(encode(desc, 1024).unwrap(), desc.to_vec());
+        assert_eq!(decode(desc).unwrap(), desc.to_vec());
+    }
+
+    #[cfg(not(feature = "zstd-desc"))]
+    #[test]
+    fn test_compress_disabled() {
+        let desc = "anchor:".repeat(200).into_bytes();
+        assert_eq!(encode(&desc, 64).unwrap(), desc);
+    }
+
+    #[cfg(feature = "zstd-desc")]
+    #[test]
+    fn test_compressed_desc() {
+        let desc = "anchor:".repeat(200).into_bytes();
+        let encoded = encode(&desc, 64).unwrap();
+        assert_eq!(encoded.starts_with(ZSTD_MAGIC), true);
+        assert_eq!(encoded.len() < desc.len(), true);
+        assert_eq!(decode(&encoded).unwrap(), desc);
+    }
+}
@@ -0,0 +1,607 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use crypto::digest::Digest;
+use crypto::sha3::Sha3;
+use num_bigint::{BigInt, Sign};
+use protobuf::Message;
+
+use crate::{config, contract, deploy_contract, wallet};
+use xchain_node_sdk::{errors::*, protos::xchain};
+
+// EVM合约(Solidity)的部署和调用，签名、背书和提交与WASM合约一样走Session。
+// 部署: 调用xkernel的Deploy，contract_code是solc输出的bin(hex解码之后)，contract_abi是abi json，
+//   init_args里input是构造函数参数的ABI编码，jsonEncoded为false。
+// 调用: module是evm，参数input是"4字节selector + 参数的ABI编码"，返回的body是返回值的ABI编码。
+// 这里的ABI编码只支持基本类型和一维变长数组(T[])，不支持tuple和定长数组(T[k])。
+// SDK的WasmCodeDesc没有contract_type字段(字段5)，部署时作为unknown field加上再编码。
+
+pub const EVM_MODULE: &str = "evm";
+
+const WORD: usize = 32;
+
+/// ABI参数类型
+#[derive(Debug, PartialEq, Clone)]
+pub enum ParamType {
+    Address,
+    /// 位数
+    Uint(usize),
+    Int(usize),
+    Bool,
+    Bytes,
+    /// bytes1到bytes32
+    FixedBytes(usize),
+    String,
+    /// T[]
+    Array(Box<ParamType>),
+}
+
+impl ParamType {
+    /// 解析solidity的类型名，uint、int等同uint256、int256
+    pub fn parse(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.ends_with("[]") {
+            return Ok(ParamType::Array(Box::new(Self::parse(&s[..s.len() - 2])?)));
+        }
+        let invalid = || {
+            Error::new(
+                ErrorKind::InvalidArguments,
+                format!("unsupported abi type {}", s),
+            )
+        };
+        let bits = |n: &str| -> Result<usize> {
+            if n.is_empty() {
+                return Ok(256);
+            }
+            match n.parse::<usize>() {
+                Ok(b) if b > 0 && b <= 256 && b % 8 == 0 => Ok(b),
+                _ => Err(invalid()),
+            }
+        };
+        match s {
+            "address" => Ok(ParamType::Address),
+            "bool" => Ok(ParamType::Bool),
+            "bytes" => Ok(ParamType::Bytes),
+            "string" => Ok(ParamType::String),
+            _ if s.starts_with("uint") => Ok(ParamType::Uint(bits(&s[4..])?)),
+            _ if s.starts_with("int") => Ok(ParamType::Int(bits(&s[3..])?)),
+            _ if s.starts_with("bytes") => match s[5..].parse::<usize>() {
+                Ok(n) if n > 0 && n <= WORD => Ok(ParamType::FixedBytes(n)),
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        }
+    }
+
+    fn is_dynamic(&self) -> bool {
+        match self {
+            ParamType::Bytes | ParamType::String | ParamType::Array(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for ParamType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParamType::Address => write!(f, "address"),
+            ParamType::Uint(bits) => write!(f, "uint{}", bits),
+            ParamType::Int(bits) => write!(f, "int{}", bits),
+            ParamType::Bool => write!(f, "bool"),
+            ParamType::Bytes => write!(f, "bytes"),
+            ParamType::FixedBytes(n) => write!(f, "bytes{}", n),
+            ParamType::String => write!(f, "string"),
+            ParamType::Array(t) => write!(f, "{}[]", t),
+        }
+    }
+}
+
+/// ABI参数值
+#[derive(Debug, PartialEq, Clone)]
+pub enum Token {
+    Address([u8; 20]),
+    Uint(BigInt),
+    Int(BigInt),
+    Bool(bool),
+    Bytes(Vec<u8>),
+    FixedBytes(Vec<u8>),
+    String(String),
+    Array(Vec<Token>),
+}
+
+impl Token {
+    /// hex编码的EVM地址，可以有0x前缀
+    pub fn address(s: &str) -> Result<Self> {
+        let b = hex::decode(s.trim_start_matches("0x"))?;
+        if b.len() != 20 {
+            return Err(Error::new(
+                ErrorKind::InvalidArguments,
+                format!("invalid evm address {}", s),
+            ));
+        }
+        let mut a = [0u8; 20];
+        a.copy_from_slice(&b);
+        Ok(Token::Address(a))
+    }
+
+    fn type_check(&self, t: &ParamType) -> bool {
+        match (self, t) {
+            (Token::Address(_), ParamType::Address)
+            | (Token::Bool(_), ParamType::Bool)
+            | (Token::Bytes(_), ParamType::Bytes)
+            | (Token::String(_), ParamType::String) => true,
+            // 数值要在类型的位数范围内，否则编码之后合约里的值会被截断
+            (Token::Uint(n), ParamType::Uint(bits)) => {
+                n.sign() != Sign::Minus && *n < (BigInt::from(1) << *bits)
+            }
+            (Token::Int(n), ParamType::Int(bits)) => {
+                let bound = BigInt::from(1) << (*bits - 1);
+                *n >= -bound.clone() && *n < bound
+            }
+            (Token::FixedBytes(b), ParamType::FixedBytes(n)) => b.len() == *n,
+            (Token::Array(tokens), ParamType::Array(t)) => tokens.iter().all(|v| v.type_check(t)),
+            _ => false,
+        }
+    }
+}
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha3::keccak256();
+    hasher.input(data);
+    let mut out = [0u8; 32];
+    hasher.result(&mut out);
+    out
+}
+
+/// 解析"transfer(address,uint256)"形式的方法签名
+pub fn parse_signature(signature: &str) -> Result<(String, Vec<ParamType>)> {
+    let invalid = || {
+        Error::new(
+            ErrorKind::InvalidArguments,
+            format!("invalid method signature {}", signature),
+        )
+    };
+    let signature = signature.trim();
+    let open = signature.find('(').ok_or_else(invalid)?;
+    if open == 0 || !signature.ends_with(')') {
+        return Err(invalid());
+    }
+    let params = &signature[open + 1..signature.len() - 1];
+    let types = if params.trim().is_empty() {
+        vec![]
+    } else {
+        params
+            .split(',')
+            .map(ParamType::parse)
+            .collect::<Result<Vec<_>>>()?
+    };
+    Ok((signature[..open].to_owned(), types))
+}
+
+/// 方法签名的4字节selector，签名里的类型先转成规范形式(uint -> uint256)
+pub fn selector(name: &str, types: &[ParamType]) -> [u8; 4] {
+    let types: Vec<String> = types.iter().map(|t| t.to_string()).collect();
+    let hash = keccak256(format!("{}({})", name, types.join(",")).as_bytes());
+    let mut s = [0u8; 4];
+    s.copy_from_slice(&hash[..4]);
+    s
+}
+
+/// 调用方法的input: selector + 参数的ABI编码，参数和签名不一致时返回InvalidArguments
+pub fn encode_call(signature: &str, tokens: &[Token]) -> Result<Vec<u8>> {
+    let (name, types) = parse_signature(signature)?;
+    check_tokens(&types, tokens)?;
+    let mut input = selector(&name, &types).to_vec();
+    input.extend(encode(tokens)?);
+    Ok(input)
+}
+
+fn check_tokens(types: &[ParamType], tokens: &[Token]) -> Result<()> {
+    if types.len() != tokens.len() || !tokens.iter().zip(types).all(|(v, t)| v.type_check(t)) {
+        return Err(Error::new(
+            ErrorKind::InvalidArguments,
+            "arguments do not match the method signature",
+        ));
+    }
+    Ok(())
+}
+
+fn word_usize(n: usize) -> Vec<u8> {
+    let mut w = vec![0u8; WORD];
+    w[WORD - 8..].copy_from_slice(&(n as u64).to_be_bytes());
+    w
+}
+
+/// 右边补0到32字节的整数倍
+fn pad_right(b: &[u8]) -> Vec<u8> {
+    let mut v = b.to_vec();
+    v.resize((b.len() + WORD - 1) / WORD * WORD, 0);
+    v
+}
+
+fn pad_left(b: &[u8], fill: u8) -> Result<Vec<u8>> {
+    if b.len() > WORD {
+        return Err(Error::new(
+            ErrorKind::InvalidArguments,
+            "integer overflows 256 bits",
+        ));
+    }
+    let mut w = vec![fill; WORD - b.len()];
+    w.extend_from_slice(b);
+    Ok(w)
+}
+
+/// 参数的ABI编码(不含selector)
+pub fn encode(tokens: &[Token]) -> Result<Vec<u8>> {
+    let mut head = vec![];
+    let mut tail = vec![];
+    for token in tokens {
+        match token {
+            Token::Bytes(_) | Token::String(_) | Token::Array(_) => {
+                head.extend(word_usize(WORD * tokens.len() + tail.len()));
+                tail.extend(encode_token(token)?);
+            }
+            _ => head.extend(encode_token(token)?),
+        }
+    }
+    head.extend(tail);
+    Ok(head)
+}
+
+fn encode_token(token: &Token) -> Result<Vec<u8>> {
+    match token {
+        Token::Address(a) => pad_left(a, 0),
+        Token::Uint(n) => {
+            if n.sign() == Sign::Minus {
+                return Err(Error::new(ErrorKind::InvalidArguments, "negative uint"));
+            }
+            pad_left(&n.to_bytes_be().1, 0)
+        }
+        Token::Int(n) => {
+            let fill = if n.sign() == Sign::Minus { 0xff } else { 0 };
+            pad_left(&n.to_signed_bytes_be(), fill)
+        }
+        Token::Bool(b) => Ok(word_usize(*b as usize)),
+        Token::FixedBytes(b) => {
+            if b.is_empty() || b.len() > WORD {
+                return Err(Error::new(
+                    ErrorKind::InvalidArguments,
+                    "invalid fixed bytes",
+                ));
+            }
+            Ok(pad_right(b))
+        }
+        Token::Bytes(b) => {
+            let mut v = word_usize(b.len());
+            v.extend(pad_right(b));
+            Ok(v)
+        }
+        Token::String(s) => encode_token(&Token::Bytes(s.as_bytes().to_vec())),
+        Token::Array(tokens) => {
+            let mut v = word_usize(tokens.len());
+            v.extend(encode(tokens)?);
+            Ok(v)
+        }
+    }
+}
+
+/// 按types解码返回值的ABI编码
+pub fn decode(types: &[ParamType], data: &[u8]) -> Result<Vec<Token>> {
+    types
+        .iter()
+        .enumerate()
+        .map(|(i, t)| {
+            let w = word_at(data, WORD * i)?;
+            if t.is_dynamic() {
+                decode_dynamic(t, data, as_usize(w)?)
+            } else {
+                Ok(decode_static(t, w))
+            }
+        })
+        .collect()
+}
+
+fn malformed() -> Error {
+    Error::new(ErrorKind::ParseError, "malformed abi data")
+}
+
+fn word_at(data: &[u8], offset: usize) -> Result<&[u8]> {
+    let end = offset.checked_add(WORD).ok_or_else(malformed)?;
+    data.get(offset..end).ok_or_else(malformed)
+}
+
+fn as_usize(w: &[u8]) -> Result<usize> {
+    if w[..WORD - 8].iter().any(|b| *b != 0) {
+        return Err(malformed());
+    }
+    let mut b = [0u8; 8];
+    b.copy_from_slice(&w[WORD - 8..]);
+    usize::try_from(u64::from_be_bytes(b)).map_err(|_| malformed())
+}
+
+fn decode_static(t: &ParamType, w: &[u8]) -> Token {
+    match t {
+        ParamType::Address => {
+            let mut a = [0u8; 20];
+            a.copy_from_slice(&w[WORD - 20..]);
+            Token::Address(a)
+        }
+        ParamType::Uint(_) => Token::Uint(BigInt::from_bytes_be(Sign::Plus, w)),
+        ParamType::Int(_) => Token::Int(BigInt::from_signed_bytes_be(w)),
+        ParamType::Bool => Token::Bool(w.iter().any(|b| *b != 0)),
+        ParamType::FixedBytes(n) => Token::FixedBytes(w[..*n].to_vec()),
+        _ => unreachable!(),
+    }
+}
+
+fn decode_dynamic(t: &ParamType, data: &[u8], offset: usize) -> Result<Token> {
+    let len = as_usize(word_at(data, offset)?)?;
+    let start = offset + WORD;
+    let body = data.get(start..).ok_or_else(malformed)?;
+    match t {
+        ParamType::Bytes | ParamType::String => {
+            let b = body.get(..len).ok_or_else(malformed)?.to_vec();
+            if *t == ParamType::Bytes {
+                return Ok(Token::Bytes(b));
+            }
+            String::from_utf8(b)
+                .map(Token::String)
+                .map_err(|e| Error::new(ErrorKind::ParseError, e))
+        }
+        ParamType::Array(inner) => {
+            // 每个元素至少占一个word，避免按错误的长度分配内存
+            if len > body.len() / WORD {
+                return Err(malformed());
+            }
+            decode(&vec![(**inner).clone(); len], body).map(Token::Array)
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// WasmCodeDesc{contract_type: "evm"}的protobuf编码
+fn evm_code_desc() -> Result<Vec<u8>> {
+    let mut desc = xchain::WasmCodeDesc::new();
+    desc.mut_unknown_fields()
+        .add_length_delimited(5, EVM_MODULE.as_bytes().to_vec());
+    desc.write_to_bytes()
+        .map_err(|_| Error::from(ErrorKind::ParseError))
+}
+
+/// 生成部署EVM合约的调用请求，bin是solc输出的hex，abi是abi json，args是构造函数的参数
+pub fn build_evm_deploy_request(
+    account: &wallet::Account,
+    contract_name: &String,
+    bin: &str,
+    abi: &str,
+    args: &[Token],
+) -> Result<xchain::InvokeRequest> {
+    if account.contract_account.is_empty() || contract_name.is_empty() || abi.is_empty() {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let code = hex::decode(bin.trim().trim_start_matches("0x"))?;
+    if code.is_empty() {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let mut init_args = HashMap::new();
+    init_args.insert("input", base64::encode(&encode(args)?));
+    init_args.insert("jsonEncoded", base64::encode("false"));
+
+    let mut a = HashMap::new();
+    a.insert(
+        String::from("account_name"),
+        account.contract_account.to_owned().into_bytes(),
+    );
+    a.insert(
+        String::from("contract_name"),
+        contract_name.to_owned().into_bytes(),
+    );
+    a.insert(String::from("contract_code"), code);
+    a.insert(String::from("contract_desc"), evm_code_desc()?);
+    a.insert(String::from("contract_abi"), abi.as_bytes().to_vec());
+    a.insert(String::from("init_args"), serde_json::to_vec(&init_args)?);
+
+    let mut invoke_req = xchain::InvokeRequest::new();
+    invoke_req.set_module_name(contract::KERNEL_MODULE.to_owned());
+    invoke_req.set_method_name(String::from("Deploy"));
+    invoke_req.set_args(a);
+    Ok(invoke_req)
+}
+
+/// account用合约账户部署EVM合约contract_name，返回txid，fee同deploy_contract
+pub fn deploy_evm_contract(
+    account: &wallet::Account,
+    chain_name: &String,
+    contract_name: &String,
+    bin: &str,
+    abi: &str,
+    args: &[Token],
+    fee: &String,
+) -> Result<String> {
+    let invoke_req = build_evm_deploy_request(account, contract_name, bin, abi, args)?;
+    deploy_contract::deploy(
+        &config::ClientConfig::global(),
+        account,
+        chain_name,
+        invoke_req,
+        fee,
+    )
+}
+
+/// 调用EVM合约的方法，signature形如"transfer(address,uint256)"，
+/// 返回txid和按outputs解码的返回值，fee同invoke_contract
+pub fn invoke_evm_contract(
+    account: &wallet::Account,
+    chain_name: &String,
+    contract_name: &String,
+    signature: &str,
+    args: &[Token],
+    outputs: &[ParamType],
+    fee: &String,
+) -> Result<(String, Vec<Token>)> {
+    let (method_name, _) = parse_signature(signature)?;
+    let mut a = HashMap::new();
+    a.insert(String::from("input"), encode_call(signature, args)?);
+    a.insert(String::from("jsonEncoded"), b"false".to_vec());
+    let (txid, body) = contract::do_invoke_contract(
+        &config::ClientConfig::global(),
+        account,
+        EVM_MODULE,
+        chain_name,
+        contract_name,
+        &method_name,
+        a,
+        fee,
+        &[],
+        None,
+    )?;
+    Ok((txid, decode(outputs, &body)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_signature() {
+        let (name, types) = parse_signature("transfer(address, uint)").unwrap();
+        assert_eq!(name, "transfer");
+        assert_eq!(types, vec![ParamType::Address, ParamType::Uint(256)]);
+        assert_eq!(parse_signature("totalSupply()").unwrap().1, vec![]);
+        assert_eq!(
+            parse_signature("f(bytes32,int8[],string)").unwrap().1,
+            vec![
+                ParamType::FixedBytes(32),
+                ParamType::Array(Box::new(ParamType::Int(8))),
+                ParamType::String
+            ]
+        );
+        assert_eq!(parse_signature("f(uint7)").is_err(), true);
+        assert_eq!(parse_signature("f(bytes33)").is_err(), true);
+        assert_eq!(parse_signature("(uint256)").is_err(), true);
+        assert_eq!(parse_signature("f").is_err(), true);
+    }
+
+    #[test]
+    fn test_encode_call() {
+        // 和solidity的transfer(address,uint256)一致
+        let input = encode_call(
+            "transfer(address,uint)",
+            &[
+                Token::address("0x00000000000000000000000000000000000000aa").unwrap(),
+                Token::Uint(BigInt::from(1000)),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            hex::encode(&input),
+            "a9059cbb\
+             00000000000000000000000000000000000000000000000000000000000000aa\
+             00000000000000000000000000000000000000000000000000000000000003e8"
+        );
+        assert_eq!(
+            encode_call("transfer(address,uint256)", &[Token::Bool(true)]).is_err(),
+            true
+        );
+        assert_eq!(
+            encode_call("f(uint256)", &[Token::Uint(BigInt::from(-1))]).is_err(),
+            true
+        );
+        // 超出位数范围
+        assert_eq!(
+            encode_call("f(uint8)", &[Token::Uint(BigInt::from(300))]).is_err(),
+            true
+        );
+        assert_eq!(
+            encode_call("f(uint8)", &[Token::Uint(BigInt::from(255))]).is_ok(),
+            true
+        );
+        assert_eq!(
+            encode_call("f(int8)", &[Token::Int(BigInt::from(-129))]).is_err(),
+            true
+        );
+        assert_eq!(
+            encode_call("f(int8)", &[Token::Int(BigInt::from(128))]).is_err(),
+            true
+        );
+        assert_eq!(
+            encode_call("f(int8)", &[Token::Int(BigInt::from(-128))]).is_ok(),
+            true
+        );
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let tokens = vec![
+            Token::Int(BigInt::from(-2)),
+            Token::String(String::from("hello")),
+            Token::Array(vec![
+                Token::Uint(BigInt::from(1)),
+                Token::Uint(BigInt::from(2)),
+            ]),
+            Token::FixedBytes(vec![0xab; 4]),
+            Token::Bool(true),
+        ];
+        let data = encode(&tokens).unwrap();
+        // 5个head，string占2个word，数组占3个word
+        assert_eq!(data.len(), 32 * 10);
+        let mut minus_two = vec![0xff; 32];
+        minus_two[31] = 0xfe;
+        assert_eq!(&data[..32], &minus_two[..]);
+        let types = vec![
+            ParamType::Int(256),
+            ParamType::String,
+            ParamType::Array(Box::new(ParamType::Uint(256))),
+            ParamType::FixedBytes(4),
+            ParamType::Bool,
+        ];
+        assert_eq!(decode(&types, &data).unwrap(), tokens);
+
+        assert_eq!(decode(&types, &data[..64]).is_err(), true);
+        // 数组长度超出数据
+        let mut bad = encode(&[Token::Array(vec![])]).unwrap();
+        bad[63] = 0xff;
+        assert_eq!(
+            decode(&[ParamType::Array(Box::new(ParamType::Bool))], &bad)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::ParseError
+        );
+    }
+
+    #[test]
+    fn test_build_evm_deploy_request() {
+        let acc = wallet::Account {
+            address: String::from("alice"),
+            contract_account: String::from("XC1111111111000000@xuper"),
+            ..Default::default()
+        };
+        let name = String::from("token");
+        let req =
+            build_evm_deploy_request(&acc, &name, "0x6080", "[]", &[Token::Uint(BigInt::from(1))])
+                .unwrap();
+        assert_eq!(req.module_name, "xkernel");
+        assert_eq!(req.method_name, "Deploy");
+        let args = req.get_args();
+        assert_eq!(args["contract_code"], vec![0x60, 0x80]);
+        assert_eq!(args["contract_desc"], b"\x2a\x03evm".to_vec());
+        let desc: xchain::WasmCodeDesc =
+            protobuf::parse_from_bytes(&args["contract_desc"]).unwrap();
+        assert_eq!(
+            desc.get_unknown_fields().get(5).unwrap().length_delimited,
+            vec![EVM_MODULE.as_bytes().to_vec()]
+        );
+        let init_args: HashMap<String, String> =
+            serde_json::from_slice(&args["init_args"]).unwrap();
+        assert_eq!(base64::decode(&init_args["input"]).unwrap(), word_usize(1));
+        assert_eq!(
+            build_evm_deploy_request(&acc, &name, "zz", "[]", &[]).is_err(),
+            true
+        );
+        let acc = wallet::Account::default();
+        assert_eq!(
+            build_evm_deploy_request(&acc, &name, "6080", "[]", &[]).is_err(),
+            true
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::{consts, transfer, wallet};
+use xchain_node_sdk::errors::*;
+
+// 测试网络的水龙头: CI和开发环境自己领取测试币。
+// 币的来源可以是本地有余额的账户，也可以是外部的水龙头服务(SDK不带http客户端，由调用方提供请求函数)。
+
+/// 请求外部水龙头服务，参数是(地址, 金额)，返回txid
+pub type Requester = Box<dyn Fn(&String, &String) -> Result<String> + Send + Sync>;
+
+/// 同一个地址两次领取的默认最小间隔
+pub const DEFAULT_DRIP_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct Faucet {
+    requester: Requester,
+    max_amount: num_bigint::BigInt,
+    interval: Duration,
+    last_drip: Mutex<HashMap<String, Instant>>,
+}
+
+impl Faucet {
+    /// 从外部水龙头服务领取
+    pub fn new(requester: Requester, max_amount: &String, interval: Duration) -> Result<Self> {
+        Ok(Faucet {
+            requester: requester,
+            max_amount: consts::str_as_bigint(max_amount)?,
+            interval: interval,
+            last_drip: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// 从本地有余额的账户转账
+    pub fn from_account(
+        account: wallet::Account,
+        chain_name: &String,
+        max_amount: &String,
+        interval: Duration,
+    ) -> Result<Self> {
+        let chain_name = chain_name.to_owned();
+        let requester: Requester = Box::new(move |to, amount| {
+            transfer::transfer(
+                &account,
+                &chain_name,
+                to,
+                amount,
+                &String::from("0"),
+                &String::from("faucet"),
+            )
+        });
+        Self::new(requester, max_amount, interval)
+    }
+
+    /// 给address领取amount，单次不能超过max_amount，同一个地址interval内只能领取一次
+    pub fn drip(&self, address: &String, amount: &String) -> Result<String> {
+        let am = consts::str_as_bigint(amount)?;
+        if am <= num_traits::Zero::zero() || am > self.max_amount {
+            return Err(Error::from(ErrorKind::InvalidArguments));
+        }
+        {
+            let mut last_drip = self.last_drip.lock().unwrap();
+            if let Some(t) = last_drip.get(address) {
+                if t.elapsed() < self.interval {
+                    return Err(Error::from(ErrorKind::InvalidArguments));
+                }
+            }
+            // 先占住，避免并发的请求都通过检查
+            last_drip.insert(address.to_owned(), Instant::now());
+        }
+        let res = (self.requester)(address, amount);
+        if res.is_err() {
+            // 没有领到，允许马上重试
+            self.last_drip.lock().unwrap().remove(address);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drip_rate_limit() {
+        let faucet = Faucet::new(
+            Box::new(|to, amount| Ok(format!("{}:{}", to, amount))),
+            &String::from("100"),
+            Duration::from_millis(50),
+        )
+        .unwrap();
+        let alice = String::from("alice");
+        let bob = String::from("bob");
+
+        assert_eq!(faucet.drip(&alice, &String::from("200")).is_ok(), false);
+        assert_eq!(faucet.drip(&alice, &String::from("0")).is_ok(), false);
+        assert_eq!(
+            faucet.drip(&alice, &String::from("100")).unwrap(),
+            "alice:100"
+        );
+        assert_eq!(faucet.drip(&alice, &String::from("10")).is_ok(), false);
+        assert_eq!(faucet.drip(&bob, &String::from("10")).is_ok(), true);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(faucet.drip(&alice, &String::from("10")).is_ok(), true);
+
+        let failing = Faucet::new(
+            Box::new(|_, _| Err(Error::from(ErrorKind::TransportError))),
+            &String::from("100"),
+            Duration::from_secs(60),
+        )
+        .unwrap();
+        assert_eq!(failing.drip(&alice, &String::from("10")).is_ok(), false);
+        // 失败不占用领取次数
+        assert_eq!(
+            failing
+                .drip(&alice, &String::from("10"))
+                .unwrap_err()
+                .kind(),
+            ErrorKind::TransportError
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use num_bigint::BigInt;
+
+use crate::{config, consts};
+use xchain_node_sdk::{errors::*, protos::xchain};
+
+// 开启了手续费市场的链上，手续费不够的交易被节点拒绝(TX_FEE_NOT_ENOUGH_ERROR、GAS_NOT_ENOUGH_ERROR)。
+// 配置了feeBump时依次按原手续费的schedulePercent%重新预执行、生成并提交交易，
+// 直到成功、因为其他原因失败、用完schedulePercent或者到达maxFee。
+// 被拒绝的交易没有上链，每次重新提交都是新的交易，txid不同。
+
+/// 节点因为手续费不够拒绝了交易
+pub fn is_fee_rejection(e: &Error) -> bool {
+    match e.rpc() {
+        Some(r) => {
+            r.error == xchain::XChainErrorEnum::TX_FEE_NOT_ENOUGH_ERROR
+                || r.error == xchain::XChainErrorEnum::GAS_NOT_ENOUGH_ERROR
+        }
+        None => false,
+    }
+}
+
+/// 原手续费是fee时依次重新提交的手续费，递增，不超过maxFee
+pub fn schedule(c: &config::FeeBumpConfig, fee: &str) -> Result<Vec<BigInt>> {
+    let fee = consts::str_as_bigint(fee)?;
+    let max_fee = consts::str_as_bigint(&c.max_fee)?;
+    let mut fees: Vec<BigInt> = vec![];
+    for percent in c.schedule_percent.iter() {
+        let bumped = std::cmp::min(&fee * *percent / 100u32, max_fee.clone());
+        if bumped <= *fees.last().unwrap_or(&fee) {
+            continue;
+        }
+        fees.push(bumped);
+    }
+    Ok(fees)
+}
+
+/// 用fee调用post，手续费不够时按config.fee_bump提高手续费再调用，返回最后一次的结果
+pub fn with_fee_bump<F>(config: &config::ClientConfig, fee: &String, mut post: F) -> Result<String>
+where
+    F: FnMut(&String) -> Result<String>,
+{
+    let mut res = post(fee);
+    let c = match config.fee_bump {
+        Some(ref c) => c,
+        None => return res,
+    };
+    for bumped in schedule(c, fee)? {
+        match res {
+            Err(ref e) if is_fee_rejection(e) => {}
+            _ => break,
+        }
+        tracing::info!(fee = %bumped, "fee not enough, resubmitting with bumped fee");
+        res = post(&bumped.to_str_radix(10));
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fee_bump(schedule_percent: Vec<u32>, max_fee: &str) -> config::FeeBumpConfig {
+        config::FeeBumpConfig {
+            schedule_percent,
+            max_fee: max_fee.to_owned(),
+        }
+    }
+
+    fn rejected(error: xchain::XChainErrorEnum) -> Error {
+        let mut header = xchain::Header::new();
+        header.set_error(error);
+        RpcError::check(&header).unwrap_err()
+    }
+
+    #[test]
+    fn test_schedule() {
+        let c = fee_bump(vec![150, 200, 400, 800], "350");
+        assert_eq!(
+            schedule(&c, "100").unwrap(),
+            vec![BigInt::from(150), BigInt::from(200), BigInt::from(350)]
+        );
+        // 不提高的比例跳过
+        let c = fee_bump(vec![100, 50, 120], "1000");
+        assert_eq!(schedule(&c, "100").unwrap(), vec![BigInt::from(120)]);
+        assert_eq!(schedule(&c, "0").unwrap().is_empty(), true);
+        assert_eq!(schedule(&c, "x").is_err(), true);
+    }
+
+    #[test]
+    fn test_with_fee_bump() {
+        let mut c = (*config::ClientConfig::global()).clone();
+        c.fee_bump = Some(fee_bump(vec![200, 400], "1000"));
+        let config = config::ClientConfig::new(c);
+
+        let mut fees = vec![];
+        let res = with_fee_bump(&config, &String::from("100"), |fee| {
+            fees.push(fee.to_owned());
+            if fee == "400" {
+                Ok(String::from("txid"))
+            } else {
+                Err(rejected(xchain::XChainErrorEnum::TX_FEE_NOT_ENOUGH_ERROR))
+            }
+        });
+        assert_eq!(res.unwrap(), "txid");
+        assert_eq!(fees, vec!["100", "200", "400"]);
+
+        // 其他原因失败不重新提交
+        let mut calls = 0;
+        let res = with_fee_bump(&config, &String::from("100"), |_| {
+            calls += 1;
+            Err(rejected(xchain::XChainErrorEnum::TX_SIGN_ERROR))
+        });
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::ChainRPCError);
+        assert_eq!(calls, 1);
+
+        // 用完schedule之后返回最后一次的错误
+        let mut calls = 0;
+        let res = with_fee_bump(&config, &String::from("100"), |_| {
+            calls += 1;
+            Err(rejected(xchain::XChainErrorEnum::GAS_NOT_ENOUGH_ERROR))
+        });
+        assert_eq!(is_fee_rejection(&res.unwrap_err()), true);
+        assert_eq!(calls, 3);
+
+        // 没有配置feeBump
+        let mut calls = 0;
+        let res = with_fee_bump(
+            &config::ClientConfig::global(),
+            &String::from("100"),
+            |_| {
+                calls += 1;
+                Err(rejected(xchain::XChainErrorEnum::TX_FEE_NOT_ENOUGH_ERROR))
+            },
+        );
+        assert_eq!(res.is_err(), true);
+        assert_eq!(calls, 1);
+    }
+}
@@ -0,0 +1,108 @@
+use std::ops::Range;
+
+use xchain_node_sdk::{errors::*, ocall, protos::xchain};
+
+// 按发起者查询交易: 节点没有按发起者的索引，逐个查询高度区间内主干上的区块再过滤，
+// 区间不宜过大。
+
+/// 合约账户形如XC1111111111000000@xuper
+pub fn is_contract_account(initiator: &str) -> bool {
+    initiator.starts_with("XC") && initiator.contains('@')
+}
+
+/// 交易是否由initiator发起或者授权:
+/// AK地址匹配交易的initiator；合约账户还匹配auth_require里的"合约账户/AK"
+pub fn is_initiated_by(tx: &xchain::Transaction, initiator: &str) -> bool {
+    if tx.initiator == initiator {
+        return true;
+    }
+    if !is_contract_account(initiator) {
+        return false;
+    }
+    let prefix = format!("{}/", initiator);
+    tx.get_auth_require().iter().any(|a| a.starts_with(&prefix))
+}
+
+/// 区块里由initiator发起或者授权的交易，不在主干上的区块返回空
+pub fn block_txs_by_initiator(
+    block: &xchain::Block,
+    initiator: &str,
+) -> Result<Vec<xchain::Transaction>> {
+    if block.status == xchain::Block_EBlockStatus::NOEXIST || block.block.is_none() {
+        return Err(Error::from(ErrorKind::ChainRPCError));
+    }
+    if block.status != xchain::Block_EBlockStatus::TRUNK {
+        return Ok(vec![]);
+    }
+    Ok(block
+        .get_block()
+        .get_transactions()
+        .iter()
+        .filter(|tx| !tx.coinbase && is_initiated_by(tx, initiator))
+        .cloned()
+        .collect())
+}
+
+/// 查询高度在range内(左闭右开)由initiator发起或者授权的交易，按高度排序
+/// initiator可以是AK地址，也可以是合约账户
+pub fn query_txs_by_initiator(
+    initiator: &str,
+    range: Range<i64>,
+) -> Result<Vec<xchain::Transaction>> {
+    if initiator.is_empty() || range.start < 0 {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let mut txs = vec![];
+    for height in range {
+        let block = ocall::ocall_xchain_get_block_by_height(height)?;
+        txs.extend(block_txs_by_initiator(&block, initiator)?);
+    }
+    Ok(txs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(initiator: &str, auth_require: &[&str]) -> xchain::Transaction {
+        let mut t = xchain::Transaction::new();
+        t.set_initiator(String::from(initiator));
+        t.set_auth_require(protobuf::RepeatedField::from_vec(
+            auth_require.iter().map(|a| a.to_string()).collect(),
+        ));
+        t
+    }
+
+    #[test]
+    fn test_block_txs_by_initiator() {
+        let account = "XC1111111111000000@xuper";
+        let mut block = xchain::Block::new();
+        block.set_status(xchain::Block_EBlockStatus::TRUNK);
+        let txs = block.mut_block().mut_transactions();
+        txs.push(tx("alice", &[]));
+        txs.push(tx("bob", &["XC1111111111000000@xuper/bob", "endorser"]));
+        txs.push(tx("carol", &["XC2222222222000000@xuper/carol"]));
+        let mut coinbase = tx("alice", &[]);
+        coinbase.set_coinbase(true);
+        txs.push(coinbase);
+
+        let res = block_txs_by_initiator(&block, "alice").unwrap();
+        assert_eq!(res.len(), 1);
+        // 只匹配合约账户本身，不匹配"合约账户/AK"
+        assert_eq!(
+            block_txs_by_initiator(&block, "XC1111111111000000@xuper/bob")
+                .unwrap()
+                .len(),
+            0
+        );
+        let res = block_txs_by_initiator(&block, account).unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].initiator, "bob");
+
+        // 分叉上的区块
+        block.set_status(xchain::Block_EBlockStatus::BRANCH);
+        assert_eq!(block_txs_by_initiator(&block, "alice").unwrap().len(), 0);
+        block.set_status(xchain::Block_EBlockStatus::NOEXIST);
+        assert_eq!(block_txs_by_initiator(&block, "alice").is_ok(), false);
+    }
+}
@@ -0,0 +1,283 @@
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::{AeadInPlace, NewAead};
+use aes_gcm::Aes256Gcm;
+use serde::{Deserialize, Serialize};
+
+use crate::wallet;
+use xchain_node_sdk::errors::*;
+
+// 用密码加密的私钥文件: 用scrypt从密码推导256位的密钥，AES-256-GCM加密Go SDK格式的private.key，
+// 地址作为附加数据参与认证，密码错误或者文件被篡改都会解密失败(CryptoError)。
+// 用Account::from_keystore加载，convert_plaintext_key把已有的明文私钥转换成加密格式。
+
+pub const VERSION: u32 = 1;
+pub const KDF: &str = "scrypt";
+pub const CIPHER: &str = "aes-256-gcm";
+
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// 加载文件时接受的scrypt参数上限，超出时返回InvalidArguments，避免构造的文件耗尽内存
+pub const MAX_LOG_N: u8 = 20;
+pub const MAX_R_P: u64 = 256;
+/// scrypt需要128 * r * N字节内存
+pub const MAX_MEMORY: u64 = 1 << 30;
+
+/// scrypt的参数，N = 2^log_n
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+impl Default for KdfParams {
+    /// 大约需要32MB内存
+    fn default() -> Self {
+        KdfParams {
+            log_n: 15,
+            r: 8,
+            p: 1,
+        }
+    }
+}
+
+/// 加密的私钥文件的内容，二进制字段都是hex编码
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Keystore {
+    pub version: u32,
+    pub address: String,
+    pub kdf: String,
+    pub kdf_params: KdfParams,
+    pub salt: String,
+    pub cipher: String,
+    pub nonce: String,
+    pub ciphertext: String,
+    pub tag: String,
+}
+
+impl KdfParams {
+    /// 参数在上限之内，并且满足scrypt::ScryptParams的要求(log_n < 16 * r)
+    pub fn check(&self) -> Result<()> {
+        let (r, p) = (self.r as u64, self.p as u64);
+        if self.log_n == 0
+            || self.log_n > MAX_LOG_N
+            || r == 0
+            || p == 0
+            || r * p > MAX_R_P
+            || self.log_n as u64 >= 16 * r
+            || 128 * r * (1u64 << self.log_n) > MAX_MEMORY
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidArguments,
+                format!("scrypt parameters out of range: {:?}", self),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn derive_key(password: &str, salt: &[u8], params: &KdfParams) -> Result<Vec<u8>> {
+    params.check()?;
+    let scrypt_params = scrypt::ScryptParams::new(params.log_n, params.r, params.p)
+        .map_err(|_| Error::from(ErrorKind::InvalidArguments))?;
+    let mut key = vec![0u8; KEY_LEN];
+    scrypt::scrypt(password.as_bytes(), salt, &scrypt_params, &mut key)
+        .map_err(|_| Error::from(ErrorKind::CryptoError))?;
+    Ok(key)
+}
+
+fn aes_gcm(key: &[u8]) -> Aes256Gcm {
+    Aes256Gcm::new(GenericArray::from_slice(key))
+}
+
+/// 加密json格式的私钥，address是私钥对应的地址
+pub fn encrypt_key(
+    private_key: &str,
+    address: &str,
+    password: &str,
+    params: KdfParams,
+) -> Result<Keystore> {
+    let salt = wallet::random_bytes(SALT_LEN)?;
+    let nonce = wallet::random_bytes(NONCE_LEN)?;
+    let key = derive_key(password, &salt, &params)?;
+    let mut ciphertext = private_key.as_bytes().to_vec();
+    let tag = aes_gcm(&key)
+        .encrypt_in_place_detached(
+            GenericArray::from_slice(&nonce),
+            address.as_bytes(),
+            &mut ciphertext,
+        )
+        .map_err(|_| Error::from(ErrorKind::CryptoError))?;
+    Ok(Keystore {
+        version: VERSION,
+        address: address.to_owned(),
+        kdf: String::from(KDF),
+        kdf_params: params,
+        salt: hex::encode(salt),
+        cipher: String::from(CIPHER),
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(ciphertext),
+        tag: hex::encode(tag),
+    })
+}
+
+/// 解密出json格式的私钥，密码错误或者内容被篡改时返回CryptoError
+pub fn decrypt_key(keystore: &Keystore, password: &str) -> Result<String> {
+    if keystore.version != VERSION || keystore.kdf != KDF || keystore.cipher != CIPHER {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let nonce = hex::decode(&keystore.nonce)?;
+    let tag = hex::decode(&keystore.tag)?;
+    if nonce.len() != NONCE_LEN || tag.len() != TAG_LEN {
+        return Err(Error::from(ErrorKind::ParseError));
+    }
+    let key = derive_key(
+        password,
+        &hex::decode(&keystore.salt)?,
+        &keystore.kdf_params,
+    )?;
+    let mut plaintext = hex::decode(&keystore.ciphertext)?;
+    aes_gcm(&key)
+        .decrypt_in_place_detached(
+            GenericArray::from_slice(&nonce),
+            keystore.address.as_bytes(),
+            &mut plaintext,
+            GenericArray::from_slice(&tag),
+        )
+        .map_err(|_| Error::from(ErrorKind::CryptoError))?;
+    String::from_utf8(plaintext).map_err(|_| Error::from(ErrorKind::CryptoError))
+}
+
+pub fn read_keystore(path: &str) -> Result<Keystore> {
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+pub fn write_keystore(path: &str, keystore: &Keystore) -> Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(keystore)?)?;
+    Ok(())
+}
+
+/// 把明文的private.key加密写入keystore_path，不删除明文文件，返回地址
+pub fn convert_plaintext_key(
+    private_key_path: &str,
+    keystore_path: &str,
+    password: &str,
+    params: KdfParams,
+) -> Result<String> {
+    let private_key = std::fs::read_to_string(private_key_path)?;
+    let address = wallet::address_from_private_key(&private_key)?;
+    let keystore = encrypt_key(&private_key, &address, password, params)?;
+    write_keystore(keystore_path, &keystore)?;
+    Ok(address)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const TEST_PARAMS: KdfParams = KdfParams {
+        log_n: 10,
+        r: 8,
+        p: 1,
+    };
+
+    #[test]
+    fn test_keystore() {
+        let key_path = crate::testkit::test_key_path();
+        let plain = wallet::Account::new(key_path.to_str().unwrap(), "", "");
+
+        let path = std::env::temp_dir().join("xchain-sdk-test-keystore.json");
+        let address = convert_plaintext_key(
+            key_path.to_str().unwrap(),
+            path.to_str().unwrap(),
+            "secret",
+            TEST_PARAMS,
+        )
+        .unwrap();
+        assert_eq!(address, plain.address);
+
+        let keystore = read_keystore(path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            decrypt_key(&keystore, "secret").unwrap(),
+            std::fs::read_to_string(&key_path).unwrap()
+        );
+        assert_eq!(
+            decrypt_key(&keystore, "wrong").unwrap_err().kind(),
+            ErrorKind::CryptoError
+        );
+
+        let mut tampered = keystore.clone();
+        tampered.address = String::from("someone_else");
+        assert_eq!(decrypt_key(&tampered, "secret").is_ok(), false);
+
+        let acc = wallet::Account::from_keystore(path.to_str().unwrap(), "secret", "", "").unwrap();
+        assert_eq!(acc.address, plain.address);
+        let sig = acc.sign(b"msg").unwrap();
+        assert_eq!(plain.verify(b"msg", &sig).is_ok(), true);
+        assert_eq!(
+            wallet::Account::from_keystore(path.to_str().unwrap(), "wrong", "", "").is_ok(),
+            false
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_decrypt_existing_keystore() {
+        // 之前版本写入的文件仍然可以解密
+        let keystore = Keystore {
+            version: VERSION,
+            address: String::from("test_address"),
+            kdf: String::from(KDF),
+            kdf_params: TEST_PARAMS,
+            salt: String::from("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"),
+            cipher: String::from(CIPHER),
+            nonce: String::from("000102030405060708090a0b"),
+            ciphertext: String::from("bfc8699d24fd545ed88e4002898c6f81dba0f5d2"),
+            tag: String::from("067e0f3c877c1110eec3aa5817768eb1"),
+        };
+        assert_eq!(
+            decrypt_key(&keystore, "secret").unwrap(),
+            r#"{"curvname":"P-256"}"#
+        );
+        assert_eq!(
+            decrypt_key(&keystore, "wrong").unwrap_err().kind(),
+            ErrorKind::CryptoError
+        );
+    }
+
+    #[test]
+    fn test_kdf_params() {
+        assert_eq!(KdfParams::default().check().is_ok(), true);
+        assert_eq!(TEST_PARAMS.check().is_ok(), true);
+        let invalid = [
+            (0, 8, 1),
+            (21, 8, 1),
+            (31, 8, 1),
+            (10, 0, 1),
+            (10, 8, 0),
+            (10, 1024, 1024),
+            (10, 4096, 1),
+            (20, 1, 1),
+            (20, 16, 1),
+        ];
+        for (log_n, r, p) in invalid.iter() {
+            let params = KdfParams {
+                log_n: *log_n,
+                r: *r,
+                p: *p,
+            };
+            assert_eq!(
+                params.check().unwrap_err().kind(),
+                ErrorKind::InvalidArguments
+            );
+            assert_eq!(
+                derive_key("secret", b"salt", &params).unwrap_err().kind(),
+                ErrorKind::InvalidArguments
+            );
+        }
+    }
+}
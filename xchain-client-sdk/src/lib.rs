@@ -4,10 +4,65 @@ extern crate xchain_node_sdk;
 #[macro_use]
 extern crate lazy_static;
 
+pub mod attestation;
+pub mod award;
+pub mod balance;
+pub mod block;
+pub mod builder;
+pub mod bulk;
+pub mod chain_id;
+pub mod chains;
+pub mod channel;
+pub mod checkpoint;
+pub mod client;
+pub mod codec;
+pub mod confirm;
+pub mod consolidate;
 pub mod consts;
 pub mod contract;
+pub mod contract_account;
+pub mod deploy_contract;
+pub mod desc;
+pub mod evm;
+pub mod faucet;
+pub mod fee_bump;
+pub mod history;
+pub mod keystore;
 
 pub mod config;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod multisig;
+pub mod names;
+pub mod notifier;
+pub mod payment;
+pub mod policy;
+pub mod receipt;
+pub mod reconcile;
+pub mod rejection;
+pub mod recovery;
+#[cfg(feature = "sandbox")]
+pub mod sandbox;
+#[cfg(feature = "sgx-seal")]
+pub mod seal;
+pub mod sequence;
 pub mod session;
+#[cfg(all(test, feature = "stress"))]
+mod stress;
+pub mod subscribe;
+pub mod tags;
+pub mod template;
+pub mod tenant;
+#[cfg(any(test, feature = "testkit"))]
+pub mod testkit;
+pub mod token;
+#[cfg(feature = "trace-context")]
+pub mod trace;
+pub mod transaction;
+pub mod transcript;
 pub mod transfer;
+pub mod transport;
+pub mod utxo;
+pub mod utxo_cache;
+pub mod utxo_stats;
 pub mod wallet;
@@ -0,0 +1,677 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use num_bigint::BigInt;
+
+use crate::wallet;
+use xchain_node_sdk::{
+    canonical,
+    client::NodeClient,
+    deadline, encoder,
+    errors::*,
+    ocall,
+    protos::{xchain, xendorser},
+};
+
+// 内存里的节点和背书服务，不连接节点的测试用，需要开启mock feature，用install设置为ocall的NodeClient。
+// 账本只记录utxo和交易，不执行合约，预执行返回空的结果:
+//   PreExecWithFee   选出请求地址没有锁定的utxo并锁定，直到被花费
+//   ComplianceCheck  提交请求里的手续费交易(同一笔只提交一次)并锁定它的找零，留给真正的交易花费，
+//                    用endorser的私钥对交易签名
+// 提交交易时检查txid和nonce不重复、输入存在并且没有被花费、输入不少于输出，不校验签名。
+// 冻结的输出不会被选中，锁定的utxo不出现在query_utxo_record的openUtxoRecord里。
+//
+// 故障注入: inject(op, fault)给一种请求排队一个一次性的故障，请求按顺序取走故障，
+// 没有排队的故障时正常处理，同样的注入顺序得到同样的结果，用来测试重试、熔断和strictResponse。
+// op是背书服务的请求名(PRE_EXEC_WITH_FEE、COMPLIANCE_CHECK)或者POST_TX，
+// 提交交易只支持Timeout、TimeoutAfterApply和Delay，其他故障忽略。
+// Delay之后超过调用方的deadline(见deadline::scope)时不处理请求，返回DeadlineExceeded，
+// 和服务端按grpc-timeout取消请求一样。
+
+type OutputKey = (Vec<u8>, i32);
+
+pub const PRE_EXEC_WITH_FEE: &str = "PreExecWithFee";
+pub const COMPLIANCE_CHECK: &str = "ComplianceCheck";
+pub const POST_TX: &str = "PostTx";
+
+/// 注入的故障
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    /// 请求没有到达，返回TransportError
+    Timeout,
+    /// 请求已经处理(utxo已锁定、交易已提交)，但是返回TransportError
+    TimeoutAfterApply,
+    /// 处理之前等待一段时间
+    Delay(std::time::Duration),
+    /// ResponseData不是合法的json
+    MalformedJson,
+    /// ResponseData只返回前一半
+    PartialResponse,
+    /// ResponseData多一个SDK不认识的字段
+    UnknownField,
+    /// 响应没有EndorserSign
+    SignMissing,
+    /// 不处理请求，按code拒绝，见rejection
+    Reject(String),
+}
+
+fn timeout() -> Error {
+    Error::new(ErrorKind::TransportError, "mock node timeout")
+}
+
+fn amount_of(output: &xchain::TxOutput) -> BigInt {
+    BigInt::from_bytes_be(num_bigint::Sign::Plus, &output.amount)
+}
+
+fn rejected(msg: String) -> Error {
+    Error::new(ErrorKind::ChainRPCError, msg)
+}
+
+fn unsupported() -> Error {
+    Error::new(ErrorKind::ChainRPCError, "not supported by mock node")
+}
+
+#[derive(Default)]
+struct Ledger {
+    utxos: BTreeMap<OutputKey, xchain::TxOutput>,
+    locked: HashSet<OutputKey>,
+    txs: HashMap<Vec<u8>, xchain::Transaction>,
+    nonces: HashSet<String>,
+    /// 凭空生成utxo的次数，用来生成不重复的txid
+    funded: u64,
+}
+
+impl Ledger {
+    fn unspent(&self, address: &str) -> Vec<(&OutputKey, &xchain::TxOutput)> {
+        self.utxos
+            .iter()
+            .filter(|(_, o)| o.to_addr == address.as_bytes() && o.frozen_height == 0)
+            .collect()
+    }
+
+    fn apply(&mut self, tx: &xchain::Transaction) -> Result<()> {
+        if self.txs.contains_key(&tx.txid) {
+            return Err(rejected(format!(
+                "duplicate txid {}",
+                hex::encode(&tx.txid)
+            )));
+        }
+        if !tx.nonce.is_empty() && self.nonces.contains(&tx.nonce) {
+            return Err(rejected(format!("duplicate nonce {}", tx.nonce)));
+        }
+        let mut spent = HashSet::new();
+        let mut total_in = BigInt::from(0);
+        for input in tx.get_tx_inputs().iter() {
+            let key = (input.ref_txid.clone(), input.ref_offset);
+            let output = self.utxos.get(&key).ok_or(rejected(format!(
+                "utxo {}:{} is spent or does not exist",
+                hex::encode(&input.ref_txid),
+                input.ref_offset
+            )))?;
+            if output.to_addr != input.from_addr || !spent.insert(key) {
+                return Err(rejected(String::from("invalid tx input")));
+            }
+            total_in += amount_of(output);
+        }
+        let total_out: BigInt = tx.get_tx_outputs().iter().map(amount_of).sum();
+        if total_out > total_in {
+            return Err(rejected(format!(
+                "outputs {} exceed inputs {}",
+                total_out, total_in
+            )));
+        }
+
+        for key in spent.iter() {
+            self.utxos.remove(key);
+            self.locked.remove(key);
+        }
+        for (offset, output) in tx.get_tx_outputs().iter().enumerate() {
+            self.utxos
+                .insert((tx.txid.clone(), offset as i32), output.clone());
+        }
+        self.nonces.insert(tx.nonce.to_owned());
+        self.txs.insert(tx.txid.clone(), tx.clone());
+        Ok(())
+    }
+}
+
+/// 内存里的节点和背书服务，clone之后共用同一个账本
+#[derive(Clone)]
+pub struct MockNode {
+    bcname: String,
+    endorser: wallet::Account,
+    ledger: Arc<Mutex<Ledger>>,
+    faults: Arc<Mutex<HashMap<String, VecDeque<Fault>>>>,
+}
+
+impl MockNode {
+    pub fn new(bcname: &str, endorser: wallet::Account) -> Self {
+        MockNode {
+            bcname: bcname.to_owned(),
+            endorser: endorser,
+            ledger: Default::default(),
+            faults: Default::default(),
+        }
+    }
+
+    /// 下一个op请求出现fault，多次注入时按顺序出现
+    pub fn inject(&self, op: &str, fault: Fault) {
+        self.inject_n(op, fault, 1);
+    }
+
+    /// 接下来的n个op请求都出现fault
+    pub fn inject_n(&self, op: &str, fault: Fault, n: usize) {
+        let mut faults = self.faults.lock().unwrap();
+        let queue = faults.entry(op.to_owned()).or_insert_with(VecDeque::new);
+        for _ in 0..n {
+            queue.push_back(fault.clone());
+        }
+    }
+
+    /// 还没有出现的op故障个数
+    pub fn pending_faults(&self, op: &str) -> usize {
+        self.faults
+            .lock()
+            .unwrap()
+            .get(op)
+            .map(|q| q.len())
+            .unwrap_or(0)
+    }
+
+    pub fn clear_faults(&self) {
+        self.faults.lock().unwrap().clear();
+    }
+
+    fn take_fault(&self, op: &str) -> Option<Fault> {
+        let fault = self
+            .faults
+            .lock()
+            .unwrap()
+            .get_mut(op)
+            .and_then(|q| q.pop_front());
+        if let Some(Fault::Delay(d)) = fault {
+            std::thread::sleep(d);
+        }
+        fault
+    }
+
+    fn take_fault_until_deadline(&self, op: &str) -> Result<Option<Fault>> {
+        let fault = self.take_fault(op);
+        deadline::remaining()?;
+        Ok(fault)
+    }
+
+    /// 凭空给address一个金额为amount的utxo
+    pub fn fund(&self, address: &str, amount: &BigInt) -> xchain::Utxo {
+        let mut ledger = self.ledger.lock().unwrap();
+        ledger.funded += 1;
+        let txid = wallet::sha256(format!("mock fund {}", ledger.funded).as_bytes());
+        let mut output = xchain::TxOutput::new();
+        output.set_to_addr(address.as_bytes().to_vec());
+        output.set_amount(amount.to_bytes_be().1);
+        ledger.utxos.insert((txid.clone(), 0), output);
+
+        let mut utxo = xchain::Utxo::new();
+        utxo.set_refTxid(txid);
+        utxo.set_refOffset(0);
+        utxo.set_toAddr(address.as_bytes().to_vec());
+        utxo.set_amount(amount.to_bytes_be().1);
+        utxo
+    }
+
+    /// address没有冻结的utxo总额
+    pub fn balance(&self, address: &str) -> BigInt {
+        let ledger = self.ledger.lock().unwrap();
+        ledger
+            .unspent(address)
+            .into_iter()
+            .map(|(_, o)| amount_of(o))
+            .sum()
+    }
+
+    /// 已经提交的交易，包括手续费交易
+    pub fn txs(&self) -> Vec<xchain::Transaction> {
+        self.ledger.lock().unwrap().txs.values().cloned().collect()
+    }
+
+    pub fn tx_count(&self) -> usize {
+        self.ledger.lock().unwrap().txs.len()
+    }
+
+    fn pre_exec_with_fee(
+        &self,
+        req: &xendorser::EndorserRequest,
+    ) -> Result<xendorser::EndorserResponse> {
+        let pre_sel_utxo_req: xchain::PreExecWithSelectUTXORequest =
+            canonical::from_canonical_json(&req.RequestData)?;
+        let total_need = BigInt::from(pre_sel_utxo_req.totalAmount);
+
+        let mut utxo_output = xchain::UtxoOutput::new();
+        let mut total = BigInt::from(0);
+        if pre_sel_utxo_req.totalAmount > 0 {
+            let mut ledger = self.ledger.lock().unwrap();
+            let mut selected = vec![];
+            for (key, output) in ledger.unspent(&pre_sel_utxo_req.address).into_iter() {
+                if total >= total_need {
+                    break;
+                }
+                if ledger.locked.contains(key) {
+                    continue;
+                }
+                total += amount_of(output);
+                let mut utxo = xchain::Utxo::new();
+                utxo.set_refTxid(key.0.clone());
+                utxo.set_refOffset(key.1);
+                utxo.set_toAddr(output.to_addr.clone());
+                utxo.set_amount(output.amount.clone());
+                selected.push(utxo);
+            }
+            if total < total_need {
+                return Err(rejected(format!("utxos are less than {}", total_need)));
+            }
+            for utxo in selected.iter() {
+                ledger.locked.insert((utxo.refTxid.clone(), utxo.refOffset));
+            }
+            utxo_output.set_utxoList(protobuf::RepeatedField::from_vec(selected));
+        }
+        utxo_output.set_totalSelected(total.to_str_radix(10));
+
+        let mut resp = xchain::PreExecWithSelectUTXOResponse::new();
+        resp.set_bcname(pre_sel_utxo_req.bcname.to_owned());
+        resp.set_utxoOutput(utxo_output);
+        resp.set_response(xchain::InvokeResponse::new());
+        self.response(req, serde_json::to_vec(&resp)?, None)
+    }
+
+    fn compliance_check(
+        &self,
+        req: &xendorser::EndorserRequest,
+    ) -> Result<xendorser::EndorserResponse> {
+        let status: xchain::TxStatus = canonical::from_canonical_json(&req.RequestData)?;
+        if req.has_Fee() {
+            let mut ledger = self.ledger.lock().unwrap();
+            // 重试时收到同一笔手续费交易
+            let fee = req.get_Fee();
+            if !ledger.txs.contains_key(&fee.txid) {
+                ledger.apply(fee)?;
+                for (offset, output) in fee.get_tx_outputs().iter().enumerate() {
+                    if output.to_addr == fee.initiator.as_bytes() {
+                        ledger.locked.insert((fee.txid.clone(), offset as i32));
+                    }
+                }
+            }
+        }
+        let digest = encoder::make_tx_digest_hash(status.get_tx())?;
+        let mut sign = xchain::SignatureInfo::new();
+        sign.set_PublicKey(self.endorser.public_key()?);
+        sign.set_Sign(self.endorser.sign(&digest)?);
+        self.response(req, vec![], Some(sign))
+    }
+
+    fn response(
+        &self,
+        req: &xendorser::EndorserRequest,
+        data: Vec<u8>,
+        sign: Option<xchain::SignatureInfo>,
+    ) -> Result<xendorser::EndorserResponse> {
+        let mut resp = xendorser::EndorserResponse::new();
+        resp.set_ResponseName(req.RequestName.to_owned());
+        resp.set_EndorserAddress(self.endorser.address.to_owned());
+        resp.set_ResponseData(data);
+        if let Some(sign) = sign {
+            resp.set_EndorserSign(sign);
+        }
+        Ok(resp)
+    }
+}
+
+impl NodeClient for MockNode {
+    fn call(&self, r: xendorser::EndorserRequest) -> Result<xendorser::EndorserResponse> {
+        let fault = self.take_fault_until_deadline(&r.RequestName)?;
+        match fault {
+            Some(Fault::Timeout) => return Err(timeout()),
+            Some(Fault::Reject(ref code)) => {
+                let body = serde_json::json!({"code": code, "message": "rejected by mock node"});
+                return self.response(&r, serde_json::to_vec(&body)?, None);
+            }
+            _ => {}
+        }
+        let mut resp = match &r.RequestName[..] {
+            PRE_EXEC_WITH_FEE => self.pre_exec_with_fee(&r)?,
+            COMPLIANCE_CHECK => self.compliance_check(&r)?,
+            _ => return Err(unsupported()),
+        };
+        match fault {
+            Some(Fault::TimeoutAfterApply) => return Err(timeout()),
+            Some(Fault::MalformedJson) => resp.set_ResponseData(b"{\"bcname\": ".to_vec()),
+            Some(Fault::PartialResponse) => {
+                let n = resp.ResponseData.len() / 2;
+                resp.ResponseData.truncate(n);
+            }
+            Some(Fault::UnknownField) => {
+                let mut v = if resp.ResponseData.is_empty() {
+                    serde_json::Value::Object(Default::default())
+                } else {
+                    serde_json::from_slice(&resp.ResponseData)?
+                };
+                v["mockUnknownField"] = serde_json::Value::Bool(true);
+                resp.set_ResponseData(serde_json::to_vec(&v)?);
+            }
+            Some(Fault::SignMissing) => resp.clear_EndorserSign(),
+            _ => {}
+        }
+        Ok(resp)
+    }
+
+    fn post_tx(&self, tx: &xchain::Transaction) -> Result<()> {
+        let fault = self.take_fault_until_deadline(POST_TX)?;
+        if fault == Some(Fault::Timeout) {
+            return Err(timeout());
+        }
+        self.ledger.lock().unwrap().apply(tx)?;
+        match fault {
+            Some(Fault::TimeoutAfterApply) => Err(timeout()),
+            _ => Ok(()),
+        }
+    }
+
+    fn query_tx(&self, txid: &String) -> Result<xchain::TxStatus> {
+        let txid = hex::decode(txid)?;
+        let mut status = xchain::TxStatus::new();
+        status.set_bcname(self.bcname.to_owned());
+        status.set_txid(txid.clone());
+        match self.ledger.lock().unwrap().txs.get(&txid) {
+            Some(tx) => {
+                status.set_status(xchain::TransactionStatus::CONFIRM);
+                status.set_tx(tx.clone());
+            }
+            None => status.set_status(xchain::TransactionStatus::NOEXIST),
+        }
+        Ok(status)
+    }
+
+    fn get_balance(&self, address: &String) -> Result<xchain::AddressStatus> {
+        let mut token = xchain::TokenDetail::new();
+        token.set_bcname(self.bcname.to_owned());
+        token.set_balance(self.balance(address).to_string());
+        let mut status = xchain::AddressStatus::new();
+        status.set_address(address.to_owned());
+        status.set_bcs(protobuf::RepeatedField::from_vec(vec![token]));
+        Ok(status)
+    }
+
+    fn get_balance_detail(&self, _address: &String) -> Result<xchain::AddressBalanceStatus> {
+        Err(unsupported())
+    }
+
+    fn query_utxo_record(
+        &self,
+        account: &String,
+        display_count: i64,
+    ) -> Result<xchain::UtxoRecordDetail> {
+        let ledger = self.ledger.lock().unwrap();
+        // 锁定的utxo不算在open里
+        let unspent: Vec<_> = ledger
+            .unspent(account)
+            .into_iter()
+            .filter(|(k, _)| !ledger.locked.contains(*k))
+            .collect();
+        let mut record = xchain::UtxoRecord::new();
+        record.set_utxoCount(unspent.len().to_string());
+        record.set_utxoAmount(
+            unspent
+                .iter()
+                .map(|(_, o)| amount_of(o))
+                .sum::<BigInt>()
+                .to_string(),
+        );
+        for (key, output) in unspent.iter().take(display_count.max(0) as usize) {
+            let mut k = xchain::UtxoKey::new();
+            k.set_refTxid(hex::encode(&key.0));
+            k.set_offset(key.1.to_string());
+            k.set_amount(amount_of(output).to_string());
+            record.mut_item().push(k);
+        }
+        let mut detail = xchain::UtxoRecordDetail::new();
+        detail.set_bcname(self.bcname.to_owned());
+        detail.set_accountName(account.to_owned());
+        detail.set_openUtxoRecord(record);
+        detail.set_displayCount(display_count);
+        Ok(detail)
+    }
+
+    fn query_acl(&self, _account_name: &String) -> Result<xchain::AclStatus> {
+        Err(unsupported())
+    }
+
+    fn get_block_by_height(&self, _height: i64) -> Result<xchain::Block> {
+        Err(unsupported())
+    }
+
+    fn get_block(&self, _blockid: &String) -> Result<xchain::Block> {
+        Err(unsupported())
+    }
+
+    fn pre_exec(
+        &self,
+        invoke_rpc_req: xchain::InvokeRPCRequest,
+    ) -> Result<xchain::InvokeRPCResponse> {
+        let mut resp = xchain::InvokeRPCResponse::new();
+        resp.set_bcname(invoke_rpc_req.bcname.to_owned());
+        resp.set_response(xchain::InvokeResponse::new());
+        Ok(resp)
+    }
+
+    fn get_block_chains(&self) -> Result<Vec<String>> {
+        Ok(vec![self.bcname.to_owned()])
+    }
+
+    fn get_block_chain_status(&self, _bcname: &String) -> Result<xchain::BCStatus> {
+        Err(unsupported())
+    }
+}
+
+/// 把node设置为ocall的NodeClient，ocall已经初始化过时返回InvalidArguments
+pub fn install(node: &MockNode) -> Result<()> {
+    if ocall::initialized() {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    ocall::init_with_client(Box::new(node.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endorser() -> wallet::Account {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        wallet::Account::new(d.to_str().unwrap(), "", "")
+    }
+
+    fn transfer_tx(utxo: &xchain::Utxo, to: &str, amount: u8, nonce: &str) -> xchain::Transaction {
+        let mut tx = xchain::Transaction::new();
+        let mut input = xchain::TxInput::new();
+        input.set_ref_txid(utxo.refTxid.clone());
+        input.set_ref_offset(utxo.refOffset);
+        input.set_from_addr(utxo.toAddr.clone());
+        input.set_amount(utxo.amount.clone());
+        tx.mut_tx_inputs().push(input);
+        let mut output = xchain::TxOutput::new();
+        output.set_to_addr(to.as_bytes().to_vec());
+        output.set_amount(vec![amount]);
+        tx.mut_tx_outputs().push(output);
+        tx.set_nonce(nonce.to_owned());
+        tx.set_txid(encoder::make_transaction_id(&tx).unwrap());
+        tx
+    }
+
+    #[test]
+    fn test_mock_ledger() {
+        let node = MockNode::new("xuper", endorser());
+        let utxo = node.fund("alice", &BigInt::from(10));
+        assert_eq!(node.balance("alice"), BigInt::from(10));
+
+        let tx = transfer_tx(&utxo, "bob", 10, "n1");
+        node.post_tx(&tx).unwrap();
+        assert_eq!(node.balance("alice"), BigInt::from(0));
+        assert_eq!(node.balance("bob"), BigInt::from(10));
+        let status = node.query_tx(&hex::encode(&tx.txid)).unwrap();
+        assert_eq!(status.status, xchain::TransactionStatus::CONFIRM);
+
+        // 重复花费
+        let tx = transfer_tx(&utxo, "carol", 10, "n2");
+        assert_eq!(node.post_tx(&tx).is_ok(), false);
+        // 重复的nonce
+        let utxo = node.fund("alice", &BigInt::from(10));
+        let tx = transfer_tx(&utxo, "carol", 10, "n1");
+        assert_eq!(node.post_tx(&tx).is_ok(), false);
+        // 输出大于输入
+        let tx = transfer_tx(&utxo, "carol", 11, "n3");
+        assert_eq!(node.post_tx(&tx).is_ok(), false);
+        assert_eq!(node.tx_count(), 1);
+
+        let record = node.query_utxo_record(&String::from("alice"), 10).unwrap();
+        assert_eq!(record.get_openUtxoRecord().utxoCount, "1");
+        assert_eq!(record.get_openUtxoRecord().utxoAmount, "10");
+    }
+
+    fn pre_exec_request(total_amount: i64) -> xendorser::EndorserRequest {
+        let mut req = xchain::PreExecWithSelectUTXORequest::new();
+        req.set_bcname(String::from("xuper"));
+        req.set_address(String::from("alice"));
+        req.set_totalAmount(total_amount);
+        let mut endorser_request = xendorser::EndorserRequest::new();
+        endorser_request.set_RequestName(String::from(PRE_EXEC_WITH_FEE));
+        endorser_request.set_RequestData(canonical::to_canonical_json(&req).unwrap());
+        endorser_request
+    }
+
+    #[test]
+    fn test_mock_pre_exec_locks_utxos() {
+        let node = MockNode::new("xuper", endorser());
+        node.fund("alice", &BigInt::from(5));
+        node.fund("alice", &BigInt::from(5));
+
+        let endorser_request = pre_exec_request(5);
+        let select = |r: &xendorser::EndorserRequest| -> Result<xchain::UtxoOutput> {
+            let resp = node.call(r.clone())?;
+            let (resp, _): (xchain::PreExecWithSelectUTXOResponse, _) =
+                encoder::from_slice_tolerant(&resp.ResponseData)?;
+            Ok(resp.get_utxoOutput().clone())
+        };
+        let first = select(&endorser_request).unwrap();
+        let second = select(&endorser_request).unwrap();
+        assert_eq!(first.totalSelected, "5");
+        assert_ne!(first.get_utxoList()[0], second.get_utxoList()[0]);
+        // 都被锁定了
+        assert_eq!(select(&endorser_request).is_ok(), false);
+    }
+
+    #[test]
+    fn test_mock_faults() {
+        let node = MockNode::new("xuper", endorser());
+        node.fund("alice", &BigInt::from(5));
+        node.fund("alice", &BigInt::from(5));
+        let req = pre_exec_request(5);
+
+        // 重试两次超时之后成功
+        node.inject_n(PRE_EXEC_WITH_FEE, Fault::Timeout, 2);
+        let retry = crate::session::RetryPolicy {
+            max_attempts: 3,
+            ..Default::default()
+        };
+        assert_eq!(retry.call(|| node.call(req.clone())).is_ok(), true);
+        assert_eq!(node.pending_faults(PRE_EXEC_WITH_FEE), 0);
+
+        // 处理之后超时，utxo已经被锁定
+        node.inject(PRE_EXEC_WITH_FEE, Fault::TimeoutAfterApply);
+        let err = node.call(req.clone()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TransportError);
+        node.clear_faults();
+        assert_eq!(node.call(req.clone()).is_ok(), false);
+
+        // 熔断
+        let cb = xchain_node_sdk::circuit::CircuitBreaker::new(
+            "mock",
+            2,
+            std::time::Duration::from_secs(60),
+        );
+        node.inject_n(PRE_EXEC_WITH_FEE, Fault::Timeout, 2);
+        for _ in 0..2 {
+            assert_eq!(cb.call(|| node.call(req.clone())).is_err(), true);
+        }
+        assert_eq!(
+            cb.call(|| node.call(req.clone())).unwrap_err().kind(),
+            ErrorKind::CircuitOpen
+        );
+    }
+
+    #[test]
+    fn test_mock_deadline() {
+        let node = MockNode::new("xuper", endorser());
+        node.fund("alice", &BigInt::from(5));
+        let req = pre_exec_request(5);
+        let within =
+            |ms: u64| Some(std::time::Instant::now() + std::time::Duration::from_millis(ms));
+
+        // 处理之前deadline已过，utxo没有被锁定
+        node.inject(
+            PRE_EXEC_WITH_FEE,
+            Fault::Delay(std::time::Duration::from_millis(50)),
+        );
+        let err = deadline::scope(within(10), || node.call(req.clone())).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DeadlineExceeded);
+        assert_eq!(
+            deadline::scope(within(1000), || node.call(req.clone())).is_ok(),
+            true
+        );
+
+        let utxo = node.fund("alice", &BigInt::from(1));
+        node.inject(POST_TX, Fault::Delay(std::time::Duration::from_millis(50)));
+        let tx = transfer_tx(&utxo, "bob", 1, "n1");
+        let err = deadline::scope(within(10), || node.post_tx(&tx)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DeadlineExceeded);
+        assert_eq!(node.tx_count(), 0);
+    }
+
+    #[test]
+    fn test_mock_malformed_responses() {
+        let node = MockNode::new("xuper", endorser());
+        for _ in 0..4 {
+            node.fund("alice", &BigInt::from(5));
+        }
+        let req = pre_exec_request(5);
+        let parse = |fault: Fault| -> Result<xchain::PreExecWithSelectUTXOResponse> {
+            node.inject(PRE_EXEC_WITH_FEE, fault);
+            let resp = node.call(req.clone())?;
+            encoder::from_slice_strict(&resp.ResponseData)
+        };
+        assert_eq!(parse(Fault::MalformedJson).is_ok(), false);
+        assert_eq!(parse(Fault::PartialResponse).is_ok(), false);
+        assert_eq!(
+            parse(Fault::UnknownField).unwrap_err().kind(),
+            ErrorKind::UnexpectedResponse
+        );
+        assert_eq!(parse(Fault::Delay(Default::default())).is_ok(), true);
+
+        let mut status = xchain::TxStatus::new();
+        status.set_tx(xchain::Transaction::new());
+        let mut check = xendorser::EndorserRequest::new();
+        check.set_RequestName(String::from(COMPLIANCE_CHECK));
+        check.set_RequestData(canonical::to_canonical_json(&status).unwrap());
+        assert_eq!(node.call(check.clone()).unwrap().has_EndorserSign(), true);
+        node.inject(COMPLIANCE_CHECK, Fault::SignMissing);
+        assert_eq!(node.call(check.clone()).unwrap().has_EndorserSign(), false);
+        node.inject(COMPLIANCE_CHECK, Fault::Reject(String::from("KYC_MISSING")));
+        let rejection = crate::rejection::parse(&node.call(check).unwrap()).unwrap();
+        assert_eq!(rejection.reason, crate::rejection::Reason::KycMissing);
+
+        // 提交之后超时，交易已经上链
+        let utxo = node.fund("alice", &BigInt::from(1));
+        node.inject(POST_TX, Fault::TimeoutAfterApply);
+        let tx = transfer_tx(&utxo, "bob", 1, "n1");
+        assert_eq!(node.post_tx(&tx).is_ok(), false);
+        assert_eq!(node.tx_count(), 1);
+    }
+}
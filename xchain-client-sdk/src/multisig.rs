@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+
+use crate::{session, wallet};
+use xchain_node_sdk::{
+    encoder,
+    errors::*,
+    ocall,
+    protos::xchain::{self, PermissionRule},
+};
+
+// 合约账户由多个AK共同授权的交易: auth_require里每个AK一项("合约账户/AK地址")，
+// auth_require_signs按同样的顺序由对应的AK签名(见Message::auth_require_signers)，背书服务的签名在最后。
+// 提交之前用AclHook按链上的ACL校验签名集合，签名不够时不提交，避免白白付手续费。
+// 只支持签名阈值(SIGN_THRESHOLD)和AK集合(SIGN_AKSET)两种权限模型。
+
+/// 每个签名者对应的auth_require
+pub fn auth_require(contract_account: &str, signers: &[wallet::Account]) -> Vec<String> {
+    signers
+        .iter()
+        .map(|s| format!("{}/{}", contract_account, s.address))
+        .collect()
+}
+
+/// 查询合约账户的ACL
+pub fn query_acl(contract_account: &String) -> Result<xchain::Acl> {
+    let status = ocall::ocall_xchain_query_acl(contract_account)?;
+    status
+        .acl
+        .into_option()
+        .ok_or(Error::from(ErrorKind::ChainRPCError))
+}
+
+/// 签了名的AK是否满足acl
+pub fn check_acl(acl: &xchain::Acl, signed: &[String]) -> Result<()> {
+    let signed: HashSet<&String> = signed.iter().collect();
+    let pm = acl.get_pm();
+    let ok = match pm.rule {
+        PermissionRule::NULL => true,
+        PermissionRule::SIGN_THRESHOLD => {
+            let weight: f64 = acl
+                .get_aksWeight()
+                .iter()
+                .filter(|(ak, _)| signed.contains(ak))
+                .map(|(_, w)| w)
+                .sum();
+            weight >= pm.acceptValue
+        }
+        PermissionRule::SIGN_AKSET => acl
+            .get_akSets()
+            .get_sets()
+            .values()
+            .any(|set| !set.aks.is_empty() && set.aks.iter().all(|ak| signed.contains(ak))),
+        _ => return Err(Error::from(ErrorKind::InvalidArguments)),
+    };
+    if !ok {
+        return Err(Error::from(ErrorKind::AclNotSatisfied));
+    }
+    Ok(())
+}
+
+/// 校验tx里合约账户的授权签名，返回签名正确并且在auth_require里的AK
+pub fn verified_signers(tx: &xchain::Transaction, contract_account: &str) -> Result<Vec<String>> {
+    let digest_hash = encoder::make_tx_digest_hash(tx)?;
+    let mut signers = vec![];
+    for sign in tx.get_auth_require_signs().iter() {
+        let address =
+            match wallet::verify_with_public_key(&sign.PublicKey, &digest_hash, &sign.Sign) {
+                Ok(address) => address,
+                Err(_) => continue,
+            };
+        let ak = format!("{}/{}", contract_account, address);
+        if tx.get_auth_require().iter().any(|a| a == &ak) {
+            signers.push(address);
+        }
+    }
+    Ok(signers)
+}
+
+/// 提交之前按acl校验签名，通过Session::add_hook使用
+pub struct AclHook {
+    pub contract_account: String,
+    pub acl: xchain::Acl,
+}
+
+impl session::TxHook for AclHook {
+    fn pre_post(&self, tx: &xchain::Transaction) -> Result<()> {
+        check_acl(&self.acl, &verified_signers(tx, &self.contract_account)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::TxHook;
+
+    fn threshold_acl(weights: &[(&str, f64)], accept: f64) -> xchain::Acl {
+        let mut acl = xchain::Acl::new();
+        acl.mut_pm().set_rule(PermissionRule::SIGN_THRESHOLD);
+        acl.mut_pm().set_acceptValue(accept);
+        for (ak, w) in weights {
+            acl.mut_aksWeight().insert(ak.to_string(), *w);
+        }
+        acl
+    }
+
+    fn s(v: &[&str]) -> Vec<String> {
+        v.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_check_acl() {
+        let acl = threshold_acl(&[("a", 0.5), ("b", 0.5), ("c", 0.25)], 0.75);
+        assert_eq!(check_acl(&acl, &s(&["a", "b"])).is_ok(), true);
+        assert_eq!(check_acl(&acl, &s(&["a", "c"])).is_ok(), true);
+        assert_eq!(
+            check_acl(&acl, &s(&["a", "a", "d"])).unwrap_err().kind(),
+            ErrorKind::AclNotSatisfied
+        );
+
+        let mut acl = xchain::Acl::new();
+        acl.mut_pm().set_rule(PermissionRule::SIGN_AKSET);
+        let mut set = xchain::AkSet::new();
+        set.set_aks(protobuf::RepeatedField::from_vec(s(&["a", "b"])));
+        acl.mut_akSets().mut_sets().insert(String::from("1"), set);
+        let mut set = xchain::AkSet::new();
+        set.set_aks(protobuf::RepeatedField::from_vec(s(&["c"])));
+        acl.mut_akSets().mut_sets().insert(String::from("2"), set);
+        assert_eq!(check_acl(&acl, &s(&["a", "b"])).is_ok(), true);
+        assert_eq!(check_acl(&acl, &s(&["c"])).is_ok(), true);
+        assert_eq!(check_acl(&acl, &s(&["a"])).is_ok(), false);
+    }
+
+    #[test]
+    fn test_acl_hook() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = wallet::Account::new(d.to_str().unwrap(), "", "");
+        let contract_account = "XC1111111111000000@xuper";
+
+        let mut tx = xchain::Transaction::new();
+        tx.set_initiator(acc.address.to_owned());
+        tx.set_auth_require(protobuf::RepeatedField::from_vec(auth_require(
+            contract_account,
+            &[acc.clone()],
+        )));
+        let digest_hash = encoder::make_tx_digest_hash(&tx).unwrap();
+        let mut sign = xchain::SignatureInfo::new();
+        sign.set_PublicKey(acc.public_key().unwrap());
+        sign.set_Sign(acc.sign(&digest_hash).unwrap());
+        tx.mut_auth_require_signs().push(sign);
+
+        let hook = AclHook {
+            contract_account: contract_account.to_owned(),
+            acl: threshold_acl(&[(&acc.address, 0.6), ("other", 0.4)], 0.6),
+        };
+        assert_eq!(hook.pre_post(&tx).is_ok(), true);
+        let hook = AclHook {
+            acl: threshold_acl(&[(&acc.address, 0.6), ("other", 0.4)], 1.0),
+            ..hook
+        };
+        assert_eq!(hook.pre_post(&tx).is_ok(), false);
+
+        // 签名不对的不算
+        tx.mut_auth_require_signs()[0].set_Sign(vec![0x30]);
+        assert_eq!(
+            verified_signers(&tx, contract_account).unwrap(),
+            Vec::<String>::new()
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::{config, contract};
+use xchain_node_sdk::errors::*;
+
+// 部署了名字服务合约(nameService)时，转账的收款方可以是名字，构造交易之前解析成地址。
+// 解析是对合约method的预执行，参数是{"name": 名字}，返回的body是地址，名字没有注册时返回空。
+// 地址和合约账户(XC...@链名)原样使用，只有其他的收款方当作名字。
+// 解析结果按(链名, 名字)缓存ttlSecs秒，名字改绑之后最多ttlSecs秒才生效。
+// 合约调用没有收款方，参数里的地址由调用方用resolve_to解析。
+
+/// 名字服务合约的参数名
+pub const NAME_ARG: &str = "name";
+
+/// 名字解析结果的缓存
+pub struct NameCache {
+    entries: Mutex<HashMap<(String, String), (String, Instant)>>,
+}
+
+impl NameCache {
+    pub fn new() -> Self {
+        NameCache {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 缓存里没有或者超过ttl时用query解析，解析失败不缓存
+    pub fn get_or_resolve<F>(
+        &self,
+        chain_name: &str,
+        name: &str,
+        ttl: Duration,
+        query: F,
+    ) -> Result<String>
+    where
+        F: FnOnce() -> Result<String>,
+    {
+        let key = (chain_name.to_owned(), name.to_owned());
+        if let Some((addr, resolved_at)) = self.entries.lock().unwrap().get(&key) {
+            if resolved_at.elapsed() < ttl {
+                return Ok(addr.to_owned());
+            }
+        }
+        let addr = query()?;
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key, (addr.to_owned(), Instant::now()));
+        Ok(addr)
+    }
+
+    pub fn invalidate(&self, chain_name: &str, name: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .remove(&(chain_name.to_owned(), name.to_owned()));
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+lazy_static! {
+    /// resolve_name使用的缓存
+    pub static ref NAMES: NameCache = NameCache::new();
+}
+
+/// to是地址或者合约账户，不需要解析
+pub fn is_address(to: &str) -> bool {
+    (to.starts_with("XC") && to.contains('@'))
+        || xchain_crypto::account::address::check_address_format(&to.to_owned()).is_ok()
+}
+
+/// 用全局CONFIG里的名字服务合约解析name
+pub fn resolve_name(chain_name: &String, name: &str) -> Result<String> {
+    resolve_name_with_config(&config::ClientConfig::global(), chain_name, name)
+}
+
+/// 同resolve_name，使用config里的名字服务合约，没有配置时返回InvalidArguments
+pub fn resolve_name_with_config(
+    config: &config::ClientConfig,
+    chain_name: &String,
+    name: &str,
+) -> Result<String> {
+    let ns = config.name_service.as_ref().ok_or(Error::new(
+        ErrorKind::InvalidArguments,
+        "nameService is not configured",
+    ))?;
+    NAMES.get_or_resolve(chain_name, name, Duration::from_secs(ns.ttl_secs), || {
+        query_name(ns, chain_name, name)
+    })
+}
+
+/// 配置了名字服务时把不是地址的to解析成地址，否则原样返回
+pub fn resolve_to(config: &config::ClientConfig, chain_name: &String, to: &str) -> Result<String> {
+    if to.is_empty() || config.name_service.is_none() || is_address(to) {
+        return Ok(to.to_owned());
+    }
+    resolve_name_with_config(config, chain_name, to)
+}
+
+fn query_name(ns: &config::NameServiceConfig, chain_name: &String, name: &str) -> Result<String> {
+    let mut args = HashMap::new();
+    args.insert(NAME_ARG.to_owned(), name.as_bytes().to_vec());
+    let resp = contract::query_contract(chain_name, &ns.contract, &ns.method, args)?;
+    parse_address(name, &resp.body)
+}
+
+/// 名字服务合约返回的地址，空表示名字没有注册
+pub fn parse_address(name: &str, body: &[u8]) -> Result<String> {
+    let addr = String::from_utf8_lossy(body).trim().to_owned();
+    if addr.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidArguments,
+            format!("name {} is not registered", name),
+        ));
+    }
+    if !is_address(&addr) {
+        return Err(Error::new(
+            ErrorKind::UnexpectedResponse,
+            format!("name service resolved {} to invalid address {}", name, addr),
+        ));
+    }
+    Ok(addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDR: &str = "dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN";
+
+    #[test]
+    fn test_is_address() {
+        assert_eq!(is_address(ADDR), true);
+        assert_eq!(is_address("XC1111111111000000@xuper"), true);
+        assert_eq!(is_address("alice"), false);
+        assert_eq!(is_address("alice.pay"), false);
+        assert_eq!(is_address("1"), false);
+    }
+
+    #[test]
+    fn test_name_cache() {
+        let cache = NameCache::new();
+        let queries = std::cell::Cell::new(0);
+        let query = || {
+            queries.set(queries.get() + 1);
+            Ok(ADDR.to_owned())
+        };
+        let ttl = Duration::from_secs(60);
+        assert_eq!(
+            cache.get_or_resolve("xuper", "alice", ttl, &query).unwrap(),
+            ADDR
+        );
+        assert_eq!(
+            cache.get_or_resolve("xuper", "alice", ttl, &query).unwrap(),
+            ADDR
+        );
+        // 不同的链分别缓存
+        cache.get_or_resolve("other", "alice", ttl, &query).unwrap();
+        assert_eq!(queries.get(), 2);
+        // 过期之后重新解析
+        cache
+            .get_or_resolve("xuper", "alice", Duration::from_secs(0), &query)
+            .unwrap();
+        assert_eq!(queries.get(), 3);
+        cache.invalidate("xuper", "alice");
+        cache.get_or_resolve("xuper", "alice", ttl, &query).unwrap();
+        assert_eq!(queries.get(), 4);
+
+        // 解析失败不缓存
+        let res = cache.get_or_resolve("xuper", "bob", ttl, || {
+            Err(Error::from(ErrorKind::InvalidArguments))
+        });
+        assert_eq!(res.is_err(), true);
+        assert_eq!(
+            cache
+                .get_or_resolve("xuper", "bob", ttl, || Ok(ADDR.to_owned()))
+                .unwrap(),
+            ADDR
+        );
+    }
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(
+            parse_address("alice", format!(" {}\n", ADDR).as_bytes()).unwrap(),
+            ADDR
+        );
+        assert_eq!(
+            parse_address("alice", b"").unwrap_err().kind(),
+            ErrorKind::InvalidArguments
+        );
+        assert_eq!(
+            parse_address("alice", b"not an address")
+                .unwrap_err()
+                .kind(),
+            ErrorKind::UnexpectedResponse
+        );
+    }
+
+    #[test]
+    fn test_resolve_to() {
+        let chain_name = String::from("xuper");
+        let mut c = (*config::ClientConfig::global()).clone();
+        c.name_service = None;
+        let config = config::ClientConfig::new(c.clone());
+        // 没有配置名字服务时原样返回
+        assert_eq!(resolve_to(&config, &chain_name, "alice").unwrap(), "alice");
+        assert_eq!(
+            resolve_name_with_config(&config, &chain_name, "alice")
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidArguments
+        );
+
+        c.name_service = Some(config::NameServiceConfig {
+            contract: String::from("names"),
+            method: String::from("Resolve"),
+            ttl_secs: 300,
+        });
+        let config = config::ClientConfig::new(c);
+        assert_eq!(resolve_to(&config, &chain_name, ADDR).unwrap(), ADDR);
+        assert_eq!(resolve_to(&config, &chain_name, "").unwrap(), "");
+        NAMES
+            .get_or_resolve(&chain_name, "carol", Duration::from_secs(300), || {
+                Ok(ADDR.to_owned())
+            })
+            .unwrap();
+        assert_eq!(resolve_to(&config, &chain_name, "carol").unwrap(), ADDR);
+    }
+}
@@ -0,0 +1,234 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{consts, wallet};
+use xchain_node_sdk::{errors::*, ocall, protos::xchain};
+
+// 账户动态的webhook通知: 把事件签名之后POST到配置的URL，下游系统不需要链接rust代码。
+// 事件由调用方产生(例如用tx_event轮询交易状态，或者subscribe模块按区块订阅)之后交给Notifier。
+// 节点返回NOEXIST不一定是交易被丢弃: 刚提交的交易可能还没有进入交易池，或者还没有广播到查询的节点，
+// tx_event在调用方给的期限之前把NOEXIST当作还在等待，期限过后才通知tx_rejected。
+// SDK不带http客户端，POST由调用方提供的Sender完成。
+
+/// 发送http POST，参数是(url, body)
+pub type Sender = Box<dyn Fn(&str, &[u8]) -> Result<()> + Send + Sync>;
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum WebhookEvent {
+    #[serde(rename = "tx_confirmed")]
+    TxConfirmed { txid: String },
+    #[serde(rename = "deposit_received")]
+    DepositReceived {
+        address: String,
+        txid: String,
+        amount: String,
+    },
+    #[serde(rename = "tx_rejected")]
+    TxRejected { txid: String, reason: String },
+}
+
+/// POST的body，payload是被签名的json字符串，接收方先验签再解析payload
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub payload: String,
+    pub public_key: String,
+    /// hex编码
+    pub sign: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+struct Payload {
+    event: WebhookEvent,
+    timestamp: i64,
+}
+
+pub struct Notifier {
+    urls: Vec<String>,
+    account: wallet::Account,
+    sender: Sender,
+    max_attempts: u32,
+    backoff: Duration,
+}
+
+impl Notifier {
+    /// 每个URL最多尝试max_attempts次，第n次重试之前等待backoff * 2^(n-1)
+    pub fn new(
+        urls: Vec<String>,
+        account: wallet::Account,
+        sender: Sender,
+        max_attempts: u32,
+        backoff: Duration,
+    ) -> Self {
+        Notifier {
+            urls: urls,
+            account: account,
+            sender: sender,
+            max_attempts: std::cmp::max(max_attempts, 1),
+            backoff: backoff,
+        }
+    }
+
+    pub fn sign(&self, event: &WebhookEvent) -> Result<Webhook> {
+        let payload = serde_json::to_string(&Payload {
+            event: event.clone(),
+            timestamp: consts::now_as_secs(),
+        })?;
+        Ok(Webhook {
+            sign: hex::encode(self.account.sign(payload.as_bytes())?),
+            public_key: self.account.public_key()?,
+            payload: payload,
+        })
+    }
+
+    /// 发给所有URL，有URL重试之后仍然失败时返回最后一个错误
+    pub fn notify(&self, event: &WebhookEvent) -> Result<()> {
+        let body = serde_json::to_vec(&self.sign(event)?)?;
+        let mut res = Ok(());
+        for url in self.urls.iter() {
+            if let Err(e) = self.send_with_retry(url, &body) {
+                res = Err(e);
+            }
+        }
+        res
+    }
+
+    fn send_with_retry(&self, url: &str, body: &[u8]) -> Result<()> {
+        let mut backoff = self.backoff;
+        let mut attempt = 1;
+        loop {
+            match (self.sender)(url, body) {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= self.max_attempts => return Err(e),
+                Err(_) => {}
+            }
+            std::thread::sleep(backoff);
+            backoff *= 2;
+            attempt += 1;
+        }
+    }
+}
+
+/// 接收方校验webhook，返回签名者地址和事件
+pub fn verify_webhook(body: &[u8]) -> Result<(String, WebhookEvent)> {
+    let webhook: Webhook = serde_json::from_slice(body)?;
+    let address = wallet::verify_with_public_key(
+        &webhook.public_key,
+        webhook.payload.as_bytes(),
+        &hex::decode(&webhook.sign)?,
+    )?;
+    let payload: Payload = serde_json::from_str(&webhook.payload)?;
+    Ok((address, payload.event))
+}
+
+/// 查询交易状态，已确认或者失败时返回对应的事件，还在等待时返回None
+/// 查不到交易(NOEXIST)时，pending_until之前返回None，之后返回tx_rejected
+pub fn tx_event(txid: &String, pending_until: Instant) -> Result<Option<WebhookEvent>> {
+    let status = ocall::ocall_xchain_query_tx(txid)?;
+    Ok(status_event(txid, &status, Instant::now() >= pending_until))
+}
+
+fn status_event(
+    txid: &String,
+    status: &xchain::TxStatus,
+    pending_expired: bool,
+) -> Option<WebhookEvent> {
+    match status.status {
+        xchain::TransactionStatus::CONFIRM => Some(WebhookEvent::TxConfirmed {
+            txid: txid.to_owned(),
+        }),
+        xchain::TransactionStatus::NOEXIST if !pending_expired => None,
+        xchain::TransactionStatus::FAILED | xchain::TransactionStatus::NOEXIST => {
+            Some(WebhookEvent::TxRejected {
+                txid: txid.to_owned(),
+                reason: format!("{:?}", status.status),
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_notify_with_retry() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = wallet::Account::new(d.to_str().unwrap(), "", "");
+        let address = acc.address.to_owned();
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let c = calls.clone();
+        let sender: Sender = Box::new(move |url, body| {
+            let n = c.fetch_add(1, Ordering::SeqCst);
+            let (signer, event) = verify_webhook(body)?;
+            assert_eq!(url, "http://127.0.0.1/hook");
+            assert_eq!(signer, address);
+            assert_eq!(
+                event,
+                WebhookEvent::TxConfirmed {
+                    txid: String::from("aa")
+                }
+            );
+            // 前两次失败
+            if n < 2 {
+                return Err(Error::from(ErrorKind::TransportError));
+            }
+            Ok(())
+        });
+        let notifier = Notifier::new(
+            vec![String::from("http://127.0.0.1/hook")],
+            acc,
+            sender,
+            3,
+            Duration::from_millis(1),
+        );
+        let event = WebhookEvent::TxConfirmed {
+            txid: String::from("aa"),
+        };
+        assert_eq!(notifier.notify(&event).is_ok(), true);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // 超过最大尝试次数
+        calls.store(0, Ordering::SeqCst);
+        let notifier = Notifier {
+            max_attempts: 2,
+            ..notifier
+        };
+        assert_eq!(notifier.notify(&event).is_ok(), false);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_status_event() {
+        let txid = String::from("aa");
+        let mut status = xchain::TxStatus::new();
+        status.set_status(xchain::TransactionStatus::UNCONFIRM);
+        assert_eq!(status_event(&txid, &status, true), None);
+        status.set_status(xchain::TransactionStatus::FAILED);
+        assert_eq!(
+            status_event(&txid, &status, false),
+            Some(WebhookEvent::TxRejected {
+                txid: txid.to_owned(),
+                reason: String::from("FAILED")
+            })
+        );
+        // 期限之前查不到交易仍然在等待
+        status.set_status(xchain::TransactionStatus::NOEXIST);
+        assert_eq!(status_event(&txid, &status, false), None);
+        assert_eq!(
+            status_event(&txid, &status, true),
+            Some(WebhookEvent::TxRejected {
+                txid: txid.to_owned(),
+                reason: String::from("NOEXIST")
+            })
+        );
+    }
+}
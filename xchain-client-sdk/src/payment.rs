@@ -0,0 +1,177 @@
+use crate::consts;
+use xchain_node_sdk::errors::*;
+
+// 收款请求的URI，用于二维码，移动端和SDK按同样的格式生成和解析:
+//   xuper:<address>?amount=<amount>&desc=<desc>
+// amount是十进制整数，desc是UTF-8按RFC 3986百分号编码(除字母数字和-._~之外都编码，空格编码成%20)，
+// 参数按amount, desc的顺序，为空时省略。解析时参数顺序任意，忽略不认识的参数，重复的参数视为无效。
+
+pub const SCHEME: &str = "xuper";
+
+/// 解析之后的收款请求，amount和desc为空表示由付款方填写
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PaymentRequest {
+    pub address: String,
+    pub amount: String,
+    pub desc: String,
+}
+
+fn check_address(address: &str) -> Result<()> {
+    if address.is_empty()
+        || !address
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'@')
+    {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    Ok(())
+}
+
+fn check_amount(amount: &str) -> Result<()> {
+    if !amount.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    consts::str_as_bigint(amount)?;
+    Ok(())
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut res = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                res.push(b as char)
+            }
+            _ => res.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    res
+}
+
+fn percent_decode(s: &str) -> Result<String> {
+    let bytes = s.as_bytes();
+    let mut res = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s
+                .get(i + 1..i + 3)
+                .filter(|h| h.bytes().all(|b| b.is_ascii_hexdigit()))
+                .ok_or(Error::from(ErrorKind::ParseError))?;
+            res.push(u8::from_str_radix(hex, 16).map_err(|_| Error::from(ErrorKind::ParseError))?);
+            i += 3;
+        } else {
+            res.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(res).map_err(|_| Error::from(ErrorKind::ParseError))
+}
+
+/// 生成规范格式的收款URI
+pub fn payment_uri(address: &str, amount: &str, desc: &str) -> Result<String> {
+    check_address(address)?;
+    check_amount(amount)?;
+    let mut params = vec![];
+    if !amount.is_empty() {
+        params.push(format!("amount={}", amount));
+    }
+    if !desc.is_empty() {
+        params.push(format!("desc={}", percent_encode(desc)));
+    }
+    let mut uri = format!("{}:{}", SCHEME, address);
+    if !params.is_empty() {
+        uri.push('?');
+        uri.push_str(&params.join("&"));
+    }
+    Ok(uri)
+}
+
+pub fn parse_payment_uri(uri: &str) -> Result<PaymentRequest> {
+    let prefix = format!("{}:", SCHEME);
+    if !uri.starts_with(&prefix) {
+        return Err(Error::from(ErrorKind::ParseError));
+    }
+    let rest = &uri[prefix.len()..];
+    let (address, query) = match rest.find('?') {
+        Some(i) => (&rest[..i], &rest[i + 1..]),
+        None => (rest, ""),
+    };
+    check_address(address)?;
+    let mut req = PaymentRequest {
+        address: address.to_owned(),
+        ..Default::default()
+    };
+    let (mut has_amount, mut has_desc) = (false, false);
+    for param in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = match param.find('=') {
+            Some(i) => (&param[..i], &param[i + 1..]),
+            None => (param, ""),
+        };
+        match key {
+            "amount" if !has_amount => {
+                check_amount(value)?;
+                req.amount = value.to_owned();
+                has_amount = true;
+            }
+            "desc" if !has_desc => {
+                req.desc = percent_decode(value)?;
+                has_desc = true;
+            }
+            "amount" | "desc" => return Err(Error::from(ErrorKind::ParseError)),
+            _ => {}
+        }
+    }
+    Ok(req)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payment_uri() {
+        let address = "dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN";
+        let uri = payment_uri(address, "1401", "转账 a&b=c").unwrap();
+        assert_eq!(
+            uri,
+            "xuper:dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN?amount=1401&desc=%E8%BD%AC%E8%B4%A6%20a%26b%3Dc"
+        );
+        assert_eq!(
+            parse_payment_uri(&uri).unwrap(),
+            PaymentRequest {
+                address: String::from(address),
+                amount: String::from("1401"),
+                desc: String::from("转账 a&b=c"),
+            }
+        );
+        assert_eq!(
+            payment_uri(address, "", "").unwrap(),
+            format!("xuper:{}", address)
+        );
+
+        // 参数顺序任意，忽略不认识的参数
+        let req = parse_payment_uri(&format!("xuper:{}?v=2&desc=x&amount=5", address)).unwrap();
+        assert_eq!((&req.amount[..], &req.desc[..]), ("5", "x"));
+
+        assert_eq!(payment_uri(address, "-1", "").is_ok(), false);
+        assert_eq!(payment_uri("", "1", "").is_ok(), false);
+        assert_eq!(
+            parse_payment_uri(&format!("bitcoin:{}", address)).is_ok(),
+            false
+        );
+        assert_eq!(
+            parse_payment_uri(&format!("xuper:{}?amount=1&amount=2", address)).is_ok(),
+            false
+        );
+        assert_eq!(
+            parse_payment_uri(&format!("xuper:{}?desc=%E8%BD", address)).is_ok(),
+            false
+        );
+        assert_eq!(
+            parse_payment_uri(&format!("xuper:{}?desc=%4", address)).is_ok(),
+            false
+        );
+    }
+}
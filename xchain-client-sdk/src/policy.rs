@@ -0,0 +1,92 @@
+use crate::config;
+use xchain_node_sdk::{errors::*, protos::xchain};
+
+// 签名策略: 配置了contractAllowlist时，只对调用的合约方法全部在名单里的交易签名，
+// 防止调用方被攻破之后用热钱包的私钥调用任意合约。
+// 在签名的位置检查，Session和离线签名(sign_tx)都经过这里；系统合约($acl, $contract等)同样需要列出。
+
+/// 允许合约的所有方法
+pub const ANY_METHOD: &str = "*";
+
+pub fn is_allowed(allowlist: &[config::AllowedContract], contract: &str, method: &str) -> bool {
+    allowlist
+        .iter()
+        .any(|c| c.contract == contract && c.methods.iter().any(|m| m == ANY_METHOD || m == method))
+}
+
+/// 所有的合约调用都在名单里时返回Ok
+pub fn check_contract_requests(
+    allowlist: &[config::AllowedContract],
+    requests: &[xchain::InvokeRequest],
+) -> Result<()> {
+    for r in requests.iter() {
+        if !is_allowed(allowlist, &r.contract_name, &r.method_name) {
+            return Err(Error::new(
+                ErrorKind::InvalidArguments,
+                format!(
+                    "contract method {}.{} is not allowlisted",
+                    r.contract_name, r.method_name
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 按配置检查待签名的交易，没有配置contractAllowlist时不限制
+pub fn check_tx(config: &config::CommConfig, tx: &xchain::Transaction) -> Result<()> {
+    match config.contract_allowlist {
+        Some(ref allowlist) => check_contract_requests(allowlist, tx.get_contract_requests()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(contract: &str, method: &str) -> xchain::InvokeRequest {
+        let mut r = xchain::InvokeRequest::new();
+        r.set_contract_name(String::from(contract));
+        r.set_method_name(String::from(method));
+        r
+    }
+
+    #[test]
+    fn test_check_contract_requests() {
+        let allowlist = vec![
+            config::AllowedContract {
+                contract: String::from("counter"),
+                methods: vec![String::from("increase")],
+            },
+            config::AllowedContract {
+                contract: String::from("$acl"),
+                methods: vec![String::from(ANY_METHOD)],
+            },
+        ];
+        assert_eq!(check_contract_requests(&allowlist, &[]).is_ok(), true);
+        assert_eq!(
+            check_contract_requests(
+                &allowlist,
+                &[
+                    request("counter", "increase"),
+                    request("$acl", "NewAccount")
+                ]
+            )
+            .is_ok(),
+            true
+        );
+        assert_eq!(
+            check_contract_requests(&allowlist, &[request("counter", "reset")]).is_ok(),
+            false
+        );
+        assert_eq!(
+            check_contract_requests(
+                &allowlist,
+                &[request("counter", "increase"), request("token", "transfer")]
+            )
+            .is_ok(),
+            false
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use protobuf::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::{consts, wallet};
+use xchain_node_sdk::{encoder, errors::*, protos::xchain};
+
+// 交易回执，CBOR编码之后用于大量归档，之后可以离线重新校验背书签名。
+// XuperChain 3.7的交易里面没有合约事件，events记录交易里的合约调用。
+// 回执里保存交易的protobuf编码，校验时由交易重新计算txid、背书签名的摘要、fee和events，
+// 背书签名只对这个交易有效，不能把回执挪到其他交易上。
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ReceiptEvent {
+    pub contract_name: String,
+    pub method_name: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ReceiptSign {
+    pub public_key: String,
+    #[serde(with = "serde_bytes")]
+    pub sign: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Receipt {
+    #[serde(with = "serde_bytes")]
+    pub txid: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    pub blockid: Vec<u8>,
+    pub height: i64,
+    /// 付给矿工的手续费(手续费输出之和)，不是合约执行的gas
+    pub fee: String,
+    pub events: Vec<ReceiptEvent>,
+    /// 背书签名所签的交易摘要
+    #[serde(with = "serde_bytes")]
+    pub digest: Vec<u8>,
+    pub endorser_sign: Option<ReceiptSign>,
+    /// 交易的protobuf编码
+    #[serde(with = "serde_bytes")]
+    pub tx: Vec<u8>,
+}
+
+fn events(tx: &xchain::Transaction) -> Vec<ReceiptEvent> {
+    tx.get_contract_requests()
+        .iter()
+        .map(|r| ReceiptEvent {
+            contract_name: r.contract_name.to_owned(),
+            method_name: r.method_name.to_owned(),
+        })
+        .collect()
+}
+
+impl Receipt {
+    /// 由上链的交易生成回执，height为交易所在区块的高度
+    /// SDK把背书服务的签名放在auth_require_signs的最后
+    pub fn from_tx(tx: &xchain::Transaction, height: i64) -> Result<Self> {
+        Ok(Receipt {
+            txid: tx.txid.clone(),
+            blockid: tx.blockid.clone(),
+            height: height,
+            fee: consts::FeeOutput::total(tx).to_str_radix(10),
+            events: events(tx),
+            digest: encoder::make_tx_digest_hash(tx)?,
+            endorser_sign: tx.get_auth_require_signs().last().map(|s| ReceiptSign {
+                public_key: s.PublicKey.to_owned(),
+                sign: s.Sign.clone(),
+            }),
+            tx: tx
+                .write_to_bytes()
+                .map_err(|e| Error::new(ErrorKind::ParseError, e))?,
+        })
+    }
+
+    pub fn to_cbor(&self) -> Result<Vec<u8>> {
+        serde_cbor::to_vec(self).map_err(|_| Error::from(ErrorKind::ParseError))
+    }
+
+    pub fn from_cbor(data: &[u8]) -> Result<Self> {
+        serde_cbor::from_slice(data).map_err(|_| Error::from(ErrorKind::ParseError))
+    }
+
+    /// 回执里的字段和交易一致，返回解码的交易
+    fn check_tx(&self) -> Result<xchain::Transaction> {
+        let tx: xchain::Transaction = protobuf::parse_from_bytes(&self.tx)
+            .map_err(|e| Error::new(ErrorKind::ParseError, e))?;
+        if encoder::make_transaction_id(&tx)? != self.txid
+            || tx.txid != self.txid
+            || encoder::make_tx_digest_hash(&tx)? != self.digest
+            || consts::FeeOutput::total(&tx).to_str_radix(10) != self.fee
+            || events(&tx) != self.events
+        {
+            return Err(Error::new(
+                ErrorKind::CryptoError,
+                "receipt does not match its tx",
+            ));
+        }
+        Ok(tx)
+    }
+
+    /// 离线校验: 回执和交易一致，交易带有背书签名，并且背书者是endorser_addr
+    pub fn verify(&self, endorser_addr: &str) -> Result<()> {
+        let tx = self.check_tx()?;
+        let s = self
+            .endorser_sign
+            .as_ref()
+            .ok_or(Error::from(ErrorKind::EndorserSignMissing))?;
+        if !tx
+            .get_auth_require_signs()
+            .iter()
+            .any(|t| t.PublicKey == s.public_key && t.Sign == s.sign)
+        {
+            return Err(Error::from(ErrorKind::EndorserSignMissing));
+        }
+        let address = wallet::verify_with_public_key(&s.public_key, &self.digest, &s.sign)?;
+        if address != endorser_addr {
+            return Err(Error::from(ErrorKind::CryptoError));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_receipt_cbor() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let endorser = wallet::Account::new(d.to_str().unwrap(), "", "");
+
+        let mut tx = xchain::Transaction::new();
+        tx.set_version(1);
+        tx.set_nonce(String::from("receipt"));
+        tx.set_blockid(vec![0xbb; 32]);
+        tx.set_initiator(String::from("alice"));
+        tx.mut_tx_outputs()
+            .push(consts::FeeOutput::new("100").unwrap().to_tx_output());
+        let mut req = xchain::InvokeRequest::new();
+        req.set_contract_name(String::from("counter"));
+        req.set_method_name(String::from("increase"));
+        tx.mut_contract_requests().push(req);
+        let digest = encoder::make_tx_digest_hash(&tx).unwrap();
+        let mut sign = xchain::SignatureInfo::new();
+        sign.set_PublicKey(endorser.public_key().unwrap());
+        sign.set_Sign(endorser.sign(&digest).unwrap());
+        tx.mut_auth_require_signs().push(sign);
+        let txid = encoder::make_transaction_id(&tx).unwrap();
+        tx.set_txid(txid);
+
+        let receipt = Receipt::from_tx(&tx, 10).unwrap();
+        assert_eq!(receipt.fee, "100");
+        assert_eq!(receipt.events[0].method_name, "increase");
+
+        let data = receipt.to_cbor().unwrap();
+        // 字节串编码，比json紧凑
+        assert_eq!(
+            data.len() < serde_json::to_vec(&receipt).unwrap().len(),
+            true
+        );
+        let decoded = Receipt::from_cbor(&data).unwrap();
+        assert_eq!(decoded, receipt);
+        assert_eq!(decoded.verify(&endorser.address).is_ok(), true);
+        assert_eq!(decoded.verify("other").is_ok(), false);
+
+        let mut tampered = decoded.clone();
+        tampered.digest[0] ^= 1;
+        assert_eq!(tampered.verify(&endorser.address).is_ok(), false);
+
+        // 同一个背书签名挪到其他交易上
+        let mut other = tx.clone();
+        other.set_nonce(String::from("other"));
+        let txid = encoder::make_transaction_id(&other).unwrap();
+        other.set_txid(txid);
+        let mut moved = Receipt::from_tx(&other, 10).unwrap();
+        moved.digest = receipt.digest.clone();
+        assert_eq!(moved.verify(&endorser.address).is_ok(), false);
+        let mut moved = receipt.clone();
+        moved.txid = other.txid.clone();
+        assert_eq!(moved.verify(&endorser.address).is_ok(), false);
+        let mut moved = receipt.clone();
+        moved.fee = String::from("1");
+        assert_eq!(moved.verify(&endorser.address).is_ok(), false);
+    }
+}
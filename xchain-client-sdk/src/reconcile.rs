@@ -0,0 +1,372 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use std::ops::{AddAssign, Sub};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{consts, session};
+use xchain_node_sdk::{errors::*, ocall, protos::xchain};
+
+// 对账: 按本地交易索引(TxIndex)计算每个托管账户的预期余额，和节点返回的余额比较。
+// 索引记录每笔交易对各个地址余额的影响(输出减去输入，手续费算在输入里)，
+// 按行追加json到文件，没有文件时只在内存里；同一个txid只记录一次，重复提交不会重复记账。
+// TxIndexHook在节点接受交易之后记录本钱包发出的交易，包括它花费的合规检查交易(背书服务费)，
+// 别人的转入由调用方(例如subscribe按区块订阅)用add_tx记录。
+// 节点接受之后又被丢弃的交易仍然在索引里，对账时表现为不一致。
+
+/// 对账结果中不一致的一项
+/// delta = reported - expected:
+///   大于0说明本地漏记了入账(例如别人的转入)
+///   小于0说明本地漏记了出账或者重复记了入账
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Discrepancy {
+    pub address: String,
+    pub expected: num_bigint::BigInt,
+    pub reported: num_bigint::BigInt,
+    pub delta: num_bigint::BigInt,
+}
+
+/// 查询address在chain上面的余额
+pub fn node_balance(chain_name: &String, address: &String) -> Result<num_bigint::BigInt> {
+    let resp = ocall::ocall_xchain_get_balance(address)?;
+    for token in resp.get_bcs().iter() {
+        if &token.bcname == chain_name {
+            if token.error != xchain_node_sdk::protos::xchain::XChainErrorEnum::SUCCESS {
+                return Err(Error::from(ErrorKind::ChainRPCError));
+            }
+            return crate::consts::str_as_bigint(&token.balance);
+        }
+    }
+    Err(Error::from(ErrorKind::ChainRPCError))
+}
+
+/// 索引里的一笔交易
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct IndexedTx {
+    /// hex编码，期初余额为空
+    pub txid: String,
+    /// 地址 -> 余额变化(十进制，可以为负)
+    pub deltas: BTreeMap<String, String>,
+}
+
+impl IndexedTx {
+    pub fn from_tx(tx: &xchain::Transaction) -> Self {
+        let mut deltas: BTreeMap<String, num_bigint::BigInt> = BTreeMap::new();
+        let amount = |b: &[u8]| num_bigint::BigInt::from_bytes_be(num_bigint::Sign::Plus, b);
+        for input in tx.get_tx_inputs().iter() {
+            let from = String::from_utf8_lossy(&input.from_addr).into_owned();
+            deltas
+                .entry(from)
+                .or_default()
+                .add_assign(-amount(&input.amount));
+        }
+        for output in tx.get_tx_outputs().iter() {
+            let to = String::from_utf8_lossy(&output.to_addr).into_owned();
+            deltas
+                .entry(to)
+                .or_default()
+                .add_assign(amount(&output.amount));
+        }
+        IndexedTx {
+            txid: hex::encode(&tx.txid),
+            deltas: deltas
+                .into_iter()
+                .map(|(address, delta)| (address, delta.to_string()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TxIndex {
+    path: Option<String>,
+    txs: Mutex<(Vec<IndexedTx>, HashSet<String>)>,
+}
+
+impl TxIndex {
+    /// 只在内存里的索引
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// 从文件加载，之后的记录追加到文件
+    pub fn open(path: &str) -> Result<Self> {
+        let txs = match std::fs::read_to_string(path) {
+            Ok(s) => s
+                .lines()
+                .filter(|l| !l.trim().is_empty())
+                .map(|l| serde_json::from_str(l).map_err(Error::from))
+                .collect::<Result<Vec<IndexedTx>>>()?,
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(Error::from(e)),
+        };
+        let seen = txs
+            .iter()
+            .filter(|t| !t.txid.is_empty())
+            .map(|t| t.txid.to_owned())
+            .collect();
+        Ok(TxIndex {
+            path: Some(path.to_owned()),
+            txs: Mutex::new((txs, seen)),
+        })
+    }
+
+    /// 记录一笔交易，已经记录过时返回false
+    pub fn add_tx(&self, tx: &xchain::Transaction) -> Result<bool> {
+        self.add(IndexedTx::from_tx(tx))
+    }
+
+    /// 开始使用索引之前账户已有的余额
+    pub fn add_opening_balance(&self, address: &str, balance: &num_bigint::BigInt) -> Result<()> {
+        let mut deltas = BTreeMap::new();
+        deltas.insert(address.to_owned(), balance.to_string());
+        self.add(IndexedTx {
+            txid: String::new(),
+            deltas: deltas,
+        })
+        .map(|_| ())
+    }
+
+    fn add(&self, record: IndexedTx) -> Result<bool> {
+        let mut txs = self.txs.lock().unwrap();
+        if !record.txid.is_empty() && txs.1.contains(&record.txid) {
+            return Ok(false);
+        }
+        if let Some(ref path) = self.path {
+            let mut line = serde_json::to_vec(&record)?;
+            line.push(b'\n');
+            let mut f = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            f.write_all(&line)?;
+            f.sync_all()?;
+        }
+        if !record.txid.is_empty() {
+            txs.1.insert(record.txid.to_owned());
+        }
+        txs.0.push(record);
+        Ok(true)
+    }
+
+    /// 按索引计算的addresses的预期余额
+    pub fn expected_balances(
+        &self,
+        addresses: &[String],
+    ) -> Result<BTreeMap<String, num_bigint::BigInt>> {
+        let mut balances: BTreeMap<String, num_bigint::BigInt> = addresses
+            .iter()
+            .map(|a| (a.to_owned(), num_traits::Zero::zero()))
+            .collect();
+        for record in self.txs.lock().unwrap().0.iter() {
+            for (address, delta) in record.deltas.iter() {
+                if let Some(balance) = balances.get_mut(address) {
+                    balance.add_assign(consts::str_as_bigint(delta)?);
+                }
+            }
+        }
+        Ok(balances)
+    }
+}
+
+/// 节点接受交易之后记录到索引，通过Session::add_hook使用
+pub struct TxIndexHook {
+    pub index: std::sync::Arc<TxIndex>,
+}
+
+impl TxIndexHook {
+    fn add(&self, tx: &xchain::Transaction) {
+        if let Err(e) = self.index.add_tx(tx) {
+            tracing::warn!(txid = %hex::encode(&tx.txid), error = %e, "index tx failed");
+        }
+    }
+}
+
+impl session::TxHook for TxIndexHook {
+    fn posted(&self, tx: &xchain::Transaction) {
+        self.add(tx);
+    }
+
+    fn fee_tx_posted(&self, fee_tx: &xchain::Transaction) {
+        self.add(fee_tx);
+    }
+}
+
+/// 按索引计算addresses的预期余额，和节点返回的余额比较，返回所有不一致的账户
+pub fn reconcile_index(
+    chain_name: &String,
+    index: &TxIndex,
+    addresses: &[String],
+) -> Result<Vec<Discrepancy>> {
+    reconcile(chain_name, &index.expected_balances(addresses)?)
+}
+
+/// 对每个托管账户，比较本地账本的预期余额(address -> balance)和节点返回的余额，返回所有不一致的账户
+/// 预期余额可以用TxIndex::expected_balances计算，见reconcile_index
+pub fn reconcile(
+    chain_name: &String,
+    expected: &BTreeMap<String, num_bigint::BigInt>,
+) -> Result<Vec<Discrepancy>> {
+    reconcile_with(expected, |address| node_balance(chain_name, address))
+}
+
+/// 同reconcile，链上余额通过reported_balance获取
+pub fn reconcile_with<F>(
+    expected: &BTreeMap<String, num_bigint::BigInt>,
+    reported_balance: F,
+) -> Result<Vec<Discrepancy>>
+where
+    F: Fn(&String) -> Result<num_bigint::BigInt>,
+{
+    let mut discrepancies = vec![];
+    for (address, expected_balance) in expected.iter() {
+        let reported = reported_balance(address)?;
+        if &reported != expected_balance {
+            discrepancies.push(Discrepancy {
+                address: address.to_owned(),
+                expected: expected_balance.clone(),
+                delta: (&reported).sub(expected_balance),
+                reported: reported,
+            });
+        }
+    }
+    Ok(discrepancies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_traits::FromPrimitive;
+
+    #[test]
+    fn test_reconcile_with() {
+        let mut expected = BTreeMap::new();
+        expected.insert(
+            String::from("alice"),
+            num_bigint::BigInt::from_i64(100).unwrap(),
+        );
+        expected.insert(
+            String::from("bob"),
+            num_bigint::BigInt::from_i64(50).unwrap(),
+        );
+        expected.insert(
+            String::from("carol"),
+            num_bigint::BigInt::from_i64(10).unwrap(),
+        );
+
+        let res = reconcile_with(&expected, |address| {
+            let b = match address.as_str() {
+                "alice" => 100,
+                "bob" => 80,
+                _ => 0,
+            };
+            Ok(num_bigint::BigInt::from_i64(b).unwrap())
+        });
+        assert_eq!(res.is_ok(), true);
+        let res = res.unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].address, "bob");
+        assert_eq!(res[0].delta, num_bigint::BigInt::from_i64(30).unwrap());
+        assert_eq!(res[1].address, "carol");
+        assert_eq!(res[1].delta, num_bigint::BigInt::from_i64(-10).unwrap());
+    }
+
+    fn tx(txid: u8, inputs: &[(&str, u8)], outputs: &[(&str, u8)]) -> xchain::Transaction {
+        let mut tx = xchain::Transaction::new();
+        tx.set_txid(vec![txid]);
+        for (from, amount) in inputs.iter() {
+            let mut input = xchain::TxInput::new();
+            input.set_from_addr(from.as_bytes().to_vec());
+            input.set_amount(vec![*amount]);
+            tx.mut_tx_inputs().push(input);
+        }
+        for (to, amount) in outputs.iter() {
+            let mut output = xchain::TxOutput::new();
+            output.set_to_addr(to.as_bytes().to_vec());
+            output.set_amount(vec![*amount]);
+            tx.mut_tx_outputs().push(output);
+        }
+        tx
+    }
+
+    #[test]
+    fn test_tx_index() {
+        let dir = std::env::temp_dir().join(format!(
+            "xchain-reconcile-{}",
+            crate::wallet::get_nonce().unwrap()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("txs.jsonl");
+        let path = path.to_str().unwrap();
+        let am = |i| num_bigint::BigInt::from_i64(i).unwrap();
+        let addresses = vec![String::from("alice"), String::from("bob")];
+
+        let index = std::sync::Arc::new(TxIndex::open(path).unwrap());
+        index.add_opening_balance("alice", &am(100)).unwrap();
+        // alice转给bob 30，手续费1，找零69
+        let pay = tx(
+            1,
+            &[("alice", 100)],
+            &[("bob", 30), ("$", 1), ("alice", 69)],
+        );
+        let hook = TxIndexHook {
+            index: index.clone(),
+        };
+        session::TxHook::posted(&hook, &pay);
+        // 重复提交不重复记账
+        assert_eq!(index.add_tx(&pay).unwrap(), false);
+        let expected = index.expected_balances(&addresses).unwrap();
+        assert_eq!(expected["alice"], am(69));
+        assert_eq!(expected["bob"], am(30));
+
+        // 重新打开之后还在，漏记了carol的转入
+        let index = TxIndex::open(path).unwrap();
+        assert_eq!(index.add_tx(&pay).unwrap(), false);
+        let res = reconcile_with(&index.expected_balances(&addresses).unwrap(), |address| {
+            Ok(match address.as_str() {
+                "alice" => am(69),
+                _ => am(35),
+            })
+        })
+        .unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].address, "bob");
+        assert_eq!(res[0].delta, am(5));
+
+        index
+            .add_tx(&tx(2, &[("carol", 5)], &[("bob", 5)]))
+            .unwrap();
+        assert_eq!(index.expected_balances(&addresses).unwrap()["bob"], am(35));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_tx_index_with_fee_tx() {
+        let index = std::sync::Arc::new(TxIndex::new());
+        let am = |i| num_bigint::BigInt::from_i64(i).unwrap();
+        let addresses = vec![String::from("alice"), String::from("bob")];
+        index.add_opening_balance("alice", &am(100)).unwrap();
+        // 合规检查交易付给背书服务4，找零96，真正的交易花费找零
+        let fee_tx = tx(1, &[("alice", 100)], &[("endorser", 4), ("alice", 96)]);
+        let mut pay = tx(2, &[("alice", 96)], &[("bob", 30), ("alice", 66)]);
+        pay.mut_tx_inputs()[0].set_ref_txid(fee_tx.txid.clone());
+        let hook = TxIndexHook {
+            index: index.clone(),
+        };
+        session::TxHook::fee_tx_posted(&hook, &fee_tx);
+        session::TxHook::posted(&hook, &pay);
+        let expected = index.expected_balances(&addresses).unwrap();
+        assert_eq!(expected["alice"], am(66));
+        assert_eq!(expected["bob"], am(30));
+        let res = reconcile_with(&expected, |address| {
+            Ok(match address.as_str() {
+                "alice" => am(66),
+                _ => am(30),
+            })
+        })
+        .unwrap();
+        assert_eq!(res.is_empty(), true);
+    }
+}
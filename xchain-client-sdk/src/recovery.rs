@@ -0,0 +1,128 @@
+use crate::{chains, contract_account, multisig, wallet};
+use xchain_node_sdk::{
+    errors::*,
+    ocall,
+    protos::xchain::{self, PermissionRule},
+};
+
+// 合约账户的恢复AK，防止私钥丢失之后账户无法再修改ACL。
+// setup_recovery把恢复AK加入合约账户的ACL，权重单独就能满足ACL；recover用恢复AK把ACL换成新的。
+// 注意这不是时间锁: 链上的ACL不支持按高度生效，恢复AK从加入ACL开始就是合约账户的完全共同所有者，
+// 可以单独签名任何交易。active_height只是recover在签名之前检查的SDK侧约束，
+// 拿到恢复AK私钥的人不经过recover就能绕过，所以恢复AK的私钥要和账户的其他私钥一样保管。
+
+/// 恢复方案，在设置时和恢复AK的私钥一起交给保管方
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecoveryPlan {
+    pub contract_account: String,
+    pub recovery_address: String,
+    /// recover从这个高度开始才签名，只在SDK里检查，链上不生效
+    pub active_height: i64,
+}
+
+/// 在current里加入恢复AK，权重等于acceptValue，恢复AK单独就能满足ACL；只支持SIGN_THRESHOLD规则的ACL
+pub fn recovery_acl(current: &xchain::Acl, recovery_address: &str) -> Result<xchain::Acl> {
+    let pm = current.get_pm();
+    if pm.rule != PermissionRule::SIGN_THRESHOLD || recovery_address.is_empty() {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let mut acl = current.clone();
+    acl.mut_aksWeight()
+        .insert(recovery_address.to_owned(), pm.acceptValue);
+    Ok(acl)
+}
+
+/// 高度达到plan.active_height之前返回InvalidArguments
+pub fn check_active(plan: &RecoveryPlan, height: i64) -> Result<()> {
+    if height < plan.active_height {
+        return Err(Error::new(
+            ErrorKind::InvalidArguments,
+            format!(
+                "recover refuses to sign before height {}, current height {}",
+                plan.active_height, height
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// 把恢复AK加入account.contract_account的ACL，signers需要满足当前的ACL
+/// 交易上链之后恢复AK立即可以单独修改ACL或者转账
+pub fn setup_recovery(
+    account: &wallet::Account,
+    signers: &[wallet::Account],
+    chain_name: &String,
+    plan: &RecoveryPlan,
+    fee: &String,
+) -> Result<String> {
+    if account.contract_account != plan.contract_account {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let acl = recovery_acl(
+        &multisig::query_acl(&plan.contract_account)?,
+        &plan.recovery_address,
+    )?;
+    contract_account::set_acl(account, signers, chain_name, &acl, fee)
+}
+
+/// 用恢复AK把合约账户的ACL换成new_acl，链的高度没有达到plan.active_height时SDK拒绝签名
+pub fn recover(
+    recovery: &wallet::Account,
+    chain_name: &String,
+    plan: &RecoveryPlan,
+    new_acl: &xchain::Acl,
+    fee: &String,
+) -> Result<String> {
+    if recovery.address != plan.recovery_address {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let status = ocall::ocall_xchain_get_block_chain_status(chain_name)?;
+    check_active(plan, chains::decode_chain_info(&status)?.height)?;
+    let mut account = recovery.clone();
+    account.contract_account = plan.contract_account.to_owned();
+    contract_account::set_acl(&account, &[recovery.clone()], chain_name, new_acl, fee)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_recovery_acl() {
+        let mut aks = HashMap::new();
+        aks.insert(String::from("alice"), 0.6);
+        aks.insert(String::from("bob"), 0.4);
+        let current = contract_account::threshold_acl(1.0, aks).unwrap();
+        let acl = recovery_acl(&current, "carol").unwrap();
+        assert_eq!(
+            multisig::check_acl(&acl, &[String::from("carol")]).is_ok(),
+            true
+        );
+        assert_eq!(
+            multisig::check_acl(&acl, &[String::from("alice"), String::from("bob")]).is_ok(),
+            true
+        );
+        assert_eq!(recovery_acl(&current, "").is_ok(), false);
+        assert_eq!(recovery_acl(&xchain::Acl::new(), "carol").is_ok(), false);
+
+        let plan = RecoveryPlan {
+            contract_account: String::from("XC1111111111111111@xuper"),
+            recovery_address: String::from("carol"),
+            active_height: 100,
+        };
+        assert_eq!(check_active(&plan, 99).is_ok(), false);
+        assert_eq!(check_active(&plan, 100).is_ok(), true);
+
+        let acc = wallet::Account {
+            address: String::from("dave"),
+            ..Default::default()
+        };
+        assert_eq!(
+            recover(&acc, &String::from("xuper"), &plan, &acl, &String::new()).is_ok(),
+            false
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use std::fmt;
+
+use xchain_node_sdk::{
+    errors::*,
+    protos::{xchain, xendorser},
+};
+
+// 合规检查被背书服务拒绝时，响应的header.error不是SUCCESS，或者没有签名并且ResponseData是错误信息:
+//   {"code": "BLACKLIST_HIT", "message": "..."}
+// code也可以放在reason、errorCode里，message也可以放在msg、error里。
+// Session返回ComplianceRejected，错误里带着解析出来的Rejection，应用用rejection::of按Reason处理，
+// 不需要匹配背书服务的错误文本。不认识的code是Reason::Other，保留原始的code。
+
+/// 拒绝的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Reason {
+    /// 地址命中黑名单
+    Blacklisted,
+    /// 超出额度
+    QuotaExceeded,
+    /// 没有完成KYC
+    KycMissing,
+    /// 其他原因，背书服务返回的code
+    Other(String),
+}
+
+impl Reason {
+    /// 按背书服务返回的code归类，不区分大小写和分隔符
+    pub fn from_code(code: &str) -> Self {
+        let c = code
+            .to_lowercase()
+            .replace(|c: char| c == '-' || c == ' ', "_");
+        if c.contains("blacklist") || c.contains("black_list") || c.contains("sanction") {
+            Reason::Blacklisted
+        } else if c.contains("quota") || c.contains("limit_exceeded") {
+            Reason::QuotaExceeded
+        } else if c.contains("kyc") {
+            Reason::KycMissing
+        } else {
+            Reason::Other(code.to_owned())
+        }
+    }
+
+    /// 给用户看的说明
+    pub fn description(&self) -> &str {
+        match self {
+            Reason::Blacklisted => "地址命中黑名单",
+            Reason::QuotaExceeded => "超出额度",
+            Reason::KycMissing => "没有完成KYC认证",
+            Reason::Other(_) => "合规检查未通过",
+        }
+    }
+}
+
+/// 背书服务拒绝合规检查的原因
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rejection {
+    pub reason: Reason,
+    /// 背书服务返回的code
+    pub code: String,
+    pub message: String,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "compliance check rejected: {} {}",
+            self.code, self.message
+        )
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+fn field(body: &serde_json::Map<String, serde_json::Value>, keys: &[&str]) -> Option<String> {
+    keys.iter().find_map(|k| match body.get(*k) {
+        Some(serde_json::Value::String(s)) if !s.is_empty() => Some(s.to_owned()),
+        Some(serde_json::Value::Number(n)) => Some(n.to_string()),
+        _ => None,
+    })
+}
+
+/// 合规检查的响应是拒绝时返回原因
+pub fn parse(resp: &xendorser::EndorserResponse) -> Option<Rejection> {
+    let header_error = resp.get_header().error;
+    let body: Option<serde_json::Map<String, serde_json::Value>> =
+        serde_json::from_slice(&resp.ResponseData).ok();
+    let code = body
+        .as_ref()
+        .and_then(|b| field(b, &["code", "reason", "errorCode"]));
+    let failed = header_error != xchain::XChainErrorEnum::SUCCESS;
+    let code = match code {
+        Some(code) if failed || !resp.has_EndorserSign() => code,
+        None if failed => format!("{:?}", header_error),
+        _ => return None,
+    };
+    let message = body
+        .as_ref()
+        .and_then(|b| field(b, &["message", "msg", "error"]))
+        .unwrap_or_else(|| String::from_utf8_lossy(&resp.ResponseData).into_owned());
+    Some(Rejection {
+        reason: Reason::from_code(&code),
+        code: code,
+        message: message,
+    })
+}
+
+/// ComplianceRejected错误里的拒绝原因
+pub fn of(e: &Error) -> Option<&Rejection> {
+    if e.kind() != ErrorKind::ComplianceRejected {
+        return None;
+    }
+    e.get_ref().and_then(|r| r.downcast_ref::<Rejection>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(body: &str) -> xendorser::EndorserResponse {
+        let mut resp = xendorser::EndorserResponse::new();
+        resp.set_ResponseData(body.as_bytes().to_vec());
+        resp
+    }
+
+    #[test]
+    fn test_parse_rejection() {
+        let r = parse(&response(
+            r#"{"code": "BLACKLIST_HIT", "message": "address is blacklisted"}"#,
+        ))
+        .unwrap();
+        assert_eq!(r.reason, Reason::Blacklisted);
+        assert_eq!(r.code, "BLACKLIST_HIT");
+        assert_eq!(r.message, "address is blacklisted");
+
+        let r = parse(&response(r#"{"reason": "quota-exceeded", "msg": "daily"}"#)).unwrap();
+        assert_eq!(r.reason, Reason::QuotaExceeded);
+        let r = parse(&response(r#"{"errorCode": "KYC_MISSING"}"#)).unwrap();
+        assert_eq!(r.reason, Reason::KycMissing);
+        let r = parse(&response(r#"{"code": 4031, "error": "risk"}"#)).unwrap();
+        assert_eq!(r.reason, Reason::Other(String::from("4031")));
+        assert_eq!(r.message, "risk");
+
+        // header里的错误，body不是json
+        let mut resp = response("denied");
+        resp.mut_header().error = xchain::XChainErrorEnum::UNKNOW_ERROR;
+        let r = parse(&resp).unwrap();
+        assert_eq!(r.code, "UNKNOW_ERROR");
+        assert_eq!(r.message, "denied");
+
+        // 正常的响应
+        assert_eq!(parse(&response("")).is_none(), true);
+        let mut resp = response(r#"{"code": "BLACKLIST_HIT"}"#);
+        resp.set_EndorserSign(xchain::SignatureInfo::new());
+        assert_eq!(parse(&resp).is_none(), true);
+
+        let e = Error::new(ErrorKind::ComplianceRejected, r.clone());
+        assert_eq!(of(&e), Some(&r));
+        assert_eq!(of(&Error::from(ErrorKind::ComplianceRejected)), None);
+    }
+}
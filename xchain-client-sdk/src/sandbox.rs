@@ -0,0 +1,469 @@
+use std::collections::{BTreeMap, HashMap};
+
+use xchain_node_sdk::{errors::*, protos::xchain};
+
+// 单元测试用的本地合约执行器: 在内存KV上执行合约，生成和背书服务一样的预执行结果，
+// 不需要节点和背书服务。
+// 合约可以是rust闭包，也可以是WASM。SDK里没有xuperchain合约的contract.proto，
+// WASM合约使用下面的简化ABI，不能直接运行为xuperchain编译的合约:
+//   导出 memory 和以方法名命名的函数 () -> i32，返回值是合约的status
+//   导入 env.get_object(key_ptr, key_len, buf_ptr, buf_cap) -> i32  值的长度，不存在时为-1，
+//        长度大于buf_cap时不拷贝
+//        env.put_object(key_ptr, key_len, value_ptr, value_len)
+//        env.get_arg(name_ptr, name_len, buf_ptr, buf_cap) -> i32  同get_object
+//        env.set_output(ptr, len)
+// 每次读写消耗1个gas。
+
+/// 合约执行的上下文，bucket是合约名
+pub struct Context<'a> {
+    bucket: String,
+    args: &'a HashMap<String, Vec<u8>>,
+    state: &'a BTreeMap<(String, Vec<u8>), Version>,
+    reads: BTreeMap<Vec<u8>, (Vec<u8>, i32)>,
+    writes: BTreeMap<Vec<u8>, Vec<u8>>,
+    output: Vec<u8>,
+    gas: i64,
+}
+
+#[derive(Debug, Clone)]
+struct Version {
+    value: Vec<u8>,
+    ref_txid: Vec<u8>,
+    ref_offset: i32,
+}
+
+impl<'a> Context<'a> {
+    pub fn get_object(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        self.gas += 1;
+        if let Some(v) = self.writes.get(key) {
+            return Some(v.clone());
+        }
+        let version = self.state.get(&(self.bucket.to_owned(), key.to_vec()));
+        self.reads.entry(key.to_vec()).or_insert(match version {
+            Some(v) => (v.ref_txid.clone(), v.ref_offset),
+            None => (vec![], 0),
+        });
+        version.map(|v| v.value.clone())
+    }
+
+    pub fn put_object(&mut self, key: &[u8], value: &[u8]) {
+        self.gas += 1;
+        self.writes.insert(key.to_vec(), value.to_vec());
+    }
+
+    pub fn arg(&self, name: &str) -> Option<&Vec<u8>> {
+        self.args.get(name)
+    }
+
+    pub fn set_output(&mut self, output: &[u8]) {
+        self.output = output.to_vec();
+    }
+}
+
+pub trait Contract: Send + Sync {
+    /// 执行方法，返回status，body通过Context::set_output设置
+    fn invoke(&self, ctx: &mut Context, method: &str) -> Result<i32>;
+}
+
+impl<F> Contract for F
+where
+    F: Fn(&mut Context, &str) -> Result<i32> + Send + Sync,
+{
+    fn invoke(&self, ctx: &mut Context, method: &str) -> Result<i32> {
+        self(ctx, method)
+    }
+}
+
+#[derive(Default)]
+pub struct Sandbox {
+    contracts: HashMap<String, Box<dyn Contract>>,
+    state: BTreeMap<(String, Vec<u8>), Version>,
+    utxos: Vec<xchain::Utxo>,
+}
+
+impl Sandbox {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn deploy(&mut self, contract_name: &str, contract: Box<dyn Contract>) {
+        self.contracts.insert(contract_name.to_owned(), contract);
+    }
+
+    /// 给address一个金额为amount的utxo
+    pub fn add_utxo(
+        &mut self,
+        address: &str,
+        amount: &str,
+        ref_txid: &[u8],
+        ref_offset: i32,
+    ) -> Result<()> {
+        let mut u = xchain::Utxo::new();
+        u.set_toAddr(address.as_bytes().to_vec());
+        u.set_amount(crate::consts::str_as_bigint(amount)?.to_bytes_be().1);
+        u.set_refTxid(ref_txid.to_vec());
+        u.set_refOffset(ref_offset);
+        self.utxos.push(u);
+        Ok(())
+    }
+
+    pub fn get_object(&self, contract_name: &str, key: &[u8]) -> Option<Vec<u8>> {
+        self.state
+            .get(&(contract_name.to_owned(), key.to_vec()))
+            .map(|v| v.value.clone())
+    }
+
+    /// 模拟PreExecWithFee: 依次执行合约调用，选出足够的utxo
+    pub fn pre_exec(
+        &self,
+        req: &xchain::PreExecWithSelectUTXORequest,
+    ) -> Result<xchain::PreExecWithSelectUTXOResponse> {
+        let mut resp = xchain::PreExecWithSelectUTXOResponse::new();
+        resp.set_bcname(req.bcname.to_owned());
+        let invoke_resp = resp.mut_response();
+        for r in req.get_request().get_requests().iter() {
+            let contract = self
+                .contracts
+                .get(&r.contract_name)
+                .ok_or(Error::from(ErrorKind::InvalidArguments))?;
+            let mut ctx = Context {
+                bucket: r.contract_name.to_owned(),
+                args: &r.args,
+                state: &self.state,
+                reads: BTreeMap::new(),
+                writes: BTreeMap::new(),
+                output: vec![],
+                gas: 0,
+            };
+            let status = contract.invoke(&mut ctx, &r.method_name)?;
+            for (key, (ref_txid, ref_offset)) in ctx.reads.into_iter() {
+                let mut input = xchain::TxInputExt::new();
+                input.set_bucket(r.contract_name.to_owned());
+                input.set_key(key);
+                input.set_ref_txid(ref_txid);
+                input.set_ref_offset(ref_offset);
+                invoke_resp.mut_inputs().push(input);
+            }
+            for (key, value) in ctx.writes.into_iter() {
+                let mut output = xchain::TxOutputExt::new();
+                output.set_bucket(r.contract_name.to_owned());
+                output.set_key(key);
+                output.set_value(value);
+                invoke_resp.mut_outputs().push(output);
+            }
+            let mut cr = xchain::ContractResponse::new();
+            cr.set_status(status);
+            cr.set_body(ctx.output.clone());
+            invoke_resp.mut_responses().push(cr);
+            invoke_resp.mut_response().push(ctx.output);
+            invoke_resp.mut_requests().push(r.clone());
+            invoke_resp.gas_used += ctx.gas;
+        }
+
+        let need = num_bigint::BigInt::from(req.totalAmount);
+        let mut total: num_bigint::BigInt = num_traits::Zero::zero();
+        let mut selected = vec![];
+        for u in self.utxos.iter() {
+            if total >= need {
+                break;
+            }
+            if u.toAddr == req.address.as_bytes() {
+                total += num_bigint::BigInt::from_bytes_be(num_bigint::Sign::Plus, &u.amount);
+                selected.push(u.clone());
+            }
+        }
+        if total < need {
+            return Err(Error::from(ErrorKind::InvalidArguments));
+        }
+        let utxo_output = resp.mut_utxoOutput();
+        utxo_output.set_utxoList(protobuf::RepeatedField::from_vec(selected));
+        utxo_output.set_totalSelected(total.to_str_radix(10));
+        Ok(resp)
+    }
+
+    /// 交易上链: 写集生效，花掉的utxo删除，输出成为新的utxo
+    pub fn commit(&mut self, tx: &xchain::Transaction) {
+        for (i, output) in tx.get_tx_outputs_ext().iter().enumerate() {
+            self.state.insert(
+                (output.bucket.to_owned(), output.key.clone()),
+                Version {
+                    value: output.value.clone(),
+                    ref_txid: tx.txid.clone(),
+                    ref_offset: i as i32,
+                },
+            );
+        }
+        let spent: Vec<(&[u8], i32)> = tx
+            .get_tx_inputs()
+            .iter()
+            .map(|i| (i.get_ref_txid(), i.ref_offset))
+            .collect();
+        self.utxos
+            .retain(|u| !spent.contains(&(u.get_refTxid(), u.refOffset)));
+        for (i, output) in tx.get_tx_outputs().iter().enumerate() {
+            let mut u = xchain::Utxo::new();
+            u.set_toAddr(output.to_addr.clone());
+            u.set_amount(output.amount.clone());
+            u.set_refTxid(tx.txid.clone());
+            u.set_refOffset(i as i32);
+            self.utxos.push(u);
+        }
+    }
+}
+
+/// 使用上面简化ABI的WASM合约
+pub struct WasmContract {
+    module: wasmi::Module,
+}
+
+const GET_OBJECT: usize = 0;
+const PUT_OBJECT: usize = 1;
+const GET_ARG: usize = 2;
+const SET_OUTPUT: usize = 3;
+
+struct Resolver;
+
+impl wasmi::ModuleImportResolver for Resolver {
+    fn resolve_func(
+        &self,
+        field_name: &str,
+        _signature: &wasmi::Signature,
+    ) -> std::result::Result<wasmi::FuncRef, wasmi::Error> {
+        use wasmi::ValueType::I32;
+        let (index, params, ret): (usize, &'static [wasmi::ValueType], _) = match field_name {
+            "get_object" => (GET_OBJECT, &[I32, I32, I32, I32], Some(I32)),
+            "put_object" => (PUT_OBJECT, &[I32, I32, I32, I32], None),
+            "get_arg" => (GET_ARG, &[I32, I32, I32, I32], Some(I32)),
+            "set_output" => (SET_OUTPUT, &[I32, I32], None),
+            _ => {
+                return Err(wasmi::Error::Instantiation(format!(
+                    "unknown import {}",
+                    field_name
+                )))
+            }
+        };
+        Ok(wasmi::FuncInstance::alloc_host(
+            wasmi::Signature::new(params, ret),
+            index,
+        ))
+    }
+}
+
+struct Host<'a, 'b> {
+    ctx: &'a mut Context<'b>,
+    memory: wasmi::MemoryRef,
+}
+
+impl<'a, 'b> Host<'a, 'b> {
+    fn read(
+        &self,
+        args: &wasmi::RuntimeArgs,
+        i: usize,
+    ) -> std::result::Result<Vec<u8>, wasmi::Trap> {
+        let ptr: u32 = args.nth_checked(i)?;
+        let len: u32 = args.nth_checked(i + 1)?;
+        self.memory
+            .get(ptr, len as usize)
+            .map_err(|_| wasmi::Trap::new(wasmi::TrapKind::MemoryAccessOutOfBounds))
+    }
+
+    /// 把value写到(buf_ptr, buf_cap)，返回长度
+    fn write(
+        &self,
+        args: &wasmi::RuntimeArgs,
+        i: usize,
+        value: Option<Vec<u8>>,
+    ) -> std::result::Result<Option<wasmi::RuntimeValue>, wasmi::Trap> {
+        let ptr: u32 = args.nth_checked(i)?;
+        let cap: u32 = args.nth_checked(i + 1)?;
+        let len = match value {
+            Some(v) => {
+                if v.len() <= cap as usize {
+                    self.memory
+                        .set(ptr, &v)
+                        .map_err(|_| wasmi::Trap::new(wasmi::TrapKind::MemoryAccessOutOfBounds))?;
+                }
+                v.len() as i32
+            }
+            None => -1,
+        };
+        Ok(Some(wasmi::RuntimeValue::I32(len)))
+    }
+}
+
+impl<'a, 'b> wasmi::Externals for Host<'a, 'b> {
+    fn invoke_index(
+        &mut self,
+        index: usize,
+        args: wasmi::RuntimeArgs,
+    ) -> std::result::Result<Option<wasmi::RuntimeValue>, wasmi::Trap> {
+        match index {
+            GET_OBJECT => {
+                let key = self.read(&args, 0)?;
+                let value = self.ctx.get_object(&key);
+                self.write(&args, 2, value)
+            }
+            PUT_OBJECT => {
+                let key = self.read(&args, 0)?;
+                let value = self.read(&args, 2)?;
+                self.ctx.put_object(&key, &value);
+                Ok(None)
+            }
+            GET_ARG => {
+                let name = String::from_utf8(self.read(&args, 0)?)
+                    .map_err(|_| wasmi::Trap::new(wasmi::TrapKind::UnexpectedSignature))?;
+                let value = self.ctx.arg(&name).cloned();
+                self.write(&args, 2, value)
+            }
+            SET_OUTPUT => {
+                let output = self.read(&args, 0)?;
+                self.ctx.set_output(&output);
+                Ok(None)
+            }
+            _ => Err(wasmi::Trap::new(wasmi::TrapKind::UnexpectedSignature)),
+        }
+    }
+}
+
+impl WasmContract {
+    pub fn new(code: &[u8]) -> Result<Self> {
+        let module = wasmi::Module::from_buffer(code)
+            .map_err(|_| Error::from(ErrorKind::InvalidArguments))?;
+        Ok(WasmContract { module: module })
+    }
+}
+
+impl Contract for WasmContract {
+    /// 每次调用都重新实例化，合约的状态只能保存在KV里
+    /// 合约trap时返回status 500
+    fn invoke(&self, ctx: &mut Context, method: &str) -> Result<i32> {
+        let imports = wasmi::ImportsBuilder::new().with_resolver("env", &Resolver);
+        let instance = wasmi::ModuleInstance::new(&self.module, &imports)
+            .map_err(|_| Error::from(ErrorKind::InvalidArguments))?
+            .assert_no_start();
+        let memory = instance
+            .export_by_name("memory")
+            .and_then(|e| e.as_memory().cloned())
+            .ok_or(Error::from(ErrorKind::InvalidArguments))?;
+        let mut host = Host {
+            ctx: ctx,
+            memory: memory,
+        };
+        match instance.invoke_export(method, &[], &mut host) {
+            Ok(Some(wasmi::RuntimeValue::I32(status))) => Ok(status),
+            Ok(_) => Err(Error::from(ErrorKind::InvalidArguments)),
+            Err(wasmi::Error::Function(_)) => Err(Error::from(ErrorKind::InvalidArguments)),
+            Err(e) => {
+                host.ctx.set_output(format!("{:?}", e).as_bytes());
+                Ok(500)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session;
+
+    const COUNTER: &str = r#"
+(module
+  (import "env" "get_object" (func $get (param i32 i32 i32 i32) (result i32)))
+  (import "env" "put_object" (func $put (param i32 i32 i32 i32)))
+  (import "env" "set_output" (func $out (param i32 i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "counter")
+  (func (export "increase") (result i32)
+    (local $n i32)
+    (if (i32.eq (call $get (i32.const 0) (i32.const 7) (i32.const 16) (i32.const 1)) (i32.const 1))
+      (then (local.set $n (i32.load8_u (i32.const 16)))))
+    (i32.store8 (i32.const 16) (i32.add (local.get $n) (i32.const 1)))
+    (call $put (i32.const 0) (i32.const 7) (i32.const 16) (i32.const 1))
+    (call $out (i32.const 16) (i32.const 1))
+    (i32.const 200))
+  (func (export "crash") (result i32)
+    unreachable))
+"#;
+
+    fn request(contract_name: &str, method_name: &str) -> xchain::PreExecWithSelectUTXORequest {
+        let mut r = xchain::InvokeRequest::new();
+        r.set_module_name(String::from("wasm"));
+        r.set_contract_name(String::from(contract_name));
+        r.set_method_name(String::from(method_name));
+        let mut req = xchain::PreExecWithSelectUTXORequest::new();
+        req.set_bcname(String::from("xuper"));
+        req.set_address(String::from("alice"));
+        req.set_totalAmount(5);
+        req.mut_request().mut_requests().push(r);
+        req
+    }
+
+    #[test]
+    fn test_wasm_counter() {
+        let code = wat::parse_str(COUNTER).unwrap();
+        let mut sandbox = Sandbox::new();
+        sandbox.deploy("counter", Box::new(WasmContract::new(&code).unwrap()));
+        sandbox.add_utxo("alice", "3", &[0xaa], 0).unwrap();
+        sandbox.add_utxo("bob", "10", &[0xaa], 1).unwrap();
+        sandbox.add_utxo("alice", "3", &[0xaa], 2).unwrap();
+
+        let req = request("counter", "increase");
+        let resp = sandbox.pre_exec(&req).unwrap();
+        session::verify_pre_exec_binding(&req, &resp).unwrap();
+        session::verify_read_set(req.get_request(), &resp).unwrap();
+        assert_eq!(resp.get_response().get_responses()[0].status, 200);
+        assert_eq!(resp.get_response().get_responses()[0].body, vec![1]);
+        assert_eq!(resp.get_response().gas_used, 2);
+        assert_eq!(resp.get_utxoOutput().totalSelected, "6");
+
+        let mut tx = xchain::Transaction::new();
+        tx.set_txid(vec![0xbb]);
+        tx.set_tx_outputs_ext(resp.get_response().outputs.clone());
+        sandbox.commit(&tx);
+        assert_eq!(sandbox.get_object("counter", b"counter"), Some(vec![1]));
+
+        // 读集带上版本
+        let resp = sandbox.pre_exec(&req).unwrap();
+        let input = &resp.get_response().get_inputs()[0];
+        assert_eq!((input.get_ref_txid(), input.ref_offset), (&[0xbb][..], 0));
+        assert_eq!(resp.get_response().get_responses()[0].body, vec![2]);
+
+        let resp = sandbox.pre_exec(&request("counter", "crash")).unwrap();
+        assert_eq!(resp.get_response().get_responses()[0].status, 500);
+        assert_eq!(
+            sandbox.pre_exec(&request("counter", "missing")).is_ok(),
+            false
+        );
+        assert_eq!(
+            sandbox.pre_exec(&request("other", "increase")).is_ok(),
+            false
+        );
+    }
+
+    #[test]
+    fn test_native_contract() {
+        let mut sandbox = Sandbox::new();
+        let contract = |ctx: &mut Context, method: &str| -> Result<i32> {
+            let value = ctx.arg("value").cloned().unwrap_or_default();
+            if method != "set" {
+                return Ok(404);
+            }
+            ctx.put_object(b"key", &value);
+            ctx.set_output(&value);
+            Ok(200)
+        };
+        sandbox.deploy("kv", Box::new(contract));
+        sandbox.add_utxo("alice", "5", &[0xaa], 0).unwrap();
+        let mut req = request("kv", "set");
+        req.mut_request().mut_requests()[0]
+            .mut_args()
+            .insert(String::from("value"), b"v".to_vec());
+        let resp = sandbox.pre_exec(&req).unwrap();
+        assert_eq!(resp.get_response().get_outputs()[0].value, b"v".to_vec());
+        assert_eq!(resp.get_response().get_inputs().len(), 0);
+
+        // utxo不够
+        req.set_totalAmount(6);
+        assert_eq!(sandbox.pre_exec(&req).is_ok(), false);
+    }
+}
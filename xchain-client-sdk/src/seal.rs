@@ -0,0 +1,90 @@
+use sgx_tseal::SgxSealedData;
+use sgx_types::sgx_sealed_data_t;
+
+use xchain_node_sdk::errors::*;
+
+// SGX sealing: 用enclave的密钥(MRSIGNER策略)加密数据，只有同一个签名者的enclave在同一台机器上能解密。
+// 密文可以交给不可信的宿主保存，私钥的明文只存在于enclave内部。
+
+fn sgx_error(status: sgx_types::sgx_status_t) -> Error {
+    Error::new(ErrorKind::CryptoError, format!("sgx seal: {:?}", status))
+}
+
+/// 加密data，aad以明文保存并参与认证
+pub fn seal(aad: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let sealed = SgxSealedData::<[u8]>::seal_data(aad, data).map_err(sgx_error)?;
+    let size =
+        SgxSealedData::<[u8]>::calc_raw_sealed_data_size(aad.len() as u32, data.len() as u32);
+    if size == u32::max_value() {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let mut raw = vec![0u8; size as usize];
+    let res =
+        unsafe { sealed.to_raw_sealed_data_t(raw.as_mut_ptr() as *mut sgx_sealed_data_t, size) };
+    res.ok_or(Error::from(ErrorKind::CryptoError))?;
+    Ok(raw)
+}
+
+/// 返回(aad, data)，不是本enclave加密的或者被篡改时返回CryptoError
+pub fn unseal(raw: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut raw = raw.to_vec();
+    let sealed = unsafe {
+        SgxSealedData::<[u8]>::from_raw_sealed_data_t(
+            raw.as_mut_ptr() as *mut sgx_sealed_data_t,
+            raw.len() as u32,
+        )
+    }
+    .ok_or(Error::from(ErrorKind::ParseError))?;
+    let unsealed = sealed.unseal_data().map_err(sgx_error)?;
+    Ok((
+        unsealed.get_additional_txt().to_vec(),
+        unsealed.get_decrypt_txt().to_vec(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 需要SGX环境(硬件或者SGX_MODE=SIM): cargo test --features sgx-seal seal::
+    #[test]
+    fn test_seal_round_trip() {
+        let raw = seal(b"address", b"private key").unwrap();
+        let (aad, data) = unseal(&raw).unwrap();
+        assert_eq!(aad, b"address".to_vec());
+        assert_eq!(data, b"private key".to_vec());
+
+        // 篡改密文或者附加数据
+        let mut tampered = raw.clone();
+        let n = tampered.len();
+        tampered[n - 1] ^= 1;
+        assert_eq!(unseal(&tampered).is_ok(), false);
+        let mut tampered = raw.clone();
+        tampered[n - "private key".len() - 1] ^= 1;
+        assert_eq!(unseal(&tampered).is_ok(), false);
+
+        assert_eq!(unseal(&raw[..raw.len() / 2]).is_ok(), false);
+        assert_eq!(unseal(&[]).is_ok(), false);
+    }
+
+    #[test]
+    fn test_seal_account() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = crate::wallet::Account::new(d.to_str().unwrap(), "", "");
+        let sealed_path = std::env::temp_dir().join(format!(
+            "xchain-sealed-{}",
+            crate::wallet::get_nonce().unwrap()
+        ));
+        let sealed_path = sealed_path.to_str().unwrap();
+        acc.seal_to_file(sealed_path).unwrap();
+        let loaded = crate::wallet::Account::from_sealed_file(sealed_path, "", "").unwrap();
+        assert_eq!(loaded.address, acc.address);
+        let sig = loaded.sign(b"msg").unwrap();
+        assert_eq!(acc.verify(b"msg", &sig).is_ok(), true);
+
+        // 已经在内存里的账户不能再封存
+        assert_eq!(loaded.seal_to_file(sealed_path).is_ok(), false);
+        std::fs::remove_file(sealed_path).unwrap();
+    }
+}
@@ -0,0 +1,302 @@
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{session, wallet};
+use xchain_node_sdk::{errors::*, protos::xchain};
+
+// 每个账户在一个enclave实例里的单调递增序号，嵌入在交易的nonce里:
+//   SEQ_PREFIX + instance + ":" + seq + ":" + 原来的nonce
+// 下游对账时按(initiator, instance)收集链上交易的序号，用find_gaps找出缺失的交易。
+// 序号在使用之前先持久化(用账户签名封存，由Saver写到存储，例如SGX sealed文件)，
+// 崩溃之后可能跳过序号但不会重复。签名只防篡改，不防回滚到旧的封存状态。
+// 序号嵌入在nonce里，参与txid的计算，只能在签名之前分配；合规检查被拒绝、提交失败或者崩溃时序号被烧掉。
+// 分配之后还没有确认提交的序号记录在状态里(unposted)，节点接受交易之后删除，
+// find_gaps跳过这些序号，它们不算缺失的交易。
+
+pub const SEQ_PREFIX: &str = "seq:";
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SequenceState {
+    pub address: String,
+    pub instance: String,
+    /// 下一个可用的序号，从1开始
+    pub next: u64,
+    /// 已经分配、还没有确认提交的序号，包括正在处理的和被烧掉的
+    #[serde(default)]
+    pub unposted: Vec<u64>,
+}
+
+/// 封存之后的状态，payload是被签名的json字符串
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SealedSequence {
+    pub payload: String,
+    pub public_key: String,
+    /// hex编码
+    pub sign: String,
+}
+
+/// 读取封存的状态，没有时返回None
+pub type Loader = Box<dyn Fn() -> Result<Option<String>> + Send + Sync>;
+/// 写入封存的状态
+pub type Saver = Box<dyn Fn(&str) -> Result<()> + Send + Sync>;
+
+pub fn seal(account: &wallet::Account, state: &SequenceState) -> Result<String> {
+    let payload = serde_json::to_string(state)?;
+    let sealed = SealedSequence {
+        sign: hex::encode(account.sign(payload.as_bytes())?),
+        public_key: account.public_key()?,
+        payload: payload,
+    };
+    Ok(serde_json::to_string(&sealed)?)
+}
+
+/// 校验封存签名是account签的
+pub fn unseal(account: &wallet::Account, sealed: &str) -> Result<SequenceState> {
+    let sealed: SealedSequence = serde_json::from_str(sealed)?;
+    let address = wallet::verify_with_public_key(
+        &sealed.public_key,
+        sealed.payload.as_bytes(),
+        &hex::decode(&sealed.sign)?,
+    )?;
+    let state: SequenceState = serde_json::from_str(&sealed.payload)?;
+    if address != account.address || state.address != account.address {
+        return Err(Error::from(ErrorKind::CryptoError));
+    }
+    Ok(state)
+}
+
+pub struct Sequence {
+    account: wallet::Account,
+    state: Mutex<SequenceState>,
+    saver: Saver,
+}
+
+impl Sequence {
+    /// 从loader恢复状态，没有时从1开始
+    /// instance标识enclave实例，不能包含":"
+    pub fn open(
+        account: wallet::Account,
+        instance: &str,
+        loader: Loader,
+        saver: Saver,
+    ) -> Result<Self> {
+        if instance.is_empty() || instance.contains(':') {
+            return Err(Error::from(ErrorKind::InvalidArguments));
+        }
+        let state = match loader()? {
+            Some(sealed) => unseal(&account, &sealed)?,
+            None => SequenceState {
+                address: account.address.to_owned(),
+                instance: instance.to_owned(),
+                next: 1,
+                unposted: vec![],
+            },
+        };
+        if state.instance != instance {
+            return Err(Error::from(ErrorKind::InvalidArguments));
+        }
+        Ok(Sequence {
+            account: account,
+            state: Mutex::new(state),
+            saver: saver,
+        })
+    }
+
+    pub fn instance(&self) -> String {
+        self.state.lock().unwrap().instance.to_owned()
+    }
+
+    /// 下一个将被分配的序号
+    pub fn peek(&self) -> u64 {
+        self.state.lock().unwrap().next
+    }
+
+    /// 分配一个序号，持久化成功之后才返回，之后用posted确认
+    pub fn next(&self) -> Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        let seq = state.next;
+        let mut updated = state.clone();
+        updated.next = seq
+            .checked_add(1)
+            .ok_or(Error::from(ErrorKind::InvalidArguments))?;
+        updated.unposted.push(seq);
+        (self.saver)(&seal(&self.account, &updated)?)?;
+        *state = updated;
+        Ok(seq)
+    }
+
+    /// 使用seq的交易已经被节点接受
+    pub fn posted(&self, seq: u64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if !state.unposted.contains(&seq) {
+            return Ok(());
+        }
+        let mut updated = state.clone();
+        updated.unposted.retain(|s| *s != seq);
+        (self.saver)(&seal(&self.account, &updated)?)?;
+        *state = updated;
+        Ok(())
+    }
+
+    /// 分配之后没有确认提交的序号，传给find_gaps
+    /// 包括正在处理的交易，对账时这些交易如果已经上链也不影响结果
+    pub fn unposted(&self) -> Vec<u64> {
+        self.state.lock().unwrap().unposted.clone()
+    }
+}
+
+/// 把序号嵌入nonce
+pub fn embed(nonce: &str, instance: &str, seq: u64) -> String {
+    format!("{}{}:{}:{}", SEQ_PREFIX, instance, seq, nonce)
+}
+
+/// 取出nonce里嵌入的(instance, seq)
+pub fn extract(nonce: &str) -> Option<(String, u64)> {
+    if !nonce.starts_with(SEQ_PREFIX) {
+        return None;
+    }
+    let mut parts = nonce[SEQ_PREFIX.len()..].splitn(3, ':');
+    let instance = parts.next()?;
+    let seq = parts.next()?.parse().ok()?;
+    parts.next()?;
+    Some((instance.to_owned(), seq))
+}
+
+/// 查询到的交易里嵌入的(instance, seq)
+pub fn tx_sequence(tx: &xchain::Transaction) -> Option<(String, u64)> {
+    extract(&tx.nonce)
+}
+
+/// 同一个实例的序号里缺失的区间(左闭右开)，只检查到最大的序号为止
+/// burned是Sequence::unposted返回的没有提交的序号，不算缺失
+pub fn find_gaps(seqs: &[u64], burned: &[u64]) -> Vec<std::ops::Range<u64>> {
+    let mut seqs = seqs.to_vec();
+    seqs.extend_from_slice(burned);
+    seqs.sort();
+    seqs.dedup();
+    let mut gaps = vec![];
+    let mut expected = 1;
+    for s in seqs {
+        if s > expected {
+            gaps.push(expected..s);
+        }
+        expected = s + 1;
+    }
+    gaps
+}
+
+/// 签名之前分配序号并嵌入交易的nonce，通过Session::add_hook使用
+/// 只对sequence账户发起的交易分配序号
+pub struct SequenceHook {
+    pub sequence: std::sync::Arc<Sequence>,
+}
+
+impl session::TxHook for SequenceHook {
+    fn post_build(&self, tx: &mut xchain::Transaction) -> Result<()> {
+        if tx.initiator != self.sequence.account.address {
+            return Ok(());
+        }
+        let seq = self.sequence.next()?;
+        let nonce = embed(&tx.nonce, &self.sequence.instance(), seq);
+        tx.set_nonce(nonce);
+        Ok(())
+    }
+
+    fn posted(&self, tx: &xchain::Transaction) {
+        let seq = match tx_sequence(tx) {
+            Some((ref instance, seq)) if *instance == self.sequence.instance() => seq,
+            _ => return,
+        };
+        if let Err(e) = self.sequence.posted(seq) {
+            tracing::warn!(seq, error = %e, "failed to persist posted sequence");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::session::TxHook;
+
+    fn account() -> wallet::Account {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        wallet::Account::new(d.to_str().unwrap(), "", "")
+    }
+
+    fn open(store: &Arc<Mutex<Option<String>>>, instance: &str) -> Result<Sequence> {
+        let (l, s) = (store.clone(), store.clone());
+        Sequence::open(
+            account(),
+            instance,
+            Box::new(move || Ok(l.lock().unwrap().clone())),
+            Box::new(move |sealed| {
+                *s.lock().unwrap() = Some(sealed.to_owned());
+                Ok(())
+            }),
+        )
+    }
+
+    #[test]
+    fn test_sequence_persisted() {
+        let store = Arc::new(Mutex::new(None));
+        let seq = open(&store, "enclave-1").unwrap();
+        assert_eq!(seq.next().unwrap(), 1);
+        assert_eq!(seq.next().unwrap(), 2);
+
+        // 重启之后接着分配
+        let seq = open(&store, "enclave-1").unwrap();
+        assert_eq!(seq.peek(), 3);
+        assert_eq!(open(&store, "enclave-2").is_ok(), false);
+
+        // 篡改封存的状态
+        let mut sealed: SealedSequence =
+            serde_json::from_str(store.lock().unwrap().as_ref().unwrap()).unwrap();
+        sealed.payload = sealed.payload.replace("3", "1");
+        *store.lock().unwrap() = Some(serde_json::to_string(&sealed).unwrap());
+        assert_eq!(open(&store, "enclave-1").is_ok(), false);
+    }
+
+    #[test]
+    fn test_sequence_hook() {
+        let store = Arc::new(Mutex::new(None));
+        let hook = SequenceHook {
+            sequence: Arc::new(open(&store, "enclave-1").unwrap()),
+        };
+        let mut tx = xchain::Transaction::new();
+        tx.set_initiator(account().address);
+        tx.set_nonce(String::from("160000000012345678"));
+        hook.post_build(&mut tx).unwrap();
+        assert_eq!(tx.nonce, "seq:enclave-1:1:160000000012345678");
+        assert_eq!(tx_sequence(&tx), Some((String::from("enclave-1"), 1)));
+        assert_eq!(hook.sequence.unposted(), vec![1]);
+
+        // 第2个序号的交易没有提交，第1个提交成功
+        let mut burned = xchain::Transaction::new();
+        burned.set_initiator(account().address);
+        hook.post_build(&mut burned).unwrap();
+        hook.posted(&tx);
+        assert_eq!(hook.sequence.unposted(), vec![2]);
+        let seq = open(&store, "enclave-1").unwrap();
+        assert_eq!(seq.unposted(), vec![2]);
+        assert_eq!(find_gaps(&[1, 3], &seq.unposted()), vec![]);
+
+        let mut other = xchain::Transaction::new();
+        other.set_initiator(String::from("other"));
+        hook.post_build(&mut other).unwrap();
+        assert_eq!(tx_sequence(&other), None);
+        assert_eq!(extract("160000000012345678"), None);
+    }
+
+    #[test]
+    fn test_find_gaps() {
+        assert_eq!(find_gaps(&[], &[]), vec![]);
+        assert_eq!(find_gaps(&[1, 2, 3], &[]), vec![]);
+        assert_eq!(find_gaps(&[5, 2, 2, 8], &[]), vec![1..2, 3..5, 6..8]);
+        assert_eq!(find_gaps(&[5, 2, 8], &[1, 4, 7]), vec![3..4, 6..7]);
+    }
+}
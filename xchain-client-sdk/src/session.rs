@@ -1,5 +1,4 @@
 use std::ops::AddAssign;
-use std::ops::Sub;
 
 use num_bigint;
 use num_traits;
@@ -9,21 +8,561 @@ use serde_json;
 use super::config;
 
 use xchain_node_sdk::{
-    encoder,
+    canonical, circuit, deadline, encoder,
     errors::*,
     ocall,
     protos::{xchain, xendorser},
 };
 
-#[derive(Default)]
+#[derive(Default, Clone)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Message {
+    /// to为空并且amount为0时交易只有desc，没有收款(见transfer::post_desc)
     pub to: String,
     pub amount: String,
+    /// 除to之外的收款(to, amount)，在同一笔交易里，共用一个找零输出
+    pub outputs: Vec<(String, String)>,
     pub fee: String,
     pub desc: String,
+    /// 收款输出冻结到的区块高度，0表示不冻结
     pub frozen_height: i64,
     pub initiator: String,
     pub auth_require: Vec<String>,
+    /// 合约账户授权的AK，按auth_require的顺序对交易签名(背书节点的签名除外)
+    /// 为空时由发起者签名
+    #[cfg_attr(feature = "with-serde", serde(skip))]
+    pub auth_require_signers: Vec<super::wallet::Account>,
+    /// 交易类别，决定合规检查的手续费付给哪个地址
+    pub tx_class: config::TxClass,
+    /// 交易的时间戳，为None时使用当前时间
+    /// 重新生成同一笔交易(同样的utxo)需要固定timestamp和nonce，txid才相同
+    pub timestamp: Option<i64>,
+    /// 交易的nonce，为None时随机生成
+    pub nonce: Option<String>,
+}
+
+impl Message {
+    /// 按顺序的所有收款(to, amount)，第一个是to，to为空时不包括
+    pub fn payments(&self) -> Vec<(&str, &str)> {
+        let mut payments = vec![];
+        if !self.to.is_empty() {
+            payments.push((&self.to[..], &self.amount[..]));
+        }
+        for (to, amount) in self.outputs.iter() {
+            payments.push((&to[..], &amount[..]));
+        }
+        payments
+    }
+
+    fn tx_timestamp(&self) -> i64 {
+        self.timestamp.unwrap_or_else(crate::consts::now_as_nanos)
+    }
+
+    fn tx_nonce(&self) -> Result<String> {
+        match self.nonce {
+            Some(ref n) => Ok(n.to_owned()),
+            None => crate::wallet::get_nonce(),
+        }
+    }
+
+    /// amount和outputs的金额之和
+    pub fn total_amount(&self) -> Result<num_bigint::BigInt> {
+        let mut amounts = vec![&self.amount[..]];
+        for (_, amount) in self.outputs.iter() {
+            amounts.push(&amount[..]);
+        }
+        crate::consts::checked_sum(&amounts)
+    }
+
+    /// 需要选中的utxo总额: 收款金额、手续费和背书服务费，不受i64的限制
+    pub fn total_need(&self, endorser_fee: i32) -> Result<num_bigint::BigInt> {
+        let endorser_fee = endorser_fee.to_string();
+        Ok(self.total_amount()? + crate::consts::checked_sum(&[&self.fee, &endorser_fee])?)
+    }
+}
+
+/// 校验预执行返回的结果确实是针对本次请求的，防止不可信的宿主替换:
+/// 1. 选出的utxo都属于请求的地址，并且没有重复
+/// 2. utxo金额之和等于totalSelected，且不少于请求的金额
+/// 3. 每个合约调用都有对应的返回
+pub fn verify_pre_exec_binding(
+    req: &xchain::PreExecWithSelectUTXORequest,
+    resp: &xchain::PreExecWithSelectUTXOResponse,
+) -> Result<()> {
+    if !resp.bcname.is_empty() && resp.bcname != req.bcname {
+        return Err(Error::from(ErrorKind::PreExecTampered));
+    }
+
+    let utxo_output = resp.get_utxoOutput();
+    let mut total: num_bigint::BigInt = num_traits::Zero::zero();
+    let mut refs = std::collections::HashSet::new();
+    for utxo in utxo_output.get_utxoList().iter() {
+        if utxo.get_toAddr() != req.address.as_bytes() {
+            return Err(Error::from(ErrorKind::PreExecTampered));
+        }
+        if !refs.insert((utxo.get_refTxid(), utxo.refOffset)) {
+            return Err(Error::from(ErrorKind::PreExecTampered));
+        }
+        total.add_assign(num_bigint::BigInt::from_bytes_be(
+            num_bigint::Sign::Plus,
+            utxo.get_amount(),
+        ));
+    }
+    let total_selected = crate::consts::str_as_bigint(utxo_output.get_totalSelected())?;
+    let total_amount =
+        num_bigint::BigInt::from_i64(req.totalAmount).ok_or(Error::from(ErrorKind::ParseError))?;
+    if total != total_selected || total_selected < total_amount {
+        return Err(Error::from(ErrorKind::PreExecTampered));
+    }
+
+    let invoke_resp = resp.get_response();
+    if invoke_resp.get_responses().len() != invoke_resp.get_requests().len() {
+        return Err(Error::from(ErrorKind::PreExecTampered));
+    }
+    Ok(())
+}
+
+/// 校验背书服务对tx的签名: 签名的公钥是配置的public_key(json格式)，并且对交易摘要的签名有效，
+/// 否则返回EndorserSignInvalid
+pub fn verify_endorser_sign(
+    tx: &xchain::Transaction,
+    sign: &xchain::SignatureInfo,
+    public_key: &str,
+) -> Result<()> {
+    let invalid = |_| Error::from(ErrorKind::EndorserSignInvalid);
+    let expected = super::wallet::address_from_public_key(public_key)?;
+    if super::wallet::address_from_public_key(&sign.PublicKey).map_err(invalid)? != expected {
+        return Err(Error::new(
+            ErrorKind::EndorserSignInvalid,
+            "endorser signed with an unexpected public key",
+        ));
+    }
+    let digest = encoder::make_tx_digest_hash(tx)?;
+    super::wallet::verify_with_public_key(public_key, &digest, &sign.Sign).map_err(invalid)?;
+    Ok(())
+}
+
+/// 校验预执行返回的读集(tx_inputs_ext)，防止不可信的宿主篡改预执行结果:
+/// 1. 返回的合约调用以请求的合约调用开头(之后可能是跨合约调用)
+/// 2. 读集的bucket都属于返回的合约调用涉及的合约
+/// 3. 同一个key只能出现一次
+/// 4. 版本(ref_txid, ref_offset)格式正确，不存在的key版本为空
+pub fn verify_read_set(
+    req: &xchain::InvokeRPCRequest,
+    resp: &xchain::PreExecWithSelectUTXOResponse,
+) -> Result<()> {
+    let invoke_resp = resp.get_response();
+    let requested = req.get_requests();
+    let returned = invoke_resp.get_requests();
+    if returned.len() < requested.len() {
+        return Err(Error::from(ErrorKind::PreExecTampered));
+    }
+    for (a, b) in requested.iter().zip(returned.iter()) {
+        if a.module_name != b.module_name
+            || a.contract_name != b.contract_name
+            || a.method_name != b.method_name
+            || a.args != b.args
+        {
+            return Err(Error::from(ErrorKind::PreExecTampered));
+        }
+    }
+
+    let contracts: std::collections::HashSet<&str> =
+        returned.iter().map(|r| r.get_contract_name()).collect();
+    let mut keys = std::collections::HashSet::new();
+    for input in invoke_resp.get_inputs().iter() {
+        if !contracts.contains(input.get_bucket()) {
+            return Err(Error::from(ErrorKind::PreExecTampered));
+        }
+        if !keys.insert((input.get_bucket(), input.get_key())) {
+            return Err(Error::from(ErrorKind::PreExecTampered));
+        }
+        if input.ref_offset < 0 || (input.ref_txid.is_empty() && input.ref_offset != 0) {
+            return Err(Error::from(ErrorKind::PreExecTampered));
+        }
+    }
+    Ok(())
+}
+
+/// 严格模式下拒绝protobuf里不认识的字段
+fn check_unknown_fields<M: protobuf::Message>(m: &M) -> Result<()> {
+    if m.get_unknown_fields().iter().next().is_some() {
+        return Err(Error::from(ErrorKind::UnexpectedResponse));
+    }
+    Ok(())
+}
+
+/// 一个输出: 用途、收款地址、金额
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize))]
+pub struct FeeAuditItem {
+    pub name: &'static str,
+    pub to: String,
+    pub amount: num_bigint::BigInt,
+}
+
+/// 一笔交易需要的金额明细，见audit_fee
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize))]
+pub struct FeeAudit {
+    pub amount: num_bigint::BigInt,
+    pub fee: num_bigint::BigInt,
+    /// 合规检查交易付给背书服务的手续费
+    pub endorser_fee: num_bigint::BigInt,
+    /// 预执行时选择utxo的总金额: amount + fee + endorser_fee
+    pub total_need: num_bigint::BigInt,
+    /// 按交易顺序的输出: 合规检查交易的背书手续费，真实交易的转账和矿工手续费，找零不列出
+    pub outputs: Vec<FeeAuditItem>,
+    /// 背书手续费大于转账金额，背书手续费由合规检查交易单独支付，这是合法的
+    pub endorser_fee_exceeds_amount: bool,
+}
+
+/// 不发送任何请求，按全局CONFIG当前的配置计算msg需要的金额，用于核对定价逻辑
+pub fn audit_fee(msg: &Message) -> Result<FeeAudit> {
+    audit_fee_with_config(&config::ClientConfig::global(), msg)
+}
+
+/// 同audit_fee，使用config
+pub fn audit_fee_with_config(config: &config::ClientConfig, msg: &Message) -> Result<FeeAudit> {
+    let amount = msg.total_amount()?;
+    let fee = crate::consts::str_as_bigint(&msg.fee)?;
+    let c = &config.compliance_check;
+    let endorser_fee = num_bigint::BigInt::from_i64(c.compliance_check_endorse_service_fee as i64)
+        .ok_or(Error::from(ErrorKind::ParseError))?;
+    let fee_addr = c.fee_addr(msg.tx_class).to_owned();
+
+    let mut outputs = vec![FeeAuditItem {
+        name: "endorser_fee",
+        to: fee_addr,
+        amount: endorser_fee.clone(),
+    }];
+    for (to, am) in msg.payments() {
+        let am = crate::consts::str_as_bigint(am)?;
+        if am != num_traits::Zero::zero() {
+            outputs.push(FeeAuditItem {
+                name: "amount",
+                to: to.to_owned(),
+                amount: am,
+            });
+        }
+    }
+    if fee != num_traits::Zero::zero() {
+        outputs.push(FeeAuditItem {
+            name: "fee",
+            to: String::from(crate::consts::FEE_ADDR),
+            amount: fee.clone(),
+        });
+    }
+
+    let total_need = crate::consts::checked_sum(&[
+        &amount.to_str_radix(10),
+        &msg.fee,
+        &endorser_fee.to_str_radix(10),
+    ])?;
+    Ok(FeeAudit {
+        endorser_fee_exceeds_amount: endorser_fee > amount,
+        amount: amount,
+        fee: fee,
+        endorser_fee: endorser_fee,
+        total_need: total_need,
+        outputs: outputs,
+    })
+}
+
+/// 合规检查交易的核对结果，开启transcript时记录在里面，金额都是十进制字符串
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FeeTxCheck {
+    pub fee_addr: String,
+    pub expected_fee: String,
+    pub paid_fee: String,
+    pub inputs_total: String,
+    pub change: String,
+    /// 不满足的检查，为空表示通过
+    pub violations: Vec<String>,
+}
+
+/// 核对合规检查交易: 输入都属于发起者，付给fee_addr的正好是endorser_fee，
+/// 其余的都找零给发起者，输入输出相等(没有多付的手续费)
+pub fn check_compliance_tx(
+    tx: &xchain::Transaction,
+    fee_addr: &str,
+    endorser_fee: &num_bigint::BigInt,
+) -> FeeTxCheck {
+    let amount = |a: &[u8]| num_bigint::BigInt::from_bytes_be(num_bigint::Sign::Plus, a);
+    let mut violations = vec![];
+    let mut inputs_total: num_bigint::BigInt = num_traits::Zero::zero();
+    for input in tx.get_tx_inputs().iter() {
+        if input.from_addr != tx.initiator.as_bytes() {
+            violations.push(format!(
+                "input from {}",
+                String::from_utf8_lossy(&input.from_addr)
+            ));
+        }
+        inputs_total.add_assign(amount(&input.amount));
+    }
+    let mut paid_fee: num_bigint::BigInt = num_traits::Zero::zero();
+    let mut change: num_bigint::BigInt = num_traits::Zero::zero();
+    for output in tx.get_tx_outputs().iter() {
+        if output.to_addr == fee_addr.as_bytes() {
+            paid_fee.add_assign(amount(&output.amount));
+        } else if output.to_addr == tx.initiator.as_bytes() {
+            change.add_assign(amount(&output.amount));
+        } else {
+            violations.push(format!(
+                "output to {}",
+                String::from_utf8_lossy(&output.to_addr)
+            ));
+        }
+    }
+    if &paid_fee != endorser_fee {
+        violations.push(String::from("fee mismatch"));
+    }
+    if inputs_total != &paid_fee + &change {
+        violations.push(String::from("inputs and outputs do not balance"));
+    }
+    FeeTxCheck {
+        fee_addr: fee_addr.to_owned(),
+        expected_fee: endorser_fee.to_string(),
+        paid_fee: paid_fee.to_string(),
+        inputs_total: inputs_total.to_string(),
+        change: change.to_string(),
+        violations: violations,
+    }
+}
+
+/// 离线签名的交易: 合规检查交易和真正的交易，可以序列化之后在机器之间传递
+/// 真正的交易花费合规检查交易的找零，签名之后合规检查交易的txid会变，sign_tx同时更新引用
+/// 不需要合规检查交易(skipComplianceCheck或者omitZeroFeeTx)时compliance_tx为空的交易
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct OfflineTx {
+    pub compliance_tx: xchain::Transaction,
+    pub tx: xchain::Transaction,
+}
+
+impl OfflineTx {
+    pub fn has_compliance_tx(&self) -> bool {
+        self.compliance_tx != xchain::Transaction::new()
+    }
+}
+
+/// 在离线的机器(或者enclave)上对build_unsigned_tx生成的交易签名
+/// auth_require_signers同Message::auth_require_signers，config用于签名前的策略检查(见policy)，
+/// 需要和在线机器生成交易时的配置一致
+/// 离线签名不执行Session的pre_sign钩子
+pub fn sign_tx(
+    config: &config::ClientConfig,
+    account: &super::wallet::Account,
+    auth_require_signers: &[super::wallet::Account],
+    unsigned: &OfflineTx,
+) -> Result<OfflineTx> {
+    let has_compliance_tx = unsigned.has_compliance_tx();
+    if (has_compliance_tx && unsigned.compliance_tx.initiator != account.address)
+        || unsigned.tx.initiator != account.address
+    {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let mut compliance_tx = unsigned.compliance_tx.clone();
+    let mut tx = unsigned.tx.clone();
+    if has_compliance_tx {
+        sign_initiator(account, &mut compliance_tx)?;
+        for input in tx.mut_tx_inputs().iter_mut() {
+            if input.ref_txid == unsigned.compliance_tx.txid {
+                input.set_ref_txid(compliance_tx.txid.clone());
+            }
+        }
+    }
+    let digest_hash = encoder::make_tx_digest_hash(&tx)?;
+    sign_with_digest(config, account, auth_require_signers, &mut tx, &digest_hash)?;
+    Ok(OfflineTx {
+        compliance_tx: compliance_tx,
+        tx: tx,
+    })
+}
+
+/// 发起者签名，并重新计算txid
+fn sign_initiator(account: &super::wallet::Account, tx: &mut xchain::Transaction) -> Result<()> {
+    let digest_hash = encoder::make_tx_digest_hash(tx)?;
+    let mut signature_info = xchain::SignatureInfo::new();
+    signature_info.set_PublicKey(account.public_key()?);
+    signature_info.set_Sign(account.sign(&digest_hash)?);
+    tx.set_initiator_signs(protobuf::RepeatedField::from_vec(vec![signature_info]));
+    tx.set_txid(encoder::make_transaction_id(tx)?);
+    Ok(())
+}
+
+/// 发起者和auth_require签名，没有auth_require_signers并且是合约账户时由发起者签名
+fn sign_with_digest(
+    config: &config::CommConfig,
+    account: &super::wallet::Account,
+    auth_require_signers: &[super::wallet::Account],
+    tx: &mut xchain::Transaction,
+    digest_hash: &[u8],
+) -> Result<()> {
+    crate::policy::check_tx(config, tx)?;
+    //sign the digest_hash
+    let sig = account.sign(digest_hash)?;
+    let mut signature_info = xchain::SignatureInfo::new();
+
+    signature_info.set_PublicKey(account.public_key()?);
+    signature_info.set_Sign(sig);
+    let signature_infos = vec![signature_info; 1];
+    tx.set_initiator_signs(protobuf::RepeatedField::from_vec(signature_infos.clone()));
+    if !auth_require_signers.is_empty() {
+        let mut auth_require_signs = vec![];
+        for signer in auth_require_signers.iter() {
+            let mut signature_info = xchain::SignatureInfo::new();
+            signature_info.set_PublicKey(signer.public_key()?);
+            signature_info.set_Sign(signer.sign(digest_hash)?);
+            auth_require_signs.push(signature_info);
+        }
+        tx.set_auth_require_signs(protobuf::RepeatedField::from_vec(auth_require_signs));
+    } else if !account.contract_name.is_empty() {
+        tx.set_auth_require_signs(protobuf::RepeatedField::from_vec(signature_infos));
+    }
+
+    tx.set_txid(encoder::make_transaction_id(tx)?);
+    Ok(())
+}
+
+/// 交易流水线的钩子，用于在不修改gen_real_tx的情况下扩展交易
+/// 任何一个阶段返回错误都会中止交易，交易不会被提交，选中的utxo不会被花费
+pub trait TxHook: Send + Sync {
+    /// 交易组装完成、签名之前，可以修改交易(例如增加输出、在desc里附加信息)
+    /// 增加输出时需要自己保证输入输出平衡
+    fn post_build(&self, _tx: &mut xchain::Transaction) -> Result<()> {
+        Ok(())
+    }
+
+    /// 签名之前，digest为待签名的哈希
+    fn pre_sign(&self, _tx: &xchain::Transaction, _digest: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    /// 背书签名完成、提交之前
+    fn pre_post(&self, _tx: &xchain::Transaction) -> Result<()> {
+        Ok(())
+    }
+
+    /// 节点接受了交易之后，交易已经提交，钩子自己处理失败
+    fn posted(&self, _tx: &xchain::Transaction) {}
+
+    /// 节点接受了花费合规检查交易找零的真正交易之后，在posted之前对合规检查交易调用，
+    /// 合规检查交易同样已经上链(例如记账时需要它的输入和背书服务费输出)
+    fn fee_tx_posted(&self, _fee_tx: &xchain::Transaction) {}
+}
+
+/// 调用背书服务(预执行、合规检查)时传输层失败的重试策略
+/// 重试时原样重发同一个请求，合规检查的手续费交易和真正的交易都不会重新生成，
+/// 背书服务重复收到同一笔手续费交易时txid相同，不会被花两次；提交交易(PostTx)不重试
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// 包括第一次在内最多请求几次，1表示不重试
+    pub max_attempts: u32,
+    /// 第n次重试之前等待backoff * 2^(n-1)，最多max_backoff
+    pub backoff: std::time::Duration,
+    pub max_backoff: std::time::Duration,
+    /// [0, 1]，等待时间随机减少的最大比例，避免多个客户端同时重试
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            backoff: std::time::Duration::from_millis(0),
+            max_backoff: std::time::Duration::from_millis(0),
+            jitter: 0.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 配置里的endorserRetry，没有配置时不重试
+    pub fn from_config(c: &config::CommConfig) -> Self {
+        match c.endorser_retry {
+            Some(ref c) => RetryPolicy {
+                max_attempts: std::cmp::max(c.max_attempts, 1),
+                backoff: std::time::Duration::from_millis(c.backoff_ms),
+                max_backoff: std::time::Duration::from_millis(c.max_backoff_ms),
+                jitter: c.jitter.max(0.0).min(1.0),
+            },
+            None => Default::default(),
+        }
+    }
+
+    /// 第attempt次请求失败之后、重试之前等待的时间，attempt从1开始
+    pub fn delay(&self, attempt: u32) -> std::time::Duration {
+        let exp = std::cmp::min(attempt.saturating_sub(1), 31);
+        let delay = std::cmp::min(
+            self.backoff
+                .checked_mul(1 << exp)
+                .unwrap_or(self.max_backoff),
+            self.max_backoff,
+        );
+        if self.jitter <= 0.0 {
+            return delay;
+        }
+        delay.mul_f64(1.0 - self.jitter * rand::random::<f64>())
+    }
+
+    /// 只重试传输层的失败，背书服务返回的业务错误原样返回
+    pub fn call<T, F>(&self, mut f: F) -> Result<T>
+    where
+        F: FnMut() -> Result<T>,
+    {
+        let mut attempt = 1;
+        loop {
+            match f() {
+                Err(ref e) if attempt < self.max_attempts && circuit::is_transport_failure(e) => {
+                    tracing::warn!(attempt, error = %e, "endorser call failed, retrying");
+                }
+                res => return res,
+            }
+            std::thread::sleep(self.delay(attempt));
+            attempt += 1;
+        }
+    }
+
+    /// 同call，f返回future，重试之前的等待不阻塞调用方的线程
+    #[cfg(feature = "async")]
+    pub async fn call_async<T, F, Fut>(&self, mut f: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match f().await {
+                Err(ref e) if attempt < self.max_attempts && circuit::is_transport_failure(e) => {
+                    tracing::warn!(attempt, error = %e, "endorser call failed, retrying");
+                }
+                res => return res,
+            }
+            xchain_node_sdk::client::sleep(self.delay(attempt)).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// pre_exec_with_auto_fee最多预执行的次数
+const MAX_AUTO_FEE_ROUNDS: usize = 3;
+
+/// skipComplianceCheck时在节点返回的前多少个utxo里选
+const NODE_SELECT_UTXO_COUNT: i64 = 1000;
+
+/// 传输层失败或者熔断时切换接入点，背书服务返回的业务错误不切换
+fn should_failover(e: &Error) -> bool {
+    circuit::is_transport_failure(e) || e.kind() == ErrorKind::CircuitOpen
+}
+
+/// 签名的合规背书服务，见Session::endorsements
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Endorsement {
+    /// 签名公钥对应的地址
+    pub addr: String,
+    /// 实际访问的接入点，"host:port"
+    pub endpoint: String,
 }
 
 pub struct Session<'a, 'b, 'c> {
@@ -32,95 +571,493 @@ pub struct Session<'a, 'b, 'c> {
     account: &'b super::wallet::Account,
 
     msg: &'c Message,
+
+    hooks: Vec<std::sync::Arc<dyn TxHook>>,
+
+    transcript: Option<std::sync::Arc<crate::transcript::Transcript>>,
+
+    retry: RetryPolicy,
+
+    config: config::ClientConfig,
+
+    endorsements: std::sync::Mutex<Vec<Endorsement>>,
+
+    /// gen_complete_tx生成的合规检查交易，提交之后交给钩子的fee_tx_posted
+    fee_tx: std::sync::Mutex<Option<xchain::Transaction>>,
+
+    attestation: Option<std::sync::Arc<crate::attestation::AttestedChannel>>,
+
+    /// pre_exec_with_auto_fee按gas_used计算的手续费，覆盖msg.fee
+    auto_fee: std::sync::Mutex<Option<String>>,
+
+    /// 交易是否带上msg.desc，默认取配置的descOnChain
+    desc_on_chain: bool,
+
+    /// 设置之后预执行之前检查节点是否服务chain_name
+    chains: Option<std::sync::Arc<crate::chains::Chains>>,
 }
 
 impl<'a, 'b, 'c> Session<'a, 'b, 'c> {
+    /// 使用全局CONFIG当前的配置
     pub fn new(c: &'a String, w: &'b super::wallet::Account, m: &'c Message) -> Self {
+        Session::with_config(c, w, m, config::ClientConfig::global())
+    }
+
+    pub fn with_config(
+        c: &'a String,
+        w: &'b super::wallet::Account,
+        m: &'c Message,
+        config: config::ClientConfig,
+    ) -> Self {
+        let desc_on_chain = config.desc_on_chain;
         Session {
             msg: m,
             chain_name: c,
             account: w,
+            hooks: vec![],
+            transcript: None,
+            retry: RetryPolicy::from_config(&config),
+            config: config,
+            endorsements: Default::default(),
+            fee_tx: Default::default(),
+            attestation: None,
+            auto_fee: Default::default(),
+            desc_on_chain: desc_on_chain,
+            chains: None,
+        }
+    }
+
+    pub fn config(&self) -> &config::ClientConfig {
+        &self.config
+    }
+
+    /// 覆盖配置里的endorserRetry
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    /// 覆盖配置里的descOnChain
+    pub fn set_desc_on_chain(&mut self, desc_on_chain: bool) {
+        self.desc_on_chain = desc_on_chain;
+    }
+
+    /// 预执行之前用chains检查链名，节点不服务chain_name(例如写死的"xuper")时返回InvalidArguments，
+    /// 不再把请求发给背书服务和节点
+    pub fn set_chains(&mut self, chains: std::sync::Arc<crate::chains::Chains>) {
+        self.chains = Some(chains);
+    }
+
+    /// 请求的链名要和Session的一致，并且是节点服务的链
+    fn check_chain(&self, bcname: &str) -> Result<()> {
+        if bcname != self.chain_name.as_str() {
+            return Err(Error::new(
+                ErrorKind::InvalidArguments,
+                format!("request chain {} is not {}", bcname, self.chain_name),
+            ));
+        }
+        match self.chains {
+            Some(ref c) => c.chain(bcname).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    /// 背书请求都经过认证过的通道，见attestation
+    pub fn set_attestation(
+        &mut self,
+        channel: std::sync::Arc<crate::attestation::AttestedChannel>,
+    ) {
+        self.attestation = Some(channel);
+    }
+
+    /// 配置了attestation时必须先设置认证过的通道
+    fn authenticate(
+        &self,
+        req: &mut xendorser::EndorserRequest,
+    ) -> Result<Option<crate::attestation::PendingRequest>> {
+        match self.attestation {
+            Some(ref c) => Ok(Some(c.authenticate_request(req)?)),
+            None if self.config.compliance_check.attestation.is_some() => Err(Error::new(
+                ErrorKind::AttestationFailed,
+                "endorser channel is not attested",
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn verify_attested(
+        &self,
+        resp: &mut xendorser::EndorserResponse,
+        pending: &Option<crate::attestation::PendingRequest>,
+    ) -> Result<()> {
+        match (self.attestation.as_ref(), pending) {
+            (Some(c), Some(p)) => c.verify_response(resp, p),
+            _ => Ok(()),
+        }
+    }
+
+    /// 记录和背书服务的交互、摘要和提交的交易，见transcript
+    pub fn set_transcript(&mut self, transcript: std::sync::Arc<crate::transcript::Transcript>) {
+        self.transcript = Some(transcript);
+    }
+
+    fn record<M: protobuf::Message>(&self, stage: &str, m: &M) -> Result<()> {
+        match self.transcript {
+            Some(ref t) => t.record_message(stage, m),
+            None => Ok(()),
         }
     }
 
+    /// 按添加的顺序执行，只作用于真正的交易，不作用于合规检查的手续费交易
+    pub fn add_hook(&mut self, hook: std::sync::Arc<dyn TxHook>) {
+        self.hooks.push(hook);
+    }
+
+    /// 合约返回的status大于400时返回ContractCodeGT400，错误里带着合约的返回(见Error::contract)
     pub fn check_resp_code(&self, resp: &[xchain::ContractResponse]) -> Result<()> {
-        for i in resp.iter() {
-            if i.status > 400 {
-                return Err(Error::from(ErrorKind::ContractCodeGT400));
+        ContractError::check(resp)
+    }
+
+    pub fn pre_exec_with_select_utxo(
+        &self,
+        pre_sel_utxo_req: xchain::PreExecWithSelectUTXORequest,
+    ) -> Result<xchain::PreExecWithSelectUTXOResponse> {
+        let (resp, _) = self.pre_exec_with_select_utxo_tolerant(pre_sel_utxo_req)?;
+        Ok(resp)
+    }
+
+    /// 同pre_exec_with_select_utxo，额外返回背书服务新版本增加的、SDK不认识的字段
+    pub fn pre_exec_with_select_utxo_tolerant(
+        &self,
+        pre_sel_utxo_req: xchain::PreExecWithSelectUTXORequest,
+    ) -> Result<(
+        xchain::PreExecWithSelectUTXOResponse,
+        serde_json::Map<String, serde_json::Value>,
+    )> {
+        let _span = tracing::debug_span!(
+            "pre_exec",
+            chain = %self.chain_name,
+            address = %pre_sel_utxo_req.address,
+            total_amount = pre_sel_utxo_req.totalAmount,
+        )
+        .entered();
+        self.check_chain(&pre_sel_utxo_req.bcname)?;
+        if self.config.compliance_check.skip_compliance_check {
+            return Ok((
+                self.pre_exec_on_node(&pre_sel_utxo_req)?,
+                Default::default(),
+            ));
+        }
+        let endorser_request = self.pre_exec_request(&pre_sel_utxo_req)?;
+        let (resp, endpoint) = self
+            .retry
+            .call(|| self.endorser_call(endorser_request.clone()))?;
+        let res = self.pre_exec_response(&pre_sel_utxo_req, &resp);
+        match res {
+            Ok((ref r, ref unknown_fields)) => tracing::debug!(
+                %endpoint,
+                utxos = r.get_utxoOutput().get_utxoList().len(),
+                gas_used = r.get_response().gas_used,
+                unknown_fields = unknown_fields.len(),
+                "pre-exec done"
+            ),
+            Err(ref e) => tracing::warn!(%endpoint, error = %e, "pre-exec failed"),
+        }
+        res
+    }
+
+    /// 自动手续费: 按预执行返回的gas_used计算手续费(见contract::invoke_fee，msg.fee为空时等于gas_used)，
+    /// 之后生成的交易使用这个手续费，选中的utxo不够支付时按新的金额重新预执行选utxo
+    pub fn pre_exec_with_auto_fee(
+        &self,
+        mut pre_sel_utxo_req: xchain::PreExecWithSelectUTXORequest,
+    ) -> Result<xchain::PreExecWithSelectUTXOResponse> {
+        for _ in 0..MAX_AUTO_FEE_ROUNDS {
+            let resp = self.pre_exec_with_select_utxo(pre_sel_utxo_req.clone())?;
+            let fee =
+                crate::contract::invoke_fee(&self.msg.fee, resp.get_response().get_gas_used())?;
+            let total_need = self.auto_fee_need(&fee)?;
+            *self.auto_fee.lock().unwrap() = Some(fee);
+            let selected = crate::consts::str_as_bigint(resp.get_utxoOutput().get_totalSelected())?;
+            if selected >= total_need {
+                return Ok(resp);
             }
+            // 两次预执行之间gas_used可能变化，重新计算
+            pre_sel_utxo_req.set_totalAmount(crate::consts::bigint_as_i64(&total_need)?);
         }
-        Ok(())
+        Err(Error::new(
+            ErrorKind::InvalidArguments,
+            "selected utxos can not cover the fee of gas_used",
+        ))
     }
 
-    pub fn pre_exec_with_select_utxo(
+    /// 手动选择utxo: 不预执行，用调用方选出的utxo代替背书服务选出的utxo，之后用gen_complete_tx生成交易。
+    /// 没有预执行的结果，只适合没有合约调用的交易(转账)。utxo都要属于发起交易的账户并且不重复，
+    /// 总额不少于转账金额、手续费和背书服务费之和，totalSelected为空时按utxo金额计算
+    pub fn select_utxo_manually(
+        &self,
+        mut utxo_output: xchain::UtxoOutput,
+    ) -> Result<xchain::PreExecWithSelectUTXOResponse> {
+        if utxo_output.get_utxoList().is_empty() {
+            return Err(Error::new(ErrorKind::InvalidArguments, "no utxo selected"));
+        }
+        self.check_chain(self.chain_name)?;
+        if utxo_output.get_totalSelected().is_empty() {
+            let total: num_bigint::BigInt = utxo_output
+                .get_utxoList()
+                .iter()
+                .map(|u| num_bigint::BigInt::from_bytes_be(num_bigint::Sign::Plus, &u.amount))
+                .sum();
+            utxo_output.set_totalSelected(total.to_str_radix(10));
+        }
+        let mut resp = xchain::PreExecWithSelectUTXOResponse::new();
+        resp.set_bcname(self.chain_name.to_owned());
+        resp.set_utxoOutput(utxo_output);
+
+        let mut req = xchain::PreExecWithSelectUTXORequest::new();
+        req.set_bcname(self.chain_name.to_owned());
+        req.set_address(self.account.address.to_owned());
+        verify_pre_exec_binding(&req, &resp).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidArguments,
+                "selected utxos must belong to the account, be distinct and sum to totalSelected",
+            )
+        })?;
+        let total_need = self
+            .msg
+            .total_need(self.config.compliance_check.endorse_service_fee())?;
+        let total = crate::consts::str_as_bigint(resp.get_utxoOutput().get_totalSelected())?;
+        if total < total_need {
+            return Err(Error::new(
+                ErrorKind::InvalidArguments,
+                format!("selected utxos {} are less than {}", total, total_need),
+            ));
+        }
+        Ok(resp)
+    }
+
+    /// 手续费为fee时需要选中的金额: 转账金额、手续费和背书服务费
+    fn auto_fee_need(&self, fee: &str) -> Result<num_bigint::BigInt> {
+        Ok(self.msg.total_amount()?
+            + crate::consts::str_as_bigint(fee)?
+            + num_bigint::BigInt::from(self.config.compliance_check.endorse_service_fee()))
+    }
+
+    /// 生成交易使用的手续费，pre_exec_with_auto_fee之后是按gas_used计算的手续费
+    pub fn fee(&self) -> String {
+        self.auto_fee
+            .lock()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(|| self.msg.fee.to_owned())
+    }
+
+    /// msg的fee替换成自动计算的手续费，没有开启desc_on_chain时desc为空
+    fn effective_msg(&self) -> std::borrow::Cow<Message> {
+        let mut msg = match *self.auto_fee.lock().unwrap() {
+            Some(ref fee) => std::borrow::Cow::Owned(Message {
+                fee: fee.to_owned(),
+                ..self.msg.clone()
+            }),
+            None => std::borrow::Cow::Borrowed(self.msg),
+        };
+        if !self.desc_on_chain && !msg.desc.is_empty() {
+            msg.to_mut().desc.clear();
+        }
+        msg
+    }
+
+    /// 不阻塞线程的pre_exec_with_select_utxo，重试策略同pre_exec_with_select_utxo
+    #[cfg(feature = "async")]
+    pub async fn pre_exec_with_select_utxo_async(
         &self,
         pre_sel_utxo_req: xchain::PreExecWithSelectUTXORequest,
     ) -> Result<xchain::PreExecWithSelectUTXOResponse> {
-        let request_data = serde_json::to_string(&pre_sel_utxo_req)?;
+        self.check_chain(&pre_sel_utxo_req.bcname)?;
+        if self.config.compliance_check.skip_compliance_check {
+            return self.pre_exec_on_node_async(&pre_sel_utxo_req, None).await;
+        }
+        let endorser_request = self.pre_exec_request(&pre_sel_utxo_req)?;
+        let (resp, endpoint) = self
+            .retry
+            .call_async(|| self.endorser_call_async(endorser_request.clone(), None))
+            .await?;
+        let (resp, _) = self.pre_exec_response(&pre_sel_utxo_req, &resp)?;
+        tracing::debug!(
+            chain = %self.chain_name,
+            %endpoint,
+            utxos = resp.get_utxoOutput().get_utxoList().len(),
+            gas_used = resp.get_response().gas_used,
+            "pre-exec done"
+        );
+        Ok(resp)
+    }
+
+    /// skipComplianceCheck时不访问背书服务: 有合约调用时由节点预执行，
+    /// 在节点返回的utxo记录里按顺序选utxo。节点不会锁定选出的utxo，并发的交易可能选到同一个utxo
+    fn pre_exec_on_node(
+        &self,
+        pre_sel_utxo_req: &xchain::PreExecWithSelectUTXORequest,
+    ) -> Result<xchain::PreExecWithSelectUTXOResponse> {
+        tracing::debug!("pre-exec on node, compliance check skipped");
+        let invoke = if !pre_sel_utxo_req.get_request().get_requests().is_empty() {
+            Some(ocall::ocall_xchain_pre_exec(
+                pre_sel_utxo_req.get_request().clone(),
+            )?)
+        } else {
+            None
+        };
+        let record = if pre_sel_utxo_req.totalAmount > 0 {
+            Some(ocall::ocall_xchain_query_utxo_record(
+                &pre_sel_utxo_req.address,
+                NODE_SELECT_UTXO_COUNT,
+            )?)
+        } else {
+            None
+        };
+        self.node_pre_exec_response(pre_sel_utxo_req, invoke, record)
+    }
+
+    /// 不阻塞线程的pre_exec_on_node
+    #[cfg(feature = "async")]
+    async fn pre_exec_on_node_async(
+        &self,
+        pre_sel_utxo_req: &xchain::PreExecWithSelectUTXORequest,
+        deadline: Option<std::time::Instant>,
+    ) -> Result<xchain::PreExecWithSelectUTXOResponse> {
+        tracing::debug!("pre-exec on node, compliance check skipped");
+        let invoke = if !pre_sel_utxo_req.get_request().get_requests().is_empty() {
+            Some(
+                ocall::ocall_xchain_pre_exec_async(
+                    pre_sel_utxo_req.get_request().clone(),
+                    deadline,
+                )
+                .await?,
+            )
+        } else {
+            None
+        };
+        let record = if pre_sel_utxo_req.totalAmount > 0 {
+            Some(
+                ocall::ocall_xchain_query_utxo_record_async(
+                    pre_sel_utxo_req.address.to_owned(),
+                    NODE_SELECT_UTXO_COUNT,
+                    deadline,
+                )
+                .await?,
+            )
+        } else {
+            None
+        };
+        self.node_pre_exec_response(pre_sel_utxo_req, invoke, record)
+    }
+
+    /// 用节点预执行的结果和utxo记录组成预执行的响应
+    fn node_pre_exec_response(
+        &self,
+        pre_sel_utxo_req: &xchain::PreExecWithSelectUTXORequest,
+        invoke: Option<xchain::InvokeRPCResponse>,
+        record: Option<xchain::UtxoRecordDetail>,
+    ) -> Result<xchain::PreExecWithSelectUTXOResponse> {
+        let mut resp = xchain::PreExecWithSelectUTXOResponse::new();
+        resp.set_bcname(pre_sel_utxo_req.bcname.to_owned());
+        if let Some(invoke) = invoke {
+            resp.set_response(invoke.get_response().clone());
+        }
+        if let Some(record) = record {
+            let address = &pre_sel_utxo_req.address;
+            let utxos = record
+                .get_openUtxoRecord()
+                .get_item()
+                .iter()
+                .map(|k| crate::consolidate::utxo_from_key(k, address))
+                .collect::<Result<Vec<_>>>()?;
+            let total_amount = num_bigint::BigInt::from(pre_sel_utxo_req.totalAmount);
+            resp.set_utxoOutput(crate::utxo::select(&utxos, &total_amount)?);
+        }
+
+        self.check_resp_code(resp.get_response().get_responses())?;
+        if self.config.verify_read_set {
+            verify_read_set(pre_sel_utxo_req.get_request(), &resp)?;
+        }
+        Ok(resp)
+    }
+
+    fn pre_exec_request(
+        &self,
+        pre_sel_utxo_req: &xchain::PreExecWithSelectUTXORequest,
+    ) -> Result<xendorser::EndorserRequest> {
+        let request_data = canonical::to_canonical_json(pre_sel_utxo_req)?;
         let mut endorser_request = xendorser::EndorserRequest::new();
         endorser_request.set_RequestName(String::from("PreExecWithFee"));
         endorser_request.set_BcName(self.chain_name.to_owned());
-        endorser_request.set_RequestData(request_data.into_bytes());
-        let resp = ocall::ocall_xchain_endorser_call(endorser_request)?;
+        endorser_request.set_RequestData(request_data);
+        self.record(crate::transcript::PRE_EXEC_REQUEST, &endorser_request)?;
+        Ok(endorser_request)
+    }
+
+    fn pre_exec_response(
+        &self,
+        pre_sel_utxo_req: &xchain::PreExecWithSelectUTXORequest,
+        resp: &xendorser::EndorserResponse,
+    ) -> Result<(
+        xchain::PreExecWithSelectUTXOResponse,
+        serde_json::Map<String, serde_json::Value>,
+    )> {
+        self.record(crate::transcript::PRE_EXEC_RESPONSE, resp)?;
+        RpcError::check_endorser(resp)?;
 
-        let pre_exec_with_select_utxo_resp: xchain::PreExecWithSelectUTXOResponse =
-            serde_json::from_slice(&resp.ResponseData)?;
+        let (pre_exec_with_select_utxo_resp, unknown_fields): (
+            xchain::PreExecWithSelectUTXOResponse,
+            _,
+        ) = if self.config.strict_response {
+            check_unknown_fields(resp)?;
+            (
+                encoder::from_slice_strict(&resp.ResponseData)?,
+                Default::default(),
+            )
+        } else {
+            encoder::from_slice_tolerant(&resp.ResponseData)?
+        };
 
         self.check_resp_code(
             pre_exec_with_select_utxo_resp
                 .get_response()
                 .get_responses(),
         )?;
-        Ok(pre_exec_with_select_utxo_resp)
+        verify_pre_exec_binding(pre_sel_utxo_req, &pre_exec_with_select_utxo_resp)?;
+        if self.config.verify_read_set {
+            verify_read_set(
+                pre_sel_utxo_req.get_request(),
+                &pre_exec_with_select_utxo_resp,
+            )?;
+        }
+        Ok((pre_exec_with_select_utxo_resp, unknown_fields))
     }
 
-    fn generate_tx_input(
+    /// utxo_output里的utxo全部作为交易输入(按引用排序)，超出total_need的部分找零给账户，
+    /// 不需要找零时返回的输出to_addr为空
+    pub fn generate_tx_input(
         &self,
         utxo_output: &xchain::UtxoOutput,
         total_need: &num_bigint::BigInt,
     ) -> Result<(Vec<xchain::TxInput>, xchain::TxOutput)> {
-        let mut tx_inputs = std::vec::Vec::<xchain::TxInput>::new();
-        for utxo in utxo_output.utxoList.iter() {
-            let mut ti = xchain::TxInput::new();
-            ti.set_ref_txid(utxo.refTxid.clone());
-            ti.set_ref_offset(utxo.refOffset);
-            ti.set_from_addr(utxo.toAddr.clone());
-            ti.set_amount(utxo.amount.clone());
-            tx_inputs.push(ti);
-        }
-
+        let tx_inputs = crate::builder::tx_inputs(&utxo_output.utxoList);
         let utxo_total = crate::consts::str_as_bigint(&utxo_output.totalSelected)?;
-
-        let mut to = xchain::TxOutput::new();
-        if utxo_total.cmp(total_need) == std::cmp::Ordering::Greater {
-            let delta = utxo_total.sub(total_need);
-            to.set_to_addr(self.account.address.clone().into_bytes());
-            to.set_amount(delta.to_bytes_be().1);
-        }
+        let to = crate::builder::change_output(&utxo_total, total_need, &self.account.address)
+            .unwrap_or_default();
         return Ok((tx_inputs, to));
     }
 
+    /// 收款的输出冻结到frozen_height，手续费输出不冻结
     fn generate_tx_output(
         &self,
-        to: &String,
-        amount: &String,
+        payments: &[(&str, &str)],
         fee: &str,
+        frozen_height: i64,
     ) -> Result<Vec<xchain::TxOutput>> {
-        let mut tx_outputs = std::vec::Vec::<xchain::TxOutput>::new();
-        //TODO amount > 0
-        if !to.is_empty() {
-            let mut t = xchain::TxOutput::new();
-            t.set_to_addr(to.clone().into_bytes());
-            let am = crate::consts::str_as_bigint(&amount)?;
-            t.set_amount(am.to_bytes_be().1);
-            tx_outputs.push(t);
-        }
+        let mut tx_outputs = crate::builder::payment_outputs(payments, frozen_height)?;
         if !fee.is_empty() && fee != "0" {
-            let mut t = xchain::TxOutput::new();
-            t.set_to_addr(String::from("$").into_bytes());
-            let am = crate::consts::str_as_bigint(&fee)?;
-            t.set_amount(am.to_bytes_be().1);
-            tx_outputs.push(t);
+            tx_outputs.push(crate::consts::FeeOutput::new(fee)?.to_tx_output());
         }
         Ok(tx_outputs)
     }
@@ -128,31 +1065,48 @@ impl<'a, 'b, 'c> Session<'a, 'b, 'c> {
     pub fn gen_compliance_check_tx(
         &self,
         resp: &mut xchain::PreExecWithSelectUTXOResponse,
+    ) -> Result<xchain::Transaction> {
+        let mut tx = self.build_compliance_check_tx(resp)?;
+        sign_initiator(self.account, &mut tx)?;
+        Ok(tx)
+    }
+
+    /// 需要时生成签名的合规检查交易，见ComplianceCheckConfig::needs_fee_tx
+    fn gen_fee_tx(
+        &self,
+        resp: &mut xchain::PreExecWithSelectUTXOResponse,
+    ) -> Result<Option<xchain::Transaction>> {
+        *self.fee_tx.lock().unwrap() = None;
+        if !self.config.compliance_check.needs_fee_tx() {
+            return Ok(None);
+        }
+        let tx = self.gen_compliance_check_tx(resp)?;
+        *self.fee_tx.lock().unwrap() = Some(tx.clone());
+        Ok(Some(tx))
+    }
+
+    /// 没有签名的合规检查交易，txid是没有签名时的txid
+    fn build_compliance_check_tx(
+        &self,
+        resp: &mut xchain::PreExecWithSelectUTXOResponse,
     ) -> Result<xchain::Transaction> {
         let total_need = num_bigint::BigInt::from_i64(
-            config::CONFIG
-                .read()
-                .unwrap()
+            self.config
                 .compliance_check
                 .compliance_check_endorse_service_fee as i64,
         )
         .ok_or(Error::from(ErrorKind::ParseError))?;
 
         let (tx_inputs, tx_output) = self.generate_tx_input(resp.get_utxoOutput(), &total_need)?;
-        let mut tx_outputs = self.generate_tx_output(
-            &config::CONFIG
-                .read()
-                .unwrap()
-                .compliance_check
-                .compliance_check_endorse_service_fee_addr,
-            &config::CONFIG
-                .read()
-                .unwrap()
-                .compliance_check
-                .compliance_check_endorse_service_fee
-                .to_string(),
-            "0",
-        )?;
+        let (fee_addr, endorser_fee) = {
+            let c = &self.config.compliance_check;
+            (
+                c.fee_addr(self.msg.tx_class).to_owned(),
+                c.compliance_check_endorse_service_fee.to_string(),
+            )
+        };
+        let mut tx_outputs =
+            self.generate_tx_output(&[(&fee_addr[..], &endorser_fee[..])], "0", 0)?;
 
         if !tx_output.to_addr.is_empty() {
             tx_outputs.push(tx_output);
@@ -163,22 +1117,20 @@ impl<'a, 'b, 'c> Session<'a, 'b, 'c> {
         tx.set_desc(String::from("compliance check tx").into_bytes());
         tx.set_version(super::consts::TXVersion);
         tx.set_coinbase(false);
-        tx.set_timestamp(super::consts::now_as_nanos());
+        tx.set_timestamp(self.msg.tx_timestamp());
         tx.set_tx_inputs(protobuf::RepeatedField::from_vec(tx_inputs));
         tx.set_tx_outputs(protobuf::RepeatedField::from_vec(tx_outputs));
         tx.set_initiator(self.msg.initiator.to_owned());
-        tx.set_nonce(super::wallet::get_nonce()?);
-
-        let digest_hash = encoder::make_tx_digest_hash(&tx)?;
-
-        //sign the digest_hash
-        let sig = self.account.sign(&digest_hash)?;
-        let mut signature_info = xchain::SignatureInfo::new();
-        signature_info.set_PublicKey(self.account.public_key()?);
-        signature_info.set_Sign(sig);
-        let signature_infos = vec![signature_info; 1];
-        tx.set_initiator_signs(protobuf::RepeatedField::from_vec(signature_infos));
+        tx.set_nonce(self.msg.tx_nonce()?);
+        self.bind_chain_id(&mut tx)?;
         tx.set_txid(encoder::make_transaction_id(&tx)?);
+        tracing::debug!(
+            txid = %hex::encode(&tx.txid),
+            inputs = tx.tx_inputs.len(),
+            "compliance check tx built"
+        );
+
+        self.audit_compliance_tx(&tx)?;
         Ok(tx)
     }
 
@@ -187,11 +1139,33 @@ impl<'a, 'b, 'c> Session<'a, 'b, 'c> {
         resp: &xchain::PreExecWithSelectUTXOResponse,
         cctx: &xchain::Transaction,
     ) -> Result<xchain::Transaction> {
-        let mut tx_outputs =
-            self.generate_tx_output(&self.msg.to, &self.msg.amount, &self.msg.fee)?;
+        self.build_real_tx(
+            resp,
+            &self.compliance_change(cctx),
+            self.msg.auth_require.to_owned(),
+        )
+    }
 
-        let mut total_selected: num_bigint::BigInt = num_traits::Zero::zero();
-        let mut utxo_list = std::vec::Vec::<xchain::Utxo>::new();
+    /// 没有合规检查交易时直接花费预执行选出的utxo，auth_require不变
+    fn gen_real_tx_with(
+        &self,
+        resp: &xchain::PreExecWithSelectUTXOResponse,
+        cctx: Option<&xchain::Transaction>,
+    ) -> Result<xchain::Transaction> {
+        match cctx {
+            Some(cctx) => self.gen_real_tx(resp, cctx),
+            None => self.build_real_tx(
+                resp,
+                resp.get_utxoOutput(),
+                self.msg.auth_require.to_owned(),
+            ),
+        }
+    }
+
+    /// 合规检查交易找零给发起者的输出，真正的交易花费这些输出
+    fn compliance_change(&self, cctx: &xchain::Transaction) -> xchain::UtxoOutput {
+        let mut total_selected: num_bigint::BigInt = num_traits::Zero::zero();
+        let mut utxo_list = std::vec::Vec::<xchain::Utxo>::new();
         let mut index = 0;
         for tx_output in cctx.tx_outputs.iter() {
             if tx_output.to_addr == self.msg.initiator.as_bytes() {
@@ -212,80 +1186,656 @@ impl<'a, 'b, 'c> Session<'a, 'b, 'c> {
         let mut utxo_output = xchain::UtxoOutput::new();
         utxo_output.set_utxoList(protobuf::RepeatedField::from_vec(utxo_list));
         utxo_output.set_totalSelected(total_selected.to_str_radix(10));
+        utxo_output
+    }
 
-        let mut total_need = crate::consts::str_as_bigint(&self.msg.amount)?;
-        let fee = crate::consts::str_as_bigint(&self.msg.fee)?;
-        total_need.add_assign(fee);
+    /// 不做合规检查的交易: 直接花费预执行选出的utxo，auth_require里面去掉背书服务的地址
+    pub fn gen_real_tx_without_compliance(
+        &self,
+        resp: &xchain::PreExecWithSelectUTXOResponse,
+    ) -> Result<xchain::Transaction> {
+        self.build_real_tx(
+            resp,
+            resp.get_utxoOutput(),
+            self.auth_require_without_endorsers(),
+        )
+    }
+
+    /// 不做合规检查时auth_require里去掉背书服务的地址
+    fn auth_require_without_endorsers(&self) -> Vec<String> {
+        let endorsers = self.config.compliance_check.endorser_addrs();
+        self.msg
+            .auth_require
+            .iter()
+            .filter(|a| !endorsers.contains(a))
+            .cloned()
+            .collect()
+    }
 
-        let (tx_inputs, delta_tx_ouput) = self.generate_tx_input(&utxo_output, &total_need)?;
+    fn build_real_tx(
+        &self,
+        resp: &xchain::PreExecWithSelectUTXOResponse,
+        utxo_output: &xchain::UtxoOutput,
+        auth_require: Vec<String>,
+    ) -> Result<xchain::Transaction> {
+        let _span = tracing::debug_span!(
+            "build_tx",
+            chain = %self.chain_name,
+            initiator = %self.msg.initiator,
+        )
+        .entered();
+        let mut tx = self.build_unsigned_real_tx(resp, utxo_output, auth_require)?;
+        tracing::debug!(
+            inputs = tx.tx_inputs.len(),
+            outputs = tx.tx_outputs.len(),
+            "tx built"
+        );
+        let digest_hash = encoder::make_tx_digest_hash(&tx)?;
+        if let Some(ref t) = self.transcript {
+            t.record(crate::transcript::DIGEST, &digest_hash);
+        }
+        for hook in self.hooks.iter() {
+            hook.pre_sign(&tx, &digest_hash)?;
+        }
+        sign_with_digest(
+            &self.config,
+            self.account,
+            &self.msg.auth_require_signers,
+            &mut tx,
+            &digest_hash,
+        )?;
+        tracing::debug!(
+            digest = %hex::encode(&digest_hash),
+            signs = tx.auth_require_signs.len(),
+            "tx signed"
+        );
+        Ok(tx)
+    }
+
+    fn build_unsigned_real_tx(
+        &self,
+        resp: &xchain::PreExecWithSelectUTXOResponse,
+        utxo_output: &xchain::UtxoOutput,
+        auth_require: Vec<String>,
+    ) -> Result<xchain::Transaction> {
+        let msg = self.effective_msg();
+        let template = crate::template::TEMPLATES.get(
+            &msg,
+            &auth_require,
+            self.config.desc_compress_threshold,
+        )?;
+        let mut tx_outputs =
+            self.generate_tx_output(&self.msg.payments(), "", self.msg.frozen_height)?;
+        if let Some(ref fee_output) = template.fee_output {
+            tx_outputs.push(fee_output.clone());
+        }
+
+        let total_need = self.msg.total_amount()? + &template.fee;
+
+        let (tx_inputs, delta_tx_ouput) = self.generate_tx_input(utxo_output, &total_need)?;
         if !delta_tx_ouput.to_addr.is_empty() {
             tx_outputs.push(delta_tx_ouput);
         }
-        let mut tx = xchain::Transaction::new();
-        tx.set_desc(vec![]);
-        tx.set_version(super::consts::TXVersion);
-        tx.set_coinbase(false);
-        tx.set_timestamp(super::consts::now_as_nanos());
+        let mut tx = template.to_tx();
+        tx.set_timestamp(self.msg.tx_timestamp());
         tx.set_tx_inputs(protobuf::RepeatedField::from_vec(tx_inputs));
         tx.set_tx_outputs(protobuf::RepeatedField::from_vec(tx_outputs));
-        tx.set_initiator(self.msg.initiator.to_owned());
-        tx.set_nonce(super::wallet::get_nonce()?);
-        tx.set_auth_require(protobuf::RepeatedField::from_vec(
-            self.msg.auth_require.to_owned(),
-        ));
+        tx.set_nonce(self.msg.tx_nonce()?);
 
         tx.set_tx_inputs_ext(resp.get_response().inputs.clone());
         tx.set_tx_outputs_ext(resp.get_response().outputs.clone());
         tx.set_contract_requests(resp.get_response().requests.clone());
+        for hook in self.hooks.iter() {
+            hook.post_build(&mut tx)?;
+        }
+        self.bind_chain_id(&mut tx)?;
+        Ok(tx)
+    }
 
-        let digest_hash = encoder::make_tx_digest_hash(&tx)?;
+    /// 配置了chainId时绑定，在钩子之后，不会被钩子修改desc时去掉
+    fn bind_chain_id(&self, tx: &mut xchain::Transaction) -> Result<()> {
+        match self.config.chain_id {
+            Some(ref chain_id) => crate::chain_id::bind_tx(tx, chain_id),
+            None => Ok(()),
+        }
+    }
 
-        //sign the digest_hash
-        let sig = self.account.sign(&digest_hash)?;
-        let mut signature_info = xchain::SignatureInfo::new();
+    pub fn compliance_check(
+        &self,
+        tx: &xchain::Transaction,
+        fee: &xchain::Transaction,
+    ) -> Result<xchain::SignatureInfo> {
+        self.endorse(tx, Some(fee))
+    }
+
+    /// 合规检查，fee为None时请求不带Fee
+    fn endorse(
+        &self,
+        tx: &xchain::Transaction,
+        fee: Option<&xchain::Transaction>,
+    ) -> Result<xchain::SignatureInfo> {
+        let addr = self
+            .config
+            .compliance_check
+            .compliance_check_endorse_service_addr
+            .to_owned();
+        let _span = tracing::debug_span!("compliance_check", endorser = %addr).entered();
+        let endorser_request = self.compliance_check_request(tx, fee)?;
+        let (resp, endpoint) = self
+            .retry
+            .call(|| self.endorser_call(endorser_request.clone()))?;
+        self.compliance_check_response(resp, endpoint, tx, &addr)
+    }
 
-        signature_info.set_PublicKey(self.account.public_key()?);
-        signature_info.set_Sign(sig);
-        let signature_infos = vec![signature_info; 1];
-        tx.set_initiator_signs(protobuf::RepeatedField::from_vec(signature_infos.clone()));
-        if !self.account.contract_name.is_empty() {
-            tx.set_auth_require_signs(protobuf::RepeatedField::from_vec(signature_infos));
+    /// 不阻塞线程的compliance_check
+    #[cfg(feature = "async")]
+    pub async fn compliance_check_async(
+        &self,
+        tx: &xchain::Transaction,
+        fee: &xchain::Transaction,
+    ) -> Result<xchain::SignatureInfo> {
+        self.endorse_async(tx, Some(fee), None).await
+    }
+
+    #[cfg(feature = "async")]
+    async fn endorse_async(
+        &self,
+        tx: &xchain::Transaction,
+        fee: Option<&xchain::Transaction>,
+        deadline: Option<std::time::Instant>,
+    ) -> Result<xchain::SignatureInfo> {
+        let endorser_request = self.compliance_check_request(tx, fee)?;
+        let (resp, endpoint) = self
+            .retry
+            .call_async(|| self.endorser_call_async(endorser_request.clone(), deadline))
+            .await?;
+        let addr = self
+            .config
+            .compliance_check
+            .compliance_check_endorse_service_addr
+            .to_owned();
+        self.compliance_check_response(resp, endpoint, tx, &addr)
+    }
+
+    /// 配置的背书服务用ClientConfig::install_endorsers单独连接时按地址访问，否则使用ocall::init的连接
+    /// 传输层失败或者熔断时按顺序切换到备用接入点，返回响应和接入点
+    fn endorser_call(
+        &self,
+        mut req: xendorser::EndorserRequest,
+    ) -> Result<(xendorser::EndorserResponse, String)> {
+        let pending = self.authenticate(&mut req)?;
+        let addr = &self
+            .config
+            .compliance_check
+            .compliance_check_endorse_service_addr;
+        let mut res = if ocall::has_endorser(addr) {
+            ocall::ocall_xchain_endorser_call_by(addr, req.clone())
+        } else {
+            ocall::ocall_xchain_endorser_call(req.clone())
         }
+        .map(|resp| (resp, self.primary_endpoint()));
+        for endpoint in self.failover_endpoints() {
+            match res {
+                Err(ref e) if should_failover(e) => {
+                    tracing::warn!(%endpoint, error = %e, "endorser unavailable, failing over");
+                    res = ocall::ocall_xchain_endorser_call_by(&endpoint, req.clone())
+                        .map(|resp| (resp, endpoint));
+                }
+                _ => break,
+            }
+        }
+        let (mut resp, endpoint) = res?;
+        self.verify_attested(&mut resp, &pending)?;
+        Ok((resp, endpoint))
+    }
 
-        tx.set_txid(encoder::make_transaction_id(&tx)?);
-        Ok(tx)
+    #[cfg(feature = "async")]
+    async fn endorser_call_async(
+        &self,
+        mut req: xendorser::EndorserRequest,
+        deadline: Option<std::time::Instant>,
+    ) -> Result<(xendorser::EndorserResponse, String)> {
+        let pending = self.authenticate(&mut req)?;
+        let addr = &self
+            .config
+            .compliance_check
+            .compliance_check_endorse_service_addr;
+        let mut res = if ocall::has_endorser(addr) {
+            ocall::ocall_xchain_endorser_call_by_async(addr, req.clone(), deadline).await
+        } else {
+            ocall::ocall_xchain_endorser_call_async(req.clone(), deadline).await
+        }
+        .map(|resp| (resp, self.primary_endpoint()));
+        for endpoint in self.failover_endpoints() {
+            match res {
+                Err(ref e) if should_failover(e) => {
+                    tracing::warn!(%endpoint, error = %e, "endorser unavailable, failing over");
+                    res = ocall::ocall_xchain_endorser_call_by_async(
+                        &endpoint,
+                        req.clone(),
+                        deadline,
+                    )
+                    .await
+                    .map(|resp| (resp, endpoint));
+                }
+                _ => break,
+            }
+        }
+        let (mut resp, endpoint) = res?;
+        self.verify_attested(&mut resp, &pending)?;
+        Ok((resp, endpoint))
     }
 
-    pub fn compliance_check(
+    fn primary_endpoint(&self) -> String {
+        format!("{}:{}", self.config.node, self.config.endorse_port)
+    }
+
+    /// 已经用install_endorsers连接的备用接入点
+    fn failover_endpoints(&self) -> Vec<String> {
+        self.config
+            .compliance_check
+            .failover_endpoints
+            .iter()
+            .map(|e| e.key())
+            .filter(|k| ocall::has_endorser(k))
+            .collect()
+    }
+
+    /// 合规检查签名的背书服务地址和接入点，按背书的顺序
+    pub fn endorsements(&self) -> Vec<Endorsement> {
+        self.endorsements.lock().unwrap().clone()
+    }
+
+    fn extra_endpoint(&self, addr: &str) -> String {
+        self.config
+            .compliance_check
+            .extra_endorsers
+            .iter()
+            .find(|e| e.addr == addr)
+            .map(|e| format!("{}:{}", e.host, e.port))
+            .unwrap_or_default()
+    }
+
+    fn extra_endorser_addrs(&self) -> Vec<String> {
+        self.config
+            .compliance_check
+            .extra_endorsers
+            .iter()
+            .map(|e| e.addr.to_owned())
+            .collect()
+    }
+
+    /// 依次请求所有的合规背书服务，签名按auth_require里的顺序
+    pub fn compliance_check_all(
         &self,
         tx: &xchain::Transaction,
         fee: &xchain::Transaction,
-    ) -> Result<xchain::SignatureInfo> {
+    ) -> Result<Vec<xchain::SignatureInfo>> {
+        self.endorse_all(tx, Some(fee))
+    }
+
+    fn endorse_all(
+        &self,
+        tx: &xchain::Transaction,
+        fee: Option<&xchain::Transaction>,
+    ) -> Result<Vec<xchain::SignatureInfo>> {
+        let mut signs = vec![self.endorse(tx, fee)?];
+        for addr in self.extra_endorser_addrs() {
+            let _span = tracing::debug_span!("compliance_check", endorser = %addr).entered();
+            let endorser_request = self.compliance_check_request(tx, fee)?;
+            let resp = self
+                .retry
+                .call(|| ocall::ocall_xchain_endorser_call_by(&addr, endorser_request.clone()))?;
+            signs.push(self.compliance_check_response(
+                resp,
+                self.extra_endpoint(&addr),
+                tx,
+                &addr,
+            )?);
+        }
+        Ok(signs)
+    }
+
+    /// 不阻塞线程的compliance_check_all
+    #[cfg(feature = "async")]
+    pub async fn compliance_check_all_async(
+        &self,
+        tx: &xchain::Transaction,
+        fee: &xchain::Transaction,
+    ) -> Result<Vec<xchain::SignatureInfo>> {
+        self.endorse_all_async(tx, Some(fee), None).await
+    }
+
+    #[cfg(feature = "async")]
+    async fn endorse_all_async(
+        &self,
+        tx: &xchain::Transaction,
+        fee: Option<&xchain::Transaction>,
+        deadline: Option<std::time::Instant>,
+    ) -> Result<Vec<xchain::SignatureInfo>> {
+        let mut signs = vec![self.endorse_async(tx, fee, deadline).await?];
+        for addr in self.extra_endorser_addrs() {
+            let endorser_request = self.compliance_check_request(tx, fee)?;
+            let resp = self
+                .retry
+                .call_async(|| {
+                    ocall::ocall_xchain_endorser_call_by_async(
+                        &addr,
+                        endorser_request.clone(),
+                        deadline,
+                    )
+                })
+                .await?;
+            signs.push(self.compliance_check_response(
+                resp,
+                self.extra_endpoint(&addr),
+                tx,
+                &addr,
+            )?);
+        }
+        Ok(signs)
+    }
+
+    fn compliance_check_request(
+        &self,
+        tx: &xchain::Transaction,
+        fee: Option<&xchain::Transaction>,
+    ) -> Result<xendorser::EndorserRequest> {
         let mut tx_status = xchain::TxStatus::new();
         tx_status.set_bcname(self.chain_name.to_owned());
         tx_status.set_tx(tx.clone());
-        let request_data = serde_json::to_string(&tx_status)?;
+        let request_data = canonical::to_canonical_json(&tx_status)?;
         let mut endorser_request = xendorser::EndorserRequest::new();
         endorser_request.set_RequestName(String::from("ComplianceCheck"));
         endorser_request.set_BcName(self.chain_name.to_owned());
-        endorser_request.set_Fee(fee.clone());
-        endorser_request.set_RequestData(request_data.into_bytes());
-        let resp = ocall::ocall_xchain_endorser_call(endorser_request)?;
-        Ok(resp.EndorserSign.unwrap())
+        if let Some(fee) = fee {
+            endorser_request.set_Fee(fee.clone());
+        }
+        endorser_request.set_RequestData(request_data);
+        self.record(
+            crate::transcript::COMPLIANCE_CHECK_REQUEST,
+            &endorser_request,
+        )?;
+        Ok(endorser_request)
+    }
+
+    /// addr是请求的背书服务，配置了它的公钥时校验签名
+    fn compliance_check_response(
+        &self,
+        resp: xendorser::EndorserResponse,
+        endpoint: String,
+        tx: &xchain::Transaction,
+        addr: &str,
+    ) -> Result<xchain::SignatureInfo> {
+        self.record(crate::transcript::COMPLIANCE_CHECK_RESPONSE, &resp)?;
+        if self.config.strict_response {
+            check_unknown_fields(&resp)?;
+            check_unknown_fields(resp.get_EndorserSign())?;
+        }
+        if let Some(rejection) = crate::rejection::parse(&resp) {
+            tracing::warn!(
+                %endpoint,
+                code = %rejection.code,
+                reason = rejection.reason.description(),
+                "compliance check rejected"
+            );
+            return Err(Error::new(ErrorKind::ComplianceRejected, rejection));
+        }
+        let sign = resp
+            .EndorserSign
+            .into_option()
+            .ok_or(Error::from(ErrorKind::EndorserSignMissing))?;
+        if let Some(public_key) = self.config.compliance_check.endorser_public_key(addr) {
+            verify_endorser_sign(tx, &sign, public_key)?;
+        }
+        let endorsement = Endorsement {
+            addr: super::wallet::address_from_public_key(&sign.PublicKey)?,
+            endpoint: endpoint,
+        };
+        if let Some(ref t) = self.transcript {
+            t.record(
+                crate::transcript::ENDORSED_BY,
+                format!("{} {}", endorsement.addr, endorsement.endpoint).as_bytes(),
+            );
+        }
+        tracing::debug!(
+            endorser = %endorsement.addr,
+            endpoint = %endorsement.endpoint,
+            "endorsed"
+        );
+        self.endorsements.lock().unwrap().push(endorsement);
+        Ok(sign)
     }
 
     pub fn gen_complete_tx_and_post(
         &self,
         pre_exec_resp: &mut xchain::PreExecWithSelectUTXOResponse,
     ) -> Result<String> {
-        let cctx = self.gen_compliance_check_tx(pre_exec_resp)?;
-        let mut tx = self.gen_real_tx(&pre_exec_resp, &cctx)?;
-        let end_sign = self.compliance_check(&tx, &cctx)?;
+        self.gen_complete_tx_and_post_with_deadline(pre_exec_resp, None)
+    }
+
+    /// 合规检查和提交交易需要在deadline之前完成，否则放弃交易并返回DeadlineExceeded
+    /// 放弃的交易不会被提交，选中的utxo不会被花费。每个RPC都以剩余的时间为超时(见deadline::scope)，
+    /// 提交交易超时的时候节点可能已经收到交易，需要按txid查询
+    pub fn gen_complete_tx_and_post_with_deadline(
+        &self,
+        pre_exec_resp: &mut xchain::PreExecWithSelectUTXOResponse,
+        deadline: Option<std::time::Instant>,
+    ) -> Result<String> {
+        let tx = self.gen_complete_tx(pre_exec_resp, deadline)?;
+        deadline::scope(deadline, || self.post_complete_tx(&tx))
+    }
+
+    /// 不阻塞线程的gen_complete_tx_and_post
+    #[cfg(feature = "async")]
+    pub async fn gen_complete_tx_and_post_async(
+        &self,
+        pre_exec_resp: &mut xchain::PreExecWithSelectUTXOResponse,
+    ) -> Result<String> {
+        self.gen_complete_tx_and_post_with_deadline_async(pre_exec_resp, None)
+            .await
+    }
 
-        tx.auth_require_signs.push(end_sign);
+    /// 不阻塞线程的gen_complete_tx_and_post_with_deadline
+    #[cfg(feature = "async")]
+    pub async fn gen_complete_tx_and_post_with_deadline_async(
+        &self,
+        pre_exec_resp: &mut xchain::PreExecWithSelectUTXOResponse,
+        deadline: Option<std::time::Instant>,
+    ) -> Result<String> {
+        crate::consts::check_deadline(deadline)?;
+        let tx = if self.config.compliance_check.skip_compliance_check {
+            self.gen_real_tx_without_compliance(pre_exec_resp)?
+        } else {
+            let cctx = self.gen_fee_tx(pre_exec_resp)?;
+            let tx = self.gen_real_tx_with(&pre_exec_resp, cctx.as_ref())?;
+            let res = self.endorse_all_async(&tx, cctx.as_ref(), deadline).await;
+            self.complete_tx(pre_exec_resp, tx, res, deadline)?
+        };
+        let txid = hex::encode(&tx.txid);
+        self.pre_post_tx(&tx)?;
+        if let Err(e) = ocall::ocall_xchain_post_tx_async(tx.clone(), deadline).await {
+            tracing::warn!(chain = %self.chain_name, %txid, error = %e, "post tx failed");
+            return Err(e);
+        }
+        tracing::info!(chain = %self.chain_name, %txid, "tx posted");
+        let fee_tx = self.fee_tx.lock().unwrap().clone();
+        self.post_post_tx(&tx, fee_tx.as_ref());
+        Ok(txid)
+    }
+
+    /// 生成交易并完成合规检查，返回带背书签名的交易，skipComplianceCheck时不做合规检查
+    pub fn gen_complete_tx(
+        &self,
+        pre_exec_resp: &mut xchain::PreExecWithSelectUTXOResponse,
+        deadline: Option<std::time::Instant>,
+    ) -> Result<xchain::Transaction> {
+        crate::consts::check_deadline(deadline)?;
+        if self.config.compliance_check.skip_compliance_check {
+            return self.gen_real_tx_without_compliance(pre_exec_resp);
+        }
+        let cctx = self.gen_fee_tx(pre_exec_resp)?;
+        let tx = self.gen_real_tx_with(&pre_exec_resp, cctx.as_ref())?;
+        let res = deadline::scope(deadline, || self.endorse_all(&tx, cctx.as_ref()));
+        self.complete_tx(pre_exec_resp, tx, res, deadline)
+    }
+
+    /// 根据合规检查的结果给交易加上背书签名
+    fn complete_tx(
+        &self,
+        pre_exec_resp: &xchain::PreExecWithSelectUTXOResponse,
+        mut tx: xchain::Transaction,
+        res: Result<Vec<xchain::SignatureInfo>>,
+        deadline: Option<std::time::Instant>,
+    ) -> Result<xchain::Transaction> {
+        let end_signs = match res {
+            Err(ref e)
+                if e.kind() == ErrorKind::EndorserSignMissing
+                    && self.config.compliance_check.skip_on_missing_endorser_sign =>
+            {
+                crate::consts::check_deadline(deadline)?;
+                return self.gen_real_tx_without_compliance(pre_exec_resp);
+            }
+            res => res?,
+        };
+        crate::consts::check_deadline(deadline)?;
+
+        for end_sign in end_signs {
+            tx.auth_require_signs.push(end_sign);
+        }
         tx.set_txid(encoder::make_transaction_id(&tx)?);
-        ocall::ocall_xchain_post_tx(&tx)?;
-        Ok(hex::encode(tx.txid))
+        Ok(tx)
+    }
+
+    /// 提交gen_complete_tx生成的交易，返回txid
+    pub fn post_complete_tx(&self, tx: &xchain::Transaction) -> Result<String> {
+        let fee_tx = self.fee_tx.lock().unwrap().clone();
+        self.post_with_fee_tx(tx, fee_tx.as_ref())
+    }
+
+    /// fee_tx是tx花费的合规检查交易，提交之后交给钩子
+    fn post_with_fee_tx(
+        &self,
+        tx: &xchain::Transaction,
+        fee_tx: Option<&xchain::Transaction>,
+    ) -> Result<String> {
+        let txid = hex::encode(&tx.txid);
+        let _span = tracing::info_span!("post_tx", chain = %self.chain_name, %txid).entered();
+        self.pre_post_tx(tx)?;
+        if let Err(e) = ocall::ocall_xchain_post_tx(tx) {
+            tracing::warn!(error = %e, "post tx failed");
+            return Err(e);
+        }
+        tracing::info!("tx posted");
+        self.post_post_tx(tx, fee_tx);
+        Ok(txid)
+    }
+
+    fn pre_post_tx(&self, tx: &xchain::Transaction) -> Result<()> {
+        for hook in self.hooks.iter() {
+            hook.pre_post(tx)?;
+        }
+        self.record(crate::transcript::POST_TX, tx)
+    }
+
+    fn post_post_tx(&self, tx: &xchain::Transaction, fee_tx: Option<&xchain::Transaction>) {
+        // 背书服务缺签名时退回到不做合规检查的交易，不花费合规检查交易，它没有上链
+        let fee_tx = fee_tx.filter(|f| tx.tx_inputs.iter().any(|i| i.ref_txid == f.txid));
+        for hook in self.hooks.iter() {
+            if let Some(f) = fee_tx {
+                hook.fee_tx_posted(f);
+            }
+            hook.posted(tx);
+        }
+    }
+
+    /// 核对合规检查交易，结果记录在transcript里，不通过时返回FeeTxInvalid
+    fn audit_compliance_tx(&self, tx: &xchain::Transaction) -> Result<()> {
+        let (fee_addr, endorser_fee) = {
+            let c = &self.config.compliance_check;
+            (
+                c.fee_addr(self.msg.tx_class).to_owned(),
+                num_bigint::BigInt::from(c.compliance_check_endorse_service_fee),
+            )
+        };
+        let check = check_compliance_tx(tx, &fee_addr, &endorser_fee);
+        if let Some(ref t) = self.transcript {
+            t.record(
+                crate::transcript::COMPLIANCE_TX_CHECK,
+                &serde_json::to_vec(&check)?,
+            );
+        }
+        if !check.violations.is_empty() {
+            return Err(Error::from(ErrorKind::FeeTxInvalid));
+        }
+        Ok(())
+    }
+
+    /// 生成没有签名的交易，交给离线的机器用sign_tx签名，之后用broadcast_tx提交
+    pub fn build_unsigned_tx(
+        &self,
+        pre_exec_resp: &mut xchain::PreExecWithSelectUTXOResponse,
+    ) -> Result<OfflineTx> {
+        let check = &self.config.compliance_check;
+        if check.skip_compliance_check || !check.needs_fee_tx() {
+            let auth_require = if check.skip_compliance_check {
+                self.auth_require_without_endorsers()
+            } else {
+                self.msg.auth_require.to_owned()
+            };
+            let tx = self.build_unsigned_real_tx(
+                pre_exec_resp,
+                pre_exec_resp.get_utxoOutput(),
+                auth_require,
+            )?;
+            return Ok(OfflineTx {
+                compliance_tx: xchain::Transaction::new(),
+                tx: tx,
+            });
+        }
+        let compliance_tx = self.build_compliance_check_tx(pre_exec_resp)?;
+        let tx = self.build_unsigned_real_tx(
+            pre_exec_resp,
+            &self.compliance_change(&compliance_tx),
+            self.msg.auth_require.to_owned(),
+        )?;
+        Ok(OfflineTx {
+            compliance_tx: compliance_tx,
+            tx: tx,
+        })
+    }
+
+    /// 对sign_tx签好的交易做合规检查并提交，返回txid
+    /// skipComplianceCheck时不访问背书服务，直接提交
+    pub fn broadcast_tx(&self, signed: &OfflineTx) -> Result<String> {
+        let check = &self.config.compliance_check;
+        let has_compliance_tx = signed.has_compliance_tx();
+        // 合规检查交易要和配置一致，不能多也不能少
+        if has_compliance_tx != (!check.skip_compliance_check && check.needs_fee_tx()) {
+            return Err(Error::from(ErrorKind::InvalidArguments));
+        }
+        if let Some(ref chain_id) = self.config.chain_id {
+            if has_compliance_tx {
+                crate::chain_id::verify(&signed.compliance_tx, chain_id)?;
+            }
+            crate::chain_id::verify(&signed.tx, chain_id)?;
+        }
+        if check.skip_compliance_check {
+            return self.post_with_fee_tx(&signed.tx, None);
+        }
+        let fee = if has_compliance_tx {
+            self.audit_compliance_tx(&signed.compliance_tx)?;
+            Some(&signed.compliance_tx)
+        } else {
+            None
+        };
+        let end_signs = self.endorse_all(&signed.tx, fee)?;
+        let mut tx = signed.tx.clone();
+        for end_sign in end_signs {
+            tx.auth_require_signs.push(end_sign);
+        }
+        tx.set_txid(encoder::make_transaction_id(&tx)?);
+        self.post_with_fee_tx(&tx, fee)
     }
 
     #[allow(dead_code)]
@@ -294,10 +1844,1180 @@ impl<'a, 'b, 'c> Session<'a, 'b, 'c> {
             crate::consts::print_bytes_num(&i.amount);
         }
         for i in tx.tx_outputs.iter() {
+            if crate::consts::FeeOutput::is_fee(i) {
+                tracing::debug!("fee output");
+            }
             crate::consts::print_bytes_num(&i.amount);
         }
     }
 
-    //TODO
-    //pub fn get_balance() -> Result<String> {}
+    /// 查询address在当前链上的余额
+    pub fn get_balance(&self, address: &String) -> Result<crate::balance::Balance> {
+        crate::balance::get_balance(self.chain_name, address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invoke_request(contract_name: &str) -> xchain::InvokeRequest {
+        let mut r = xchain::InvokeRequest::new();
+        r.set_module_name(String::from("wasm"));
+        r.set_contract_name(String::from(contract_name));
+        r.set_method_name(String::from("increase"));
+        r
+    }
+
+    fn input_ext(bucket: &str, key: &str, ref_offset: i32) -> xchain::TxInputExt {
+        let mut i = xchain::TxInputExt::new();
+        i.set_bucket(String::from(bucket));
+        i.set_key(key.as_bytes().to_vec());
+        i.set_ref_txid(vec![0xaa, 0xaa]);
+        i.set_ref_offset(ref_offset);
+        i
+    }
+
+    fn utxo(to_addr: &str, amount: u8, ref_offset: i32) -> xchain::Utxo {
+        let mut u = xchain::Utxo::new();
+        u.set_toAddr(to_addr.as_bytes().to_vec());
+        u.set_amount(vec![amount]);
+        u.set_refTxid(vec![0xaa, 0xaa]);
+        u.set_refOffset(ref_offset);
+        u
+    }
+
+    #[test]
+    fn test_verify_pre_exec_binding() {
+        let mut req = xchain::PreExecWithSelectUTXORequest::new();
+        req.set_bcname(String::from("xuper"));
+        req.set_address(String::from("alice"));
+        req.set_totalAmount(5);
+
+        let mut resp = xchain::PreExecWithSelectUTXOResponse::new();
+        resp.set_bcname(String::from("xuper"));
+        resp.mut_utxoOutput()
+            .set_utxoList(protobuf::RepeatedField::from_vec(vec![
+                utxo("alice", 3, 0),
+                utxo("alice", 4, 1),
+            ]));
+        resp.mut_utxoOutput().set_totalSelected(String::from("7"));
+        assert_eq!(verify_pre_exec_binding(&req, &resp).is_ok(), true);
+
+        // 别人的utxo
+        let mut other = resp.clone();
+        other.mut_utxoOutput().mut_utxoList()[1] = utxo("bob", 4, 1);
+        assert_eq!(
+            verify_pre_exec_binding(&req, &other).unwrap_err().kind(),
+            ErrorKind::PreExecTampered
+        );
+
+        // totalSelected和utxo之和不一致
+        let mut total = resp.clone();
+        total.mut_utxoOutput().set_totalSelected(String::from("8"));
+        assert_eq!(verify_pre_exec_binding(&req, &total).is_ok(), false);
+
+        // 金额不够
+        req.set_totalAmount(8);
+        assert_eq!(verify_pre_exec_binding(&req, &resp).is_ok(), false);
+    }
+
+    #[test]
+    fn test_verify_read_set() {
+        let mut req = xchain::InvokeRPCRequest::new();
+        req.set_requests(protobuf::RepeatedField::from_vec(vec![invoke_request(
+            "counter",
+        )]));
+
+        let mut resp = xchain::PreExecWithSelectUTXOResponse::new();
+        resp.mut_response()
+            .set_requests(protobuf::RepeatedField::from_vec(vec![
+                invoke_request("counter"),
+                invoke_request("callee"),
+            ]));
+        resp.mut_response()
+            .set_inputs(protobuf::RepeatedField::from_vec(vec![
+                input_ext("counter", "k1", 0),
+                input_ext("callee", "k1", 1),
+            ]));
+        assert_eq!(verify_read_set(&req, &resp).is_ok(), true);
+
+        // 重复的key
+        let mut dup = resp.clone();
+        dup.mut_response()
+            .mut_inputs()
+            .push(input_ext("counter", "k1", 2));
+        assert_eq!(
+            verify_read_set(&req, &dup).unwrap_err().kind(),
+            ErrorKind::PreExecTampered
+        );
+
+        // 读了没有调用的合约
+        let mut other = resp.clone();
+        other
+            .mut_response()
+            .mut_inputs()
+            .push(input_ext("other", "k1", 0));
+        assert_eq!(verify_read_set(&req, &other).is_ok(), false);
+
+        // 合约调用被替换
+        let mut replaced = resp.clone();
+        replaced.mut_response().mut_requests()[0].set_method_name(String::from("get"));
+        assert_eq!(verify_read_set(&req, &replaced).is_ok(), false);
+    }
+
+    struct DescHook;
+
+    impl TxHook for DescHook {
+        fn post_build(&self, tx: &mut xchain::Transaction) -> Result<()> {
+            tx.set_desc(b"hooked".to_vec());
+            Ok(())
+        }
+    }
+
+    struct VetoHook;
+
+    impl TxHook for VetoHook {
+        fn pre_sign(&self, tx: &xchain::Transaction, _digest: &[u8]) -> Result<()> {
+            if tx.get_desc() == b"hooked" {
+                return Err(Error::from(ErrorKind::InvalidArguments));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_tx_hooks() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = super::super::wallet::Account::new(d.to_str().unwrap(), "", "");
+        let msg = Message {
+            to: String::from("dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN"),
+            amount: String::from("1"),
+            fee: String::from("0"),
+            initiator: acc.address.to_owned(),
+            ..Default::default()
+        };
+        let chain_name = String::from("xuper");
+
+        let mut cctx = xchain::Transaction::new();
+        let mut change = xchain::TxOutput::new();
+        change.set_to_addr(acc.address.to_owned().into_bytes());
+        change.set_amount(vec![10]);
+        cctx.mut_tx_outputs().push(change);
+        let resp = xchain::PreExecWithSelectUTXOResponse::new();
+
+        let mut sess = Session::new(&chain_name, &acc, &msg);
+        sess.add_hook(std::sync::Arc::new(DescHook));
+        let tx = sess.gen_real_tx(&resp, &cctx).unwrap();
+        assert_eq!(tx.get_desc(), b"hooked");
+        assert_eq!(tx.get_initiator_signs().len(), 1);
+
+        sess.add_hook(std::sync::Arc::new(VetoHook));
+        assert_eq!(
+            sess.gen_real_tx(&resp, &cctx).unwrap_err().kind(),
+            ErrorKind::InvalidArguments
+        );
+    }
+
+    #[test]
+    fn test_tx_inputs_sorted() {
+        let acc = super::super::wallet::Account::default();
+        let msg = Message::default();
+        let chain_name = String::from("xuper");
+        let sess = Session::new(&chain_name, &acc, &msg);
+
+        let mut a = utxo("alice", 1, 1);
+        a.set_refTxid(vec![0xbb]);
+        let b = utxo("alice", 2, 1);
+        let c = utxo("alice", 3, 0);
+        let total_need = num_bigint::BigInt::from_i64(6).unwrap();
+
+        let mut utxo_output = xchain::UtxoOutput::new();
+        utxo_output.set_totalSelected(String::from("6"));
+        utxo_output.set_utxoList(protobuf::RepeatedField::from_vec(vec![
+            a.clone(),
+            b.clone(),
+            c.clone(),
+        ]));
+        let (first, _) = sess.generate_tx_input(&utxo_output, &total_need).unwrap();
+        utxo_output.set_utxoList(protobuf::RepeatedField::from_vec(vec![c, a, b]));
+        let (second, _) = sess.generate_tx_input(&utxo_output, &total_need).unwrap();
+
+        assert_eq!(first, second);
+        let refs: Vec<(Vec<u8>, i32)> = first
+            .iter()
+            .map(|i| (i.ref_txid.clone(), i.ref_offset))
+            .collect();
+        assert_eq!(
+            refs,
+            vec![
+                (vec![0xaa, 0xaa], 0),
+                (vec![0xaa, 0xaa], 1),
+                (vec![0xbb], 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rebuild_same_txid() {
+        let acc = super::super::wallet::Account::default();
+        let chain_name = String::from("xuper");
+        let mut msg = Message {
+            to: String::from("bob"),
+            amount: String::from("1"),
+            fee: String::from("0"),
+            initiator: String::from("alice"),
+            timestamp: Some(1),
+            nonce: Some(String::from("n")),
+            ..Default::default()
+        };
+
+        let mut a = utxo("alice", 1, 1);
+        a.set_refTxid(vec![0xbb]);
+        let b = utxo("alice", 2, 1);
+        let mut utxo_output = xchain::UtxoOutput::new();
+        utxo_output.set_totalSelected(String::from("3"));
+        let resp = xchain::PreExecWithSelectUTXOResponse::new();
+        let mut build = |msg: &Message, utxos: Vec<xchain::Utxo>| {
+            utxo_output.set_utxoList(protobuf::RepeatedField::from_vec(utxos));
+            let sess = Session::new(&chain_name, &acc, msg);
+            let tx = sess
+                .build_unsigned_real_tx(&resp, &utxo_output, vec![])
+                .unwrap();
+            encoder::make_transaction_id(&tx).unwrap()
+        };
+
+        // 固定timestamp和nonce时，utxo的顺序不影响txid
+        let first = build(&msg, vec![a.clone(), b.clone()]);
+        assert_eq!(build(&msg, vec![b.clone(), a.clone()]), first);
+
+        msg.nonce = None;
+        assert_ne!(build(&msg, vec![a, b]), first);
+    }
+
+    #[test]
+    fn test_desc_only_tx() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = super::super::wallet::Account::new(d.to_str().unwrap(), "", "");
+        let msg = Message {
+            amount: String::from("0"),
+            fee: String::from("2"),
+            desc: String::from("anchor"),
+            initiator: acc.address.to_owned(),
+            ..Default::default()
+        };
+        let chain_name = String::from("xuper");
+        let mut resp = xchain::PreExecWithSelectUTXOResponse::new();
+        resp.mut_utxoOutput()
+            .set_utxoList(protobuf::RepeatedField::from_vec(vec![utxo(
+                &acc.address,
+                10,
+                0,
+            )]));
+        resp.mut_utxoOutput().set_totalSelected(String::from("10"));
+
+        // 默认不带desc
+        let sess = Session::new(&chain_name, &acc, &msg);
+        let tx = sess.gen_real_tx_without_compliance(&resp).unwrap();
+        assert_eq!(tx.get_desc().is_empty(), true);
+
+        let mut sess = Session::new(&chain_name, &acc, &msg);
+        sess.set_desc_on_chain(true);
+        let tx = sess.gen_real_tx_without_compliance(&resp).unwrap();
+        // 只有手续费2和找零8
+        let outputs: Vec<(&[u8], &[u8])> = tx
+            .get_tx_outputs()
+            .iter()
+            .map(|o| (o.get_to_addr(), o.get_amount()))
+            .collect();
+        assert_eq!(
+            outputs,
+            vec![(&b"$"[..], &[2][..]), (acc.address.as_bytes(), &[8][..])]
+        );
+        assert_eq!(crate::desc::tx_desc(&tx).unwrap(), b"anchor".to_vec());
+
+        // 没有手续费时没有输入和输出
+        let msg = Message {
+            fee: String::from("0"),
+            ..msg
+        };
+        let mut sess = Session::new(&chain_name, &acc, &msg);
+        sess.set_desc_on_chain(true);
+        let tx = sess
+            .gen_real_tx_without_compliance(&xchain::PreExecWithSelectUTXOResponse::new())
+            .unwrap();
+        assert_eq!(tx.get_tx_inputs().is_empty(), true);
+        assert_eq!(tx.get_tx_outputs().is_empty(), true);
+        assert_eq!(crate::desc::tx_desc(&tx).unwrap(), b"anchor".to_vec());
+    }
+
+    #[test]
+    fn test_gen_real_tx_without_compliance() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = super::super::wallet::Account::new(d.to_str().unwrap(), "", "");
+        let endorser = config::CONFIG
+            .read()
+            .unwrap()
+            .compliance_check
+            .compliance_check_endorse_service_addr
+            .to_owned();
+        let msg = Message {
+            to: String::from("dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN"),
+            amount: String::from("1"),
+            fee: String::from("0"),
+            initiator: acc.address.to_owned(),
+            auth_require: vec![String::from("XC1111111111000000@xuper/alice"), endorser],
+            ..Default::default()
+        };
+        let chain_name = String::from("xuper");
+
+        let mut resp = xchain::PreExecWithSelectUTXOResponse::new();
+        resp.mut_utxoOutput()
+            .set_utxoList(protobuf::RepeatedField::from_vec(vec![utxo(
+                &acc.address,
+                10,
+                0,
+            )]));
+        resp.mut_utxoOutput().set_totalSelected(String::from("10"));
+
+        let sess = Session::new(&chain_name, &acc, &msg);
+        let tx = sess.gen_real_tx_without_compliance(&resp).unwrap();
+        assert_eq!(
+            tx.get_auth_require(),
+            &[String::from("XC1111111111000000@xuper/alice")]
+        );
+        assert_eq!(tx.get_tx_inputs().len(), 1);
+        assert_eq!(tx.get_tx_inputs()[0].get_ref_txid(), &[0xaa, 0xaa]);
+        // 转账1，找零9
+        assert_eq!(tx.get_tx_outputs().len(), 2);
+        assert_eq!(tx.get_tx_outputs()[1].get_amount(), &[9]);
+    }
+
+    #[test]
+    fn test_frozen_height() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = super::super::wallet::Account::new(d.to_str().unwrap(), "", "");
+        let msg = Message {
+            to: String::from("dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN"),
+            amount: String::from("1"),
+            fee: String::from("2"),
+            frozen_height: 100,
+            initiator: acc.address.to_owned(),
+            ..Default::default()
+        };
+        let chain_name = String::from("xuper");
+
+        let mut resp = xchain::PreExecWithSelectUTXOResponse::new();
+        resp.mut_utxoOutput()
+            .set_utxoList(protobuf::RepeatedField::from_vec(vec![utxo(
+                &acc.address,
+                10,
+                0,
+            )]));
+        resp.mut_utxoOutput().set_totalSelected(String::from("10"));
+
+        let sess = Session::new(&chain_name, &acc, &msg);
+        let tx = sess.gen_real_tx_without_compliance(&resp).unwrap();
+        // 收款1冻结，手续费2和找零7不冻结
+        let outputs = tx.get_tx_outputs();
+        assert_eq!(outputs.len(), 3);
+        assert_eq!(outputs[0].get_amount(), &[1]);
+        assert_eq!(outputs[0].get_frozen_height(), 100);
+        assert_eq!(outputs[1].get_amount(), &[2]);
+        assert_eq!(outputs[1].get_frozen_height(), 0);
+        assert_eq!(outputs[2].get_amount(), &[7]);
+        assert_eq!(outputs[2].get_frozen_height(), 0);
+    }
+
+    #[test]
+    fn test_auto_fee() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = super::super::wallet::Account::new(d.to_str().unwrap(), "", "");
+        let msg = Message {
+            to: String::from("dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN"),
+            amount: String::from("1"),
+            initiator: acc.address.to_owned(),
+            ..Default::default()
+        };
+        let chain_name = String::from("xuper");
+
+        let mut resp = xchain::PreExecWithSelectUTXOResponse::new();
+        resp.mut_utxoOutput()
+            .set_utxoList(protobuf::RepeatedField::from_vec(vec![utxo(
+                &acc.address,
+                10,
+                0,
+            )]));
+        resp.mut_utxoOutput().set_totalSelected(String::from("10"));
+
+        let sess = Session::new(&chain_name, &acc, &msg);
+        assert_eq!(sess.fee(), "");
+        let endorser_fee = sess
+            .config
+            .compliance_check
+            .compliance_check_endorse_service_fee;
+        assert_eq!(
+            sess.auto_fee_need("3").unwrap(),
+            num_bigint::BigInt::from(4 + endorser_fee)
+        );
+
+        // gas_used为3时手续费为3
+        *sess.auto_fee.lock().unwrap() = Some(crate::contract::invoke_fee(&msg.fee, 3).unwrap());
+        assert_eq!(sess.fee(), "3");
+        let tx = sess.gen_real_tx_without_compliance(&resp).unwrap();
+        let amounts: Vec<&[u8]> = tx.get_tx_outputs().iter().map(|o| o.get_amount()).collect();
+        assert_eq!(amounts, vec![&[1][..], &[3][..], &[6][..]]);
+        // msg本身不变
+        assert_eq!(msg.fee, "");
+    }
+
+    #[test]
+    fn test_skip_compliance_check() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = super::super::wallet::Account::new(d.to_str().unwrap(), "", "");
+        let mut c = (*config::ClientConfig::global()).clone();
+        c.compliance_check.skip_compliance_check = true;
+        let c = config::ClientConfig::new(c);
+        let (_, msg) = crate::transfer::build_transfer_to_many_request_with_config(
+            &c,
+            &acc,
+            &String::from("xuper"),
+            &[(
+                String::from("dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN"),
+                String::from("1"),
+            )],
+            &String::from("2"),
+            &String::new(),
+        )
+        .unwrap();
+        assert_eq!(msg.auth_require.is_empty(), true);
+        // 不付背书服务费
+        assert_eq!(
+            msg.total_need(c.compliance_check.endorse_service_fee())
+                .unwrap(),
+            num_bigint::BigInt::from(3)
+        );
+
+        let chain_name = String::from("xuper");
+        let mut resp = xchain::PreExecWithSelectUTXOResponse::new();
+        resp.mut_utxoOutput()
+            .set_utxoList(protobuf::RepeatedField::from_vec(vec![utxo(
+                &acc.address,
+                10,
+                0,
+            )]));
+        resp.mut_utxoOutput().set_totalSelected(String::from("10"));
+        let sess = Session::with_config(&chain_name, &acc, &msg, c);
+        // 不访问背书服务，直接花费选出的utxo
+        let tx = sess.gen_complete_tx(&mut resp, None).unwrap();
+        assert_eq!(tx.get_auth_require().is_empty(), true);
+        assert_eq!(tx.get_auth_require_signs().is_empty(), true);
+        assert_eq!(tx.get_tx_inputs()[0].get_ref_txid(), &[0xaa, 0xaa]);
+        // 转账1，手续费2，找零7
+        let amounts: Vec<&[u8]> = tx.get_tx_outputs().iter().map(|o| o.get_amount()).collect();
+        assert_eq!(amounts, vec![&[1][..], &[2][..], &[7][..]]);
+        assert_eq!(tx.txid, encoder::make_transaction_id(&tx).unwrap());
+    }
+
+    #[test]
+    fn test_omit_zero_fee_tx() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = super::super::wallet::Account::new(d.to_str().unwrap(), "", "");
+        let mut c = (*config::ClientConfig::global()).clone();
+        c.compliance_check.compliance_check_endorse_service_fee = 0;
+        c.compliance_check.omit_zero_fee_tx = true;
+        let endorser = c
+            .compliance_check
+            .compliance_check_endorse_service_addr
+            .to_owned();
+        let msg = Message {
+            to: String::from("dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN"),
+            amount: String::from("1"),
+            fee: String::from("2"),
+            initiator: acc.address.to_owned(),
+            auth_require: vec![endorser.to_owned()],
+            ..Default::default()
+        };
+        let chain_name = String::from("xuper");
+
+        let mut resp = xchain::PreExecWithSelectUTXOResponse::new();
+        resp.mut_utxoOutput()
+            .set_utxoList(protobuf::RepeatedField::from_vec(vec![utxo(
+                &acc.address,
+                10,
+                0,
+            )]));
+        resp.mut_utxoOutput().set_totalSelected(String::from("10"));
+
+        let sess = Session::with_config(&chain_name, &acc, &msg, config::ClientConfig::new(c));
+        let cctx = sess.gen_fee_tx(&mut resp).unwrap();
+        assert_eq!(cctx.is_none(), true);
+        // 直接花费预执行选出的utxo，仍然需要背书服务签名
+        let tx = sess.gen_real_tx_with(&resp, cctx.as_ref()).unwrap();
+        assert_eq!(tx.get_tx_inputs().len(), 1);
+        assert_eq!(
+            tx.get_tx_inputs()[0].get_ref_txid(),
+            resp.get_utxoOutput().get_utxoList()[0].get_refTxid()
+        );
+        assert_eq!(tx.get_auth_require().to_vec(), vec![endorser]);
+        let req = sess.compliance_check_request(&tx, cctx.as_ref()).unwrap();
+        assert_eq!(req.has_Fee(), false);
+
+        // 手续费不为0时仍然生成合规检查交易
+        let mut c = (*config::ClientConfig::global()).clone();
+        c.compliance_check.compliance_check_endorse_service_fee = 1;
+        c.compliance_check.omit_zero_fee_tx = true;
+        assert_eq!(c.compliance_check.needs_fee_tx(), true);
+    }
+
+    #[test]
+    fn test_chain_id_binding() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = super::super::wallet::Account::new(d.to_str().unwrap(), "", "");
+        let msg = Message {
+            to: String::from("dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN"),
+            amount: String::from("1"),
+            fee: String::from("2"),
+            desc: String::from("test duanbing"),
+            initiator: acc.address.to_owned(),
+            ..Default::default()
+        };
+        let chain_name = String::from("xuper");
+        let mut resp = xchain::PreExecWithSelectUTXOResponse::new();
+        resp.mut_utxoOutput()
+            .set_utxoList(protobuf::RepeatedField::from_vec(vec![utxo(
+                &acc.address,
+                10,
+                0,
+            )]));
+        resp.mut_utxoOutput().set_totalSelected(String::from("10"));
+
+        let mut c = (*config::ClientConfig::global()).clone();
+        c.chain_id = Some(String::from("xuper-test"));
+        c.desc_on_chain = true;
+        let test_net = Session::with_config(&chain_name, &acc, &msg, config::ClientConfig::new(c));
+        let tx = test_net.gen_real_tx_without_compliance(&resp).unwrap();
+        assert_eq!(crate::chain_id::verify(&tx, "xuper-test").is_ok(), true);
+        assert_eq!(
+            crate::desc::tx_desc(&tx).unwrap(),
+            b"test duanbing".to_vec()
+        );
+        // 签名覆盖了绑定
+        assert_eq!(tx.get_initiator_signs()[0].get_Sign().is_empty(), false);
+
+        // 生产网拒绝广播测试网上签名的交易
+        let mut c = (*config::ClientConfig::global()).clone();
+        c.chain_id = Some(String::from("xuper-prod"));
+        let prod_net = Session::with_config(&chain_name, &acc, &msg, config::ClientConfig::new(c));
+        let offline = OfflineTx {
+            compliance_tx: tx.clone(),
+            tx: tx,
+        };
+        assert_eq!(
+            prod_net.broadcast_tx(&offline).unwrap_err().kind(),
+            ErrorKind::ChainIdMismatch
+        );
+    }
+
+    #[test]
+    fn test_verify_endorser_sign() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let endorser = super::super::wallet::Account::new(d.to_str().unwrap(), "", "");
+        let mut tx = xchain::Transaction::new();
+        tx.set_desc(b"test duanbing".to_vec());
+        tx.set_nonce(String::from("n"));
+        let digest = encoder::make_tx_digest_hash(&tx).unwrap();
+        let mut sign = xchain::SignatureInfo::new();
+        sign.set_PublicKey(endorser.public_key().unwrap());
+        sign.set_Sign(endorser.sign(&digest).unwrap());
+
+        let public_key = endorser.public_key().unwrap();
+        assert_eq!(verify_endorser_sign(&tx, &sign, &public_key).is_ok(), true);
+        // 交易被修改
+        let mut other = tx.clone();
+        other.set_nonce(String::from("m"));
+        assert_eq!(
+            verify_endorser_sign(&other, &sign, &public_key)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::EndorserSignInvalid
+        );
+        // 别的公钥
+        let dir = std::env::temp_dir().join(format!("xchain-endorser-{}", tx.nonce));
+        let impostor =
+            super::super::wallet::Account::generate(dir.to_str().unwrap(), "", "").unwrap();
+        let mut forged = sign.clone();
+        forged.set_PublicKey(impostor.public_key().unwrap());
+        forged.set_Sign(impostor.sign(&digest).unwrap());
+        assert_eq!(
+            verify_endorser_sign(&tx, &forged, &public_key)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::EndorserSignInvalid
+        );
+
+        // 配置了公钥时合规检查的响应要校验签名
+        let mut c = (*config::ClientConfig::global()).clone();
+        c.compliance_check.compliance_check_endorse_service_addr = endorser.address.to_owned();
+        c.compliance_check
+            .compliance_check_endorse_service_public_key = Some(public_key);
+        let msg = Message::default();
+        let chain_name = String::from("xuper");
+        let sess = Session::with_config(&chain_name, &endorser, &msg, config::ClientConfig::new(c));
+        let response = |sign: &xchain::SignatureInfo| {
+            let mut resp = xendorser::EndorserResponse::new();
+            resp.set_EndorserSign(sign.clone());
+            resp
+        };
+        let res =
+            sess.compliance_check_response(response(&sign), String::new(), &tx, &endorser.address);
+        assert_eq!(res.unwrap(), sign);
+        let res = sess.compliance_check_response(
+            response(&forged),
+            String::new(),
+            &tx,
+            &endorser.address,
+        );
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::EndorserSignInvalid);
+        let res = sess.compliance_check_response(
+            xendorser::EndorserResponse::new(),
+            String::new(),
+            &tx,
+            &endorser.address,
+        );
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::EndorserSignMissing);
+        // 背书服务拒绝
+        let mut rejected = xendorser::EndorserResponse::new();
+        rejected.set_ResponseData(br#"{"code": "BLACKLIST_HIT"}"#.to_vec());
+        let err = sess
+            .compliance_check_response(rejected, String::new(), &tx, &endorser.address)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ComplianceRejected);
+        assert_eq!(
+            crate::rejection::of(&err).unwrap().reason,
+            crate::rejection::Reason::Blacklisted
+        );
+    }
+
+    #[test]
+    fn test_error_details() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = super::super::wallet::Account::new(d.to_str().unwrap(), "", "");
+        let msg = Message {
+            initiator: acc.address.to_owned(),
+            ..Default::default()
+        };
+        let chain_name = String::from("xuper");
+        let sess = Session::new(&chain_name, &acc, &msg);
+
+        let mut ok = xchain::ContractResponse::new();
+        ok.set_status(200);
+        let mut failed = xchain::ContractResponse::new();
+        failed.set_status(404);
+        failed.set_message(String::from("method not found"));
+        failed.set_body(b"transfer".to_vec());
+        assert_eq!(sess.check_resp_code(&[ok.clone()]).is_ok(), true);
+        let e = sess.check_resp_code(&[ok, failed]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::ContractCodeGT400);
+        let detail = e.contract().unwrap();
+        assert_eq!(detail.status, 404);
+        assert_eq!(detail.message, "method not found");
+        assert_eq!(detail.body, b"transfer".to_vec());
+        assert_eq!(
+            e.to_string(),
+            "contract returned status 404: method not found"
+        );
+
+        // 背书服务返回的错误带着logid和ResponseData
+        let mut resp = xendorser::EndorserResponse::new();
+        resp.mut_header()
+            .set_error(xchain::XChainErrorEnum::NOT_ENOUGH_UTXO_ERROR);
+        resp.mut_header().set_logid(String::from("log1"));
+        resp.set_ResponseData(b"not enough utxo".to_vec());
+        let e = sess
+            .pre_exec_response(&xchain::PreExecWithSelectUTXORequest::new(), &resp)
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::ChainRPCError);
+        let detail = e.rpc().unwrap();
+        assert_eq!(detail.error, xchain::XChainErrorEnum::NOT_ENOUGH_UTXO_ERROR);
+        assert_eq!(detail.logid, "log1");
+        assert_eq!(detail.body, b"not enough utxo".to_vec());
+        assert_eq!(e.contract(), None);
+    }
+
+    #[test]
+    fn test_fee_tx_posted() {
+        struct Recorder(std::sync::Mutex<Vec<String>>);
+        impl TxHook for Recorder {
+            fn posted(&self, tx: &xchain::Transaction) {
+                self.0.lock().unwrap().push(format!("tx {}", tx.txid[0]));
+            }
+            fn fee_tx_posted(&self, fee_tx: &xchain::Transaction) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("fee {}", fee_tx.txid[0]));
+            }
+        }
+        let acc = super::super::wallet::Account::default();
+        let msg = Message::default();
+        let chain_name = String::from("xuper");
+        let mut sess = Session::new(&chain_name, &acc, &msg);
+        let recorder = std::sync::Arc::new(Recorder(Default::default()));
+        sess.add_hook(recorder.clone());
+
+        let mut fee_tx = xchain::Transaction::new();
+        fee_tx.set_txid(vec![1]);
+        let mut tx = xchain::Transaction::new();
+        tx.set_txid(vec![2]);
+        tx.mut_tx_inputs().push(xchain::TxInput::new());
+        // 没有花费合规检查交易时不通知
+        sess.post_post_tx(&tx, Some(&fee_tx));
+        tx.mut_tx_inputs()[0].set_ref_txid(vec![1]);
+        sess.post_post_tx(&tx, Some(&fee_tx));
+        assert_eq!(*recorder.0.lock().unwrap(), vec!["tx 2", "fee 1", "tx 2"]);
+    }
+
+    #[test]
+    fn test_check_chain() {
+        let acc = super::super::wallet::Account::default();
+        let msg = Message::default();
+        let chain_name = String::from("xuper");
+        let mut sess = Session::new(&chain_name, &acc, &msg);
+        assert_eq!(sess.check_chain("xuper").is_ok(), true);
+        assert_eq!(sess.check_chain("hello").is_ok(), false);
+
+        sess.set_chains(std::sync::Arc::new(crate::chains::Chains::with_loader(
+            Box::new(|| Ok(vec![])),
+        )));
+        // 节点不服务xuper
+        assert_eq!(
+            sess.check_chain("xuper").unwrap_err().kind(),
+            ErrorKind::InvalidArguments
+        );
+        let mut req = xchain::PreExecWithSelectUTXORequest::new();
+        req.set_bcname(chain_name.to_owned());
+        assert_eq!(
+            sess.pre_exec_with_select_utxo(req).unwrap_err().kind(),
+            ErrorKind::InvalidArguments
+        );
+    }
+
+    #[test]
+    fn test_select_utxo_manually() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = super::super::wallet::Account::new(d.to_str().unwrap(), "", "");
+        let msg = Message {
+            to: String::from("dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN"),
+            amount: String::from("1"),
+            fee: String::from("2"),
+            initiator: acc.address.to_owned(),
+            ..Default::default()
+        };
+        let chain_name = String::from("xuper");
+        let mut c = (*config::ClientConfig::global()).clone();
+        c.compliance_check.compliance_check_endorse_service_fee = 3;
+        let sess = Session::with_config(&chain_name, &acc, &msg, config::ClientConfig::new(c));
+        let output = |utxos: Vec<xchain::Utxo>| {
+            let mut o = xchain::UtxoOutput::new();
+            o.set_utxoList(protobuf::RepeatedField::from_vec(utxos));
+            o
+        };
+
+        let mut resp = sess
+            .select_utxo_manually(output(vec![
+                utxo(&acc.address, 4, 0),
+                utxo(&acc.address, 4, 1),
+            ]))
+            .unwrap();
+        assert_eq!(resp.get_utxoOutput().totalSelected, "8");
+        assert_eq!(resp.bcname, chain_name);
+        // 合规检查交易花费选出的utxo，找零8-3
+        let cctx = sess.build_compliance_check_tx(&mut resp).unwrap();
+        assert_eq!(cctx.get_tx_inputs().len(), 2);
+        let (inputs, change) = sess
+            .generate_tx_input(resp.get_utxoOutput(), &num_bigint::BigInt::from(3))
+            .unwrap();
+        assert_eq!(inputs, cctx.get_tx_inputs().to_vec());
+        assert_eq!(change.amount, vec![5]);
+
+        // 不够1+2+3
+        let err = sess
+            .select_utxo_manually(output(vec![utxo(&acc.address, 5, 0)]))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidArguments);
+        // 别人的utxo、重复的utxo、totalSelected不对
+        assert_eq!(
+            sess.select_utxo_manually(output(vec![utxo("bob", 9, 0)]))
+                .is_ok(),
+            false
+        );
+        assert_eq!(
+            sess.select_utxo_manually(output(vec![
+                utxo(&acc.address, 4, 0),
+                utxo(&acc.address, 4, 0)
+            ]))
+            .is_ok(),
+            false
+        );
+        let mut o = output(vec![utxo(&acc.address, 9, 0)]);
+        o.set_totalSelected(String::from("90"));
+        assert_eq!(sess.select_utxo_manually(o).is_ok(), false);
+        assert_eq!(sess.select_utxo_manually(output(vec![])).is_ok(), false);
+    }
+
+    #[test]
+    fn test_offline_sign() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = super::super::wallet::Account::new(d.to_str().unwrap(), "", "");
+        let endorser_fee = config::CONFIG
+            .read()
+            .unwrap()
+            .compliance_check
+            .compliance_check_endorse_service_fee as i64;
+        let msg = Message {
+            to: String::from("dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN"),
+            amount: String::from("1"),
+            fee: String::from("0"),
+            initiator: acc.address.to_owned(),
+            ..Default::default()
+        };
+        let chain_name = String::from("xuper");
+
+        let total = num_bigint::BigInt::from(endorser_fee + 10);
+        let mut u = utxo(&acc.address, 0, 0);
+        u.set_amount(total.to_bytes_be().1);
+        let mut resp = xchain::PreExecWithSelectUTXOResponse::new();
+        resp.mut_utxoOutput()
+            .set_utxoList(protobuf::RepeatedField::from_vec(vec![u]));
+        resp.mut_utxoOutput().set_totalSelected(total.to_string());
+
+        let sess = Session::new(&chain_name, &acc, &msg);
+        let unsigned = sess.build_unsigned_tx(&mut resp).unwrap();
+        assert_eq!(unsigned.compliance_tx.get_initiator_signs().len(), 0);
+        assert_eq!(unsigned.tx.get_initiator_signs().len(), 0);
+        assert_eq!(
+            unsigned.tx.get_tx_inputs()[0].ref_txid,
+            unsigned.compliance_tx.txid
+        );
+
+        // 在机器之间传递
+        let unsigned: OfflineTx =
+            serde_json::from_str(&serde_json::to_string(&unsigned).unwrap()).unwrap();
+        let c = config::ClientConfig::global();
+        let signed = sign_tx(&c, &acc, &[], &unsigned).unwrap();
+        assert_ne!(signed.compliance_tx.txid, unsigned.compliance_tx.txid);
+        assert_eq!(
+            signed.tx.get_tx_inputs()[0].ref_txid,
+            signed.compliance_tx.txid
+        );
+        assert_eq!(
+            signed.tx.txid,
+            encoder::make_transaction_id(&signed.tx).unwrap()
+        );
+        let digest = encoder::make_tx_digest_hash(&signed.tx).unwrap();
+        assert_eq!(
+            acc.verify(&digest, &signed.tx.get_initiator_signs()[0].Sign)
+                .is_ok(),
+            true
+        );
+
+        // 只能用发起者签名
+        let other = super::super::wallet::Account::default();
+        assert_eq!(sign_tx(&c, &other, &[], &unsigned).is_ok(), false);
+    }
+
+    #[test]
+    fn test_offline_sign_skip_compliance_check() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = super::super::wallet::Account::new(d.to_str().unwrap(), "", "");
+        let mut c = (*config::ClientConfig::global()).clone();
+        c.compliance_check.skip_compliance_check = true;
+        let c = config::ClientConfig::new(c);
+        let msg = Message {
+            to: String::from("dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN"),
+            amount: String::from("1"),
+            fee: String::from("0"),
+            initiator: acc.address.to_owned(),
+            auth_require: c.compliance_check.endorser_addrs(),
+            ..Default::default()
+        };
+        let chain_name = String::from("xuper");
+        let mut resp = xchain::PreExecWithSelectUTXOResponse::new();
+        resp.mut_utxoOutput()
+            .set_utxoList(protobuf::RepeatedField::from_vec(vec![utxo(
+                &acc.address,
+                10,
+                0,
+            )]));
+        resp.mut_utxoOutput().set_totalSelected(String::from("10"));
+
+        let sess = Session::with_config(&chain_name, &acc, &msg, c.clone());
+        let unsigned = sess.build_unsigned_tx(&mut resp).unwrap();
+        // 没有合规检查交易，直接花费选出的utxo
+        assert_eq!(unsigned.has_compliance_tx(), false);
+        assert_eq!(unsigned.tx.get_auth_require().is_empty(), true);
+        assert_eq!(unsigned.tx.get_tx_inputs()[0].get_ref_txid(), &[0xaa, 0xaa]);
+        let signed = sign_tx(&c, &acc, &[], &unsigned).unwrap();
+        assert_eq!(signed.has_compliance_tx(), false);
+        assert_eq!(
+            signed.tx.txid,
+            encoder::make_transaction_id(&signed.tx).unwrap()
+        );
+
+        // 需要合规检查的配置不接受没有合规检查交易的离线交易
+        let strict = Session::new(&chain_name, &acc, &msg);
+        assert_eq!(
+            strict.broadcast_tx(&signed).unwrap_err().kind(),
+            ErrorKind::InvalidArguments
+        );
+    }
+
+    #[test]
+    fn test_check_compliance_tx() {
+        let output = |to: &str, amount: u8| {
+            let mut o = xchain::TxOutput::new();
+            o.set_to_addr(to.as_bytes().to_vec());
+            o.set_amount(vec![amount]);
+            o
+        };
+        let mut input = xchain::TxInput::new();
+        input.set_from_addr(b"alice".to_vec());
+        input.set_amount(vec![100]);
+        let mut tx = xchain::Transaction::new();
+        tx.set_initiator(String::from("alice"));
+        tx.mut_tx_inputs().push(input);
+        tx.mut_tx_outputs().push(output("fee_addr", 40));
+        tx.mut_tx_outputs().push(output("alice", 60));
+
+        let fee = num_bigint::BigInt::from(40);
+        let check = check_compliance_tx(&tx, "fee_addr", &fee);
+        assert_eq!(check.violations, Vec::<String>::new());
+        assert_eq!(check.change, "60");
+
+        // 找零少了，多出来的被当作矿工手续费
+        tx.mut_tx_outputs()[1].set_amount(vec![50]);
+        let check = check_compliance_tx(&tx, "fee_addr", &fee);
+        assert_eq!(
+            check.violations,
+            vec![String::from("inputs and outputs do not balance")]
+        );
+
+        // 手续费付给了其他地址
+        tx.mut_tx_outputs()[1].set_amount(vec![60]);
+        tx.mut_tx_outputs()[0].set_to_addr(b"mallory".to_vec());
+        let check = check_compliance_tx(&tx, "fee_addr", &fee);
+        assert_eq!(check.paid_fee, "0");
+        assert_eq!(check.violations.len(), 3);
+    }
+
+    #[cfg(feature = "with-serde")]
+    #[test]
+    fn test_message_serde() {
+        let msg = Message {
+            to: String::from("bob"),
+            amount: String::from("10"),
+            initiator: String::from("alice"),
+            tx_class: config::TxClass::ContractInvoke,
+            auth_require_signers: vec![Default::default()],
+            ..Default::default()
+        };
+        let s = serde_json::to_string(&msg).unwrap();
+        // 签名者不序列化
+        assert_eq!(s.contains("auth_require_signers"), false);
+        let m: Message = serde_json::from_str(&s).unwrap();
+        assert_eq!(m.to, "bob");
+        assert_eq!(m.tx_class, config::TxClass::ContractInvoke);
+        assert_eq!(m.auth_require_signers.len(), 0);
+    }
+
+    #[test]
+    fn test_audit_fee() {
+        let endorser_fee = config::CONFIG
+            .read()
+            .unwrap()
+            .compliance_check
+            .compliance_check_endorse_service_fee as i64;
+        let am = |i| num_bigint::BigInt::from_i64(i).unwrap();
+        let msg = Message {
+            to: String::from("bob"),
+            amount: (endorser_fee + 100).to_string(),
+            fee: String::from("10"),
+            ..Default::default()
+        };
+        let audit = audit_fee(&msg).unwrap();
+        assert_eq!(audit.total_need, am(2 * endorser_fee + 110));
+        assert_eq!(audit.endorser_fee_exceeds_amount, false);
+        let names: Vec<&str> = audit.outputs.iter().map(|o| o.name).collect();
+        assert_eq!(names, vec!["endorser_fee", "amount", "fee"]);
+        assert_eq!(audit.outputs[2].to, crate::consts::FEE_ADDR);
+
+        // 调用合约没有转账金额，背书手续费大于金额
+        let msg = Message {
+            fee: String::from("0"),
+            ..Default::default()
+        };
+        let audit = audit_fee(&msg).unwrap();
+        assert_eq!(audit.total_need, am(endorser_fee));
+        assert_eq!(audit.outputs.len(), 1);
+        assert_eq!(audit.endorser_fee_exceeds_amount, endorser_fee > 0);
+
+        // 多个收款，金额为0的不生成输出
+        let msg = Message {
+            to: String::from("bob"),
+            amount: String::from("5"),
+            outputs: vec![
+                (String::from("carol"), String::from("7")),
+                (String::from("dave"), String::from("0")),
+            ],
+            fee: String::from("1"),
+            ..Default::default()
+        };
+        assert_eq!(msg.payments().len(), 3);
+        assert_eq!(msg.total_amount().unwrap(), am(12));
+        let audit = audit_fee(&msg).unwrap();
+        assert_eq!(audit.total_need, am(endorser_fee + 13));
+        let to: Vec<&str> = audit.outputs.iter().map(|o| &o.to[..]).collect();
+        assert_eq!(to[1..3], ["bob", "carol"]);
+        assert_eq!(audit.outputs.len(), 4);
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let retry = RetryPolicy {
+            max_attempts: 3,
+            backoff: std::time::Duration::from_millis(1),
+            max_backoff: std::time::Duration::from_millis(3),
+            jitter: 0.0,
+        };
+        assert_eq!(retry.delay(1), std::time::Duration::from_millis(1));
+        assert_eq!(retry.delay(2), std::time::Duration::from_millis(2));
+        assert_eq!(retry.delay(3), std::time::Duration::from_millis(3));
+        assert_eq!(retry.delay(40), std::time::Duration::from_millis(3));
+
+        let mut calls = 0;
+        let res: Result<()> = retry.call(|| {
+            calls += 1;
+            Err(Error::from(ErrorKind::TransportError))
+        });
+        assert_eq!(res.is_ok(), false);
+        assert_eq!(calls, 3);
+
+        // 业务错误不重试
+        let mut calls = 0;
+        let res: Result<()> = retry.call(|| {
+            calls += 1;
+            Err(Error::from(ErrorKind::EndorserSignMissing))
+        });
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::EndorserSignMissing);
+        assert_eq!(calls, 1);
+
+        let mut calls = 0;
+        let res = retry.call(|| {
+            calls += 1;
+            if calls < 2 {
+                return Err(Error::from(ErrorKind::TransportError));
+            }
+            Ok(calls)
+        });
+        assert_eq!(res.unwrap(), 2);
+
+        let jittered = RetryPolicy {
+            jitter: 0.5,
+            ..retry
+        };
+        let d = jittered.delay(3);
+        assert_eq!(
+            d >= std::time::Duration::from_micros(1500) && d <= std::time::Duration::from_millis(3),
+            true
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_retry_policy_async() {
+        let retry = RetryPolicy {
+            max_attempts: 3,
+            backoff: std::time::Duration::from_millis(1),
+            max_backoff: std::time::Duration::from_millis(3),
+            jitter: 0.0,
+        };
+        // 等待不依赖运行时的定时器，basic_scheduler没有打开time也能重试
+        let mut rt = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .build()
+            .unwrap();
+        let mut calls = 0;
+        let res = rt.block_on(retry.call_async(|| {
+            calls += 1;
+            let res = if calls < 3 {
+                Err(Error::from(ErrorKind::TransportError))
+            } else {
+                Ok(calls)
+            };
+            async move { res }
+        }));
+        assert_eq!(res.unwrap(), 3);
+    }
+
+    #[test]
+    fn test_should_failover() {
+        assert_eq!(
+            should_failover(&Error::from(ErrorKind::TransportError)),
+            true
+        );
+        assert_eq!(should_failover(&Error::from(ErrorKind::CircuitOpen)), true);
+        assert_eq!(
+            should_failover(&Error::from(ErrorKind::EndorserSignMissing)),
+            false
+        );
+
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = super::super::wallet::Account::new(d.to_str().unwrap(), "", "");
+        let mut c = (*config::ClientConfig::global()).clone();
+        c.compliance_check.failover_endpoints = vec![config::EndpointConfig {
+            host: String::from("127.0.0.1"),
+            port: 1,
+        }];
+        let msg = Message::default();
+        let chain = String::from("xuper");
+        let sess = Session::with_config(&chain, &acc, &msg, config::ClientConfig::new(c));
+        // 没有连接的接入点不参与切换
+        assert_eq!(sess.failover_endpoints().is_empty(), true);
+        assert_eq!(sess.endorsements().is_empty(), true);
+    }
+
+    #[test]
+    fn test_attestation_required() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = super::super::wallet::Account::new(d.to_str().unwrap(), "", "");
+        let mut c = (*config::ClientConfig::global()).clone();
+        c.compliance_check.attestation = Some(config::AttestationConfig {
+            mr_enclaves: vec![hex::encode([1u8; 32])],
+            mr_signers: vec![],
+        });
+        let msg = Message::default();
+        let chain = String::from("xuper");
+        let sess = Session::with_config(&chain, &acc, &msg, config::ClientConfig::new(c));
+        // 没有认证过的通道时不发送请求
+        let err = sess
+            .endorser_call(xendorser::EndorserRequest::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AttestationFailed);
+    }
 }
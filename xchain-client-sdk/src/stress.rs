@@ -0,0 +1,195 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once};
+use std::time::Duration;
+
+use num_bigint::BigInt;
+
+use crate::{bulk, config, mock, transfer, utxo_cache, wallet};
+
+// 并发压力测试，需要开启stress feature，单独运行: cargo test --features stress stress::
+// 所有请求发给同一个mock::MockNode(设置为ocall的NodeClient)，每个测试使用新生成的账户。
+// 检查并发转账全部成功、提交的交易nonce不重复、没有utxo被花两次(MockNode拒绝重复花费)，
+// 以及内存不随转账次数增长(账本本身除外)。测试之间用SERIAL串行，避免互相影响内存统计。
+
+/// 统计当前分配的字节数
+struct CountingAlloc;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let p = System.alloc(layout);
+        if !p.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst);
+        }
+        p
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const CHAIN: &str = "xuper";
+const WORKERS: usize = 16;
+const TRANSFERS: usize = 200;
+/// 每笔转账允许增长的内存，账本里每笔转账保存两笔交易和几个utxo
+const BYTES_PER_TRANSFER: usize = 64 * 1024;
+
+lazy_static! {
+    static ref NODE: mock::MockNode =
+        mock::MockNode::new(CHAIN, crate::testkit::test_account("", ""));
+    static ref SERIAL: Mutex<()> = Mutex::new(());
+}
+
+static INSTALL: Once = Once::new();
+
+fn node() -> &'static mock::MockNode {
+    INSTALL.call_once(|| mock::install(&NODE).expect("ocall is already initialized"));
+    &NODE
+}
+
+fn new_account() -> wallet::Account {
+    let dir = std::env::temp_dir().join(format!("xchain-stress-{}", wallet::get_nonce().unwrap()));
+    wallet::Account::generate(dir.to_str().unwrap(), "", "").unwrap()
+}
+
+fn endorser_fee() -> BigInt {
+    BigInt::from(
+        config::ClientConfig::global()
+            .compliance_check
+            .compliance_check_endorse_service_fee,
+    )
+}
+
+/// WORKERS个线程一共执行n次f，返回每次的结果
+fn run_concurrently<F>(n: usize, f: F) -> Vec<xchain_node_sdk::errors::Result<String>>
+where
+    F: Fn(usize) -> xchain_node_sdk::errors::Result<String> + Send + Sync + 'static,
+{
+    bulk::run_parallel((0..n).map(|i| vec![i]).collect(), n, WORKERS, f)
+}
+
+fn assert_unique_nonces(address: &str, expected_txs: usize) {
+    let txs: Vec<_> = node()
+        .txs()
+        .into_iter()
+        .filter(|tx| tx.initiator == address)
+        .collect();
+    assert_eq!(txs.len(), expected_txs);
+    let nonces: HashSet<String> = txs.iter().map(|tx| tx.nonce.to_owned()).collect();
+    assert_eq!(nonces.len(), txs.len());
+}
+
+#[test]
+fn test_concurrent_transfers() {
+    let _serial = SERIAL.lock().unwrap();
+    let acc = Arc::new(new_account());
+    for _ in 0..TRANSFERS {
+        node().fund(&acc.address, &BigInt::from(1000));
+    }
+
+    let a = acc.clone();
+    let results = run_concurrently(TRANSFERS, move |i| {
+        transfer::transfer(
+            &a,
+            &String::from(CHAIN),
+            &String::from("dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN"),
+            &String::from("1"),
+            &String::from("0"),
+            &format!("stress {}", i),
+        )
+    });
+    let txids: HashSet<String> = results.into_iter().map(|r| r.unwrap()).collect();
+    assert_eq!(txids.len(), TRANSFERS);
+    // 每笔转账一笔合规检查交易和一笔真正的交易
+    assert_unique_nonces(&acc.address, TRANSFERS * 2);
+    assert_eq!(
+        node().balance(&acc.address),
+        BigInt::from(1000 * TRANSFERS) - (endorser_fee() + 1) * BigInt::from(TRANSFERS)
+    );
+}
+
+#[test]
+fn test_concurrent_transfers_with_utxo_cache() {
+    let _serial = SERIAL.lock().unwrap();
+    let acc = Arc::new(new_account());
+    for _ in 0..WORKERS * 2 {
+        node().fund(&acc.address, &BigInt::from(1_000_000));
+    }
+    let cache = Arc::new(utxo_cache::UtxoCache::new(
+        &acc.address,
+        1000,
+        Duration::from_secs(60),
+    ));
+
+    let (a, c) = (acc.clone(), cache.clone());
+    let results = run_concurrently(TRANSFERS, move |i| {
+        utxo_cache::transfer_cached(
+            &c,
+            &a,
+            &String::from(CHAIN),
+            &String::from("dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN"),
+            &String::from("1"),
+            &String::from("0"),
+            &format!("stress {}", i),
+        )
+    });
+    for r in results {
+        r.unwrap();
+    }
+    assert_unique_nonces(&acc.address, TRANSFERS * 2);
+    assert_eq!(cache.stats().reserved, 0);
+
+    // 缓存和节点上的utxo一致
+    cache.refresh().unwrap();
+    let record =
+        xchain_node_sdk::ocall::ocall_xchain_query_utxo_record(&acc.address, 1000).unwrap();
+    assert_eq!(
+        cache.stats().unspent.to_string(),
+        record.get_openUtxoRecord().utxoCount
+    );
+}
+
+#[test]
+fn test_memory_bounded() {
+    let _serial = SERIAL.lock().unwrap();
+    let acc = Arc::new(new_account());
+    for _ in 0..TRANSFERS * 3 {
+        node().fund(&acc.address, &BigInt::from(1000));
+    }
+    let round = |acc: &Arc<wallet::Account>| {
+        let a = acc.clone();
+        let results = run_concurrently(TRANSFERS, move |i| {
+            transfer::transfer(
+                &a,
+                &String::from(CHAIN),
+                &String::from("dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN"),
+                &String::from("1"),
+                &String::from("0"),
+                &format!("stress {}", i),
+            )
+        });
+        for r in results {
+            r.unwrap();
+        }
+        ALLOCATED.load(Ordering::SeqCst)
+    };
+
+    // 第一轮之后缓存(交易模板等)已经填满
+    round(&acc);
+    let before = round(&acc);
+    let after = round(&acc);
+    assert!(
+        after.saturating_sub(before) < TRANSFERS * BYTES_PER_TRANSFER,
+        "memory grew {} bytes in {} transfers",
+        after.saturating_sub(before),
+        TRANSFERS
+    );
+}
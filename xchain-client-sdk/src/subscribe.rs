@@ -0,0 +1,495 @@
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{chains, client};
+use xchain_node_sdk::{errors::*, ocall, protos::xchain};
+
+// 按区块高度轮询主干上的交易，逐个交给回调处理。
+// 每处理完一个交易就持久化游标(下一个要处理的高度和交易序号)，重启之后从游标继续，
+// 不会漏掉事件；回调成功之后、游标写入之前崩溃时会重复投递，回调需要按txid幂等(at-least-once)。
+// 只处理确认数达到confirmations的区块，避免分叉回滚之后已经投递的事件失效。
+// 合约事件是调用了某个合约的交易: 回调用contract_events包装之后只收到匹配的交易。
+// replay_events用同样的回调扫描历史区块，补齐之后从to_height + 1开始订阅，不重复也不遗漏。
+// 设置了shutdown信号时poll在两个事件之间检查，置位之后返回，游标已经持久化。
+
+/// 下一个要处理的位置
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Cursor {
+    pub height: i64,
+    pub tx_index: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Event {
+    pub height: i64,
+    pub tx_index: usize,
+    /// hex编码
+    pub blockid: String,
+    pub tx: xchain::Transaction,
+}
+
+/// 读取游标，没有时返回None
+pub type CursorLoader = Box<dyn Fn() -> Result<Option<Cursor>> + Send + Sync>;
+/// 写入游标，返回之后游标必须已经持久化
+pub type CursorSaver = Box<dyn Fn(&Cursor) -> Result<()> + Send + Sync>;
+/// 主干高度
+pub type TipSource = Box<dyn Fn() -> Result<i64> + Send + Sync>;
+pub type BlockSource = Box<dyn Fn(i64) -> Result<xchain::Block> + Send + Sync>;
+
+/// 游标保存在文件里，先写临时文件再rename，写入是原子的
+pub fn file_cursor_store(path: &str) -> (CursorLoader, CursorSaver) {
+    let (p, q) = (path.to_owned(), path.to_owned());
+    let loader: CursorLoader = Box::new(move || match std::fs::read(&p) {
+        Ok(b) => Ok(Some(serde_json::from_slice(&b)?)),
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Error::from(e)),
+    });
+    let saver: CursorSaver = Box::new(move |cursor| {
+        let tmp = format!("{}.tmp", q);
+        let mut f = std::fs::File::create(&tmp)?;
+        f.write_all(&serde_json::to_vec(cursor)?)?;
+        f.sync_all()?;
+        std::fs::rename(&tmp, &q)?;
+        Ok(())
+    });
+    (loader, saver)
+}
+
+pub struct Subscriber {
+    cursor: Cursor,
+    confirmations: i64,
+    saver: CursorSaver,
+    tip: TipSource,
+    block: BlockSource,
+    shutdown: Option<client::ShutdownSignal>,
+}
+
+impl Subscriber {
+    /// 订阅bcname，有持久化的游标时从游标继续，否则从start_height开始
+    pub fn new(
+        bcname: &String,
+        start_height: i64,
+        confirmations: i64,
+        loader: CursorLoader,
+        saver: CursorSaver,
+    ) -> Result<Self> {
+        let name = bcname.to_owned();
+        Self::with_source(
+            start_height,
+            confirmations,
+            loader,
+            saver,
+            Box::new(move || {
+                let status = ocall::ocall_xchain_get_block_chain_status(&name)?;
+                Ok(chains::decode_chain_info(&status)?.height)
+            }),
+            Box::new(|height| ocall::ocall_xchain_get_block_by_height(height)),
+        )
+    }
+
+    pub fn with_source(
+        start_height: i64,
+        confirmations: i64,
+        loader: CursorLoader,
+        saver: CursorSaver,
+        tip: TipSource,
+        block: BlockSource,
+    ) -> Result<Self> {
+        if start_height < 0 || confirmations < 0 {
+            return Err(Error::from(ErrorKind::InvalidArguments));
+        }
+        let cursor = loader()?.unwrap_or(Cursor {
+            height: start_height,
+            tx_index: 0,
+        });
+        Ok(Subscriber {
+            cursor: cursor,
+            confirmations: confirmations,
+            saver: saver,
+            tip: tip,
+            block: block,
+            shutdown: None,
+        })
+    }
+
+    pub fn cursor(&self) -> &Cursor {
+        &self.cursor
+    }
+
+    /// Client::shutdown之后poll不再投递新的事件，一般传入Client::shutdown_signal()
+    pub fn set_shutdown_signal(&mut self, signal: client::ShutdownSignal) {
+        self.shutdown = Some(signal);
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        self.shutdown.as_ref().map_or(false, |s| s.is_set())
+    }
+
+    /// 处理所有已经确认的区块，返回投递的事件数
+    /// 回调失败时停止，游标停在失败的事件，下次poll重新投递
+    /// shutdown之后返回已经投递的事件数，游标停在下一个事件
+    pub fn poll<F>(&mut self, mut callback: F) -> Result<usize>
+    where
+        F: FnMut(&Event) -> Result<()>,
+    {
+        let confirmed = (self.tip)()? - self.confirmations;
+        let mut delivered = 0;
+        while self.cursor.height <= confirmed {
+            let block = (self.block)(self.cursor.height)?;
+            let b = trunk_block(&block)?;
+            let blockid = hex::encode(&b.blockid);
+            for (i, tx) in b.get_transactions().iter().enumerate() {
+                if i < self.cursor.tx_index {
+                    continue;
+                }
+                if self.is_shutting_down() {
+                    return Ok(delivered);
+                }
+                callback(&Event {
+                    height: self.cursor.height,
+                    tx_index: i,
+                    blockid: blockid.to_owned(),
+                    tx: tx.clone(),
+                })?;
+                delivered += 1;
+                self.advance(Cursor {
+                    height: self.cursor.height,
+                    tx_index: i + 1,
+                })?;
+            }
+            self.advance(Cursor {
+                height: self.cursor.height + 1,
+                tx_index: 0,
+            })?;
+        }
+        Ok(delivered)
+    }
+
+    fn advance(&mut self, cursor: Cursor) -> Result<()> {
+        (self.saver)(&cursor)?;
+        self.cursor = cursor;
+        Ok(())
+    }
+}
+
+fn trunk_block(block: &xchain::Block) -> Result<&xchain::InternalBlock> {
+    if block.status != xchain::Block_EBlockStatus::TRUNK || block.block.is_none() {
+        return Err(Error::from(ErrorKind::ChainRPCError));
+    }
+    Ok(block.get_block())
+}
+
+/// 合约事件的过滤条件，默认匹配所有调用了合约的交易
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventFilter {
+    /// 不为空时只匹配调用了这些方法的交易
+    pub methods: Vec<String>,
+    /// 只匹配这个地址发起的交易
+    pub initiator: Option<String>,
+}
+
+impl EventFilter {
+    /// tx调用了contract并且满足过滤条件
+    pub fn matches(&self, contract: &str, tx: &xchain::Transaction) -> bool {
+        if let Some(ref initiator) = self.initiator {
+            if &tx.initiator != initiator {
+                return false;
+            }
+        }
+        tx.get_contract_requests().iter().any(|r| {
+            r.contract_name == contract
+                && (self.methods.is_empty() || self.methods.contains(&r.method_name))
+        })
+    }
+}
+
+/// 包装回调，只把调用了contract并且满足filter的交易交给callback，用于Subscriber::poll和replay_events
+pub fn contract_events<'a, F>(
+    contract: &'a str,
+    filter: &'a EventFilter,
+    mut callback: F,
+) -> impl FnMut(&Event) -> Result<()> + 'a
+where
+    F: FnMut(&Event) -> Result<()> + 'a,
+{
+    move |e: &Event| {
+        if filter.matches(contract, &e.tx) {
+            callback(e)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// 按顺序扫描[from_height, to_height]的区块，把contract的事件交给callback，返回投递的事件数
+/// 回调和Subscriber::poll的一样，回调失败时停止并返回错误
+pub fn replay_events<F>(
+    contract: &str,
+    from_height: i64,
+    to_height: i64,
+    filter: &EventFilter,
+    callback: F,
+) -> Result<usize>
+where
+    F: FnMut(&Event) -> Result<()>,
+{
+    let block: BlockSource = Box::new(|height| ocall::ocall_xchain_get_block_by_height(height));
+    replay_events_with_source(&block, contract, from_height, to_height, filter, callback)
+}
+
+/// 同replay_events，区块从block读取
+pub fn replay_events_with_source<F>(
+    block: &BlockSource,
+    contract: &str,
+    from_height: i64,
+    to_height: i64,
+    filter: &EventFilter,
+    mut callback: F,
+) -> Result<usize>
+where
+    F: FnMut(&Event) -> Result<()>,
+{
+    if from_height < 0 || from_height > to_height {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let mut delivered = 0;
+    for height in from_height..=to_height {
+        let block = block(height)?;
+        let b = trunk_block(&block)?;
+        let blockid = hex::encode(&b.blockid);
+        for (i, tx) in b.get_transactions().iter().enumerate() {
+            if !filter.matches(contract, tx) {
+                continue;
+            }
+            callback(&Event {
+                height: height,
+                tx_index: i,
+                blockid: blockid.to_owned(),
+                tx: tx.clone(),
+            })?;
+            delivered += 1;
+        }
+    }
+    Ok(delivered)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicI64, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::wallet;
+
+    fn block(height: i64, txids: &[&str]) -> xchain::Block {
+        let mut b = xchain::Block::new();
+        b.set_status(xchain::Block_EBlockStatus::TRUNK);
+        b.mut_block().set_height(height);
+        for txid in txids {
+            let mut tx = xchain::Transaction::new();
+            tx.set_txid(txid.as_bytes().to_vec());
+            b.mut_block().mut_transactions().push(tx);
+        }
+        b
+    }
+
+    fn subscriber(path: &str, tip: Arc<AtomicI64>) -> Subscriber {
+        let (loader, saver) = file_cursor_store(path);
+        Subscriber::with_source(
+            1,
+            1,
+            loader,
+            saver,
+            Box::new(move || Ok(tip.load(Ordering::SeqCst))),
+            Box::new(|height| Ok(block(height, &["a", "b"]))),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_subscriber_resume() {
+        let dir =
+            std::env::temp_dir().join(format!("xchain-cursor-{}", wallet::get_nonce().unwrap()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cursor.json");
+        let path = path.to_str().unwrap();
+        let tip = Arc::new(AtomicI64::new(2));
+
+        let mut sub = subscriber(path, tip.clone());
+        let mut seen = vec![];
+        // 高度2还没有确认
+        assert_eq!(
+            sub.poll(|e| {
+                seen.push((e.height, e.tx_index));
+                Ok(())
+            })
+            .unwrap(),
+            2
+        );
+        assert_eq!(seen, vec![(1, 0), (1, 1)]);
+
+        // 处理高度2的第二个交易时失败
+        tip.store(4, Ordering::SeqCst);
+        let res = sub.poll(|e| {
+            if e.height == 2 && e.tx_index == 1 {
+                return Err(Error::from(ErrorKind::TransportError));
+            }
+            Ok(())
+        });
+        assert_eq!(res.is_ok(), false);
+        assert_eq!(
+            sub.cursor(),
+            &Cursor {
+                height: 2,
+                tx_index: 1
+            }
+        );
+
+        // 重启之后从失败的事件继续
+        let mut sub = subscriber(path, tip.clone());
+        let mut seen = vec![];
+        sub.poll(|e| {
+            seen.push((e.height, e.tx_index));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(seen, vec![(2, 1), (3, 0), (3, 1)]);
+        assert_eq!(
+            sub.cursor(),
+            &Cursor {
+                height: 4,
+                tx_index: 0
+            }
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_subscriber_shutdown() {
+        let dir =
+            std::env::temp_dir().join(format!("xchain-cursor-{}", wallet::get_nonce().unwrap()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cursor.json");
+        let path = path.to_str().unwrap();
+        let tip = Arc::new(AtomicI64::new(4));
+
+        let mut sub = subscriber(path, tip.clone());
+        let signal = client::ShutdownSignal::new();
+        sub.set_shutdown_signal(signal.clone());
+        // 处理完高度2的第一个交易之后shutdown
+        let n = sub
+            .poll(|e| {
+                if e.height == 2 {
+                    signal.set();
+                }
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(n, 3);
+        assert_eq!(
+            sub.cursor(),
+            &Cursor {
+                height: 2,
+                tx_index: 1
+            }
+        );
+        assert_eq!(sub.poll(|_| Ok(())).unwrap(), 0);
+
+        // 重启之后从下一个事件继续
+        let mut sub = subscriber(path, tip.clone());
+        let mut seen = vec![];
+        sub.poll(|e| {
+            seen.push((e.height, e.tx_index));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(seen, vec![(2, 1), (3, 0), (3, 1)]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// 高度h的区块: 第一个交易调用counter.increase，第二个调用counter.get，第三个调用other.increase
+    fn contract_block(height: i64) -> xchain::Block {
+        let mut b = block(height, &["a", "b", "c"]);
+        let calls = [
+            ("counter", "increase"),
+            ("counter", "get"),
+            ("other", "increase"),
+        ];
+        for (tx, (contract, method)) in b
+            .mut_block()
+            .mut_transactions()
+            .iter_mut()
+            .zip(calls.iter())
+        {
+            let mut r = xchain::InvokeRequest::new();
+            r.set_contract_name(contract.to_string());
+            r.set_method_name(method.to_string());
+            tx.mut_contract_requests().push(r);
+            tx.set_initiator(String::from("alice"));
+        }
+        b
+    }
+
+    #[test]
+    fn test_replay_events() {
+        let source: BlockSource = Box::new(|height| Ok(contract_block(height)));
+        let filter = EventFilter::default();
+        let mut seen = vec![];
+        let n = replay_events_with_source(&source, "counter", 1, 2, &filter, |e| {
+            seen.push((e.height, e.tx_index));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(n, 4);
+        assert_eq!(seen, vec![(1, 0), (1, 1), (2, 0), (2, 1)]);
+
+        let filter = EventFilter {
+            methods: vec![String::from("increase")],
+            ..Default::default()
+        };
+        let mut seen = vec![];
+        replay_events_with_source(&source, "counter", 1, 2, &filter, |e| {
+            seen.push((e.height, e.tx_index));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(seen, vec![(1, 0), (2, 0)]);
+        let filter = EventFilter {
+            initiator: Some(String::from("bob")),
+            ..Default::default()
+        };
+        assert_eq!(
+            replay_events_with_source(&source, "counter", 1, 2, &filter, |_| Ok(())).unwrap(),
+            0
+        );
+        assert_eq!(
+            replay_events_with_source(&source, "counter", 2, 1, &filter, |_| Ok(())).is_err(),
+            true
+        );
+
+        // 补齐到高度2之后从高度3开始订阅，用同一个回调
+        let filter = EventFilter::default();
+        let mut seen = vec![];
+        {
+            let mut callback = |e: &Event| -> Result<()> {
+                seen.push((e.height, e.tx_index));
+                Ok(())
+            };
+            replay_events_with_source(&source, "counter", 1, 2, &filter, &mut callback).unwrap();
+            let mut sub = Subscriber::with_source(
+                3,
+                0,
+                Box::new(|| Ok(None)),
+                Box::new(|_| Ok(())),
+                Box::new(|| Ok(3)),
+                Box::new(|height| Ok(contract_block(height))),
+            )
+            .unwrap();
+            sub.poll(contract_events("counter", &filter, &mut callback))
+                .unwrap();
+        }
+        assert_eq!(seen, vec![(1, 0), (1, 1), (2, 0), (2, 1), (3, 0), (3, 1)]);
+    }
+}
@@ -0,0 +1,245 @@
+use std::io::Write;
+use std::ops::Range;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{consts, desc, session};
+use xchain_node_sdk::{errors::*, protos::xchain};
+
+// 给发出的交易打标签(例如"payout", "campaign:x")，按标签和时间范围查询本地索引。
+// 标签记录在TagIndex里，可以同时嵌入交易的desc(格式见desc::TAGS_MAGIC)，方便从链上恢复。
+// 索引按行追加json到文件，没有文件时只在内存里。
+// TagHook在提交交易之前记录，提交失败的交易也在索引里，需要时用交易状态过滤。
+
+/// 标签不能为空，不能包含逗号和换行
+pub fn is_valid_tag(tag: &str) -> bool {
+    !tag.is_empty() && !tag.contains(',') && !tag.contains('\n')
+}
+
+/// 把标签嵌入desc，保留已有的traceparent，替换已有的标签
+pub fn embed(d: &[u8], tags: &[String]) -> Result<Vec<u8>> {
+    if tags.is_empty() || !tags.iter().all(|t| is_valid_tag(t)) {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let (traceparent, rest) = desc::split_traceparent(d)?;
+    let (_, rest) = desc::split_tags(rest)?;
+    let mut res = vec![];
+    if let Some(t) = traceparent {
+        res.extend_from_slice(desc::TRACE_MAGIC);
+        res.extend_from_slice(t);
+    }
+    res.extend_from_slice(desc::TAGS_MAGIC);
+    res.extend_from_slice(tags.join(",").as_bytes());
+    res.push(b'\n');
+    res.extend_from_slice(rest);
+    Ok(res)
+}
+
+/// 取出desc里嵌入的标签
+pub fn extract(d: &[u8]) -> Result<Vec<String>> {
+    let (_, rest) = desc::split_traceparent(d)?;
+    Ok(desc::split_tags(rest)?.0.unwrap_or_default())
+}
+
+pub fn tx_tags(tx: &xchain::Transaction) -> Result<Vec<String>> {
+    extract(tx.get_desc())
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TaggedOutput {
+    pub to: String,
+    pub amount: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TxRecord {
+    /// hex编码
+    pub txid: String,
+    pub tags: Vec<String>,
+    /// 秒
+    pub timestamp: i64,
+    pub initiator: String,
+    /// 转给其他地址的输出，不包括找零和手续费
+    pub outputs: Vec<TaggedOutput>,
+}
+
+impl TxRecord {
+    pub fn from_tx(tx: &xchain::Transaction, tags: &[String]) -> Self {
+        let outputs = tx
+            .get_tx_outputs()
+            .iter()
+            .filter(|o| !consts::FeeOutput::is_fee(o) && o.to_addr != tx.initiator.as_bytes())
+            .map(|o| TaggedOutput {
+                to: String::from_utf8_lossy(&o.to_addr).into_owned(),
+                amount: num_bigint::BigInt::from_bytes_be(num_bigint::Sign::Plus, &o.amount)
+                    .to_string(),
+            })
+            .collect();
+        TxRecord {
+            txid: hex::encode(&tx.txid),
+            tags: tags.to_vec(),
+            timestamp: consts::now_as_secs(),
+            initiator: tx.initiator.to_owned(),
+            outputs: outputs,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TagIndex {
+    path: Option<String>,
+    records: Mutex<Vec<TxRecord>>,
+}
+
+impl TagIndex {
+    /// 只在内存里的索引
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// 从文件加载，之后的记录追加到文件
+    pub fn open(path: &str) -> Result<Self> {
+        let records = match std::fs::read_to_string(path) {
+            Ok(s) => s
+                .lines()
+                .filter(|l| !l.trim().is_empty())
+                .map(|l| serde_json::from_str(l).map_err(Error::from))
+                .collect::<Result<Vec<TxRecord>>>()?,
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(Error::from(e)),
+        };
+        Ok(TagIndex {
+            path: Some(path.to_owned()),
+            records: Mutex::new(records),
+        })
+    }
+
+    pub fn add(&self, record: TxRecord) -> Result<()> {
+        if record.tags.is_empty() || !record.tags.iter().all(|t| is_valid_tag(t)) {
+            return Err(Error::from(ErrorKind::InvalidArguments));
+        }
+        let mut records = self.records.lock().unwrap();
+        if let Some(ref path) = self.path {
+            let mut line = serde_json::to_vec(&record)?;
+            line.push(b'\n');
+            let mut f = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            f.write_all(&line)?;
+            f.sync_all()?;
+        }
+        records.push(record);
+        Ok(())
+    }
+
+    /// 带有tag、时间(秒)在range内(左闭右开)的交易，按记录顺序
+    pub fn query(&self, tag: &str, range: Range<i64>) -> Vec<TxRecord> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| range.contains(&r.timestamp) && r.tags.iter().any(|t| t == tag))
+            .cloned()
+            .collect()
+    }
+}
+
+/// 给交易打标签，通过Session::add_hook使用
+/// embed为true时同时把标签嵌入desc
+pub struct TagHook {
+    pub tags: Vec<String>,
+    pub index: std::sync::Arc<TagIndex>,
+    pub embed: bool,
+}
+
+impl session::TxHook for TagHook {
+    fn post_build(&self, tx: &mut xchain::Transaction) -> Result<()> {
+        if self.embed {
+            let d = embed(tx.get_desc(), &self.tags)?;
+            tx.set_desc(d);
+        }
+        Ok(())
+    }
+
+    fn pre_post(&self, tx: &xchain::Transaction) -> Result<()> {
+        self.index.add(TxRecord::from_tx(tx, &self.tags))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{session::TxHook, wallet};
+
+    fn tags(t: &[&str]) -> Vec<String> {
+        t.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_embed_tags() {
+        let d = embed(b"test duanbing", &tags(&["payout", "campaign:x"])).unwrap();
+        assert_eq!(extract(&d).unwrap(), tags(&["payout", "campaign:x"]));
+        assert_eq!(desc::decode(&d).unwrap(), b"test duanbing".to_vec());
+
+        // 替换已有的标签
+        let d = embed(&d, &tags(&["refund"])).unwrap();
+        assert_eq!(extract(&d).unwrap(), tags(&["refund"]));
+        assert_eq!(desc::decode(&d).unwrap(), b"test duanbing".to_vec());
+
+        assert_eq!(extract(b"test duanbing").unwrap(), Vec::<String>::new());
+        assert_eq!(embed(b"", &tags(&["a,b"])).is_ok(), false);
+        assert_eq!(embed(b"", &[]).is_ok(), false);
+    }
+
+    #[test]
+    fn test_tag_index() {
+        let dir =
+            std::env::temp_dir().join(format!("xchain-tags-{}", wallet::get_nonce().unwrap()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tags.jsonl");
+        let path = path.to_str().unwrap();
+
+        let index = Arc::new(TagIndex::open(path).unwrap());
+        let hook = TagHook {
+            tags: tags(&["payout", "campaign:x"]),
+            index: index.clone(),
+            embed: true,
+        };
+        let mut tx = xchain::Transaction::new();
+        tx.set_txid(vec![0xaa]);
+        tx.set_initiator(String::from("alice"));
+        let mut to = xchain::TxOutput::new();
+        to.set_to_addr(b"bob".to_vec());
+        to.set_amount(vec![0x01, 0x00]);
+        let mut change = xchain::TxOutput::new();
+        change.set_to_addr(b"alice".to_vec());
+        change.set_amount(vec![0x05]);
+        tx.set_tx_outputs(protobuf::RepeatedField::from_vec(vec![to, change]));
+        hook.post_build(&mut tx).unwrap();
+        hook.pre_post(&tx).unwrap();
+        assert_eq!(tx_tags(&tx).unwrap(), tags(&["payout", "campaign:x"]));
+
+        let now = consts::now_as_secs();
+        let res = index.query("campaign:x", now - 60..now + 60);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].txid, "aa");
+        assert_eq!(
+            res[0].outputs,
+            vec![TaggedOutput {
+                to: String::from("bob"),
+                amount: String::from("256")
+            }]
+        );
+        assert_eq!(index.query("campaign:y", now - 60..now + 60).len(), 0);
+        assert_eq!(index.query("payout", 0..now - 60).len(), 0);
+
+        // 重新打开之后还在
+        let index = TagIndex::open(path).unwrap();
+        assert_eq!(index.query("payout", now - 60..now + 60).len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
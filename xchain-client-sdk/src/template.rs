@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::{consts, desc, session};
+use xchain_node_sdk::{errors::*, protos::xchain};
+
+// 同一个账户连续转账时，交易里除了输入、收款金额、时间戳和nonce之外的部分(desc, version, auth_require,
+// 手续费输出)都不变，预先生成缓存起来，热路径上只需要生成输入输出、计算摘要和签名。
+// 缓存的key包括生成这些部分的全部参数，参数变化之后不会命中旧的模板，不需要失效。
+
+/// 缓存的模板数超过这个数时清空
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// 交易里不随每笔转账变化的部分
+#[derive(Debug, Clone, PartialEq)]
+pub struct TxTemplate {
+    /// 编码(压缩)之后的desc
+    pub desc: Vec<u8>,
+    pub version: i32,
+    pub initiator: String,
+    pub auth_require: Vec<String>,
+    pub fee: num_bigint::BigInt,
+    /// 手续费为0时没有
+    pub fee_output: Option<xchain::TxOutput>,
+}
+
+impl TxTemplate {
+    pub fn new(
+        msg: &session::Message,
+        auth_require: Vec<String>,
+        desc_compress_threshold: usize,
+    ) -> Result<Self> {
+        let fee = consts::checked_sum(&[&msg.fee])?;
+        let fee_output = if msg.fee.is_empty() || msg.fee == "0" {
+            None
+        } else {
+            Some(consts::FeeOutput::new(&msg.fee)?.to_tx_output())
+        };
+        Ok(TxTemplate {
+            desc: desc::encode(msg.desc.as_bytes(), desc_compress_threshold)?,
+            version: consts::TXVersion,
+            initiator: msg.initiator.to_owned(),
+            auth_require: auth_require,
+            fee: fee,
+            fee_output: fee_output,
+        })
+    }
+
+    /// 按模板生成没有输入输出的交易
+    pub fn to_tx(&self) -> xchain::Transaction {
+        let mut tx = xchain::Transaction::new();
+        tx.set_desc(self.desc.clone());
+        tx.set_version(self.version);
+        tx.set_coinbase(false);
+        tx.set_initiator(self.initiator.to_owned());
+        tx.set_auth_require(protobuf::RepeatedField::from_vec(self.auth_require.clone()));
+        tx
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct TemplateKey {
+    initiator: String,
+    desc: String,
+    fee: String,
+    auth_require: Vec<String>,
+    desc_compress_threshold: usize,
+}
+
+pub struct TemplateCache {
+    capacity: usize,
+    templates: Mutex<HashMap<TemplateKey, Arc<TxTemplate>>>,
+}
+
+impl TemplateCache {
+    pub fn new(capacity: usize) -> Self {
+        TemplateCache {
+            capacity: capacity,
+            templates: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 命中时返回缓存的模板，否则生成并缓存
+    pub fn get(
+        &self,
+        msg: &session::Message,
+        auth_require: &[String],
+        desc_compress_threshold: usize,
+    ) -> Result<Arc<TxTemplate>> {
+        let key = TemplateKey {
+            initiator: msg.initiator.to_owned(),
+            desc: msg.desc.to_owned(),
+            fee: msg.fee.to_owned(),
+            auth_require: auth_require.to_vec(),
+            desc_compress_threshold: desc_compress_threshold,
+        };
+        if let Some(t) = self.templates.lock().unwrap().get(&key) {
+            return Ok(t.clone());
+        }
+        let template = Arc::new(TxTemplate::new(
+            msg,
+            auth_require.to_vec(),
+            desc_compress_threshold,
+        )?);
+        let mut templates = self.templates.lock().unwrap();
+        if templates.len() >= self.capacity {
+            templates.clear();
+        }
+        templates.insert(key, template.clone());
+        Ok(template)
+    }
+
+    pub fn len(&self) -> usize {
+        self.templates.lock().unwrap().len()
+    }
+
+    pub fn clear(&self) {
+        self.templates.lock().unwrap().clear();
+    }
+}
+
+lazy_static! {
+    /// Session生成真正的交易时使用的缓存
+    pub static ref TEMPLATES: TemplateCache = TemplateCache::new(DEFAULT_CAPACITY);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(fee: &str) -> session::Message {
+        session::Message {
+            fee: String::from(fee),
+            desc: String::from("transfer"),
+            initiator: String::from("alice"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_template_cache() {
+        let cache = TemplateCache::new(2);
+        let auth = vec![String::from("alice")];
+        let t1 = cache.get(&msg("10"), &auth, 0).unwrap();
+        let t2 = cache.get(&msg("10"), &auth, 0).unwrap();
+        assert_eq!(Arc::ptr_eq(&t1, &t2), true);
+        assert_eq!(t1.fee, num_bigint::BigInt::from(10));
+        assert_eq!(t1.fee_output.is_some(), true);
+        assert_eq!(t1.to_tx().desc, b"transfer".to_vec());
+
+        let t3 = cache.get(&msg("0"), &auth, 0).unwrap();
+        assert_eq!(t3.fee_output, None);
+        assert_eq!(cache.len(), 2);
+
+        // 超过容量时清空
+        cache.get(&msg("10"), &[], 0).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&msg("abc"), &auth, 0).is_ok(), false);
+    }
+}
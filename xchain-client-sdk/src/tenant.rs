@@ -0,0 +1,536 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::{AddAssign, SubAssign};
+use std::sync::{Mutex, RwLock};
+
+use crate::{balance, chains, consts, contract, transfer, wallet};
+use xchain_node_sdk::{errors::*, ocall};
+
+// 多租户的钱包: 同一个enclave进程服务多个业务方，每个租户有独立的秘钥、策略、限额和统计。
+// 只能用租户自己的账户签名，同一个地址不能属于多个租户。
+// 链名在第一次交易时向节点查询校验，为空时使用节点的默认链。
+
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// 租户的策略和限额，None表示不限制
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TenantPolicy {
+    /// 单笔转账的最大金额
+    pub max_amount_per_tx: Option<num_bigint::BigInt>,
+    /// 每天(UTC)转账的总金额上限
+    pub max_daily_amount: Option<num_bigint::BigInt>,
+    /// 允许调用的合约
+    pub allowed_contracts: Option<HashSet<String>>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TenantMetrics {
+    /// 成功提交的交易数
+    pub txs: u64,
+    /// 失败或者被策略拒绝的交易数
+    pub rejected: u64,
+    /// 累计转账金额
+    pub amount: num_bigint::BigInt,
+}
+
+/// 对账用的账户报表的一行，见Wallet::report
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AccountReport {
+    pub address: String,
+    pub alias: Option<String>,
+    pub balance: balance::Balance,
+    /// 未花费的utxo数，包括冻结和锁定的
+    pub utxo_count: i64,
+    /// 最后一次通过本钱包成功提交交易的时间，秒，没有时为None
+    pub last_activity: Option<i64>,
+}
+
+#[derive(Default)]
+struct Tenant {
+    accounts: HashMap<String, wallet::Account>,
+    aliases: HashMap<String, String>,
+    last_activity: HashMap<String, i64>,
+    policy: TenantPolicy,
+    metrics: TenantMetrics,
+    /// (第几天, 当天已经转账的金额，包括正在提交的)
+    daily: (i64, num_bigint::BigInt),
+}
+
+/// reserve占用的额度，记在占用时的那一天
+struct Reservation {
+    account: wallet::Account,
+    day: i64,
+    amount: num_bigint::BigInt,
+}
+
+#[derive(Default)]
+pub struct Wallet {
+    tenants: RwLock<HashMap<String, Mutex<Tenant>>>,
+    chains: chains::Chains,
+}
+
+impl Wallet {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_chains(chains: chains::Chains) -> Self {
+        Wallet {
+            tenants: Default::default(),
+            chains: chains,
+        }
+    }
+
+    pub fn chains(&self) -> &chains::Chains {
+        &self.chains
+    }
+
+    pub fn add_tenant(&self, tenant_id: &str, policy: TenantPolicy) -> Result<()> {
+        let mut tenants = self.tenants.write().unwrap();
+        if tenants.contains_key(tenant_id) {
+            return Err(Error::from(ErrorKind::InvalidArguments));
+        }
+        let tenant = Tenant {
+            policy: policy,
+            ..Default::default()
+        };
+        tenants.insert(tenant_id.to_owned(), Mutex::new(tenant));
+        Ok(())
+    }
+
+    pub fn set_policy(&self, tenant_id: &str, policy: TenantPolicy) -> Result<()> {
+        self.with_tenant(tenant_id, |t| {
+            t.policy = policy;
+            Ok(())
+        })
+    }
+
+    pub fn add_account(&self, tenant_id: &str, account: wallet::Account) -> Result<()> {
+        // 写锁，防止同一个地址被并发地加到两个租户
+        let tenants = self.tenants.write().unwrap();
+        for (id, t) in tenants.iter() {
+            if id != tenant_id && t.lock().unwrap().accounts.contains_key(&account.address) {
+                return Err(Error::from(ErrorKind::InvalidArguments));
+            }
+        }
+        let mut t = tenants
+            .get(tenant_id)
+            .ok_or(Error::from(ErrorKind::InvalidArguments))?
+            .lock()
+            .unwrap();
+        t.accounts.insert(account.address.to_owned(), account);
+        Ok(())
+    }
+
+    /// 只返回属于tenant_id的账户
+    pub fn account(&self, tenant_id: &str, address: &str) -> Result<wallet::Account> {
+        self.with_tenant(tenant_id, |t| {
+            t.accounts
+                .get(address)
+                .cloned()
+                .ok_or(Error::from(ErrorKind::InvalidArguments))
+        })
+    }
+
+    pub fn addresses(&self, tenant_id: &str) -> Result<Vec<String>> {
+        self.with_tenant(tenant_id, |t| {
+            let mut addresses: Vec<String> = t.accounts.keys().cloned().collect();
+            addresses.sort();
+            Ok(addresses)
+        })
+    }
+
+    pub fn metrics(&self, tenant_id: &str) -> Result<TenantMetrics> {
+        self.with_tenant(tenant_id, |t| Ok(t.metrics.clone()))
+    }
+
+    /// 给租户的账户设置别名，用于报表
+    pub fn set_alias(&self, tenant_id: &str, address: &str, alias: &str) -> Result<()> {
+        self.with_tenant(tenant_id, |t| {
+            if !t.accounts.contains_key(address) {
+                return Err(Error::from(ErrorKind::InvalidArguments));
+            }
+            t.aliases.insert(address.to_owned(), alias.to_owned());
+            Ok(())
+        })
+    }
+
+    /// 查询租户所有账户在chain_name上的余额和utxo数，按地址排序
+    pub fn report(&self, tenant_id: &str, chain_name: &String) -> Result<Vec<AccountReport>> {
+        let chain_name = self.chains.resolve(chain_name)?;
+        let accounts = self.with_tenant(tenant_id, |t| {
+            let mut accounts: Vec<(String, Option<String>, Option<i64>)> = t
+                .accounts
+                .keys()
+                .map(|a| {
+                    (
+                        a.to_owned(),
+                        t.aliases.get(a).cloned(),
+                        t.last_activity.get(a).cloned(),
+                    )
+                })
+                .collect();
+            accounts.sort();
+            Ok(accounts)
+        })?;
+        let mut rows = vec![];
+        for (address, alias, last_activity) in accounts {
+            let record = ocall::ocall_xchain_query_utxo_record(&address, 0)?;
+            rows.push(AccountReport {
+                balance: balance::get_balance(&chain_name, &address)?,
+                utxo_count: utxo_count(&record)?,
+                address: address,
+                alias: alias,
+                last_activity: last_activity,
+            });
+        }
+        Ok(rows)
+    }
+
+    /// 用租户的账户转账，参数同transfer::transfer，chain_name为空时使用默认链
+    pub fn transfer(
+        &self,
+        tenant_id: &str,
+        from: &str,
+        chain_name: &String,
+        to: &String,
+        amount: &String,
+        fee: &String,
+        desc: &String,
+    ) -> Result<String> {
+        let chain_name = self.chains.resolve(chain_name)?;
+        let am = consts::str_as_bigint(amount)?;
+        let reservation = self.reserve(tenant_id, from, &am)?;
+        let res = transfer::transfer(&reservation.account, &chain_name, to, amount, fee, desc);
+        self.finish(tenant_id, &reservation, res.is_ok())?;
+        res
+    }
+
+    /// 用租户的账户调用合约account.contract_name，按gas_used付费，chain_name为空时使用默认链
+    pub fn invoke_contract(
+        &self,
+        tenant_id: &str,
+        from: &str,
+        chain_name: &String,
+        method_name: &String,
+        args: HashMap<String, Vec<u8>>,
+    ) -> Result<String> {
+        let chain_name = self.chains.resolve(chain_name)?;
+        let zero: num_bigint::BigInt = num_traits::Zero::zero();
+        let reservation = self.reserve(tenant_id, from, &zero)?;
+        let account = &reservation.account;
+        let allowed = self.with_tenant(tenant_id, |t| {
+            Ok(match t.policy.allowed_contracts {
+                Some(ref c) => c.contains(&account.contract_name),
+                None => true,
+            })
+        })?;
+        if !allowed {
+            self.finish(tenant_id, &reservation, false)?;
+            return Err(Error::from(ErrorKind::InvalidArguments));
+        }
+        let res = contract::invoke_contract(
+            account,
+            &chain_name,
+            &account.contract_name,
+            method_name,
+            args,
+            &String::new(),
+        )
+        .map(|(txid, _)| txid);
+        self.finish(tenant_id, &reservation, res.is_ok())?;
+        res
+    }
+
+    fn with_tenant<T, F>(&self, tenant_id: &str, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Tenant) -> Result<T>,
+    {
+        let tenants = self.tenants.read().unwrap();
+        let mut t = tenants
+            .get(tenant_id)
+            .ok_or(Error::from(ErrorKind::InvalidArguments))?
+            .lock()
+            .unwrap();
+        f(&mut t)
+    }
+
+    /// 检查账户和限额，通过之后先占用当天的额度
+    fn reserve(
+        &self,
+        tenant_id: &str,
+        from: &str,
+        amount: &num_bigint::BigInt,
+    ) -> Result<Reservation> {
+        let today = consts::now_as_secs() / SECS_PER_DAY;
+        self.with_tenant(tenant_id, |t| {
+            let res = t.reserve(from, amount, today);
+            if res.is_err() {
+                t.metrics.rejected += 1;
+            }
+            res
+        })
+    }
+
+    /// 交易结束，失败时释放占用的额度
+    fn finish(&self, tenant_id: &str, reservation: &Reservation, ok: bool) -> Result<()> {
+        self.with_tenant(tenant_id, |t| {
+            if ok {
+                t.metrics.txs += 1;
+                t.metrics.amount.add_assign(&reservation.amount);
+                t.last_activity.insert(
+                    reservation.account.address.to_owned(),
+                    consts::now_as_secs(),
+                );
+            } else {
+                t.metrics.rejected += 1;
+                t.release(reservation);
+            }
+            Ok(())
+        })
+    }
+}
+
+fn utxo_count(record: &xchain_node_sdk::protos::xchain::UtxoRecordDetail) -> Result<i64> {
+    let mut count = 0;
+    for r in [
+        record.get_openUtxoRecord(),
+        record.get_lockedUtxoRecord(),
+        record.get_frozenUtxoRecord(),
+    ]
+    .iter()
+    {
+        if !r.utxoCount.is_empty() {
+            count += consts::str_as_i64(&r.utxoCount)?;
+        }
+    }
+    Ok(count)
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+/// 报表转成CSV，第一行是表头
+pub fn report_csv(rows: &[AccountReport]) -> String {
+    let mut csv = String::from("address,alias,confirmed,pending,utxo_count,last_activity\n");
+    for r in rows.iter() {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            csv_field(&r.address),
+            csv_field(r.alias.as_ref().map(|a| a.as_str()).unwrap_or("")),
+            r.balance.confirmed,
+            r.balance.pending,
+            r.utxo_count,
+            r.last_activity.map(|t| t.to_string()).unwrap_or_default()
+        ));
+    }
+    csv
+}
+
+impl Tenant {
+    /// 检查账户和限额，占用today的额度
+    fn reserve(
+        &mut self,
+        from: &str,
+        amount: &num_bigint::BigInt,
+        today: i64,
+    ) -> Result<Reservation> {
+        let account = self
+            .accounts
+            .get(from)
+            .cloned()
+            .ok_or(Error::from(ErrorKind::InvalidArguments))?;
+        if let Some(ref max) = self.policy.max_amount_per_tx {
+            if amount > max {
+                return Err(Error::from(ErrorKind::InvalidArguments));
+            }
+        }
+        if self.daily.0 != today {
+            self.daily = (today, num_traits::Zero::zero());
+        }
+        let mut spent = self.daily.1.clone();
+        spent.add_assign(amount);
+        if let Some(ref max) = self.policy.max_daily_amount {
+            if &spent > max {
+                return Err(Error::from(ErrorKind::InvalidArguments));
+            }
+        }
+        self.daily.1 = spent;
+        Ok(Reservation {
+            account: account,
+            day: today,
+            amount: amount.clone(),
+        })
+    }
+
+    /// 释放占用的额度，已经过了占用的那一天时不释放，释放之后不小于0
+    fn release(&mut self, reservation: &Reservation) {
+        if self.daily.0 != reservation.day {
+            return;
+        }
+        if self.daily.1 > reservation.amount {
+            self.daily.1.sub_assign(&reservation.amount);
+        } else {
+            self.daily.1 = num_traits::Zero::zero();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_traits::FromPrimitive;
+
+    fn account(address: &str) -> wallet::Account {
+        wallet::Account {
+            address: String::from(address),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tenant_isolation() {
+        let w = Wallet::new();
+        w.add_tenant("a", Default::default()).unwrap();
+        w.add_tenant("b", Default::default()).unwrap();
+        assert_eq!(w.add_tenant("a", Default::default()).is_ok(), false);
+
+        w.add_account("a", account("alice")).unwrap();
+        w.add_account("b", account("bob")).unwrap();
+        // 同一个地址不能属于两个租户
+        assert_eq!(w.add_account("b", account("alice")).is_ok(), false);
+        assert_eq!(w.add_account("c", account("carol")).is_ok(), false);
+
+        assert_eq!(w.account("a", "alice").unwrap().address, "alice");
+        assert_eq!(w.account("b", "alice").is_ok(), false);
+        assert_eq!(w.addresses("b").unwrap(), vec![String::from("bob")]);
+    }
+
+    #[test]
+    fn test_tenant_limits() {
+        let w = Wallet::new();
+        let policy = TenantPolicy {
+            max_amount_per_tx: num_bigint::BigInt::from_i64(10),
+            max_daily_amount: num_bigint::BigInt::from_i64(15),
+            allowed_contracts: None,
+        };
+        w.add_tenant("a", policy).unwrap();
+        w.add_account("a", account("alice")).unwrap();
+
+        let am = |i| num_bigint::BigInt::from_i64(i).unwrap();
+        assert_eq!(w.reserve("a", "alice", &am(11)).is_ok(), false);
+        assert_eq!(w.reserve("a", "alice", &am(10)).is_ok(), true);
+        assert_eq!(w.reserve("a", "alice", &am(6)).is_ok(), false);
+        // 失败的交易释放额度
+        let r = Reservation {
+            account: account("alice"),
+            day: consts::now_as_secs() / SECS_PER_DAY,
+            amount: am(10),
+        };
+        w.finish("a", &r, false).unwrap();
+        let r = w.reserve("a", "alice", &am(6)).unwrap();
+        w.finish("a", &r, true).unwrap();
+
+        let metrics = w.metrics("a").unwrap();
+        assert_eq!(metrics.txs, 1);
+        assert_eq!(metrics.rejected, 3);
+        assert_eq!(metrics.amount, am(6));
+        assert_eq!(w.metrics("b").is_ok(), false);
+    }
+
+    #[test]
+    fn test_release_after_day_rollover() {
+        let mut t = Tenant {
+            policy: TenantPolicy {
+                max_daily_amount: num_bigint::BigInt::from_i64(15),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        t.accounts.insert(String::from("alice"), account("alice"));
+        let am = |i| num_bigint::BigInt::from_i64(i).unwrap();
+
+        let yesterday = t.reserve("alice", &am(10), 1).unwrap();
+        let today = t.reserve("alice", &am(3), 2).unwrap();
+        // 前一天的交易失败不释放今天的额度，也不会变成负数
+        t.release(&yesterday);
+        assert_eq!(t.daily, (2, am(3)));
+        t.release(&today);
+        assert_eq!(t.daily, (2, am(0)));
+        t.release(&today);
+        assert_eq!(t.daily, (2, am(0)));
+        assert_eq!(t.reserve("alice", &am(15), 2).is_ok(), true);
+    }
+
+    #[test]
+    fn test_tenant_unknown_chain() {
+        let w = Wallet::with_chains(chains::Chains::with_loader(Box::new(|| Ok(vec![]))));
+        w.add_tenant("a", Default::default()).unwrap();
+        w.add_account("a", account("alice")).unwrap();
+        let empty = String::new();
+        let res = w.transfer(
+            "a",
+            "alice",
+            &String::from("xuper"),
+            &String::from("bob"),
+            &String::from("1"),
+            &String::from("0"),
+            &empty,
+        );
+        assert_eq!(res.is_ok(), false);
+        // 链名不合法时不占用额度
+        assert_eq!(w.metrics("a").unwrap(), Default::default());
+    }
+
+    #[test]
+    fn test_report_csv() {
+        let w = Wallet::new();
+        w.add_tenant("a", Default::default()).unwrap();
+        w.add_account("a", account("alice")).unwrap();
+        w.set_alias("a", "alice", "treasury, main").unwrap();
+        assert_eq!(w.set_alias("a", "bob", "x").is_ok(), false);
+        let r = w.reserve("a", "alice", &Default::default()).unwrap();
+        w.finish("a", &r, true).unwrap();
+        let (alias, last_activity) = w
+            .with_tenant("a", |t| {
+                Ok((
+                    t.aliases.get("alice").cloned(),
+                    t.last_activity.get("alice").cloned(),
+                ))
+            })
+            .unwrap();
+        assert_eq!(last_activity.is_some(), true);
+
+        let row = AccountReport {
+            address: String::from("alice"),
+            alias: alias,
+            balance: balance::Balance {
+                bcname: String::from("xuper"),
+                address: String::from("alice"),
+                confirmed: num_bigint::BigInt::from(10),
+                pending: num_bigint::BigInt::from(2),
+            },
+            utxo_count: 3,
+            last_activity: Some(1600000000),
+        };
+        assert_eq!(
+            report_csv(&[row]),
+            "address,alias,confirmed,pending,utxo_count,last_activity\n\
+             alice,\"treasury, main\",10,2,3,1600000000\n"
+        );
+
+        let mut record = xchain_node_sdk::protos::xchain::UtxoRecordDetail::new();
+        record.mut_openUtxoRecord().set_utxoCount(String::from("4"));
+        record
+            .mut_frozenUtxoRecord()
+            .set_utxoCount(String::from("1"));
+        assert_eq!(utxo_count(&record).unwrap(), 5);
+    }
+}
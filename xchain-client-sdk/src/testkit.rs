@@ -0,0 +1,124 @@
+use std::time::{Duration, Instant};
+
+use crate::{config, transfer, wallet};
+use xchain_node_sdk::{errors::*, ocall, protos::xchain};
+
+// 集成测试的辅助函数，需要开启testkit feature。
+// 不负责启动节点，先用docker启动XuperChain节点和背书服务，再通过环境变量指定:
+//   XCHAIN_TESTKIT_NODE      节点地址，默认为sdk.yaml里面的node
+//   XCHAIN_TESTKIT_PORT      端口，默认为sdk.yaml里面的endorsePort
+//   XCHAIN_TESTKIT_CHAIN     链名，默认为xuper
+//   XCHAIN_TESTKIT_FAUCET    有余额的账户的秘钥目录，默认为本crate的key目录
+
+/// 等待交易上链时查询的间隔
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 本crate的key目录下测试账户的私钥
+pub fn test_key_path() -> std::path::PathBuf {
+    let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    d.push("key/private.key");
+    d
+}
+
+/// 本crate的key目录下的测试账户
+pub fn test_account(contract_name: &str, contract_account: &str) -> wallet::Account {
+    wallet::Account::new(
+        test_key_path().to_str().unwrap(),
+        contract_name,
+        contract_account,
+    )
+}
+
+pub struct TestEnv {
+    pub chain_name: String,
+    pub host: String,
+    pub port: u16,
+    pub faucet_key_dir: String,
+}
+
+fn env_or(name: &str, default: String) -> String {
+    std::env::var(name).unwrap_or(default)
+}
+
+impl TestEnv {
+    /// 读取环境变量并连接节点
+    pub fn from_env() -> Result<Self> {
+        let (node, port) = {
+            let c = config::CONFIG.read().unwrap();
+            (c.node.clone(), c.endorse_port)
+        };
+        let port = match std::env::var("XCHAIN_TESTKIT_PORT") {
+            Ok(p) => p
+                .parse::<u16>()
+                .map_err(|_| Error::from(ErrorKind::InvalidArguments))?,
+            Err(_) => port,
+        };
+        let env = TestEnv {
+            chain_name: env_or("XCHAIN_TESTKIT_CHAIN", String::from("xuper")),
+            host: env_or("XCHAIN_TESTKIT_NODE", node),
+            port: port,
+            faucet_key_dir: env_or(
+                "XCHAIN_TESTKIT_FAUCET",
+                format!("{}/key", env!("CARGO_MANIFEST_DIR")),
+            ),
+        };
+        ocall::init(&env.chain_name, &env.host, env.port)?;
+        Ok(env)
+    }
+
+    /// 有余额的账户
+    pub fn faucet(&self, contract_name: &str, contract_account: &str) -> Result<wallet::Account> {
+        wallet::Account::from_key_dir(&self.faucet_key_dir, contract_name, contract_account)
+    }
+
+    /// 在临时目录下生成新账户
+    pub fn new_account(&self) -> Result<wallet::Account> {
+        let dir = std::env::temp_dir().join(format!("xchain-testkit-{}", wallet::get_nonce()?));
+        let dir = dir
+            .to_str()
+            .ok_or(Error::from(ErrorKind::InvalidArguments))?;
+        wallet::Account::generate(dir, Default::default(), Default::default())
+    }
+
+    /// 从faucet给to转账amount，等待交易确认
+    pub fn fund(&self, to: &String, amount: &String, timeout: Duration) -> Result<String> {
+        let faucet = self.faucet(Default::default(), Default::default())?;
+        let txid = transfer::transfer(
+            &faucet,
+            &self.chain_name,
+            to,
+            amount,
+            &String::from("0"),
+            &String::from("testkit fund"),
+        )?;
+        self.wait_tx(&txid, timeout)?;
+        Ok(txid)
+    }
+
+    /// 新账户并且转入amount
+    pub fn funded_account(&self, amount: &String, timeout: Duration) -> Result<wallet::Account> {
+        let acc = self.new_account()?;
+        self.fund(&acc.address, amount, timeout)?;
+        Ok(acc)
+    }
+
+    /// 轮询直到交易确认，超时返回DeadlineExceeded
+    pub fn wait_tx(&self, txid: &String, timeout: Duration) -> Result<xchain::TxStatus> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Ok(status) = ocall::ocall_xchain_query_tx(txid) {
+                match status.status {
+                    xchain::TransactionStatus::CONFIRM => return Ok(status),
+                    xchain::TransactionStatus::FAILED => {
+                        return Err(Error::from(ErrorKind::ChainRPCError))
+                    }
+                    _ => {}
+                }
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::from(ErrorKind::DeadlineExceeded));
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
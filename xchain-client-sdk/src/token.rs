@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use num_bigint::BigInt;
+
+use crate::{config, consts, contract, names, wallet};
+use xchain_node_sdk::errors::*;
+
+// XRC-20风格的WASM代币合约，封装方法名和参数，调用方不需要自己拼参数map。
+// 方法和参数名按XRC-20的约定:
+//   totalSupply()                     总量
+//   balanceOf(owner)                  owner的余额
+//   transfer(to, amount)              从发起者转给to
+//   approve(spender, amount)          允许spender从发起者转出amount
+// 金额是十进制字符串，合约返回的body也是十进制字符串。
+// 查询用contract::query_contract，只预执行，不提交交易；transfer和approve是普通的合约调用，配置了名字服务时to和spender可以是名字。
+// 方法名或参数名不同的代币合约直接用contract::invoke_contract。
+
+pub const TOTAL_SUPPLY: &str = "totalSupply";
+pub const BALANCE_OF: &str = "balanceOf";
+pub const TRANSFER: &str = "transfer";
+pub const APPROVE: &str = "approve";
+
+/// 链上的代币合约
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub chain_name: String,
+    pub contract_name: String,
+}
+
+impl Token {
+    pub fn new(chain_name: &str, contract_name: &str) -> Self {
+        Token {
+            chain_name: chain_name.to_owned(),
+            contract_name: contract_name.to_owned(),
+        }
+    }
+
+    pub fn total_supply(&self) -> Result<BigInt> {
+        parse_amount(&self.query(TOTAL_SUPPLY, HashMap::new())?)
+    }
+
+    pub fn balance_of(&self, owner: &str) -> Result<BigInt> {
+        let owner = names::resolve_to(&config::ClientConfig::global(), &self.chain_name, owner)?;
+        self.query(BALANCE_OF, args(&[("owner", owner.as_str())]))
+            .and_then(|body| parse_amount(&body))
+    }
+
+    /// account转给to amount个代币，返回txid，fee同invoke_contract
+    pub fn transfer(
+        &self,
+        account: &wallet::Account,
+        to: &str,
+        amount: &str,
+        fee: &String,
+    ) -> Result<String> {
+        let to = names::resolve_to(&config::ClientConfig::global(), &self.chain_name, to)?;
+        check_amount(amount)?;
+        self.invoke(
+            account,
+            TRANSFER,
+            args(&[("to", to.as_str()), ("amount", amount)]),
+            fee,
+        )
+    }
+
+    /// 允许spender从account转出amount个代币，返回txid
+    pub fn approve(
+        &self,
+        account: &wallet::Account,
+        spender: &str,
+        amount: &str,
+        fee: &String,
+    ) -> Result<String> {
+        let spender =
+            names::resolve_to(&config::ClientConfig::global(), &self.chain_name, spender)?;
+        check_amount(amount)?;
+        self.invoke(
+            account,
+            APPROVE,
+            args(&[("spender", spender.as_str()), ("amount", amount)]),
+            fee,
+        )
+    }
+
+    fn invoke(
+        &self,
+        account: &wallet::Account,
+        method_name: &str,
+        args: HashMap<String, Vec<u8>>,
+        fee: &String,
+    ) -> Result<String> {
+        contract::invoke_contract(
+            account,
+            &self.chain_name,
+            &self.contract_name,
+            &method_name.to_owned(),
+            args,
+            fee,
+        )
+        .map(|(txid, _)| txid)
+    }
+
+    fn query(&self, method_name: &str, args: HashMap<String, Vec<u8>>) -> Result<Vec<u8>> {
+        contract::query_contract(
+            &self.chain_name,
+            &self.contract_name,
+            &method_name.to_owned(),
+            args,
+        )
+        .map(|r| r.body)
+    }
+}
+
+fn args(kvs: &[(&str, &str)]) -> HashMap<String, Vec<u8>> {
+    kvs.iter()
+        .map(|(k, v)| (k.to_string(), v.as_bytes().to_vec()))
+        .collect()
+}
+
+/// 转账和授权的金额是非负整数
+fn check_amount(amount: &str) -> Result<()> {
+    if amount.is_empty() {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    consts::checked_sum(&[amount]).map(|_| ())
+}
+
+/// 合约返回的十进制金额
+pub fn parse_amount(body: &[u8]) -> Result<BigInt> {
+    let s = String::from_utf8_lossy(body).trim().to_owned();
+    if s.is_empty() {
+        return Err(Error::new(
+            ErrorKind::UnexpectedResponse,
+            "token contract returned empty amount",
+        ));
+    }
+    consts::str_as_bigint(&s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_amount(b"1000\n").unwrap(), BigInt::from(1000));
+        assert_eq!(
+            parse_amount(b"").unwrap_err().kind(),
+            ErrorKind::UnexpectedResponse
+        );
+        assert_eq!(
+            parse_amount(b"abc").unwrap_err().kind(),
+            ErrorKind::ParseError
+        );
+    }
+
+    #[test]
+    fn test_args() {
+        let a = args(&[("to", "bob"), ("amount", "10")]);
+        assert_eq!(a.len(), 2);
+        assert_eq!(a["amount"], b"10".to_vec());
+
+        assert_eq!(check_amount("10").is_ok(), true);
+        assert_eq!(check_amount("0").is_ok(), true);
+        assert_eq!(
+            check_amount("-1").unwrap_err().kind(),
+            ErrorKind::InvalidArguments
+        );
+        assert_eq!(check_amount("").is_err(), true);
+        assert_eq!(check_amount("1.5").is_err(), true);
+
+        // 金额不合法时不发起调用
+        let token = Token::new("xuper", "xrc20");
+        assert_eq!(
+            token
+                .transfer(
+                    &wallet::Account::default(),
+                    "dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN",
+                    "-1",
+                    &String::new()
+                )
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidArguments
+        );
+    }
+}
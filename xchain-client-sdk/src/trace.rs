@@ -0,0 +1,112 @@
+use crate::{desc, session};
+use xchain_node_sdk::{errors::*, protos::xchain};
+
+// 在交易的desc里嵌入W3C traceparent(https://www.w3.org/TR/trace-context/)，
+// 把链上的交易和调用方的分布式追踪关联起来。
+// 格式为 TRACE_MAGIC + traceparent + 原来的desc(可能是压缩过的)。
+
+/// 是否是合法的traceparent: 00-<32位hex trace-id>-<16位hex parent-id>-<2位hex flags>
+pub fn is_valid_traceparent(traceparent: &str) -> bool {
+    let parts: Vec<&str> = traceparent.split('-').collect();
+    if traceparent.len() != desc::TRACEPARENT_LEN || parts.len() != 4 {
+        return false;
+    }
+    let is_hex = |s: &str, len: usize| {
+        s.len() == len
+            && s.chars()
+                .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+    };
+    let is_zero = |s: &str| s.chars().all(|c| c == '0');
+    parts[0] == "00"
+        && is_hex(parts[1], 32)
+        && !is_zero(parts[1])
+        && is_hex(parts[2], 16)
+        && !is_zero(parts[2])
+        && is_hex(parts[3], 2)
+}
+
+/// 把traceparent嵌入desc
+pub fn embed(desc: &[u8], traceparent: &str) -> Result<Vec<u8>> {
+    if !is_valid_traceparent(traceparent) {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let (_, rest) = desc::split_traceparent(desc)?;
+    let mut res = desc::TRACE_MAGIC.to_vec();
+    res.extend_from_slice(traceparent.as_bytes());
+    res.extend_from_slice(rest);
+    Ok(res)
+}
+
+/// 取出desc里嵌入的traceparent
+pub fn extract(desc: &[u8]) -> Result<Option<String>> {
+    match desc::split_traceparent(desc)?.0 {
+        Some(t) => {
+            let t =
+                String::from_utf8(t.to_vec()).map_err(|_| Error::from(ErrorKind::ParseError))?;
+            if !is_valid_traceparent(&t) {
+                return Err(Error::from(ErrorKind::ParseError));
+            }
+            Ok(Some(t))
+        }
+        None => Ok(None),
+    }
+}
+
+/// 查询到的交易里嵌入的traceparent
+pub fn tx_traceparent(tx: &xchain::Transaction) -> Result<Option<String>> {
+    extract(tx.get_desc())
+}
+
+/// 签名之前把traceparent嵌入交易的desc，通过Session::add_hook使用
+pub struct TraceContextHook {
+    pub traceparent: String,
+}
+
+impl session::TxHook for TraceContextHook {
+    fn post_build(&self, tx: &mut xchain::Transaction) -> Result<()> {
+        let d = embed(tx.get_desc(), &self.traceparent)?;
+        tx.set_desc(d);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::TxHook;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_traceparent_in_desc() {
+        assert_eq!(is_valid_traceparent(TRACEPARENT), true);
+        assert_eq!(
+            is_valid_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01"),
+            false
+        );
+        assert_eq!(
+            is_valid_traceparent("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01"),
+            false
+        );
+
+        let mut tx = xchain::Transaction::new();
+        tx.set_desc(b"test duanbing".to_vec());
+        let hook = TraceContextHook {
+            traceparent: String::from(TRACEPARENT),
+        };
+        hook.post_build(&mut tx).unwrap();
+        assert_eq!(
+            tx_traceparent(&tx).unwrap(),
+            Some(String::from(TRACEPARENT))
+        );
+        assert_eq!(desc::tx_desc(&tx).unwrap(), b"test duanbing".to_vec());
+
+        // 重复嵌入时替换
+        let other = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b8-00";
+        let d = embed(tx.get_desc(), other).unwrap();
+        assert_eq!(extract(&d).unwrap(), Some(String::from(other)));
+        assert_eq!(desc::decode(&d).unwrap(), b"test duanbing".to_vec());
+
+        assert_eq!(extract(b"test duanbing").unwrap(), None);
+    }
+}
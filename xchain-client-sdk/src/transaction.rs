@@ -0,0 +1,195 @@
+use num_bigint::BigInt;
+
+use crate::{confirm, consts, desc, receipt};
+use xchain_node_sdk::{errors::*, ocall, protos::xchain};
+
+// 查询交易，返回解析之后的结果，调用方不需要处理protobuf类型:
+// txid、blockid和引用的txid是hex编码，金额是十进制字符串，地址是字符串。
+// 手续费输出(地址为$)不在outputs里，合计在fee。
+// XuperChain 3.7的交易里面没有合约事件，和Receipt一样用交易里的合约调用作为events。
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TxInputInfo {
+    pub ref_txid: String,
+    pub ref_offset: i32,
+    pub from: String,
+    pub amount: String,
+    pub frozen_height: i64,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TxOutputInfo {
+    pub to: String,
+    pub amount: String,
+    pub frozen_height: i64,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TxInfo {
+    pub txid: String,
+    pub status: confirm::TxState,
+    /// 交易所在区块的高度，没有上链时为None
+    pub height: Option<i64>,
+    pub initiator: String,
+    /// 还原之后的desc(见desc::tx_desc)，不能还原时是链上的原始desc
+    pub desc: String,
+    pub coinbase: bool,
+    /// 纳秒
+    pub timestamp: i64,
+    pub inputs: Vec<TxInputInfo>,
+    pub outputs: Vec<TxOutputInfo>,
+    /// 付给矿工的手续费
+    pub fee: String,
+    pub events: Vec<receipt::ReceiptEvent>,
+}
+
+fn amount(b: &[u8]) -> String {
+    BigInt::from_bytes_be(num_bigint::Sign::Plus, b).to_str_radix(10)
+}
+
+impl TxInfo {
+    /// 解析节点返回的交易状态，height为交易所在区块的高度
+    pub fn from_status(status: &xchain::TxStatus, height: Option<i64>) -> Self {
+        let tx = status.get_tx();
+        TxInfo {
+            txid: hex::encode(&status.txid),
+            status: confirm::tx_state(status),
+            height: height,
+            initiator: tx.initiator.to_owned(),
+            desc: String::from_utf8_lossy(&desc::tx_desc(tx).unwrap_or_else(|_| tx.desc.clone()))
+                .into_owned(),
+            coinbase: tx.coinbase,
+            timestamp: tx.timestamp,
+            inputs: tx
+                .get_tx_inputs()
+                .iter()
+                .map(|i| TxInputInfo {
+                    ref_txid: hex::encode(&i.ref_txid),
+                    ref_offset: i.ref_offset,
+                    from: String::from_utf8_lossy(&i.from_addr).into_owned(),
+                    amount: amount(&i.amount),
+                    frozen_height: i.frozen_height,
+                })
+                .collect(),
+            outputs: tx
+                .get_tx_outputs()
+                .iter()
+                .filter(|o| !consts::FeeOutput::is_fee(o))
+                .map(|o| TxOutputInfo {
+                    to: String::from_utf8_lossy(&o.to_addr).into_owned(),
+                    amount: amount(&o.amount),
+                    frozen_height: o.frozen_height,
+                })
+                .collect(),
+            fee: consts::FeeOutput::total(tx).to_str_radix(10),
+            events: tx
+                .get_contract_requests()
+                .iter()
+                .map(|r| receipt::ReceiptEvent {
+                    contract_name: r.contract_name.to_owned(),
+                    method_name: r.method_name.to_owned(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// 查询交易，已经上链时再查询所在区块的高度
+pub fn query_transaction(txid: &String) -> Result<TxInfo> {
+    let status = ocall::ocall_xchain_query_tx(txid)?;
+    let height = match confirm::tx_state(&status) {
+        confirm::TxState::Confirmed { blockid } => Some(confirm::block_height(&blockid)?),
+        _ => None,
+    };
+    Ok(TxInfo::from_status(&status, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_status() {
+        let mut tx = xchain::Transaction::new();
+        tx.set_blockid(vec![0xbb; 2]);
+        tx.set_initiator(String::from("alice"));
+        tx.set_desc(b"pay".to_vec());
+        tx.set_timestamp(1600000000000000000);
+        let mut input = xchain::TxInput::new();
+        input.set_ref_txid(vec![0xaa; 2]);
+        input.set_ref_offset(1);
+        input.set_from_addr(b"alice".to_vec());
+        input.set_amount(vec![1, 0]);
+        tx.mut_tx_inputs().push(input);
+        let mut output = xchain::TxOutput::new();
+        output.set_to_addr(b"bob".to_vec());
+        output.set_amount(vec![0xff]);
+        tx.mut_tx_outputs().push(output);
+        tx.mut_tx_outputs()
+            .push(consts::FeeOutput::new("1").unwrap().to_tx_output());
+        let mut r = xchain::InvokeRequest::new();
+        r.set_contract_name(String::from("counter"));
+        r.set_method_name(String::from("increase"));
+        tx.mut_contract_requests().push(r);
+
+        let mut status = xchain::TxStatus::new();
+        status.set_txid(vec![0x01, 0x02]);
+        status.set_status(xchain::TransactionStatus::CONFIRM);
+        status.set_tx(tx);
+
+        let info = TxInfo::from_status(&status, Some(10));
+        assert_eq!(info.txid, "0102");
+        assert_eq!(
+            info.status,
+            confirm::TxState::Confirmed {
+                blockid: String::from("bbbb")
+            }
+        );
+        assert_eq!(info.height, Some(10));
+        assert_eq!(info.desc, "pay");
+        assert_eq!(
+            info.inputs,
+            vec![TxInputInfo {
+                ref_txid: String::from("aaaa"),
+                ref_offset: 1,
+                from: String::from("alice"),
+                amount: String::from("256"),
+                frozen_height: 0,
+            }]
+        );
+        assert_eq!(
+            info.outputs,
+            vec![TxOutputInfo {
+                to: String::from("bob"),
+                amount: String::from("255"),
+                frozen_height: 0,
+            }]
+        );
+        assert_eq!(info.fee, "1");
+        assert_eq!(info.events.len(), 1);
+        assert_eq!(info.events[0].method_name, "increase");
+
+        status.set_status(xchain::TransactionStatus::NOEXIST);
+        let info = TxInfo::from_status(&status, None);
+        assert_eq!(info.height, None);
+        assert_eq!(info.fee, "1");
+    }
+
+    #[test]
+    fn test_from_status_desc() {
+        let mut status = xchain::TxStatus::new();
+        // 绑定的chain id被去掉
+        let mut d = desc::CHAIN_ID_MAGIC.to_vec();
+        d.extend_from_slice(b"xuper-test\npay");
+        status.mut_tx().set_desc(d);
+        assert_eq!(TxInfo::from_status(&status, None).desc, "pay");
+
+        let long = "anchor:".repeat(200);
+        let encoded = desc::encode(long.as_bytes(), 64).unwrap();
+        status.mut_tx().set_desc(encoded);
+        assert_eq!(TxInfo::from_status(&status, None).desc, long);
+    }
+}
@@ -0,0 +1,184 @@
+use std::sync::Mutex;
+
+use protobuf::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::{consts, wallet};
+use xchain_node_sdk::{
+    canonical, encoder,
+    errors::*,
+    protos::{xchain, xendorser},
+};
+
+// 交易的签名过程记录: 和背书服务的请求、响应，待签名的摘要，最终提交的交易。
+// 通过Session::set_transcript开启，交易完成之后用账户签名封存，和背书服务的运营方有争议时作为证据，
+// 第三方用verify_transcript校验封存签名以及背书服务对交易的签名。
+
+pub const PRE_EXEC_REQUEST: &str = "PreExecWithFee request";
+pub const PRE_EXEC_RESPONSE: &str = "PreExecWithFee response";
+pub const COMPLIANCE_TX_CHECK: &str = "ComplianceTx check";
+pub const DIGEST: &str = "digest";
+pub const COMPLIANCE_CHECK_REQUEST: &str = "ComplianceCheck request";
+pub const COMPLIANCE_CHECK_RESPONSE: &str = "ComplianceCheck response";
+/// 签名的背书服务地址和接入点，"<addr> <host:port>"
+pub const ENDORSED_BY: &str = "endorsed by";
+pub const POST_TX: &str = "PostTx";
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub stage: String,
+    pub timestamp: i64,
+    /// hex编码，请求和响应是protobuf编码
+    pub data: String,
+}
+
+/// 封存之后的记录，payload是被签名的json字符串
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SealedTranscript {
+    pub payload: String,
+    pub public_key: String,
+    /// hex编码
+    pub sign: String,
+}
+
+#[derive(Default)]
+pub struct Transcript {
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl Transcript {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn record(&self, stage: &str, data: &[u8]) {
+        self.entries.lock().unwrap().push(Entry {
+            stage: stage.to_owned(),
+            timestamp: consts::now_as_nanos(),
+            data: hex::encode(data),
+        });
+    }
+
+    pub fn record_message<M: Message>(&self, stage: &str, m: &M) -> Result<()> {
+        let data = m
+            .write_to_bytes()
+            .map_err(|_| Error::from(ErrorKind::ParseError))?;
+        self.record(stage, &data);
+        Ok(())
+    }
+
+    pub fn entries(&self) -> Vec<Entry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    /// 用account签名封存，返回json
+    pub fn seal(&self, account: &wallet::Account) -> Result<String> {
+        let payload = serde_json::to_string(&self.entries())?;
+        let sealed = SealedTranscript {
+            sign: hex::encode(account.sign(payload.as_bytes())?),
+            public_key: account.public_key()?,
+            payload: payload,
+        };
+        Ok(serde_json::to_string(&sealed)?)
+    }
+}
+
+/// 校验封存的记录，返回封存者地址和记录
+/// 记录里有合规检查的请求和响应时，同时校验背书签名是对请求里的交易签的，返回背书者地址
+pub fn verify_transcript(sealed: &str) -> Result<(String, Vec<Entry>, Option<String>)> {
+    let sealed: SealedTranscript = serde_json::from_str(sealed)?;
+    let address = wallet::verify_with_public_key(
+        &sealed.public_key,
+        sealed.payload.as_bytes(),
+        &hex::decode(&sealed.sign)?,
+    )?;
+    let entries: Vec<Entry> = serde_json::from_str(&sealed.payload)?;
+    let endorser = verify_endorser_sign(&entries)?;
+    Ok((address, entries, endorser))
+}
+
+fn last_entry<'a>(entries: &'a [Entry], stage: &str) -> Option<&'a Entry> {
+    entries.iter().rev().find(|e| e.stage == stage)
+}
+
+fn verify_endorser_sign(entries: &[Entry]) -> Result<Option<String>> {
+    let (req, resp) = match (
+        last_entry(entries, COMPLIANCE_CHECK_REQUEST),
+        last_entry(entries, COMPLIANCE_CHECK_RESPONSE),
+    ) {
+        (Some(req), Some(resp)) => (req, resp),
+        _ => return Ok(None),
+    };
+    let req: xendorser::EndorserRequest = protobuf::parse_from_bytes(&hex::decode(&req.data)?)
+        .map_err(|_| Error::from(ErrorKind::ParseError))?;
+    let resp: xendorser::EndorserResponse = protobuf::parse_from_bytes(&hex::decode(&resp.data)?)
+        .map_err(|_| Error::from(ErrorKind::ParseError))?;
+    let tx_status: xchain::TxStatus = canonical::from_canonical_json(&req.RequestData)?;
+    let digest = encoder::make_tx_digest_hash(tx_status.get_tx())?;
+    let sign = resp
+        .EndorserSign
+        .into_option()
+        .ok_or(Error::from(ErrorKind::EndorserSignMissing))?;
+    let endorser = wallet::verify_with_public_key(&sign.PublicKey, &digest, &sign.Sign)?;
+    Ok(Some(endorser))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_seal_and_verify() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = wallet::Account::new(d.to_str().unwrap(), "", "");
+
+        let mut tx = xchain::Transaction::new();
+        tx.set_initiator(acc.address.to_owned());
+        tx.set_desc(b"test duanbing".to_vec());
+        let digest = encoder::make_tx_digest_hash(&tx).unwrap();
+        let mut tx_status = xchain::TxStatus::new();
+        tx_status.set_bcname(String::from("xuper"));
+        tx_status.set_tx(tx);
+        let mut req = xendorser::EndorserRequest::new();
+        req.set_RequestName(String::from("ComplianceCheck"));
+        req.set_RequestData(canonical::to_canonical_json(&tx_status).unwrap());
+        // 测试里背书者也用同一个秘钥
+        let mut sign = xchain::SignatureInfo::new();
+        sign.set_PublicKey(acc.public_key().unwrap());
+        sign.set_Sign(acc.sign(&digest).unwrap());
+        let mut resp = xendorser::EndorserResponse::new();
+        resp.set_EndorserSign(sign);
+
+        let t = Transcript::new();
+        t.record(DIGEST, &digest);
+        t.record_message(COMPLIANCE_CHECK_REQUEST, &req).unwrap();
+        t.record_message(COMPLIANCE_CHECK_RESPONSE, &resp).unwrap();
+        let sealed = t.seal(&acc).unwrap();
+
+        let (address, entries, endorser) = verify_transcript(&sealed).unwrap();
+        assert_eq!(address, acc.address);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(endorser, Some(acc.address.to_owned()));
+
+        // 篡改记录
+        let mut s: SealedTranscript = serde_json::from_str(&sealed).unwrap();
+        s.payload = s.payload.replace(DIGEST, "other");
+        assert_eq!(
+            verify_transcript(&serde_json::to_string(&s).unwrap()).is_ok(),
+            false
+        );
+
+        // 背书签名不是对请求里的交易签的
+        let mut other = xchain::Transaction::new();
+        other.set_desc(b"other".to_vec());
+        tx_status.set_tx(other);
+        req.set_RequestData(canonical::to_canonical_json(&tx_status).unwrap());
+        let t = Transcript::new();
+        t.record_message(COMPLIANCE_CHECK_REQUEST, &req).unwrap();
+        t.record_message(COMPLIANCE_CHECK_RESPONSE, &resp).unwrap();
+        assert_eq!(verify_transcript(&t.seal(&acc).unwrap()).is_ok(), false);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{config, consts, session, wallet};
+use crate::{config, consts, fee_bump, names, session, utxo, wallet};
 use xchain_node_sdk::{errors::*, protos};
 
 /// account在chain上面给to转账amount，小费是fee，留言是desc
@@ -10,35 +10,326 @@ pub fn transfer(
     fee: &String,
     desc: &String,
 ) -> Result<String> {
-    let amount_bk = amount.to_owned();
-    let amount = consts::str_as_i64(amount.as_str())?;
-    let fee = consts::str_as_i64(fee.as_str())?;
-    let auth_requires = vec![
-        config::CONFIG
-            .read()
-            .unwrap()
-            .compliance_check
-            .compliance_check_endorse_service_addr
-            .to_owned();
-        1
-    ];
-
-    let endorser_fee = config::CONFIG
-        .read()
-        .unwrap()
-        .compliance_check
-        .compliance_check_endorse_service_fee as i64;
-    // TODO 应该不用判断
-    if endorser_fee > amount {
-        println!("endorser_fee should smaller than amount");
+    transfer_with_config(
+        &config::ClientConfig::global(),
+        account,
+        chain_name,
+        to,
+        amount,
+        fee,
+        desc,
+    )
+}
+
+/// 同transfer，使用config而不是全局的CONFIG
+/// 配置了feeBump时，手续费不够被拒绝后提高手续费重新提交(见fee_bump模块)
+pub fn transfer_with_config(
+    config: &config::ClientConfig,
+    account: &wallet::Account,
+    chain_name: &String,
+    to: &String,
+    amount: &String,
+    fee: &String,
+    desc: &String,
+) -> Result<String> {
+    fee_bump::with_fee_bump(config, fee, |fee| {
+        let (pre_sel_utxo_req, msg) = build_transfer_to_many_request_with_config(
+            config,
+            account,
+            chain_name,
+            &[(to.to_owned(), amount.to_owned())],
+            fee,
+            desc,
+        )?;
+        let sess = session::Session::with_config(chain_name, account, &msg, config.clone());
+        let mut pre_exe_with_sel_res = sess.pre_exec_with_select_utxo(pre_sel_utxo_req)?;
+        sess.gen_complete_tx_and_post(&mut pre_exe_with_sel_res)
+    })
+}
+
+/// 同transfer，收款的输出冻结到区块高度frozen_height，之前收款方不能花费
+pub fn transfer_with_frozen_height(
+    account: &wallet::Account,
+    chain_name: &String,
+    to: &String,
+    amount: &String,
+    fee: &String,
+    desc: &String,
+    frozen_height: i64,
+) -> Result<String> {
+    if frozen_height < 0 {
         return Err(Error::from(ErrorKind::InvalidArguments));
     }
-    let total_amount = amount + fee + endorser_fee;
-    //防止溢出
-    if total_amount < amount {
-        println!("totoal_amount should be greater than amount");
+    let (pre_sel_utxo_req, mut msg) =
+        build_transfer_request(account, chain_name, to, amount, fee, desc)?;
+    msg.frozen_height = frozen_height;
+    let sess = session::Session::new(chain_name, account, &msg);
+    let mut pre_exe_with_sel_res = sess.pre_exec_with_select_utxo(pre_sel_utxo_req)?;
+    sess.gen_complete_tx_and_post(&mut pre_exe_with_sel_res)
+}
+
+/// 一笔交易给多个地址转账，outputs是(to, amount)，共用一个找零输出
+/// 配置了feeBump时同transfer_with_config
+pub fn transfer_to_many(
+    account: &wallet::Account,
+    chain_name: &String,
+    outputs: &[(String, String)],
+    fee: &String,
+    desc: &String,
+) -> Result<String> {
+    if outputs.is_empty() || outputs.iter().any(|(to, _)| to.is_empty()) {
         return Err(Error::from(ErrorKind::InvalidArguments));
     }
+    fee_bump::with_fee_bump(&config::ClientConfig::global(), fee, |fee| {
+        let (pre_sel_utxo_req, msg) =
+            build_transfer_to_many_request(account, chain_name, outputs, fee, desc)?;
+        let sess = session::Session::new(chain_name, account, &msg);
+        let mut pre_exe_with_sel_res = sess.pre_exec_with_select_utxo(pre_sel_utxo_req)?;
+        sess.gen_complete_tx_and_post(&mut pre_exe_with_sel_res)
+    })
+}
+
+/// 同transfer，只花费utxos里的utxo，按顺序选到够用为止
+pub fn transfer_from_utxos(
+    account: &wallet::Account,
+    chain_name: &String,
+    to: &String,
+    amount: &String,
+    fee: &String,
+    desc: &String,
+    utxos: &[utxo::UtxoRef],
+) -> Result<String> {
+    let allowed = utxo::fetch(&account.address, utxos)?;
+    transfer_spending(account, chain_name, to, amount, fee, desc, &allowed)
+}
+
+/// 同transfer_from_utxos，utxo由调用方给出，不再向节点查询
+pub fn transfer_spending(
+    account: &wallet::Account,
+    chain_name: &String,
+    to: &String,
+    amount: &String,
+    fee: &String,
+    desc: &String,
+    allowed: &[protos::xchain::Utxo],
+) -> Result<String> {
+    transfer_spending_with_config(
+        &config::ClientConfig::global(),
+        account,
+        chain_name,
+        to,
+        amount,
+        fee,
+        desc,
+        allowed,
+    )
+}
+
+/// 同transfer_spending，使用config
+pub fn transfer_spending_with_config(
+    config: &config::ClientConfig,
+    account: &wallet::Account,
+    chain_name: &String,
+    to: &String,
+    amount: &String,
+    fee: &String,
+    desc: &String,
+    allowed: &[protos::xchain::Utxo],
+) -> Result<String> {
+    let config = config.clone();
+    let (pre_sel_utxo_req, msg) = build_request(
+        &config,
+        account,
+        chain_name,
+        &[(to.to_owned(), amount.to_owned())],
+        fee,
+        desc,
+        false,
+    )?;
+    // 金额可以超出i64，只在allowed里选utxo
+    let total_need = msg.total_need(config.compliance_check.endorse_service_fee())?;
+    let sess = session::Session::with_config(chain_name, account, &msg, config);
+    let mut pre_exe_with_sel_res = sess.pre_exec_with_select_utxo(pre_sel_utxo_req)?;
+    utxo::restrict(&mut pre_exe_with_sel_res, allowed, &total_need)?;
+    sess.gen_complete_tx_and_post(&mut pre_exe_with_sel_res)
+}
+
+/// 只有desc没有收款的交易，输出只有手续费和找零，是上链存证最便宜的方式。
+/// 手续费为0并且不需要合规检查交易(omitZeroFeeTx)时交易没有输入和输出，需要链允许这样的交易
+pub fn post_desc(
+    account: &wallet::Account,
+    chain_name: &String,
+    fee: &String,
+    desc: &String,
+) -> Result<String> {
+    let config = config::ClientConfig::global();
+    let (pre_sel_utxo_req, msg) = build_desc_request(&config, account, chain_name, fee, desc)?;
+    let mut sess = session::Session::with_config(chain_name, account, &msg, config);
+    sess.set_desc_on_chain(true);
+    let mut pre_exe_with_sel_res = sess.pre_exec_with_select_utxo(pre_sel_utxo_req)?;
+    sess.gen_complete_tx_and_post(&mut pre_exe_with_sel_res)
+}
+
+/// post_desc的预执行请求和交易信息，desc不能为空，Message的to为空、amount为0
+pub fn build_desc_request(
+    config: &config::ClientConfig,
+    account: &wallet::Account,
+    chain_name: &String,
+    fee: &String,
+    desc: &String,
+) -> Result<(
+    protos::xchain::PreExecWithSelectUTXORequest,
+    session::Message,
+)> {
+    if desc.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidArguments, "desc is empty"));
+    }
+    build_request(
+        config,
+        account,
+        chain_name,
+        &[(String::new(), String::from("0"))],
+        fee,
+        desc,
+        true,
+    )
+}
+
+/// 不阻塞线程的transfer，可以在tokio等异步运行时里直接await
+#[cfg(feature = "async")]
+pub async fn transfer_async(
+    account: &wallet::Account,
+    chain_name: &String,
+    to: &String,
+    amount: &String,
+    fee: &String,
+    desc: &String,
+) -> Result<String> {
+    let (pre_sel_utxo_req, msg) =
+        build_transfer_request(account, chain_name, to, amount, fee, desc)?;
+    let sess = session::Session::new(chain_name, account, &msg);
+    let mut pre_exe_with_sel_res = sess
+        .pre_exec_with_select_utxo_async(pre_sel_utxo_req)
+        .await?;
+    sess.gen_complete_tx_and_post_async(&mut pre_exe_with_sel_res)
+        .await
+}
+
+/// 生成转账的预执行请求和交易信息
+pub fn build_transfer_request(
+    account: &wallet::Account,
+    chain_name: &String,
+    to: &String,
+    amount: &String,
+    fee: &String,
+    desc: &String,
+) -> Result<(
+    protos::xchain::PreExecWithSelectUTXORequest,
+    session::Message,
+)> {
+    build_transfer_to_many_request(
+        account,
+        chain_name,
+        &[(to.to_owned(), amount.to_owned())],
+        fee,
+        desc,
+    )
+}
+
+/// 同build_transfer_request，按所有收款金额之和选择utxo
+pub fn build_transfer_to_many_request(
+    account: &wallet::Account,
+    chain_name: &String,
+    outputs: &[(String, String)],
+    fee: &String,
+    desc: &String,
+) -> Result<(
+    protos::xchain::PreExecWithSelectUTXORequest,
+    session::Message,
+)> {
+    build_transfer_to_many_request_with_config(
+        &config::ClientConfig::global(),
+        account,
+        chain_name,
+        outputs,
+        fee,
+        desc,
+    )
+}
+
+/// 同build_transfer_to_many_request，背书服务和手续费按config
+pub fn build_transfer_to_many_request_with_config(
+    config: &config::ClientConfig,
+    account: &wallet::Account,
+    chain_name: &String,
+    outputs: &[(String, String)],
+    fee: &String,
+    desc: &String,
+) -> Result<(
+    protos::xchain::PreExecWithSelectUTXORequest,
+    session::Message,
+)> {
+    build_request(config, account, chain_name, outputs, fee, desc, true)
+}
+
+/// select_on_node为true时由背书服务按总额选utxo，请求里的totalAmount是i64，总额超出时返回InvalidArguments；
+/// 为false时totalAmount为0，只预执行，由调用方提供utxo(utxo::restrict)
+/// 配置了nameService时收款方可以是名字，在这里解析成地址(见names模块)
+pub(crate) fn build_request(
+    config: &config::ClientConfig,
+    account: &wallet::Account,
+    chain_name: &String,
+    outputs: &[(String, String)],
+    fee: &String,
+    desc: &String,
+    select_on_node: bool,
+) -> Result<(
+    protos::xchain::PreExecWithSelectUTXORequest,
+    session::Message,
+)> {
+    let outputs = outputs
+        .iter()
+        .map(|(to, amount)| {
+            Ok((
+                names::resolve_to(config, chain_name, to)?,
+                amount.to_owned(),
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let ((to, amount), rest) = outputs
+        .split_first()
+        .ok_or(Error::from(ErrorKind::InvalidArguments))?;
+    let auth_requires = config.compliance_check.endorser_addrs();
+
+    let msg = session::Message {
+        to: to.to_owned(),
+        fee: fee.to_owned(),
+        desc: desc.to_owned(),
+        auth_require: auth_requires.clone(),
+        amount: amount.to_owned(),
+        outputs: rest.to_vec(),
+        frozen_height: 0,
+        initiator: account.address.to_owned(),
+        auth_require_signers: vec![],
+        tx_class: config::TxClass::Transfer,
+        timestamp: None,
+        nonce: None,
+    };
+    // 背书手续费由合规检查交易单独支付，可以大于转账金额，amount为0时只付手续费
+    let total_need = msg.total_need(config.compliance_check.endorse_service_fee())?;
+    let total_amount = if select_on_node {
+        consts::bigint_as_i64(&total_need).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidArguments,
+                format!(
+                    "total amount {} exceeds i64, select utxos with transfer_spending",
+                    total_need
+                ),
+            )
+        })?
+    } else {
+        0
+    };
 
     let mut invoke_rpc_request = protos::xchain::InvokeRPCRequest::new();
     invoke_rpc_request.set_bcname(chain_name.to_owned());
@@ -51,49 +342,26 @@ pub fn transfer(
     pre_sel_utxo_req.set_address(account.address.to_owned());
     pre_sel_utxo_req.set_totalAmount(total_amount);
     pre_sel_utxo_req.set_request(invoke_rpc_request.clone());
-
-    let msg = session::Message {
-        to: to.to_owned(),
-        fee: fee.to_string(),
-        desc: desc.to_owned(),
-        auth_require: auth_requires,
-        amount: amount_bk,
-        frozen_height: 0,
-        initiator: account.address.to_owned(),
-    };
-
-    let sess = session::Session::new(chain_name, account, &msg);
-    let mut pre_exe_with_sel_res = sess.pre_exec_with_select_utxo(pre_sel_utxo_req)?;
-    sess.gen_complete_tx_and_post(&mut pre_exe_with_sel_res)
+    Ok((pre_sel_utxo_req, msg))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::config;
-    use std::path::PathBuf;
-    use xchain_node_sdk::ocall;
+    use crate::{config, consts, testkit, utxo, wallet};
+    use xchain_node_sdk::{errors::*, ocall, protos};
 
     #[test]
     fn test_transfer() {
-        let host = config::CONFIG.read().unwrap().node.clone();
-        let port = config::CONFIG.read().unwrap().endorse_port;
-        let bcname = String::from("xuper");
-        let res = ocall::init(&bcname, &host, port);
-        assert_eq!(res.is_ok(), true);
-
-        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        d.push("key/private.key");
-        let acc = super::wallet::Account::new(
-            d.to_str().unwrap(),
-            Default::default(),
-            "XC1111111111000000@xuper",
-        );
+        let env = testkit::TestEnv::from_env().unwrap();
+        let acc = env
+            .faucet(Default::default(), "XC1111111111000000@xuper")
+            .unwrap();
         let to = "dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN".to_string();
         let amount = "1401".to_string();
         let fee = "0".to_string();
         let desc = "test duanbing".to_string();
 
-        let res = super::transfer(&acc, &bcname, &to, &amount, &fee, &desc);
+        let res = super::transfer(&acc, &env.chain_name, &to, &amount, &fee, &desc);
         println!("transfer res: {:?}", res);
         assert_eq!(res.is_ok(), true);
         let txid = res.unwrap();
@@ -105,4 +373,213 @@ mod tests {
 
         ocall::close();
     }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_transfer_async() {
+        let env = testkit::TestEnv::from_env().unwrap();
+        let acc = env
+            .faucet(Default::default(), "XC1111111111000000@xuper")
+            .unwrap();
+        let to = "dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN".to_string();
+        let amount = "1401".to_string();
+        let fee = "0".to_string();
+        let desc = "test duanbing".to_string();
+
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let res = rt.block_on(super::transfer_async(
+            &acc,
+            &env.chain_name,
+            &to,
+            &amount,
+            &fee,
+            &desc,
+        ));
+        assert_eq!(res.is_ok(), true);
+        let res = ocall::ocall_xchain_query_tx(&res.unwrap());
+        assert_eq!(res.is_ok(), true);
+    }
+
+    #[test]
+    fn test_build_desc_request() {
+        let acc: wallet::Account = Default::default();
+        let chain_name = String::from("xuper");
+        let config = config::ClientConfig::global();
+        let endorser_fee = config.compliance_check.compliance_check_endorse_service_fee as i64;
+
+        let (req, msg) = super::build_desc_request(
+            &config,
+            &acc,
+            &chain_name,
+            &String::from("2"),
+            &String::from("anchor"),
+        )
+        .unwrap();
+        assert_eq!(req.totalAmount, 2 + endorser_fee);
+        assert_eq!(msg.payments().is_empty(), true);
+        assert_eq!(msg.desc, "anchor");
+
+        let res = super::build_desc_request(
+            &config,
+            &acc,
+            &chain_name,
+            &String::from("2"),
+            &String::new(),
+        );
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::InvalidArguments);
+    }
+
+    #[test]
+    fn test_build_transfer_request() {
+        let acc: wallet::Account = Default::default();
+        let chain_name = String::from("xuper");
+        let to = String::from("bob");
+        let endorser_fee = config::CONFIG
+            .read()
+            .unwrap()
+            .compliance_check
+            .compliance_check_endorse_service_fee as i64;
+
+        // 金额小于背书手续费
+        let (req, msg) = super::build_transfer_request(
+            &acc,
+            &chain_name,
+            &to,
+            &String::from("1"),
+            &String::from("2"),
+            &String::new(),
+        )
+        .unwrap();
+        assert_eq!(req.totalAmount, 3 + endorser_fee);
+        assert_eq!(msg.amount, "1");
+
+        // 只付手续费
+        let (req, _) = super::build_transfer_request(
+            &acc,
+            &chain_name,
+            &to,
+            &String::from("0"),
+            &String::from("0"),
+            &String::new(),
+        )
+        .unwrap();
+        assert_eq!(req.totalAmount, endorser_fee);
+
+        // 溢出
+        let res = super::build_transfer_request(
+            &acc,
+            &chain_name,
+            &to,
+            &i64::MAX.to_string(),
+            &String::from("1"),
+            &String::new(),
+        );
+        assert_eq!(res.is_ok(), false);
+
+        // 负数
+        let res = super::build_transfer_request(
+            &acc,
+            &chain_name,
+            &to,
+            &String::from("10"),
+            &String::from("-1"),
+            &String::new(),
+        );
+        assert_eq!(res.is_ok(), false);
+    }
+
+    #[test]
+    fn test_build_request_beyond_i64() {
+        let acc: wallet::Account = Default::default();
+        let chain_name = String::from("xuper");
+        let config = config::ClientConfig::global();
+        let endorser_fee = config.compliance_check.compliance_check_endorse_service_fee;
+        let amount = String::from("100000000000000000000000000");
+        let outputs = vec![(String::from("bob"), amount.to_owned())];
+
+        // 背书服务不能按超出i64的总额选utxo
+        let res = super::build_request(
+            &config,
+            &acc,
+            &chain_name,
+            &outputs,
+            &String::from("1"),
+            &String::new(),
+            true,
+        );
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::InvalidArguments);
+
+        let (req, msg) = super::build_request(
+            &config,
+            &acc,
+            &chain_name,
+            &outputs,
+            &String::from("1"),
+            &String::new(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(req.totalAmount, 0);
+        let total_need = msg.total_need(endorser_fee).unwrap();
+        assert_eq!(
+            total_need,
+            consts::str_as_bigint(&amount).unwrap() + num_bigint::BigInt::from(1 + endorser_fee)
+        );
+
+        // 调用方提供的utxo按BigInt选
+        let mut big = protos::xchain::Utxo::new();
+        big.set_amount((&total_need + num_bigint::BigInt::from(5)).to_bytes_be().1);
+        big.set_refTxid(vec![0xaa]);
+        let out = utxo::select(&[big], &total_need).unwrap();
+        assert_eq!(
+            out.totalSelected,
+            (total_need + num_bigint::BigInt::from(5)).to_string()
+        );
+    }
+
+    #[test]
+    fn test_build_transfer_to_many_request() {
+        let acc: wallet::Account = Default::default();
+        let chain_name = String::from("xuper");
+        let endorser_fee = config::CONFIG
+            .read()
+            .unwrap()
+            .compliance_check
+            .compliance_check_endorse_service_fee as i64;
+        let outputs = vec![
+            (String::from("bob"), String::from("3")),
+            (String::from("carol"), String::from("4")),
+        ];
+        let (req, msg) = super::build_transfer_to_many_request(
+            &acc,
+            &chain_name,
+            &outputs,
+            &String::from("1"),
+            &String::new(),
+        )
+        .unwrap();
+        assert_eq!(req.totalAmount, 8 + endorser_fee);
+        assert_eq!(msg.to, "bob");
+        assert_eq!(msg.outputs, vec![outputs[1].clone()]);
+        assert_eq!(msg.payments(), vec![("bob", "3"), ("carol", "4")]);
+
+        let res = super::build_transfer_to_many_request(
+            &acc,
+            &chain_name,
+            &[],
+            &String::from("1"),
+            &String::new(),
+        );
+        assert_eq!(res.is_ok(), false);
+
+        let empty = String::new();
+        let res = super::transfer_to_many(
+            &acc,
+            &chain_name,
+            &[(empty.clone(), String::from("1"))],
+            &empty,
+            &empty,
+        );
+        assert_eq!(res.is_ok(), false);
+    }
 }
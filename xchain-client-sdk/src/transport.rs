@@ -0,0 +1,54 @@
+use crate::config;
+use xchain_node_sdk::{errors::*, ocall};
+
+// SDK所有的网络请求都经过ocall::ocall_xchain_*，由ocall里设置的NodeClient发送。
+// 在SGX里运行时由enclave外部初始化ocall；不在SGX里运行时调用connect，
+// 按配置里的transport直接用gRPC连接节点和背书服务，也可以用ocall::init_with_client设置其他实现。
+
+/// 按全局CONFIG当前的配置连接节点，已经连接过时不重复连接
+/// transport为ocall时只检查外部是否已经初始化，没有初始化时返回TransportError
+pub fn connect(chain_name: &String) -> Result<()> {
+    connect_with_config(&config::ClientConfig::global(), chain_name)
+}
+
+/// 同connect，使用config
+pub fn connect_with_config(config: &config::ClientConfig, chain_name: &String) -> Result<()> {
+    match config.transport {
+        config::Transport::Ocall => {}
+        config::Transport::Grpc => ocall::init_with_endorser(
+            chain_name,
+            &config.node,
+            config.node_port,
+            &config.node,
+            config.endorse_port,
+        )?,
+    }
+    if config.transport == config::Transport::Grpc {
+        if let Some(ref fallback) = config.fallback_node {
+            ocall::init_fallback_node(chain_name, &fallback.host, fallback.port);
+        }
+    }
+    if !ocall::initialized() {
+        return Err(Error::from(ErrorKind::TransportError));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transport_config() {
+        assert_eq!(
+            config::CONFIG.read().unwrap().transport,
+            config::Transport::Ocall
+        );
+        let t: config::Transport = serde_yaml::from_str("grpc").unwrap();
+        assert_eq!(t, config::Transport::Grpc);
+        assert_eq!(
+            serde_yaml::from_str::<config::Transport>("tcp").is_ok(),
+            false
+        );
+    }
+}
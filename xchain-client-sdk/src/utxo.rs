@@ -0,0 +1,128 @@
+use num_bigint::BigInt;
+
+use xchain_node_sdk::{errors::*, ocall, protos::xchain};
+
+// 限制交易只花费调用方指定的utxo(例如某个入金交易的输出)，用于分账户记账。
+// 预执行仍然由背书服务选utxo，用于计算手续费，之后用白名单里的utxo替换选中的utxo，
+// 按白名单的顺序选到够用为止。白名单里的utxo由调用方保证没有被花费，否则提交交易时节点会拒绝。
+
+/// 交易txid(hex编码)的第offset个输出
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UtxoRef {
+    pub txid: String,
+    pub offset: i32,
+}
+
+/// tx的第offset个输出，必须是转给address的
+pub fn utxo_from_tx(tx: &xchain::Transaction, offset: i32, address: &str) -> Result<xchain::Utxo> {
+    let output = tx
+        .get_tx_outputs()
+        .get(offset as usize)
+        .filter(|_| offset >= 0)
+        .ok_or(Error::from(ErrorKind::InvalidArguments))?;
+    if output.get_to_addr() != address.as_bytes() {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let mut utxo = xchain::Utxo::new();
+    utxo.set_amount(output.amount.clone());
+    utxo.set_toAddr(output.to_addr.clone());
+    utxo.set_refTxid(tx.txid.clone());
+    utxo.set_refOffset(offset);
+    Ok(utxo)
+}
+
+/// 从节点查询白名单里的utxo
+pub fn fetch(address: &str, refs: &[UtxoRef]) -> Result<Vec<xchain::Utxo>> {
+    refs.iter()
+        .map(|r| {
+            let status = ocall::ocall_xchain_query_tx(&r.txid)?;
+            utxo_from_tx(status.get_tx(), r.offset, address)
+        })
+        .collect()
+}
+
+/// 按顺序选出总额不小于total_need的utxo，不够时返回InvalidArguments
+pub fn select(utxos: &[xchain::Utxo], total_need: &BigInt) -> Result<xchain::UtxoOutput> {
+    let mut selected: Vec<xchain::Utxo> = vec![];
+    let mut total: BigInt = Default::default();
+    for utxo in utxos.iter() {
+        if &total >= total_need {
+            break;
+        }
+        if selected
+            .iter()
+            .any(|s| s.refTxid == utxo.refTxid && s.refOffset == utxo.refOffset)
+        {
+            return Err(Error::from(ErrorKind::InvalidArguments));
+        }
+        total += BigInt::from_bytes_be(num_bigint::Sign::Plus, &utxo.amount);
+        selected.push(utxo.clone());
+    }
+    if &total < total_need {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let mut output = xchain::UtxoOutput::new();
+    output.set_utxoList(protobuf::RepeatedField::from_vec(selected));
+    output.set_totalSelected(total.to_str_radix(10));
+    Ok(output)
+}
+
+/// 用白名单里的utxo替换预执行选中的utxo
+pub fn restrict(
+    resp: &mut xchain::PreExecWithSelectUTXOResponse,
+    utxos: &[xchain::Utxo],
+    total_need: &BigInt,
+) -> Result<()> {
+    resp.set_utxoOutput(select(utxos, total_need)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn funding_tx() -> xchain::Transaction {
+        let mut tx = xchain::Transaction::new();
+        tx.set_txid(vec![0xaa]);
+        for (to, amount) in [("alice", 100u8), ("bob", 50), ("alice", 30)].iter() {
+            let mut o = xchain::TxOutput::new();
+            o.set_to_addr(to.as_bytes().to_vec());
+            o.set_amount(vec![*amount]);
+            tx.mut_tx_outputs().push(o);
+        }
+        tx
+    }
+
+    #[test]
+    fn test_select_whitelisted() {
+        let tx = funding_tx();
+        let a = utxo_from_tx(&tx, 0, "alice").unwrap();
+        let b = utxo_from_tx(&tx, 2, "alice").unwrap();
+        assert_eq!(a.refOffset, 0);
+        assert_eq!(a.refTxid, vec![0xaa]);
+        // 不是转给alice的输出
+        assert_eq!(utxo_from_tx(&tx, 1, "alice").is_ok(), false);
+        assert_eq!(utxo_from_tx(&tx, 3, "alice").is_ok(), false);
+        assert_eq!(utxo_from_tx(&tx, -1, "alice").is_ok(), false);
+
+        let out = select(&[a.clone(), b.clone()], &BigInt::from(80)).unwrap();
+        assert_eq!(out.get_utxoList().len(), 1);
+        assert_eq!(out.totalSelected, "100");
+        let out = select(&[b.clone(), a.clone()], &BigInt::from(80)).unwrap();
+        assert_eq!(out.get_utxoList().len(), 2);
+        assert_eq!(out.totalSelected, "130");
+        assert_eq!(
+            select(&[a.clone(), b.clone()], &BigInt::from(131)).is_ok(),
+            false
+        );
+        assert_eq!(
+            select(&[b.clone(), b.clone()], &BigInt::from(50)).is_ok(),
+            false
+        );
+
+        let mut resp = xchain::PreExecWithSelectUTXOResponse::new();
+        restrict(&mut resp, &[a], &BigInt::from(10)).unwrap();
+        assert_eq!(resp.get_utxoOutput().get_utxoList()[0].refOffset, 0);
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use num_bigint::BigInt;
+
+use crate::{config, consolidate, session, transfer, wallet};
+use xchain_node_sdk::{errors::*, ocall, protos::xchain};
+
+// 连续转账时每次都要由背书服务预执行选utxo，多一次往返。
+// UtxoCache在本地记录账户的utxo，转账时从缓存里选，不再预执行(转账没有合约调用)。
+// 选出的utxo在交易提交之前处于预留状态，并发的转账不会选到同一个utxo；
+// 提交成功之后花掉的utxo记为已花费，交易里找零给自己的输出加入缓存。
+// 缓存每隔refresh_interval用节点的utxo记录(query_utxo_record)重新填充，
+// 节点还没有确认的已花费utxo在重新填充时仍然排除，直到节点不再返回它们。
+// 缓存只适合一个账户只在一个进程里转账的场景，别的地方花掉的utxo要等重新填充之后才会发现。
+
+type UtxoKey = (Vec<u8>, i32);
+
+fn key(utxo: &xchain::Utxo) -> UtxoKey {
+    (utxo.refTxid.clone(), utxo.refOffset)
+}
+
+fn amount(utxo: &xchain::Utxo) -> BigInt {
+    BigInt::from_bytes_be(num_bigint::Sign::Plus, &utxo.amount)
+}
+
+#[derive(Default)]
+struct State {
+    unspent: BTreeMap<UtxoKey, xchain::Utxo>,
+    reserved: HashSet<UtxoKey>,
+    /// 已经提交的交易花掉的utxo
+    spent: HashSet<UtxoKey>,
+    refreshed_at: Option<Instant>,
+}
+
+/// 缓存的utxo数量
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    /// 包括预留的
+    pub unspent: usize,
+    pub reserved: usize,
+}
+
+/// 为一笔交易预留的utxo，用UtxoCache::commit或者release结束
+#[derive(Debug)]
+pub struct Reservation {
+    utxos: Vec<xchain::Utxo>,
+}
+
+impl Reservation {
+    pub fn total(&self) -> BigInt {
+        self.utxos.iter().map(amount).sum()
+    }
+
+    /// 当作预执行选出的utxo
+    pub fn utxo_output(&self) -> xchain::UtxoOutput {
+        let mut output = xchain::UtxoOutput::new();
+        output.set_utxoList(protobuf::RepeatedField::from_vec(self.utxos.clone()));
+        output.set_totalSelected(self.total().to_str_radix(10));
+        output
+    }
+}
+
+pub struct UtxoCache {
+    address: String,
+    /// 重新填充时最多查询的utxo个数
+    display_count: i64,
+    refresh_interval: Duration,
+    state: Mutex<State>,
+}
+
+impl UtxoCache {
+    pub fn new(address: &str, display_count: i64, refresh_interval: Duration) -> Self {
+        UtxoCache {
+            address: address.to_owned(),
+            display_count: display_count,
+            refresh_interval: refresh_interval,
+            state: Default::default(),
+        }
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// 从来没有填充过或者距离上次填充超过refresh_interval
+    pub fn needs_refresh(&self) -> bool {
+        match self.state.lock().unwrap().refreshed_at {
+            Some(t) => t.elapsed() >= self.refresh_interval,
+            None => true,
+        }
+    }
+
+    /// 查询节点上的utxo重新填充
+    pub fn refresh(&self) -> Result<()> {
+        let record = ocall::ocall_xchain_query_utxo_record(&self.address, self.display_count)?;
+        let utxos = record
+            .get_openUtxoRecord()
+            .get_item()
+            .iter()
+            .map(|k| consolidate::utxo_from_key(k, &self.address))
+            .collect::<Result<Vec<_>>>()?;
+        self.refill(utxos);
+        Ok(())
+    }
+
+    /// 用节点返回的utxo替换缓存，预留的utxo保持预留，已花费的utxo不加入
+    pub fn refill(&self, utxos: Vec<xchain::Utxo>) {
+        let mut state = self.state.lock().unwrap();
+        let fresh: HashSet<UtxoKey> = utxos.iter().map(key).collect();
+        // 节点不再返回的已花费utxo已经确认，不需要再记录
+        state.spent.retain(|k| fresh.contains(k));
+        let mut unspent = BTreeMap::new();
+        for utxo in utxos.into_iter() {
+            if utxo.toAddr == self.address.as_bytes() && !state.spent.contains(&key(&utxo)) {
+                unspent.insert(key(&utxo), utxo);
+            }
+        }
+        state.unspent = unspent;
+        state.refreshed_at = Some(Instant::now());
+    }
+
+    /// 从没有预留的utxo里按金额从大到小选出总额不小于total_need的utxo并预留，不够时返回None
+    pub fn reserve(&self, total_need: &BigInt) -> Option<Reservation> {
+        let mut state = self.state.lock().unwrap();
+        let mut candidates: Vec<&xchain::Utxo> = state
+            .unspent
+            .iter()
+            .filter(|(k, _)| !state.reserved.contains(*k))
+            .map(|(_, u)| u)
+            .collect();
+        candidates.sort_by(|a, b| amount(b).cmp(&amount(a)));
+        let mut utxos = vec![];
+        let mut total = BigInt::from(0);
+        for utxo in candidates.into_iter() {
+            if &total >= total_need && !utxos.is_empty() {
+                break;
+            }
+            total += amount(utxo);
+            utxos.push(utxo.clone());
+        }
+        if &total < total_need || utxos.is_empty() {
+            return None;
+        }
+        for utxo in utxos.iter() {
+            state.reserved.insert(key(utxo));
+        }
+        Some(Reservation { utxos: utxos })
+    }
+
+    /// 交易没有提交成功。背书服务可能已经提交了花费这些utxo的合规检查交易，
+    /// 所以不放回缓存，下次使用之前重新填充
+    pub fn release(&self, reservation: Reservation) {
+        let mut state = self.state.lock().unwrap();
+        for utxo in reservation.utxos.iter() {
+            state.reserved.remove(&key(utxo));
+            state.unspent.remove(&key(utxo));
+        }
+        state.refreshed_at = None;
+    }
+
+    /// 交易tx已经提交，预留的utxo记为已花费，tx里转给自己并且没有冻结的输出加入缓存
+    pub fn commit(&self, reservation: Reservation, tx: &xchain::Transaction) {
+        let mut state = self.state.lock().unwrap();
+        for utxo in reservation.utxos.iter() {
+            state.reserved.remove(&key(utxo));
+            state.unspent.remove(&key(utxo));
+            state.spent.insert(key(utxo));
+        }
+        for input in tx.get_tx_inputs().iter() {
+            state
+                .unspent
+                .remove(&(input.ref_txid.clone(), input.ref_offset));
+        }
+        for (offset, output) in tx.get_tx_outputs().iter().enumerate() {
+            if output.to_addr != self.address.as_bytes() || output.frozen_height > 0 {
+                continue;
+            }
+            let mut utxo = xchain::Utxo::new();
+            utxo.set_amount(output.amount.clone());
+            utxo.set_toAddr(output.to_addr.clone());
+            utxo.set_refTxid(tx.txid.clone());
+            utxo.set_refOffset(offset as i32);
+            state.unspent.insert(key(&utxo), utxo);
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let state = self.state.lock().unwrap();
+        CacheStats {
+            unspent: state.unspent.len(),
+            reserved: state.reserved.len(),
+        }
+    }
+}
+
+/// 同transfer::transfer，从cache里选utxo，不预执行，缓存不够时重新填充一次
+pub fn transfer_cached(
+    cache: &UtxoCache,
+    account: &wallet::Account,
+    chain_name: &String,
+    to: &String,
+    amount: &String,
+    fee: &String,
+    desc: &String,
+) -> Result<String> {
+    if cache.address() != account.address {
+        return Err(Error::from(ErrorKind::InvalidArguments));
+    }
+    let config = config::ClientConfig::global();
+    let (_, msg) = transfer::build_request(
+        &config,
+        account,
+        chain_name,
+        &[(to.to_owned(), amount.to_owned())],
+        fee,
+        desc,
+        false,
+    )?;
+    let total_need = msg.total_need(config.compliance_check.endorse_service_fee())?;
+
+    if cache.needs_refresh() {
+        cache.refresh()?;
+    }
+    let reservation = match cache.reserve(&total_need) {
+        Some(r) => r,
+        None => {
+            cache.refresh()?;
+            cache.reserve(&total_need).ok_or(Error::new(
+                ErrorKind::InvalidArguments,
+                format!("cached utxos are less than {}", total_need),
+            ))?
+        }
+    };
+
+    let sess = session::Session::with_config(chain_name, account, &msg, config);
+    let res = sess
+        .select_utxo_manually(reservation.utxo_output())
+        .and_then(|mut resp| sess.gen_complete_tx(&mut resp, None))
+        .and_then(|tx| sess.post_complete_tx(&tx).map(|txid| (tx, txid)));
+    match res {
+        Ok((tx, txid)) => {
+            cache.commit(reservation, &tx);
+            Ok(txid)
+        }
+        Err(e) => {
+            cache.release(reservation);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utxo(amount: u8, ref_txid: u8) -> xchain::Utxo {
+        let mut u = xchain::Utxo::new();
+        u.set_toAddr(b"alice".to_vec());
+        u.set_amount(vec![amount]);
+        u.set_refTxid(vec![ref_txid]);
+        u
+    }
+
+    fn cache() -> UtxoCache {
+        let c = UtxoCache::new("alice", 100, Duration::from_secs(60));
+        assert_eq!(c.needs_refresh(), true);
+        c.refill(vec![utxo(5, 0xaa), utxo(3, 0xbb), utxo(8, 0xcc)]);
+        assert_eq!(c.needs_refresh(), false);
+        c
+    }
+
+    #[test]
+    fn test_reserve() {
+        let c = cache();
+        // 从大到小选
+        let r1 = c.reserve(&BigInt::from(10)).unwrap();
+        assert_eq!(r1.total(), BigInt::from(13));
+        assert_eq!(r1.utxo_output().totalSelected, "13");
+        // 预留的utxo不会被再次选中
+        assert_eq!(c.reserve(&BigInt::from(4)).is_none(), true);
+        let r2 = c.reserve(&BigInt::from(3)).unwrap();
+        assert_eq!(r2.utxos[0].refTxid, vec![0xbb]);
+        assert_eq!(
+            c.stats(),
+            CacheStats {
+                unspent: 3,
+                reserved: 3
+            }
+        );
+
+        // 失败之后不放回，等重新填充
+        c.release(r2);
+        assert_eq!(c.needs_refresh(), true);
+        assert_eq!(c.reserve(&BigInt::from(1)).is_none(), true);
+    }
+
+    #[test]
+    fn test_commit() {
+        let c = cache();
+        let r = c.reserve(&BigInt::from(6)).unwrap();
+        let mut tx = xchain::Transaction::new();
+        tx.set_txid(vec![0xdd]);
+        for (to, amount, frozen_height) in
+            [("bob", 6u8, 0), ("alice", 2, 0), ("alice", 1, 10)].iter()
+        {
+            let mut o = xchain::TxOutput::new();
+            o.set_to_addr(to.as_bytes().to_vec());
+            o.set_amount(vec![*amount]);
+            o.set_frozen_height(*frozen_height);
+            tx.mut_tx_outputs().push(o);
+        }
+        c.commit(r, &tx);
+        // 花掉8，找零2加入缓存，冻结的1不加入
+        assert_eq!(
+            c.stats(),
+            CacheStats {
+                unspent: 3,
+                reserved: 0
+            }
+        );
+        let r = c.reserve(&BigInt::from(10)).unwrap();
+        assert_eq!(r.total(), BigInt::from(10));
+        assert_eq!(r.utxos.iter().any(|u| u.refTxid == vec![0xdd]), true);
+        c.release(r);
+
+        // 节点还没有确认时不会重新加入已花费的utxo
+        c.refill(vec![utxo(5, 0xaa), utxo(3, 0xbb), utxo(8, 0xcc)]);
+        assert_eq!(c.stats().unspent, 2);
+        // 节点确认之后不再记录
+        c.refill(vec![utxo(5, 0xaa)]);
+        c.refill(vec![utxo(5, 0xaa), utxo(8, 0xcc)]);
+        assert_eq!(c.stats().unspent, 2);
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use num_bigint::BigInt;
+
+use crate::consts;
+use xchain_node_sdk::{errors::*, ocall, protos::xchain};
+
+// 账户utxo的数量、总额、年龄分布和金额分布，用于判断是否需要合并(见consolidate)和容量规划。
+// 数量和总额是节点统计的全部未冻结utxo；分布只统计节点返回的前display_count个utxo，
+// 年龄按产生utxo的交易的时间戳计算，每个交易查询一次，查不到的交易不计入年龄分布。
+
+/// 年龄分布的区间上界(秒): 1小时、1天、1周、30天，最后一个区间没有上界
+pub const AGE_BUCKETS: [i64; 4] = [3600, 86400, 7 * 86400, 30 * 86400];
+
+/// utxo_stats默认统计分布的utxo个数
+pub const DEFAULT_SAMPLE: i64 = 100;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UtxoStats {
+    /// 节点统计的utxo个数和总额
+    pub count: i64,
+    pub total: BigInt,
+    /// 统计了分布的utxo个数
+    pub sampled: usize,
+    /// 第i个是年龄在[AGE_BUCKETS[i-1], AGE_BUCKETS[i])秒之间的个数，长度是AGE_BUCKETS.len() + 1
+    pub age_histogram: Vec<usize>,
+    /// 第i个是金额有i+1位十进制数字的个数
+    pub size_histogram: Vec<usize>,
+    /// 最老的utxo的年龄(秒)
+    pub oldest_secs: Option<i64>,
+}
+
+fn age_bucket(age_secs: i64) -> usize {
+    AGE_BUCKETS
+        .iter()
+        .position(|b| age_secs < *b)
+        .unwrap_or(AGE_BUCKETS.len())
+}
+
+/// 从utxo记录计算统计，timestamps是hex编码的txid到交易时间戳(纳秒)，now_secs是当前时间(秒)
+pub fn stats_from_record(
+    record: &xchain::UtxoRecordDetail,
+    timestamps: &HashMap<String, i64>,
+    now_secs: i64,
+) -> Result<UtxoStats> {
+    let open = record.get_openUtxoRecord();
+    let mut stats = UtxoStats {
+        count: consts::str_as_i64(&open.utxoCount)?,
+        total: consts::str_as_bigint(&open.utxoAmount)?,
+        age_histogram: vec![0; AGE_BUCKETS.len() + 1],
+        ..Default::default()
+    };
+    for key in open.get_item().iter() {
+        let digits = consts::str_as_bigint(&key.amount)?.to_str_radix(10).len();
+        if stats.size_histogram.len() < digits {
+            stats.size_histogram.resize(digits, 0);
+        }
+        stats.size_histogram[digits - 1] += 1;
+        stats.sampled += 1;
+
+        if let Some(ts) = timestamps.get(&key.refTxid) {
+            let age = std::cmp::max(now_secs - ts / 1_000_000_000, 0);
+            stats.age_histogram[age_bucket(age)] += 1;
+            stats.oldest_secs = Some(std::cmp::max(stats.oldest_secs.unwrap_or(0), age));
+        }
+    }
+    Ok(stats)
+}
+
+/// address的utxo统计，分布统计前DEFAULT_SAMPLE个utxo
+pub fn utxo_stats(address: &str) -> Result<UtxoStats> {
+    utxo_stats_sampled(address, DEFAULT_SAMPLE)
+}
+
+/// 同utxo_stats，分布统计前display_count个utxo，每个不同的交易查询一次节点
+pub fn utxo_stats_sampled(address: &str, display_count: i64) -> Result<UtxoStats> {
+    let record = ocall::ocall_xchain_query_utxo_record(&address.to_owned(), display_count)?;
+    let mut timestamps = HashMap::new();
+    for key in record.get_openUtxoRecord().get_item().iter() {
+        if timestamps.contains_key(&key.refTxid) {
+            continue;
+        }
+        let status = ocall::ocall_xchain_query_tx(&key.refTxid)?;
+        if status.has_tx() {
+            timestamps.insert(key.refTxid.to_owned(), status.get_tx().timestamp);
+        }
+    }
+    stats_from_record(&record, &timestamps, consts::now_as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(txid: &str, amount: &str) -> xchain::UtxoKey {
+        let mut k = xchain::UtxoKey::new();
+        k.set_refTxid(txid.to_owned());
+        k.set_offset(String::from("0"));
+        k.set_amount(amount.to_owned());
+        k
+    }
+
+    #[test]
+    fn test_stats_from_record() {
+        let mut open = xchain::UtxoRecord::new();
+        open.set_utxoCount(String::from("10"));
+        open.set_utxoAmount(String::from("100000000000000000000"));
+        for k in [("aa", "5"), ("bb", "12"), ("aa", "99"), ("cc", "1000")].iter() {
+            open.mut_item().push(key(k.0, k.1));
+        }
+        let mut record = xchain::UtxoRecordDetail::new();
+        record.set_openUtxoRecord(open);
+
+        let now = 100 * 86400;
+        let mut timestamps = HashMap::new();
+        timestamps.insert(String::from("aa"), (now - 60) * 1_000_000_000);
+        timestamps.insert(String::from("bb"), (now - 40 * 86400) * 1_000_000_000);
+        // cc查不到交易
+
+        let stats = stats_from_record(&record, &timestamps, now).unwrap();
+        assert_eq!(stats.count, 10);
+        assert_eq!(
+            stats.total,
+            consts::str_as_bigint("100000000000000000000").unwrap()
+        );
+        assert_eq!(stats.sampled, 4);
+        assert_eq!(stats.age_histogram, vec![2, 0, 0, 0, 1]);
+        assert_eq!(stats.size_histogram, vec![1, 2, 0, 1]);
+        assert_eq!(stats.oldest_secs, Some(40 * 86400));
+
+        assert_eq!(age_bucket(0), 0);
+        assert_eq!(age_bucket(3600), 1);
+        assert_eq!(age_bucket(30 * 86400), 4);
+    }
+}
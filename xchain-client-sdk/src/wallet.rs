@@ -1,5 +1,10 @@
 use rand::rngs::StdRng;
 use rand_core::{RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+/// 助记词的强度，HARD是24个词
+pub use xchain_crypto::hdwallet::rand::KeyStrength;
+/// 助记词的语言
+pub use xchain_crypto::hdwallet::Language;
 use xchain_crypto::sign::ecdsa::KeyPair;
 
 /// 保管私钥，提供签名和验签
@@ -7,13 +12,75 @@ use xchain_crypto::sign::ecdsa::KeyPair;
 /// 唯一可以调用xchain_crypto的地方
 use xchain_node_sdk::errors::*;
 
+/// 发给交易对手的公开身份信息，对手方校验之后可以固定(pin)我们的签名身份
+/// 使用固定字段顺序的紧凑json作为规范格式
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct PublicBundle {
+    pub address: String,
+    pub public_key: String,
+    /// 远程认证的quote，hex编码
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote: Option<String>,
+    /// 私钥对以上内容的签名，hex编码，证明持有私钥
+    pub sign: String,
+}
+
+impl PublicBundle {
+    fn to_sign_bytes(&self) -> Vec<u8> {
+        format!(
+            "{}\n{}\n{}",
+            self.address,
+            self.public_key,
+            self.quote.as_ref().map(|q| q.as_str()).unwrap_or("")
+        )
+        .into_bytes()
+    }
+}
+
+/// 校验公开身份信息: 签名正确，并且地址和公钥匹配
+pub fn verify_bundle(bundle: &str) -> Result<PublicBundle> {
+    let b: PublicBundle = serde_json::from_str(bundle)?;
+    let sig = hex::decode(&b.sign)?;
+    let address = verify_with_public_key(&b.public_key, &b.to_sign_bytes(), &sig)?;
+    if address != b.address {
+        return Err(Error::from(ErrorKind::CryptoError));
+    }
+    Ok(b)
+}
+
+/// 私钥不在本地文件里的签名者，例如HSM或者远程签名服务
+/// 不支持门限(2-of-2)ECDSA: 没有经过审计的P-256两方签名实现，SDK不自己实现这类协议
+/// 签名格式和Account::sign一样: 对msg做SHA256之后的ASN.1 DER编码的ECDSA签名
+pub trait Signer: Send + Sync {
+    /// json格式的公钥
+    fn public_key(&self) -> Result<String>;
+    fn sign(&self, msg: &[u8]) -> Result<Vec<u8>>;
+}
+
 /// 加载钱包地址或者加载enclave
-#[derive(Default, Debug)]
+#[derive(Default, Clone)]
 pub struct Account {
     pub contract_name: String,
     pub contract_account: String,
     pub address: String,
     pub path: String,
+    /// 设置之后签名由signer完成，不再读取path
+    pub signer: Option<std::sync::Arc<dyn Signer>>,
+    /// json格式的公钥，构造时从私钥推导并缓存，为None时每次从私钥推导
+    pub cached_public_key: Option<String>,
+}
+
+impl std::fmt::Debug for Account {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Account")
+            .field("contract_name", &self.contract_name)
+            .field("contract_account", &self.contract_account)
+            .field("address", &self.address)
+            .field("path", &self.path)
+            .field("signer", &self.signer.is_some())
+            .field("cached_public_key", &self.cached_public_key)
+            .finish()
+    }
 }
 
 impl Account {
@@ -24,20 +91,128 @@ impl Account {
         let pk = xchain_crypto::account::PublicKey::new(alg, p.public_key());
         let address =
             xchain_crypto::account::address::get_address_from_public_key(&pk).expect("load key");
+        let public_key =
+            xchain_crypto::account::json_key::get_ecdsa_public_key_json_format_in_go(&p)
+                .expect("load key");
         Account {
             address: address,
             path: path.to_string(),
             contract_account: contract_account.to_string(),
             contract_name: contract_name.to_string(),
+            signer: None,
+            cached_public_key: Some(public_key),
+        }
+    }
+
+    /// 使用signer签名的账户
+    pub fn with_signer(
+        signer: std::sync::Arc<dyn Signer>,
+        contract_name: &str,
+        contract_account: &str,
+    ) -> Result<Self> {
+        Ok(Account {
+            address: address_from_public_key(&signer.public_key()?)?,
+            path: String::new(),
+            contract_account: contract_account.to_string(),
+            contract_name: contract_name.to_string(),
+            signer: Some(signer),
+            cached_public_key: None,
+        })
+    }
+
+    /// 加载Go SDK格式的秘钥目录(address, public.key, private.key)，并校验三者一致
+    pub fn from_key_dir(dir: &str, contract_name: &str, contract_account: &str) -> Result<Self> {
+        let path: std::path::PathBuf = [dir, "private.key"].iter().collect();
+        let path = path
+            .to_str()
+            .ok_or(Error::from(ErrorKind::InvalidArguments))?;
+        let p = xchain_crypto::account::get_ecdsa_private_key_from_file(path)?;
+        let alg = &xchain_crypto::sign::ecdsa::ECDSA_P256_SHA256_ASN1;
+        let pk = xchain_crypto::account::PublicKey::new(alg, p.public_key());
+        let address = xchain_crypto::account::address::get_address_from_public_key(&pk)?;
+
+        let address_path: std::path::PathBuf = [dir, "address"].iter().collect();
+        let expected_address = std::fs::read_to_string(address_path)?;
+        if expected_address.trim() != address {
+            return Err(Error::from(ErrorKind::InvalidArguments));
+        }
+
+        let public_key_path: std::path::PathBuf = [dir, "public.key"].iter().collect();
+        let public_key = xchain_crypto::account::json_key::get_ecdsa_public_key_from_file(
+            public_key_path
+                .to_str()
+                .ok_or(Error::from(ErrorKind::InvalidArguments))?,
+        )?;
+        if public_key.as_slice() != pk.as_ref() {
+            return Err(Error::from(ErrorKind::InvalidArguments));
+        }
+
+        Ok(Account {
+            address: address,
+            path: path.to_string(),
+            contract_account: contract_account.to_string(),
+            contract_name: contract_name.to_string(),
+            signer: None,
+            cached_public_key: Some(
+                xchain_crypto::account::json_key::get_ecdsa_public_key_json_format_in_go(&p)?,
+            ),
+        })
+    }
+
+    /// 在dir下面生成新的Go SDK格式的秘钥目录(mnemonic, address, public.key, private.key)
+    pub fn generate(dir: &str, contract_name: &str, contract_account: &str) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        xchain_crypto::account::account::export_new_account_with_mnenomic(
+            dir,
+            xchain_crypto::hdwallet::Language::English,
+            xchain_crypto::hdwallet::rand::KeyStrength::HARD,
+            xchain_crypto::account::address::CryptoType::NIST,
+        )?;
+        Self::from_key_dir(dir, contract_name, contract_account)
+    }
+
+    /// 从助记词恢复账户，私钥只保存在内存里，不写文件
+    /// 和Go SDK的RetrieveAccount使用同样的推导方法，得到同样的私钥和地址
+    pub fn from_mnemonic(
+        mnemonic: &str,
+        lang: Language,
+        contract_name: &str,
+        contract_account: &str,
+    ) -> Result<Self> {
+        let acc = xchain_crypto::account::account::generate_account_by_mnemonic(
+            &mnemonic.to_string(),
+            lang,
+        )?;
+        let signer = KeySigner {
+            key: xchain_crypto::account::json_key::get_ecdsa_private_key_from_json(
+                acc.json_private_key(),
+            )?,
+        };
+        let account =
+            Self::with_signer(std::sync::Arc::new(signer), contract_name, contract_account)?;
+        if &account.address != acc.address() {
+            return Err(Error::from(ErrorKind::CryptoError));
         }
+        Ok(account)
     }
 
     pub fn sign(&self, msg: &[u8]) -> Result<Vec<u8>> {
+        if let Some(ref signer) = self.signer {
+            return signer.sign(msg);
+        }
         let p = xchain_crypto::account::get_ecdsa_private_key_from_file(&self.path)?;
         Ok(p.sign(msg)?.as_ref().to_vec())
     }
 
     pub fn verify(&self, msg: &[u8], sig: &[u8]) -> Result<()> {
+        if let Some(ref signer) = self.signer {
+            verify_with_public_key(&signer.public_key()?, msg, sig)?;
+            return Ok(());
+        }
+        if let Some(ref public_key) = self.cached_public_key {
+            verify_with_public_key(public_key, msg, sig)?;
+            return Ok(());
+        }
         let p = xchain_crypto::account::get_ecdsa_private_key_from_file(&self.path)?;
         let alg = &xchain_crypto::sign::ecdsa::ECDSA_P256_SHA256_ASN1;
         let pk = xchain_crypto::account::PublicKey::new(alg, p.public_key());
@@ -45,15 +220,187 @@ impl Account {
         Ok(())
     }
 
+    /// 对链下消息签名，签名的是加了MESSAGE_PREFIX和长度的消息，不会被当作交易或其他协议的签名
+    pub fn sign_message(&self, msg: &[u8]) -> Result<Vec<u8>> {
+        self.sign(&prefixed_message(msg))
+    }
+
+    /// 生成公开身份信息(地址、公钥、可选的远程认证quote)
+    pub fn public_bundle(&self, quote: Option<&[u8]>) -> Result<String> {
+        let mut b = PublicBundle {
+            address: self.address.to_owned(),
+            public_key: self.public_key()?,
+            quote: quote.map(|q| hex::encode(q)),
+            sign: String::new(),
+        };
+        b.sign = hex::encode(self.sign(&b.to_sign_bytes())?);
+        Ok(serde_json::to_string(&b)?)
+    }
+
     pub fn public_key(&self) -> Result<String> {
+        if let Some(ref signer) = self.signer {
+            return signer.public_key();
+        }
+        if let Some(ref public_key) = self.cached_public_key {
+            return Ok(public_key.to_owned());
+        }
         let p = xchain_crypto::account::get_ecdsa_private_key_from_file(&self.path)?;
         let res = xchain_crypto::account::json_key::get_ecdsa_public_key_json_format_in_go(&p)?;
         Ok(res)
     }
 
+    /// 加载用密码加密的私钥文件(见keystore模块)，私钥解密之后只保存在内存里
+    pub fn from_keystore(
+        path: &str,
+        password: &str,
+        contract_name: &str,
+        contract_account: &str,
+    ) -> Result<Self> {
+        let keystore = crate::keystore::read_keystore(path)?;
+        let private_key = crate::keystore::decrypt_key(&keystore, password)?;
+        let signer = KeySigner {
+            key: xchain_crypto::account::json_key::get_ecdsa_private_key_from_json(&private_key)?,
+        };
+        let account =
+            Self::with_signer(std::sync::Arc::new(signer), contract_name, contract_account)?;
+        if account.address != keystore.address {
+            return Err(Error::from(ErrorKind::CryptoError));
+        }
+        Ok(account)
+    }
+
+    /// 在enclave里用SGX sealing加密path里的私钥并写入sealed_path，之后用from_sealed_file加载
+    #[cfg(feature = "sgx-seal")]
+    pub fn seal_to_file(&self, sealed_path: &str) -> Result<()> {
+        if self.signer.is_some() || self.path.is_empty() {
+            return Err(Error::from(ErrorKind::InvalidArguments));
+        }
+        let private_key = std::fs::read_to_string(&self.path)?;
+        let sealed = crate::seal::seal(self.address.as_bytes(), private_key.as_bytes())?;
+        std::fs::write(sealed_path, sealed)?;
+        Ok(())
+    }
+
+    /// 加载seal_to_file写入的私钥，只能在同一个签名者的enclave里解密
+    #[cfg(feature = "sgx-seal")]
+    pub fn from_sealed_file(
+        sealed_path: &str,
+        contract_name: &str,
+        contract_account: &str,
+    ) -> Result<Self> {
+        let (address, private_key) = crate::seal::unseal(&std::fs::read(sealed_path)?)?;
+        let private_key =
+            String::from_utf8(private_key).map_err(|_| Error::from(ErrorKind::CryptoError))?;
+        let signer = KeySigner {
+            key: xchain_crypto::account::json_key::get_ecdsa_private_key_from_json(&private_key)?,
+        };
+        let account =
+            Self::with_signer(std::sync::Arc::new(signer), contract_name, contract_account)?;
+        if account.address.as_bytes() != &address[..] {
+            return Err(Error::from(ErrorKind::CryptoError));
+        }
+        Ok(account)
+    }
+
     // TODO  把其他所有crypto相关的操作移动到这里
 }
 
+/// 私钥在内存里的签名者
+struct KeySigner {
+    key: xchain_crypto::sign::ecdsa::EcdsaKeyPair,
+}
+
+impl Signer for KeySigner {
+    fn public_key(&self) -> Result<String> {
+        Ok(xchain_crypto::account::json_key::get_ecdsa_public_key_json_format_in_go(&self.key)?)
+    }
+
+    fn sign(&self, msg: &[u8]) -> Result<Vec<u8>> {
+        Ok(self.key.sign(msg)?.as_ref().to_vec())
+    }
+}
+
+/// json格式的私钥对应的地址
+pub fn address_from_private_key(private_key: &str) -> Result<String> {
+    let p = xchain_crypto::account::json_key::get_ecdsa_private_key_from_json(private_key)?;
+    let alg = &xchain_crypto::sign::ecdsa::ECDSA_P256_SHA256_ASN1;
+    let pk = xchain_crypto::account::PublicKey::new(alg, p.public_key());
+    Ok(xchain_crypto::account::address::get_address_from_public_key(&pk)?)
+}
+
+/// 生成新的助记词，用Account::from_mnemonic恢复账户
+pub fn generate_mnemonic(lang: Language, strength: KeyStrength) -> Result<String> {
+    let acc = xchain_crypto::account::account::create_new_account_with_mnemonic(
+        lang,
+        strength,
+        xchain_crypto::account::address::CryptoType::NIST,
+    )?;
+    Ok(acc.mnemonic().to_owned())
+}
+
+/// 用json格式的公钥验签，返回公钥对应的地址
+pub fn verify_with_public_key(public_key: &str, msg: &[u8], sig: &[u8]) -> Result<String> {
+    let pk = xchain_crypto::account::json_key::get_ecdsa_public_key_from_json(public_key)?;
+    let alg = &xchain_crypto::sign::ecdsa::ECDSA_P256_SHA256_ASN1;
+    let pk = xchain_crypto::account::PublicKey::new(alg, &pk);
+    pk.verify(msg, sig)?;
+    let address = xchain_crypto::account::address::get_address_from_public_key(&pk)?;
+    Ok(address)
+}
+
+/// 链下消息签名的前缀
+pub const MESSAGE_PREFIX: &[u8] = b"XuperChain Signed Message:\n";
+
+fn prefixed_message(msg: &[u8]) -> Vec<u8> {
+    let mut m = MESSAGE_PREFIX.to_vec();
+    m.extend_from_slice(msg.len().to_string().as_bytes());
+    m.extend_from_slice(msg);
+    m
+}
+
+/// 校验Account::sign_message的签名，返回公钥对应的地址
+pub fn verify_message_with_public_key(public_key: &str, msg: &[u8], sig: &[u8]) -> Result<String> {
+    verify_with_public_key(public_key, &prefixed_message(msg), sig)
+}
+
+/// json格式的公钥对应的地址
+pub fn address_from_public_key(public_key: &str) -> Result<String> {
+    let pk = xchain_crypto::account::json_key::get_ecdsa_public_key_from_json(public_key)?;
+    let alg = &xchain_crypto::sign::ecdsa::ECDSA_P256_SHA256_ASN1;
+    let pk = xchain_crypto::account::PublicKey::new(alg, &pk);
+    let address = xchain_crypto::account::address::get_address_from_public_key(&pk)?;
+    Ok(address)
+}
+
+pub fn sha256(data: &[u8]) -> Vec<u8> {
+    xchain_crypto::hash::hash::sha256(data)
+}
+
+/// 密码学安全的随机数
+pub fn random_bytes(len: usize) -> Result<Vec<u8>> {
+    let seed = xchain_crypto::hdwallet::rand::generate_seed_with_strength_and_keylen(
+        xchain_crypto::hdwallet::rand::KeyStrength::HARD,
+        64,
+    )?;
+    let mut same_seed = [0u8; 32];
+    same_seed.copy_from_slice(&seed[..32]);
+    let mut rng = StdRng::from_seed(same_seed);
+    let mut res = vec![0u8; len];
+    rng.fill_bytes(&mut res);
+    Ok(res)
+}
+
+/// 用account对查询请求签名，配合xchain_node_sdk::auth::set_query_signer使用
+pub fn query_signer(account: Account) -> std::sync::Arc<xchain_node_sdk::auth::QuerySigner> {
+    std::sync::Arc::new(move |payload: &[u8]| {
+        Ok(xchain_node_sdk::auth::QuerySignature {
+            address: account.address.to_owned(),
+            public_key: account.public_key()?,
+            sign: account.sign(payload)?,
+        })
+    })
+}
+
 pub fn get_nonce() -> Result<String> {
     let t = super::consts::now_as_secs();
     let m: u32 = 100000000;
@@ -85,4 +432,128 @@ mod tests {
         let address = include_str!("../key/address");
         assert_eq!(acc.address, address);
     }
+
+    #[test]
+    fn test_sign_message() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = Account::new(d.to_str().unwrap(), "", "");
+        let public_key = acc.public_key().unwrap();
+
+        let sig = acc.sign_message(b"msg").unwrap();
+        assert_eq!(
+            verify_message_with_public_key(&public_key, b"msg", &sig).unwrap(),
+            acc.address
+        );
+        assert_eq!(
+            verify_message_with_public_key(&public_key, b"other", &sig).is_ok(),
+            false
+        );
+        // 消息签名不能当作对原始数据的签名
+        assert_eq!(
+            verify_with_public_key(&public_key, b"msg", &sig).is_ok(),
+            false
+        );
+        let sig = acc.sign(b"msg").unwrap();
+        assert_eq!(
+            verify_message_with_public_key(&public_key, b"msg", &sig).is_ok(),
+            false
+        );
+    }
+
+    #[test]
+    fn test_public_bundle() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = Account::new(d.to_str().unwrap(), "", "");
+
+        let bundle = acc.public_bundle(Some(b"quote")).unwrap();
+        let b = verify_bundle(&bundle).unwrap();
+        assert_eq!(b.address, acc.address);
+        assert_eq!(b.quote, Some(hex::encode(b"quote")));
+
+        let bundle = acc.public_bundle(None).unwrap();
+        assert_eq!(verify_bundle(&bundle).is_ok(), true);
+
+        let mut b: PublicBundle = serde_json::from_str(&bundle).unwrap();
+        b.address = String::from("dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN");
+        let tampered = serde_json::to_string(&b).unwrap();
+        assert_eq!(verify_bundle(&tampered).is_ok(), false);
+    }
+
+    #[test]
+    fn test_query_signer() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = Account::new(d.to_str().unwrap(), "", "");
+        let signer = query_signer(acc.clone());
+        let sig = signer(b"query").unwrap();
+        assert_eq!(sig.address, acc.address);
+        assert_eq!(
+            verify_with_public_key(&sig.public_key, b"query", &sig.sign).unwrap(),
+            acc.address
+        );
+    }
+
+    #[test]
+    fn test_load_account_from_key_dir() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key");
+        let acc = Account::from_key_dir(d.to_str().unwrap(), "", "");
+        assert_eq!(acc.is_ok(), true);
+        let address = include_str!("../key/address");
+        assert_eq!(acc.unwrap().address, address);
+
+        let res = Account::from_key_dir("/not/exist", "", "");
+        assert_eq!(res.is_ok(), false);
+    }
+
+    #[test]
+    fn test_recover_from_mnemonic() {
+        // 和Go SDK从同一个助记词恢复出的地址一致
+        let mnemonic = "呈 仓 冯 滚 刚 伙 此 丈 锅 语 揭 弃 精 塘 界 戴 玩 爬 奶 滩 哀 极 样 费";
+        let acc = Account::from_mnemonic(mnemonic, Language::ChineseSimplified, "", "").unwrap();
+        assert_eq!(acc.address, "nYA6bVyhzv38g85ejxr4aqeKPcbG8mSWC");
+        assert_eq!(acc.path.is_empty(), true);
+        assert_eq!(acc.signer.is_some(), true);
+        let sig = acc.sign(b"msg").unwrap();
+        assert_eq!(acc.verify(b"msg", &sig).is_ok(), true);
+
+        let mnemonic = generate_mnemonic(Language::English, KeyStrength::HARD).unwrap();
+        assert_eq!(mnemonic.split_whitespace().count(), 24);
+        let a = Account::from_mnemonic(&mnemonic, Language::English, "", "").unwrap();
+        let b = Account::from_mnemonic(&mnemonic, Language::English, "", "").unwrap();
+        assert_eq!(a.address, b.address);
+        assert_eq!(
+            Account::from_mnemonic("not a mnemonic", Language::English, "", "").is_ok(),
+            false
+        );
+    }
+
+    #[test]
+    fn test_cached_public_key() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("key/private.key");
+        let acc = Account::new(d.to_str().unwrap(), "", "");
+        let uncached = Account {
+            cached_public_key: None,
+            ..acc.clone()
+        };
+        assert_eq!(acc.public_key().unwrap(), uncached.public_key().unwrap());
+        assert_eq!(
+            address_from_public_key(&acc.public_key().unwrap()).unwrap(),
+            acc.address
+        );
+        let sig = acc.sign(b"msg").unwrap();
+        assert_eq!(acc.verify(b"msg", &sig).is_ok(), true);
+        assert_eq!(acc.verify(b"other", &sig).is_ok(), false);
+
+        // 公钥和地址不再读取私钥文件
+        let moved = Account {
+            path: String::from("/nonexistent/private.key"),
+            ..acc.clone()
+        };
+        assert_eq!(moved.public_key().unwrap(), acc.public_key().unwrap());
+        assert_eq!(moved.sign(b"msg").is_ok(), false);
+    }
 }
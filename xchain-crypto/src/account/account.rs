@@ -76,6 +76,20 @@ pub fn generate_account_by_mnemonic(mnemonic: &String, lang: Language) -> Result
     })
 }
 
+impl ECDSAAccount {
+    pub fn mnemonic(&self) -> &String {
+        &self.mnemonic
+    }
+
+    pub fn address(&self) -> &String {
+        &self.address
+    }
+
+    pub fn json_private_key(&self) -> &String {
+        &self.json_private_key
+    }
+}
+
 fn to_tag_byte(cryptography: u8) -> u8 {
     (cryptography & 15) << 4
 }
@@ -130,6 +144,23 @@ pub fn export_new_account_with_mnenomic(
     Ok(())
 }
 
+/// 从助记词恢复账户，在base_path下面写入private.key, public.key, address
+pub fn export_account_by_mnemonic(base_path: &str, mnemonic: &String, lang: Language) -> Result<()> {
+    let acc = generate_account_by_mnemonic(mnemonic, lang)?;
+    let path: PathBuf = [base_path, "private.key"].iter().collect();
+    let mut file = File::create(path)?;
+    file.write_all(acc.json_private_key.as_bytes())?;
+
+    let path: PathBuf = [base_path, "public.key"].iter().collect();
+    let mut file = File::create(path)?;
+    file.write_all(acc.json_public_key.as_bytes())?;
+
+    let path: PathBuf = [base_path, "address"].iter().collect();
+    let mut file = File::create(path)?;
+    file.write_all(acc.address.as_bytes())?;
+    Ok(())
+}
+
 pub fn export_new_account(base_path: &str, private_key: &EcdsaKeyPair) -> Result<()> {
     let json_sk = json_key::get_ecdsa_private_key_json_format(private_key)?;
     let json_pk = json_key::get_ecdsa_public_key_json_format(private_key)?;
@@ -87,11 +87,11 @@ pub fn verify_address_using_public_keys<B: AsRef<[u8]>>(
 pub fn check_address_format(address: &String) -> Result<u8> {
     let slice = match address.from_base58() {
         Ok(x) => x,
-        Err(e) => {
-            println!("{:?}", e);
-            return Err(Error::from(ErrorKind::ParseError));
-        }
+        Err(_) => return Err(Error::from(ErrorKind::ParseError)),
     };
+    if slice.len() < 5 {
+        return Err(Error::from(ErrorKind::InvalidAddressError));
+    }
     let check_code = &slice[(slice.len() - 4)..];
     let n_version = slice[0];
     let buf = &slice[1..slice.len() - 4];
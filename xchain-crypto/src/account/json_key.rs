@@ -74,9 +74,17 @@ impl ECDSAPrivateKey {
 struct ECDSAPublicKey {
     #[serde(rename = "Curvname")]
     curve_name: String,
-    #[serde(rename = "X", serialize_with = "big_serialize")]
+    #[serde(
+        rename = "X",
+        serialize_with = "big_serialize",
+        deserialize_with = "big_deserialize"
+    )]
     x: BigInt,
-    #[serde(rename = "Y", serialize_with = "big_serialize")]
+    #[serde(
+        rename = "Y",
+        serialize_with = "big_serialize",
+        deserialize_with = "big_deserialize"
+    )]
     y: BigInt,
 }
 
@@ -132,6 +140,36 @@ fn test_del_quote() {
     assert_eq!(rd, d.as_str());
 }
 
+// del_quote的逆操作，golang格式的秘钥里面X/Y/D是不带引号的大整数
+fn add_quote(s: &str) -> String {
+    let matcher = r#""(X|Y|D)":([0-9]+)"#;
+    let re = regex::Regex::new(matcher).unwrap();
+    re.replace_all(s, |caps: &regex::Captures| {
+        format!("\"{}\":\"{}\"", &caps[1], &caps[2])
+    })
+    .to_string()
+}
+
+#[test]
+fn test_add_quote() {
+    let r = r#"{"Curvname":"P-256","X":111614135018814739113902147296905176613869996836868032563930690178519466984733,"Y":13721631144572906234172791140317599374441573108268774470311606493984588103134}"#;
+    let d = add_quote(r);
+    let rd = r#"{"Curvname":"P-256","X":"111614135018814739113902147296905176613869996836868032563930690178519466984733","Y":"13721631144572906234172791140317599374441573108268774470311606493984588103134"}"#;
+    assert_eq!(rd, d.as_str());
+    assert_eq!(rd, add_quote(rd).as_str());
+}
+
+// 大整数转换成定长的字节
+fn big_to_fixed_bytes(x: &BigInt, len: usize) -> Result<Vec<u8>> {
+    let b = x.to_bytes_be().1;
+    if b.len() > len {
+        return Err(Error::from(ErrorKind::InvalidBigNumError));
+    }
+    let mut res = vec![0u8; len - b.len()];
+    res.extend_from_slice(&b);
+    Ok(res)
+}
+
 pub fn get_ecdsa_public_key_json_format_in_go(k: &EcdsaKeyPair) -> Result<String> {
     Ok(del_quote(get_ecdsa_public_key_json_format(k)?.as_str()))
 }
@@ -161,11 +199,13 @@ pub fn get_ecdsa_private_key_from_file(filename: &str) -> Result<EcdsaKeyPair> {
     get_ecdsa_private_key_from_json(contents.as_str())
 }
 
+/// 支持X/Y带引号(秘钥文件)和不带引号(golang签名里面的PublicKey)两种格式
+/// 返回未压缩格式的公钥: 0x04 || X || Y
 pub fn get_ecdsa_public_key_from_json(key_str: &str) -> Result<Vec<u8>> {
-    let acc: ECDSAPublicKey = serde_json::from_str(key_str)?;
+    let acc: ECDSAPublicKey = serde_json::from_str(&add_quote(key_str))?;
     let mut seed = vec![4u8; 1];
-    seed.extend_from_slice(&acc.x.to_bytes_be().1);
-    seed.extend_from_slice(&acc.y.to_bytes_be().1);
+    seed.extend_from_slice(&big_to_fixed_bytes(&acc.x, 32)?);
+    seed.extend_from_slice(&big_to_fixed_bytes(&acc.y, 32)?);
     //let alg = &crate::sign::ecdsa::ECDSA_P256_SHA256_ASN1;
     //let public_key = crate::sign::ecdsa::UnparsedPublicKey::new(alg, seed);
     //Ok(public_key.as_ref())
@@ -221,3 +261,17 @@ pub fn test_json_private() {
     assert_eq!(sk2.is_ok(), true);
     assert_eq!(sk2.unwrap().seed_as_bytes(), private_key.seed_as_bytes());
 }
+
+#[test]
+fn test_json_public_from_json() {
+    let go_format = r#"{"Curvname":"P-256","X":73410626601881997721867175952553340702382694231051348336047663271490653701388,"Y":607742109887809765560480589742271924950461540382719635282970835816092311016}"#;
+    let file_format = r#"{"Curvname":"P-256","X":"73410626601881997721867175952553340702382694231051348336047663271490653701388","Y":"607742109887809765560480589742271924950461540382719635282970835816092311016"}"#;
+    let pk = get_ecdsa_public_key_from_json(go_format).unwrap();
+    assert_eq!(pk.len(), 65);
+    assert_eq!(pk, get_ecdsa_public_key_from_json(file_format).unwrap());
+
+    let alg = &crate::sign::ecdsa::ECDSA_P256_SHA256_ASN1;
+    let public_key = crate::sign::ecdsa::UnparsedPublicKey::new(alg, &pk);
+    let address = super::address::get_address_from_public_key(&public_key).unwrap();
+    assert_eq!(address, "dy9UjyBbELfdWHZFUNXkdaaCmTWLkUeyy");
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::deadline;
+use crate::errors::{Error, ErrorKind, Result};
+
+// 带签名的查询请求: 有些许可链的节点要求读请求也要签名。
+// 按节点(host:port)配置签名者，配置了签名者的节点，余额、交易、区块查询会在gRPC metadata
+// (HTTP网关是header)里带上:
+//   xchain-query-address    签名者地址
+//   xchain-query-publickey  json格式的公钥
+//   xchain-query-timestamp  秒，防重放
+//   xchain-query-sign       hex编码的签名，签名内容见sign_payload
+// 托管的背书服务和网关要求API key: 按节点配置ApiKeyProvider，所有请求(包括背书请求)都带上，
+// 每次请求时从provider取，轮换key只需要provider返回新的key。
+// 当前线程设置了deadline(见deadline::scope)时，所有请求带上grpc-timeout，deadline已过则不发送。
+
+pub const ADDRESS_KEY: &str = "xchain-query-address";
+pub const PUBLIC_KEY_KEY: &str = "xchain-query-publickey";
+pub const TIMESTAMP_KEY: &str = "xchain-query-timestamp";
+pub const SIGN_KEY: &str = "xchain-query-sign";
+
+pub struct QuerySignature {
+    pub address: String,
+    pub public_key: String,
+    pub sign: Vec<u8>,
+}
+
+/// 对查询内容签名，由持有私钥的一方(例如client sdk的wallet)提供
+pub type QuerySigner = dyn Fn(&[u8]) -> Result<QuerySignature> + Send + Sync;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiKey {
+    /// gRPC metadata的key或者http header，例如x-api-key
+    pub header: String,
+    pub value: String,
+}
+
+/// 返回当前的API key，None表示不带
+pub type ApiKeyProvider = dyn Fn() -> Result<Option<ApiKey>> + Send + Sync;
+
+lazy_static! {
+    static ref QUERY_SIGNERS: RwLock<HashMap<String, Arc<QuerySigner>>> =
+        RwLock::new(HashMap::new());
+    static ref API_KEYS: RwLock<HashMap<String, Arc<ApiKeyProvider>>> = RwLock::new(HashMap::new());
+}
+
+pub fn endpoint(host: &str, port: u16) -> String {
+    format!("{}:{}", host, port)
+}
+
+/// 配置节点的查询签名者，signer为None时不再签名
+pub fn set_query_signer(endpoint: &str, signer: Option<Arc<QuerySigner>>) {
+    let mut signers = QUERY_SIGNERS.write().unwrap();
+    match signer {
+        Some(s) => signers.insert(endpoint.to_owned(), s),
+        None => signers.remove(endpoint),
+    };
+}
+
+pub fn query_signer(endpoint: &str) -> Option<Arc<QuerySigner>> {
+    QUERY_SIGNERS.read().unwrap().get(endpoint).cloned()
+}
+
+pub fn set_api_key_provider(endpoint: &str, provider: Option<Arc<ApiKeyProvider>>) {
+    let mut providers = API_KEYS.write().unwrap();
+    match provider {
+        Some(p) => providers.insert(endpoint.to_owned(), p),
+        None => providers.remove(endpoint),
+    };
+}
+
+/// 配置固定的API key，再次调用即轮换
+pub fn set_api_key(endpoint: &str, key: Option<ApiKey>) {
+    set_api_key_provider(
+        endpoint,
+        key.map(|k| Arc::new(move || Ok(Some(k.clone()))) as Arc<ApiKeyProvider>),
+    );
+}
+
+pub fn api_key_headers(endpoint: &str) -> Result<Vec<(String, String)>> {
+    let provider = match API_KEYS.read().unwrap().get(endpoint) {
+        Some(p) => p.clone(),
+        None => return Ok(vec![]),
+    };
+    Ok(match provider()? {
+        Some(k) => vec![(k.header, k.value)],
+        None => vec![],
+    })
+}
+
+/// 被签名的内容: 请求的protobuf编码 + "\n" + 时间戳
+pub fn sign_payload(request: &[u8], timestamp: i64) -> Vec<u8> {
+    let mut payload = request.to_vec();
+    payload.extend_from_slice(format!("\n{}", timestamp).as_bytes());
+    payload
+}
+
+/// 需要签名时返回要带上的(key, value)，不需要时为空
+pub fn signed_headers<M: protobuf::Message>(
+    endpoint: &str,
+    req: &M,
+) -> Result<Vec<(&'static str, String)>> {
+    let signer = match query_signer(endpoint) {
+        Some(s) => s,
+        None => return Ok(vec![]),
+    };
+    let request = req
+        .write_to_bytes()
+        .map_err(|_| Error::from(ErrorKind::ParseError))?;
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|_| Error::from(ErrorKind::Unknown))?
+        .as_secs() as i64;
+    let sig = signer(&sign_payload(&request, timestamp))?;
+    Ok(vec![
+        (ADDRESS_KEY, sig.address),
+        (PUBLIC_KEY_KEY, sig.public_key),
+        (TIMESTAMP_KEY, timestamp.to_string()),
+        (SIGN_KEY, hex::encode(&sig.sign)),
+    ])
+}
+
+/// 不需要签名的请求，只带API key和超时
+pub fn api_key_options(endpoint: &str) -> Result<grpc::RequestOptions> {
+    let mut options = grpc::RequestOptions::new();
+    if let Some(timeout) = deadline::remaining()? {
+        options.metadata.add(
+            grpc::MetadataKey::from(deadline::GRPC_TIMEOUT_KEY),
+            bytes::Bytes::from(deadline::grpc_timeout(timeout).into_bytes()),
+        );
+    }
+    for (key, value) in api_key_headers(endpoint)? {
+        options.metadata.add(
+            grpc::MetadataKey::from(key),
+            bytes::Bytes::from(value.into_bytes()),
+        );
+    }
+    Ok(options)
+}
+
+/// 带API key，需要签名时在请求的metadata里带上签名
+pub fn request_options<M: protobuf::Message>(
+    endpoint: &str,
+    req: &M,
+) -> Result<grpc::RequestOptions> {
+    let mut options = api_key_options(endpoint)?;
+    for (key, value) in signed_headers(endpoint, req)? {
+        options.metadata.add(
+            grpc::MetadataKey::from(key),
+            bytes::Bytes::from(value.into_bytes()),
+        );
+    }
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protos::xchain;
+
+    #[test]
+    fn test_request_options() {
+        let mut req = xchain::BlockHeight::new();
+        req.set_bcname(String::from("xuper"));
+        req.set_height(10);
+
+        let options = request_options("127.0.0.1:37101", &req).unwrap();
+        assert_eq!(options.metadata.get(SIGN_KEY), None);
+
+        let signer: Arc<QuerySigner> = Arc::new(|payload: &[u8]| {
+            Ok(QuerySignature {
+                address: String::from("alice"),
+                public_key: String::from("{}"),
+                sign: payload.to_vec(),
+            })
+        });
+        set_query_signer("127.0.0.1:37101", Some(signer));
+        let options = request_options("127.0.0.1:37101", &req).unwrap();
+        assert_eq!(options.metadata.get(ADDRESS_KEY), Some(&b"alice"[..]));
+        let timestamp: i64 = std::str::from_utf8(options.metadata.get(TIMESTAMP_KEY).unwrap())
+            .unwrap()
+            .parse()
+            .unwrap();
+        let payload = sign_payload(&req.write_to_bytes().unwrap(), timestamp);
+        assert_eq!(
+            options.metadata.get(SIGN_KEY),
+            Some(hex::encode(&payload).as_bytes())
+        );
+        // 其他节点不签名
+        let options = request_options("127.0.0.1:37102", &req).unwrap();
+        assert_eq!(options.metadata.get(SIGN_KEY), None);
+
+        set_query_signer("127.0.0.1:37101", None);
+        assert_eq!(query_signer("127.0.0.1:37101").is_none(), true);
+    }
+
+    #[test]
+    fn test_api_key_rotation() {
+        let endpoint = "127.0.0.1:37201";
+        assert_eq!(api_key_headers(endpoint).unwrap(), vec![]);
+        let key = |v: &str| ApiKey {
+            header: String::from("x-api-key"),
+            value: String::from(v),
+        };
+        set_api_key(endpoint, Some(key("k1")));
+        let options = api_key_options(endpoint).unwrap();
+        assert_eq!(options.metadata.get("x-api-key"), Some(&b"k1"[..]));
+
+        // 轮换之后的请求带新的key
+        set_api_key(endpoint, Some(key("k2")));
+        let mut req = xchain::BlockHeight::new();
+        req.set_height(1);
+        let options = request_options(endpoint, &req).unwrap();
+        assert_eq!(options.metadata.get("x-api-key"), Some(&b"k2"[..]));
+        assert_eq!(options.metadata.get(SIGN_KEY), None);
+
+        set_api_key(endpoint, None);
+        assert_eq!(api_key_headers(endpoint).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_deadline_options() {
+        let endpoint = "127.0.0.1:37301";
+        let options = api_key_options(endpoint).unwrap();
+        assert_eq!(options.metadata.get(deadline::GRPC_TIMEOUT_KEY), None);
+
+        let d = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        deadline::scope(Some(d), || {
+            let options = api_key_options(endpoint).unwrap();
+            let timeout = options.metadata.get(deadline::GRPC_TIMEOUT_KEY).unwrap();
+            let timeout = std::str::from_utf8(timeout).unwrap();
+            assert_eq!(timeout.ends_with('m'), true);
+            let millis: u64 = timeout.trim_end_matches('m').parse().unwrap();
+            assert_eq!(millis > 0 && millis <= 5000, true);
+        });
+
+        // deadline已过不发送
+        deadline::scope(Some(std::time::Instant::now()), || {
+            let e = api_key_options(endpoint).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::DeadlineExceeded);
+        });
+    }
+}
@@ -0,0 +1,870 @@
+use std::collections::BTreeMap;
+
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+use serde_json::Value;
+
+use crate::encoder::normalize_field_name;
+use crate::errors::*;
+
+// 背书服务用golang的encoding/json解析RequestData，严格的背书服务会把解析出来的结构重新序列化之后和原文比较，
+// serde_json序列化protobuf结构的结果(字段都输出，bytes是数字数组)和golang不一致。
+// 这里按照protoc-gen-go生成的结构(字段都带omitempty)的规则生成规范的json:
+// 1. 字段按照定义的顺序，值为默认值(0, false, 空字符串, 空数组, 空map, nil)的字段省略
+// 2. bytes编码成base64字符串，枚举编码成数字
+// 3. map按key的字典序排列
+// 4. 字符串里的<, >, &, U+2028, U+2029转义成\uXXXX，控制字符除\n, \r, \t之外都转义成\u00XX
+// 5. 没有多余的空白
+// protobuf的枚举是按u8序列化的(serde_repr)，u8数组识别成bytes，目前的协议里没有repeated枚举，不会混淆。
+// 浮点数按serde_json的格式输出，指数形式和golang不一样，背书请求里没有浮点数。
+
+type SerdeResult<T> = std::result::Result<T, serde_json::Error>;
+
+/// 生成和golang一致的规范json
+pub fn to_canonical_json<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: Serialize + ?Sized,
+{
+    let node = value.serialize(NodeSerializer)?;
+    let mut buf = String::new();
+    node.write(&mut buf);
+    Ok(buf.into_bytes())
+}
+
+/// 解析规范json，省略的字段取默认值，bytes从base64解码。也可以解析serde_json的输出
+pub fn from_canonical_json<T>(data: &[u8]) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let raw: Value = serde_json::from_slice(data)?;
+    Ok(T::deserialize(NodeDeserializer(Some(raw)))?)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Null,
+    Bool(bool),
+    Byte(u8),
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    Str(String),
+    Seq(Vec<Node>),
+    Map(BTreeMap<String, Node>),
+    Struct(Vec<(&'static str, Node)>),
+}
+
+impl Node {
+    /// omitempty认为是空的值，nil指针以外的结构体不是空的
+    fn is_empty(&self) -> bool {
+        match self {
+            Node::Null => true,
+            Node::Bool(b) => !b,
+            Node::Byte(b) => *b == 0,
+            Node::Int(i) => *i == 0,
+            Node::Uint(u) => *u == 0,
+            Node::Float(f) => *f == 0.0,
+            Node::Str(s) => s.is_empty(),
+            Node::Seq(s) => s.is_empty(),
+            Node::Map(m) => m.is_empty(),
+            Node::Struct(_) => false,
+        }
+    }
+
+    fn write(&self, buf: &mut String) {
+        match self {
+            Node::Null => buf.push_str("null"),
+            Node::Bool(b) => buf.push_str(if *b { "true" } else { "false" }),
+            Node::Byte(b) => buf.push_str(&b.to_string()),
+            Node::Int(i) => buf.push_str(&i.to_string()),
+            Node::Uint(u) => buf.push_str(&u.to_string()),
+            Node::Float(f) => buf.push_str(&Value::from(*f).to_string()),
+            Node::Str(s) => write_str(s, buf),
+            Node::Seq(items) => {
+                buf.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        buf.push(',');
+                    }
+                    item.write(buf);
+                }
+                buf.push(']');
+            }
+            Node::Map(m) => {
+                buf.push('{');
+                for (i, (k, v)) in m.iter().enumerate() {
+                    if i > 0 {
+                        buf.push(',');
+                    }
+                    write_str(k, buf);
+                    buf.push(':');
+                    v.write(buf);
+                }
+                buf.push('}');
+            }
+            Node::Struct(fields) => {
+                buf.push('{');
+                let mut first = true;
+                for (k, v) in fields.iter().filter(|(_, v)| !v.is_empty()) {
+                    if !first {
+                        buf.push(',');
+                    }
+                    first = false;
+                    write_str(k, buf);
+                    buf.push(':');
+                    v.write(buf);
+                }
+                buf.push('}');
+            }
+        }
+    }
+}
+
+fn write_str(s: &str, buf: &mut String) {
+    buf.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            '<' | '>' | '&' | '\u{2028}' | '\u{2029}' => {
+                buf.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c if (c as u32) < 0x20 => buf.push_str(&format!("\\u{:04x}", c as u32)),
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
+
+struct NodeSerializer;
+
+impl ser::Serializer for NodeSerializer {
+    type Ok = Node;
+    type Error = serde_json::Error;
+    type SerializeSeq = SeqNode;
+    type SerializeTuple = SeqNode;
+    type SerializeTupleStruct = SeqNode;
+    type SerializeTupleVariant = VariantNode<SeqNode>;
+    type SerializeMap = MapNode;
+    type SerializeStruct = StructNode;
+    type SerializeStructVariant = VariantNode<StructNode>;
+
+    fn serialize_bool(self, v: bool) -> SerdeResult<Node> {
+        Ok(Node::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> SerdeResult<Node> {
+        Ok(Node::Int(v as i64))
+    }
+
+    fn serialize_i16(self, v: i16) -> SerdeResult<Node> {
+        Ok(Node::Int(v as i64))
+    }
+
+    fn serialize_i32(self, v: i32) -> SerdeResult<Node> {
+        Ok(Node::Int(v as i64))
+    }
+
+    fn serialize_i64(self, v: i64) -> SerdeResult<Node> {
+        Ok(Node::Int(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> SerdeResult<Node> {
+        Ok(Node::Byte(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> SerdeResult<Node> {
+        Ok(Node::Uint(v as u64))
+    }
+
+    fn serialize_u32(self, v: u32) -> SerdeResult<Node> {
+        Ok(Node::Uint(v as u64))
+    }
+
+    fn serialize_u64(self, v: u64) -> SerdeResult<Node> {
+        Ok(Node::Uint(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> SerdeResult<Node> {
+        Ok(Node::Float(v as f64))
+    }
+
+    fn serialize_f64(self, v: f64) -> SerdeResult<Node> {
+        Ok(Node::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> SerdeResult<Node> {
+        Ok(Node::Str(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> SerdeResult<Node> {
+        Ok(Node::Str(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> SerdeResult<Node> {
+        Ok(Node::Str(base64::encode(v)))
+    }
+
+    fn serialize_none(self) -> SerdeResult<Node> {
+        Ok(Node::Null)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> SerdeResult<Node>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> SerdeResult<Node> {
+        Ok(Node::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> SerdeResult<Node> {
+        Ok(Node::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> SerdeResult<Node> {
+        Ok(Node::Uint(variant_index as u64))
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> SerdeResult<Node>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> SerdeResult<Node>
+    where
+        T: Serialize + ?Sized,
+    {
+        let mut m = BTreeMap::new();
+        m.insert(variant.to_owned(), value.serialize(NodeSerializer)?);
+        Ok(Node::Map(m))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> SerdeResult<SeqNode> {
+        Ok(SeqNode {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> SerdeResult<SeqNode> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> SerdeResult<SeqNode> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> SerdeResult<VariantNode<SeqNode>> {
+        Ok(VariantNode {
+            variant: variant,
+            inner: self.serialize_seq(Some(len))?,
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> SerdeResult<MapNode> {
+        Ok(MapNode {
+            map: BTreeMap::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> SerdeResult<StructNode> {
+        Ok(StructNode {
+            fields: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> SerdeResult<VariantNode<StructNode>> {
+        Ok(VariantNode {
+            variant: variant,
+            inner: self.serialize_struct(name, len)?,
+        })
+    }
+}
+
+struct SeqNode {
+    items: Vec<Node>,
+}
+
+impl SeqNode {
+    fn finish(self) -> Node {
+        let is_bytes = !self.items.is_empty()
+            && self.items.iter().all(|n| match n {
+                Node::Byte(_) => true,
+                _ => false,
+            });
+        if !is_bytes {
+            return Node::Seq(self.items);
+        }
+        let bytes: Vec<u8> = self
+            .items
+            .iter()
+            .map(|n| match n {
+                Node::Byte(b) => *b,
+                _ => 0,
+            })
+            .collect();
+        Node::Str(base64::encode(&bytes))
+    }
+}
+
+impl ser::SerializeSeq for SeqNode {
+    type Ok = Node;
+    type Error = serde_json::Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> SerdeResult<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.items.push(value.serialize(NodeSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> SerdeResult<Node> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTuple for SeqNode {
+    type Ok = Node;
+    type Error = serde_json::Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> SerdeResult<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> SerdeResult<Node> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqNode {
+    type Ok = Node;
+    type Error = serde_json::Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> SerdeResult<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> SerdeResult<Node> {
+        Ok(self.finish())
+    }
+}
+
+struct MapNode {
+    map: BTreeMap<String, Node>,
+    key: Option<String>,
+}
+
+impl ser::SerializeMap for MapNode {
+    type Ok = Node;
+    type Error = serde_json::Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> SerdeResult<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        // golang的map key是字符串或者整数，整数也按字符串输出
+        let key = match key.serialize(NodeSerializer)? {
+            Node::Str(s) => s,
+            Node::Byte(b) => b.to_string(),
+            Node::Int(i) => i.to_string(),
+            Node::Uint(u) => u.to_string(),
+            _ => return Err(ser::Error::custom("map key must be a string or an integer")),
+        };
+        self.key = Some(key);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> SerdeResult<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| <serde_json::Error as ser::Error>::custom("map value without key"))?;
+        self.map.insert(key, value.serialize(NodeSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> SerdeResult<Node> {
+        Ok(Node::Map(self.map))
+    }
+}
+
+struct StructNode {
+    fields: Vec<(&'static str, Node)>,
+}
+
+impl ser::SerializeStruct for StructNode {
+    type Ok = Node;
+    type Error = serde_json::Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> SerdeResult<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.fields.push((key, value.serialize(NodeSerializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> SerdeResult<Node> {
+        Ok(Node::Struct(self.fields))
+    }
+}
+
+/// 带数据的枚举按`{"variant": value}`输出
+struct VariantNode<T> {
+    variant: &'static str,
+    inner: T,
+}
+
+impl VariantNode<SeqNode> {
+    fn finish(self) -> Node {
+        let mut m = BTreeMap::new();
+        m.insert(self.variant.to_owned(), self.inner.finish());
+        Node::Map(m)
+    }
+}
+
+impl ser::SerializeTupleVariant for VariantNode<SeqNode> {
+    type Ok = Node;
+    type Error = serde_json::Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> SerdeResult<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        ser::SerializeSeq::serialize_element(&mut self.inner, value)
+    }
+
+    fn end(self) -> SerdeResult<Node> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStructVariant for VariantNode<StructNode> {
+    type Ok = Node;
+    type Error = serde_json::Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> SerdeResult<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> SerdeResult<Node> {
+        let mut m = BTreeMap::new();
+        m.insert(self.variant.to_owned(), Node::Struct(self.inner.fields));
+        Ok(Node::Map(m))
+    }
+}
+
+/// None表示json里省略了这个值，按类型的默认值解析
+struct NodeDeserializer(Option<Value>);
+
+fn decode_base64(s: &str) -> SerdeResult<Vec<u8>> {
+    base64::decode(s).map_err(de::Error::custom)
+}
+
+macro_rules! deserialize_or_zero {
+    ($method:ident, $visit:ident, $zero:expr) => {
+        fn $method<V>(self, visitor: V) -> SerdeResult<V::Value>
+        where
+            V: Visitor<'de>,
+        {
+            match self.0 {
+                None | Some(Value::Null) => visitor.$visit($zero),
+                Some(v) => v.$method(visitor),
+            }
+        }
+    };
+}
+
+impl<'de> de::Deserializer<'de> for NodeDeserializer {
+    type Error = serde_json::Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> SerdeResult<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            None => visitor.visit_unit(),
+            Some(v) => v.deserialize_any(visitor),
+        }
+    }
+
+    deserialize_or_zero!(deserialize_bool, visit_bool, false);
+    deserialize_or_zero!(deserialize_i8, visit_i64, 0);
+    deserialize_or_zero!(deserialize_i16, visit_i64, 0);
+    deserialize_or_zero!(deserialize_i32, visit_i64, 0);
+    deserialize_or_zero!(deserialize_i64, visit_i64, 0);
+    deserialize_or_zero!(deserialize_u8, visit_u64, 0);
+    deserialize_or_zero!(deserialize_u16, visit_u64, 0);
+    deserialize_or_zero!(deserialize_u32, visit_u64, 0);
+    deserialize_or_zero!(deserialize_u64, visit_u64, 0);
+    deserialize_or_zero!(deserialize_f32, visit_f64, 0.0);
+    deserialize_or_zero!(deserialize_f64, visit_f64, 0.0);
+    deserialize_or_zero!(deserialize_str, visit_str, "");
+    deserialize_or_zero!(deserialize_string, visit_str, "");
+
+    fn deserialize_bytes<V>(self, visitor: V) -> SerdeResult<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            None | Some(Value::Null) => visitor.visit_byte_buf(Vec::new()),
+            Some(Value::String(s)) => visitor.visit_byte_buf(decode_base64(&s)?),
+            Some(v) => v.deserialize_bytes(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> SerdeResult<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> SerdeResult<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            None | Some(Value::Null) => visitor.visit_none(),
+            v => visitor.visit_some(NodeDeserializer(v)),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> SerdeResult<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> SerdeResult<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            None | Some(Value::Null) => visitor.visit_seq(NodeSeq {
+                iter: Vec::new().into_iter(),
+            }),
+            // bytes
+            Some(Value::String(s)) => {
+                let bytes = decode_base64(&s)?;
+                visitor.visit_seq(de::value::SeqDeserializer::<_, serde_json::Error>::new(
+                    bytes.into_iter(),
+                ))
+            }
+            Some(Value::Array(a)) => visitor.visit_seq(NodeSeq {
+                iter: a.into_iter(),
+            }),
+            Some(v) => v.deserialize_seq(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> SerdeResult<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> SerdeResult<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> SerdeResult<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            None | Some(Value::Null) => visitor.visit_map(NodeMap::new(Vec::new())),
+            Some(Value::Object(m)) => visitor.visit_map(NodeMap::new(
+                m.into_iter().map(|(k, v)| (k, Some(v))).collect(),
+            )),
+            Some(v) => v.deserialize_map(visitor),
+        }
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> SerdeResult<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let mut entries: Vec<(String, Option<Value>)> = match self.0 {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Object(m)) => m.into_iter().map(|(k, v)| (k, Some(v))).collect(),
+            Some(v) => return v.deserialize_struct(name, fields, visitor),
+        };
+        // 省略的字段补上默认值，按归一化的名字比较，避免alias重复
+        for f in fields.iter() {
+            let n = normalize_field_name(f);
+            if !entries.iter().any(|(k, _)| normalize_field_name(k) == n) {
+                entries.push((f.to_string(), None));
+            }
+        }
+        visitor.visit_map(NodeMap::new(entries))
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> SerdeResult<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Some(v) => v.deserialize_enum(name, variants, visitor),
+            None => Err(de::Error::missing_field(name)),
+        }
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> SerdeResult<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        char unit unit_struct identifier
+    }
+}
+
+struct NodeSeq {
+    iter: std::vec::IntoIter<Value>,
+}
+
+impl<'de> de::SeqAccess<'de> for NodeSeq {
+    type Error = serde_json::Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> SerdeResult<Option<T::Value>>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        match self.iter.next() {
+            Some(v) => seed.deserialize(NodeDeserializer(Some(v))).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+struct NodeMap {
+    iter: std::vec::IntoIter<(String, Option<Value>)>,
+    value: Option<Option<Value>>,
+}
+
+impl NodeMap {
+    fn new(entries: Vec<(String, Option<Value>)>) -> Self {
+        NodeMap {
+            iter: entries.into_iter(),
+            value: None,
+        }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for NodeMap {
+    type Error = serde_json::Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> SerdeResult<Option<K::Value>>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        match self.iter.next() {
+            Some((k, v)) => {
+                self.value = Some(v);
+                seed.deserialize(k.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<T>(&mut self, seed: T) -> SerdeResult<T::Value>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        let v = self
+            .value
+            .take()
+            .ok_or_else(|| <serde_json::Error as de::Error>::custom("value is missing"))?;
+        seed.deserialize(NodeDeserializer(v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder;
+    use crate::protos::xchain;
+
+    fn pre_exec_request() -> xchain::PreExecWithSelectUTXORequest {
+        let mut invoke = xchain::InvokeRequest::new();
+        invoke.set_module_name(String::from("wasm"));
+        invoke.set_contract_name(String::from("counter"));
+        invoke.set_method_name(String::from("increase"));
+        invoke
+            .mut_args()
+            .insert(String::from("key"), b"counter".to_vec());
+        invoke.mut_args().insert(String::from("a"), b"<&>".to_vec());
+        let mut limit = xchain::ResourceLimit::new();
+        limit.set_field_type(xchain::ResourceType::MEMORY);
+        limit.set_limit(1000);
+        invoke.set_resource_limits(protobuf::RepeatedField::from_vec(vec![
+            xchain::ResourceLimit::new(),
+            limit,
+        ]));
+
+        let mut rpc = xchain::InvokeRPCRequest::new();
+        rpc.set_bcname(String::from("xuper"));
+        rpc.set_requests(protobuf::RepeatedField::from_vec(vec![invoke]));
+        rpc.set_initiator(String::from("alice"));
+        rpc.set_auth_require(protobuf::RepeatedField::from_vec(vec![String::from(
+            "alice/bob",
+        )]));
+
+        let mut sign = xchain::SignatureInfo::new();
+        sign.set_PublicKey(String::from(r#"{"Curvname":"P-256","X":1,"Y":2}"#));
+        sign.set_Sign(vec![1, 2, 3]);
+
+        let mut req = xchain::PreExecWithSelectUTXORequest::new();
+        req.set_bcname(String::from("xuper"));
+        req.set_address(String::from("dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN"));
+        req.set_totalAmount(110);
+        req.set_signInfo(sign);
+        req.set_request(rpc);
+        req
+    }
+
+    fn tx_status() -> xchain::TxStatus {
+        let mut input = xchain::TxInput::new();
+        input.set_ref_txid(vec![0xcc]);
+        input.set_from_addr(b"alice".to_vec());
+        input.set_amount(vec![0x0a]);
+        let mut output = xchain::TxOutput::new();
+        output.set_amount(vec![0x0a]);
+        output.set_to_addr(b"bob".to_vec());
+
+        let mut tx = xchain::Transaction::new();
+        tx.set_txid(vec![0xaa, 0xbb]);
+        tx.set_tx_inputs(protobuf::RepeatedField::from_vec(vec![input]));
+        tx.set_tx_outputs(protobuf::RepeatedField::from_vec(vec![output]));
+        tx.set_desc(b"transfer".to_vec());
+        tx.set_nonce(String::from("n1"));
+        tx.set_timestamp(1600000000000000000);
+        tx.set_version(1);
+        tx.set_initiator(String::from("alice"));
+        tx.set_auth_require(protobuf::RepeatedField::from_vec(vec![String::from(
+            "alice",
+        )]));
+
+        let mut status = xchain::TxStatus::new();
+        status.set_bcname(String::from("xuper"));
+        status.set_txid(vec![0xaa, 0xbb]);
+        status.set_tx(tx);
+        status
+    }
+
+    // golden数据是golang的json.Marshal对同样内容的pb结构的输出
+    const PRE_EXEC_REQUEST_GO: &str = r#"{"bcname":"xuper","address":"dpzuVdosQrF2kmzumhVeFQZa1aYcdgFpN","totalAmount":110,"signInfo":{"PublicKey":"{\"Curvname\":\"P-256\",\"X\":1,\"Y\":2}","Sign":"AQID"},"request":{"bcname":"xuper","requests":[{"module_name":"wasm","contract_name":"counter","method_name":"increase","args":{"a":"PCY+","key":"Y291bnRlcg=="},"resource_limits":[{},{"type":1,"limit":1000}]}],"initiator":"alice","auth_require":["alice/bob"]}}"#;
+
+    const TX_STATUS_GO: &str = r#"{"bcname":"xuper","txid":"qrs=","tx":{"txid":"qrs=","tx_inputs":[{"ref_txid":"zA==","from_addr":"YWxpY2U=","amount":"Cg=="}],"tx_outputs":[{"amount":"Cg==","to_addr":"Ym9i"}],"desc":"dHJhbnNmZXI=","nonce":"n1","timestamp":1600000000000000000,"version":1,"initiator":"alice","auth_require":["alice"]}}"#;
+
+    #[test]
+    fn test_pre_exec_request_golden() {
+        let req = pre_exec_request();
+        let data = to_canonical_json(&req).unwrap();
+        assert_eq!(
+            String::from_utf8(data.clone()).unwrap(),
+            PRE_EXEC_REQUEST_GO
+        );
+        // map是无序的，多次序列化结果一致
+        for _ in 0..8 {
+            assert_eq!(to_canonical_json(&req).unwrap(), data);
+        }
+        let parsed: xchain::PreExecWithSelectUTXORequest = from_canonical_json(&data).unwrap();
+        assert_eq!(parsed, req);
+    }
+
+    #[test]
+    fn test_tx_status_golden() {
+        let status = tx_status();
+        let data = to_canonical_json(&status).unwrap();
+        assert_eq!(String::from_utf8(data.clone()).unwrap(), TX_STATUS_GO);
+        let parsed: xchain::TxStatus = from_canonical_json(&data).unwrap();
+        assert_eq!(parsed, status);
+        assert_eq!(
+            encoder::make_tx_digest_hash(parsed.get_tx()).unwrap(),
+            encoder::make_tx_digest_hash(status.get_tx()).unwrap()
+        );
+
+        // 兼容serde_json的输出
+        let parsed: xchain::TxStatus =
+            from_canonical_json(&serde_json::to_vec(&status).unwrap()).unwrap();
+        assert_eq!(parsed, status);
+    }
+
+    #[test]
+    fn test_go_string_escape() {
+        // golang的json.Marshal("<a&b>\u2028\"\\\n\x01")
+        let data = to_canonical_json("<a&b>\u{2028}\"\\\n\u{1}").unwrap();
+        assert_eq!(
+            String::from_utf8(data).unwrap(),
+            r#""\u003ca\u0026b\u003e\u2028\"\\\n\u0001""#
+        );
+    }
+}
@@ -0,0 +1,315 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::errors::{Error, ErrorKind, Result};
+
+/// 默认连续失败多少次之后熔断
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+/// 默认熔断多久之后允许重新尝试
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    /// 正常
+    Closed,
+    /// 熔断中，请求直接返回CircuitOpen，调用方可以切换到其他节点
+    Open,
+    /// 冷却时间已过，只允许一个请求试探，成功则恢复，失败则继续熔断；试探期间其他请求返回CircuitOpen
+    HalfOpen,
+}
+
+/// 状态变化时的回调，参数是熔断器名字和新状态，用于对接监控
+pub type StateObserver = fn(&str, CircuitState);
+
+struct Inner {
+    failure_threshold: u32,
+    cooldown: Duration,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    /// HalfOpen时是否已经有请求在试探
+    probing: bool,
+    observer: Option<StateObserver>,
+    /// 上次通知observer的状态
+    notified: CircuitState,
+}
+
+impl Inner {
+    fn state(&self) -> CircuitState {
+        match self.opened_at {
+            None => CircuitState::Closed,
+            Some(t) if t.elapsed() >= self.cooldown => CircuitState::HalfOpen,
+            Some(_) => CircuitState::Open,
+        }
+    }
+
+    /// 状态和上次通知的不同时返回observer和新状态，调用方释放锁之后再通知
+    /// Open到HalfOpen不需要请求触发，在下一次读取状态时通知
+    fn transition(&mut self) -> Option<(StateObserver, CircuitState)> {
+        let cur = self.state();
+        if cur == self.notified {
+            return None;
+        }
+        self.notified = cur;
+        self.observer.map(|observer| (observer, cur))
+    }
+}
+
+/// 熔断器: 连续failure_threshold次传输失败之后熔断cooldown时间
+/// 只有传输层失败(TransportError)计数，合约执行失败等业务错误不影响熔断
+pub struct CircuitBreaker {
+    name: &'static str,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &'static str, failure_threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker {
+            name: name,
+            inner: Mutex::new(Inner {
+                failure_threshold: failure_threshold,
+                cooldown: cooldown,
+                consecutive_failures: 0,
+                opened_at: None,
+                probing: false,
+                observer: None,
+                notified: CircuitState::Closed,
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn configure(&self, failure_threshold: u32, cooldown: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.failure_threshold = failure_threshold;
+        inner.cooldown = cooldown;
+    }
+
+    pub fn set_observer(&self, observer: StateObserver) {
+        self.inner.lock().unwrap().observer = Some(observer);
+    }
+
+    pub fn state(&self) -> CircuitState {
+        let mut inner = self.inner.lock().unwrap();
+        let cur = inner.state();
+        let transition = inner.transition();
+        drop(inner);
+        self.notify(transition);
+        cur
+    }
+
+    /// Open时，或者HalfOpen时已经有请求在试探，返回CircuitOpen
+    /// 试探的请求在返回的Probe释放时(包括panic和future被丢弃)结束试探
+    fn acquire(&self) -> Result<Probe> {
+        let mut inner = self.inner.lock().unwrap();
+        let res = match inner.state() {
+            CircuitState::Closed => Ok(Probe(None)),
+            CircuitState::HalfOpen if !inner.probing => {
+                inner.probing = true;
+                Ok(Probe(Some(self)))
+            }
+            _ => Err(Error::from(ErrorKind::CircuitOpen)),
+        };
+        let transition = inner.transition();
+        drop(inner);
+        self.notify(transition);
+        res
+    }
+
+    pub fn call<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T>,
+    {
+        let _probe = self.acquire()?;
+        let res = f();
+        match res {
+            Err(ref e) if is_transport_failure(e) => self.on_failure(),
+            _ => self.on_success(),
+        }
+        res
+    }
+
+    /// 同call，f返回的future完成之后再计数
+    #[cfg(feature = "async")]
+    pub async fn call_async<T, F, Fut>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        let _probe = self.acquire()?;
+        let res = f().await;
+        match res {
+            Err(ref e) if is_transport_failure(e) => self.on_failure(),
+            _ => self.on_success(),
+        }
+        res
+    }
+
+    fn on_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures = 0;
+        inner.opened_at = None;
+        inner.probing = false;
+        let transition = inner.transition();
+        drop(inner);
+        self.notify(transition);
+    }
+
+    fn on_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures += 1;
+        inner.probing = false;
+        if inner.consecutive_failures >= inner.failure_threshold {
+            inner.opened_at = Some(Instant::now());
+        }
+        let transition = inner.transition();
+        drop(inner);
+        self.notify(transition);
+    }
+
+    /// 不持有锁，observer里可以调用state等方法
+    fn notify(&self, transition: Option<(StateObserver, CircuitState)>) {
+        if let Some((observer, state)) = transition {
+            observer(self.name, state);
+        }
+    }
+}
+
+/// HalfOpen时试探的许可
+struct Probe<'a>(Option<&'a CircuitBreaker>);
+
+impl<'a> Drop for Probe<'a> {
+    fn drop(&mut self) {
+        if let Some(cb) = self.0 {
+            cb.inner.lock().unwrap().probing = false;
+        }
+    }
+}
+
+/// 是否是传输层的失败(节点或者背书服务不可达)
+pub fn is_transport_failure(e: &Error) -> bool {
+    e.kind() == ErrorKind::TransportError
+}
+
+lazy_static! {
+    /// 访问节点的熔断器
+    pub static ref NODE: CircuitBreaker =
+        CircuitBreaker::new("node", DEFAULT_FAILURE_THRESHOLD, DEFAULT_COOLDOWN);
+    /// 访问背书服务的熔断器
+    pub static ref ENDORSER: CircuitBreaker =
+        CircuitBreaker::new("endorser", DEFAULT_FAILURE_THRESHOLD, DEFAULT_COOLDOWN);
+    /// 访问备用节点(ocall::set_fallback_node)的熔断器，NODE熔断时切换到备用节点
+    pub static ref FALLBACK_NODE: CircuitBreaker =
+        CircuitBreaker::new("fallback-node", DEFAULT_FAILURE_THRESHOLD, DEFAULT_COOLDOWN);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let cb = CircuitBreaker::new("test", 2, Duration::from_millis(50));
+        let transport_err = || -> Result<()> { Err(Error::from(ErrorKind::TransportError)) };
+        let business_err = || -> Result<()> { Err(Error::from(ErrorKind::ContractCodeGT400)) };
+
+        // 业务错误不计数
+        assert_eq!(cb.call(business_err).is_err(), true);
+        assert_eq!(cb.call(transport_err).is_err(), true);
+        assert_eq!(cb.state(), CircuitState::Closed);
+        assert_eq!(cb.call(transport_err).is_err(), true);
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        let res = cb.call(|| Ok(()));
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::CircuitOpen);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        // 试探失败继续熔断
+        assert_eq!(cb.call(transport_err).is_err(), true);
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(cb.call(|| Ok(())).is_ok(), true);
+        assert_eq!(cb.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_circuit_breaker_single_probe() {
+        let cb = CircuitBreaker::new("test-probe", 1, Duration::from_millis(20));
+        let transport_err = || -> Result<()> { Err(Error::from(ErrorKind::TransportError)) };
+        assert_eq!(cb.call(transport_err).is_err(), true);
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+
+        // 试探期间其他请求直接返回CircuitOpen
+        let res = cb.call(|| {
+            let other = cb.call(|| -> Result<()> { panic!("should not be called") });
+            assert_eq!(other.unwrap_err().kind(), ErrorKind::CircuitOpen);
+            Ok(())
+        });
+        assert_eq!(res.is_ok(), true);
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        // 试探的请求panic之后允许下一个请求试探
+        assert_eq!(cb.call(transport_err).is_err(), true);
+        std::thread::sleep(Duration::from_millis(30));
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cb.call(|| -> Result<()> { panic!("probe panicked") })
+        }));
+        assert_eq!(res.is_err(), true);
+        assert_eq!(cb.call(|| Ok(())).is_ok(), true);
+    }
+
+    lazy_static! {
+        static ref OBSERVED_CB: CircuitBreaker =
+            CircuitBreaker::new("test-observer", 1, Duration::from_millis(20));
+        static ref OBSERVED: Mutex<Vec<CircuitState>> = Mutex::new(Vec::new());
+    }
+
+    fn record(name: &str, state: CircuitState) {
+        assert_eq!(name, "test-observer");
+        // observer里读取状态不会死锁
+        assert_eq!(OBSERVED_CB.state(), state);
+        OBSERVED.lock().unwrap().push(state);
+    }
+
+    #[test]
+    fn test_circuit_breaker_observer() {
+        let cb = &*OBSERVED_CB;
+        cb.set_observer(record);
+        let transport_err = || -> Result<()> { Err(Error::from(ErrorKind::TransportError)) };
+        assert_eq!(cb.call(transport_err).is_err(), true);
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(cb.call(|| Ok(())).is_ok(), true);
+        assert_eq!(
+            *OBSERVED.lock().unwrap(),
+            vec![
+                CircuitState::Open,
+                CircuitState::HalfOpen,
+                CircuitState::Closed
+            ]
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_circuit_breaker_async() {
+        use futures::executor::block_on;
+        let cb = CircuitBreaker::new("test-async", 1, Duration::from_millis(50));
+        let res = block_on(cb.call_async(|| async { Ok(1) }));
+        assert_eq!(res.unwrap(), 1);
+        let res: Result<()> =
+            block_on(cb.call_async(|| async { Err(Error::from(ErrorKind::TransportError)) }));
+        assert_eq!(res.is_err(), true);
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        // 熔断时不会创建future
+        let res: Result<()> = block_on(cb.call_async(|| -> futures::future::Ready<Result<()>> {
+            panic!("should not be called")
+        }));
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::CircuitOpen);
+    }
+}
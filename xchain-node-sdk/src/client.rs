@@ -0,0 +1,113 @@
+use crate::errors::Result;
+use crate::protos::{xchain, xendorser};
+
+// 访问节点和背书服务的接口，ocall通过它发送请求。
+// 默认是gRPC(crate::xchain::XChainClient)，只开放HTTP网关的托管服务使用crate::http::HttpClient，
+// 通过ocall::init_with_client设置。
+// 打开async feature时提供不阻塞线程的*_async接口，返回的future不依赖特定的运行时，可以在tokio里直接await。
+// 没有异步实现的NodeClient(is_async为false，例如HttpClient)由ocall在单独的线程里执行同步接口。
+// 异步接口里的等待用sleep，不占用线程。
+
+#[cfg(feature = "async")]
+pub type BoxFuture<T> = std::pin::Pin<Box<dyn std::future::Future<Output = Result<T>> + Send>>;
+
+/// 在单独的线程里执行阻塞的f，不依赖特定的运行时
+#[cfg(feature = "async")]
+pub fn spawn_blocking<T, F>(f: F) -> BoxFuture<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    let (tx, rx) = futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(f());
+    });
+    Box::pin(async move {
+        rx.await
+            .map_err(|_| crate::errors::Error::from(crate::errors::ErrorKind::Unknown))?
+    })
+}
+
+/// 不阻塞线程的等待，不依赖特定的运行时
+#[cfg(feature = "async")]
+pub async fn sleep(d: std::time::Duration) {
+    futures_timer::Delay::new(d).await
+}
+
+pub trait NodeClient: Send + Sync {
+    /// 调用背书服务
+    fn call(&self, r: xendorser::EndorserRequest) -> Result<xendorser::EndorserResponse>;
+
+    fn post_tx(&self, tx: &xchain::Transaction) -> Result<()>;
+
+    /// *_async是否是真正不阻塞的实现，为false时ocall不调用它们，
+    /// 而是在单独的线程里执行对应的同步接口
+    #[cfg(feature = "async")]
+    fn is_async(&self) -> bool {
+        false
+    }
+
+    /// 异步调用背书服务，is_async为true的实现方覆盖
+    #[cfg(feature = "async")]
+    fn call_async(&self, r: xendorser::EndorserRequest) -> BoxFuture<xendorser::EndorserResponse> {
+        Box::pin(futures::future::ready(self.call(r)))
+    }
+
+    /// 异步提交交易，is_async为true的实现方覆盖
+    #[cfg(feature = "async")]
+    fn post_tx_async(&self, tx: xchain::Transaction) -> BoxFuture<()> {
+        Box::pin(futures::future::ready(self.post_tx(&tx)))
+    }
+
+    /// 异步预执行，is_async为true的实现方覆盖
+    #[cfg(feature = "async")]
+    fn pre_exec_async(
+        &self,
+        invoke_rpc_req: xchain::InvokeRPCRequest,
+    ) -> BoxFuture<xchain::InvokeRPCResponse> {
+        Box::pin(futures::future::ready(self.pre_exec(invoke_rpc_req)))
+    }
+
+    /// 异步查询utxo，is_async为true的实现方覆盖
+    #[cfg(feature = "async")]
+    fn query_utxo_record_async(
+        &self,
+        account: String,
+        display_count: i64,
+    ) -> BoxFuture<xchain::UtxoRecordDetail> {
+        Box::pin(futures::future::ready(
+            self.query_utxo_record(&account, display_count),
+        ))
+    }
+
+    fn query_tx(&self, txid: &String) -> Result<xchain::TxStatus>;
+
+    fn get_balance(&self, address: &String) -> Result<xchain::AddressStatus>;
+
+    /// 余额明细，区分冻结和未冻结的部分
+    fn get_balance_detail(&self, address: &String) -> Result<xchain::AddressBalanceStatus>;
+
+    /// account的utxo数量和总额，以及最多display_count个未冻结的utxo
+    fn query_utxo_record(
+        &self,
+        account: &String,
+        display_count: i64,
+    ) -> Result<xchain::UtxoRecordDetail>;
+
+    /// 合约账户的权限(ACL)
+    fn query_acl(&self, account_name: &String) -> Result<xchain::AclStatus>;
+
+    fn get_block_by_height(&self, height: i64) -> Result<xchain::Block>;
+
+    /// blockid是hex编码，返回的区块包含交易
+    fn get_block(&self, blockid: &String) -> Result<xchain::Block>;
+
+    fn pre_exec(
+        &self,
+        invoke_rpc_req: xchain::InvokeRPCRequest,
+    ) -> Result<xchain::InvokeRPCResponse>;
+
+    fn get_block_chains(&self) -> Result<Vec<String>>;
+
+    fn get_block_chain_status(&self, bcname: &String) -> Result<xchain::BCStatus>;
+}
@@ -0,0 +1,112 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use crate::errors::{Error, ErrorKind, Result};
+
+// 请求的截止时间: 调用方用scope设置当前线程的deadline，scope内的每个RPC(节点、背书服务)
+// 发送之前检查deadline，已过则返回DeadlineExceeded，否则把剩余时间作为超时传给服务端:
+//   gRPC请求在metadata里带上grpc-timeout，由服务端和代理在超时后取消请求
+//   HTTP请求设置HttpRequest::timeout，由transport设置连接和读超时
+// scope可以嵌套，生效的是更早的deadline。
+// 异步的ocall不在调用线程上执行，deadline作为参数传入。
+
+/// gRPC协议的超时header
+pub const GRPC_TIMEOUT_KEY: &str = "grpc-timeout";
+
+thread_local! {
+    static DEADLINE: Cell<Option<Instant>> = Cell::new(None);
+}
+
+struct Restore(Option<Instant>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        DEADLINE.with(|d| d.set(self.0));
+    }
+}
+
+fn earliest(a: Option<Instant>, b: Option<Instant>) -> Option<Instant> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(std::cmp::min(a, b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// 在deadline内执行f，f里发出的RPC都以剩余时间为超时
+pub fn scope<R, F: FnOnce() -> R>(deadline: Option<Instant>, f: F) -> R {
+    let outer = current();
+    let _restore = Restore(outer);
+    DEADLINE.with(|d| d.set(earliest(outer, deadline)));
+    f()
+}
+
+/// 当前线程生效的deadline
+pub fn current() -> Option<Instant> {
+    DEADLINE.with(|d| d.get())
+}
+
+/// 剩余时间，没有deadline时返回None，已过则返回DeadlineExceeded
+pub fn remaining() -> Result<Option<Duration>> {
+    remaining_until(current())
+}
+
+pub fn remaining_until(deadline: Option<Instant>) -> Result<Option<Duration>> {
+    match deadline {
+        None => Ok(None),
+        Some(d) => {
+            let now = Instant::now();
+            if now >= d {
+                return Err(Error::from(ErrorKind::DeadlineExceeded));
+            }
+            Ok(Some(d - now))
+        }
+    }
+}
+
+/// grpc-timeout的值，单位毫秒，协议要求最多8位数字，不足1毫秒按1毫秒
+pub fn grpc_timeout(timeout: Duration) -> String {
+    let millis = std::cmp::max(timeout.as_millis(), 1);
+    if millis < 100_000_000 {
+        return format!("{}m", millis);
+    }
+    format!("{}S", std::cmp::min(timeout.as_secs(), 99_999_999))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope() {
+        assert_eq!(current(), None);
+        assert_eq!(remaining().unwrap(), None);
+
+        let later = Instant::now() + Duration::from_secs(60);
+        let sooner = Instant::now() + Duration::from_secs(10);
+        scope(Some(later), || {
+            assert_eq!(current(), Some(later));
+            // 嵌套时取更早的deadline
+            scope(Some(sooner), || assert_eq!(current(), Some(sooner)));
+            scope(Some(later + Duration::from_secs(60)), || {
+                assert_eq!(current(), Some(later))
+            });
+            scope(None, || assert_eq!(current(), Some(later)));
+            assert_eq!(current(), Some(later));
+            assert!(remaining().unwrap().unwrap() <= Duration::from_secs(60));
+        });
+        assert_eq!(current(), None);
+
+        let passed = Instant::now();
+        scope(Some(passed), || {
+            assert_eq!(remaining().unwrap_err().kind(), ErrorKind::DeadlineExceeded);
+        });
+    }
+
+    #[test]
+    fn test_grpc_timeout() {
+        assert_eq!(grpc_timeout(Duration::from_millis(1500)), "1500m");
+        assert_eq!(grpc_timeout(Duration::from_micros(10)), "1m");
+        assert_eq!(grpc_timeout(Duration::from_secs(200_000)), "200000S");
+    }
+}
@@ -70,7 +70,7 @@ where
 {
     use serde::de::Error;
     let mut vec = Vec::new();
-    let res = Vec::<u8>::deserialize(deserializer);
+    let res = Option::<Vec<String>>::deserialize(deserializer)?.unwrap_or_default();
     for elem in res.iter() {
         let b64 = base64::decode(&elem).map_err(Error::custom)?;
         vec.push(b64);
@@ -390,6 +390,108 @@ impl From<&xchain::Transaction> for TransactionDef {
     }
 }
 
+/// 宽松解析背书服务返回的json，兼容不同版本的背书服务:
+/// 1. 缺失的字段使用默认值
+/// 2. 字段名大小写/下划线风格不同也可以识别(见protos里面的alias)
+/// 3. 不认识的字段不报错，原样保留在返回的map里面，key是以`.`分隔的路径
+pub fn from_slice_tolerant<T>(
+    data: &[u8],
+) -> Result<(T, serde_json::Map<String, serde_json::Value>)>
+where
+    T: serde::de::DeserializeOwned + Serialize,
+{
+    let raw: serde_json::Value = serde_json::from_slice(data)?;
+    let value: T = serde_json::from_value(raw.clone())?;
+    let known = serde_json::to_value(&value)?;
+    let mut unknown = serde_json::Map::new();
+    collect_unknown_fields("", &raw, &known, false, &mut unknown);
+    Ok((value, unknown))
+}
+
+/// 严格解析背书服务返回的json，用于可信环境，结构变化可能是宿主篡改:
+/// 1. 字段名必须和SDK序列化的字段名完全一致，不接受alias
+/// 2. 不认识的字段返回UnexpectedResponse，不管值是不是默认值
+pub fn from_slice_strict<T>(data: &[u8]) -> Result<T>
+where
+    T: serde::de::DeserializeOwned + Serialize,
+{
+    let raw: serde_json::Value = serde_json::from_slice(data)?;
+    // serde忽略的字段
+    let mut fields = Vec::new();
+    let value: T = serde_ignored::deserialize(raw.clone(), |path| fields.push(path.to_string()))?;
+    // 名字和序列化的字段名不同(alias)的字段。值为默认值的字段序列化时会省略，不在known里，
+    // 这样的字段如果不认识已经被serde忽略
+    let known = serde_json::to_value(&value)?;
+    let mut unknown = serde_json::Map::new();
+    collect_unknown_fields("", &raw, &known, true, &mut unknown);
+    unknown.retain(|_, v| !is_default_value(v));
+    for name in unknown.keys() {
+        if !fields.contains(name) {
+            fields.push(name.to_owned());
+        }
+    }
+    if !fields.is_empty() {
+        return Err(Error::new(
+            ErrorKind::UnexpectedResponse,
+            format!("unexpected fields: {:?}", fields),
+        ));
+    }
+    Ok(value)
+}
+
+fn is_default_value(v: &serde_json::Value) -> bool {
+    match v {
+        serde_json::Value::Null => true,
+        serde_json::Value::Bool(b) => !b,
+        serde_json::Value::Number(n) => n.as_f64() == Some(0.0),
+        serde_json::Value::String(s) => s.is_empty(),
+        serde_json::Value::Array(a) => a.is_empty(),
+        serde_json::Value::Object(o) => o.is_empty(),
+    }
+}
+
+pub(crate) fn normalize_field_name(k: &str) -> String {
+    k.replace('_', "").to_lowercase()
+}
+
+fn collect_unknown_fields(
+    path: &str,
+    raw: &serde_json::Value,
+    known: &serde_json::Value,
+    exact: bool,
+    unknown: &mut serde_json::Map<String, serde_json::Value>,
+) {
+    match (raw, known) {
+        (serde_json::Value::Object(r), serde_json::Value::Object(k)) => {
+            for (name, v) in r.iter() {
+                let field_path = if path.is_empty() {
+                    name.to_owned()
+                } else {
+                    format!("{}.{}", path, name)
+                };
+                let n = normalize_field_name(name);
+                let found = if exact {
+                    k.get_key_value(name)
+                } else {
+                    k.iter().find(|(kn, _)| normalize_field_name(kn) == n)
+                };
+                match found {
+                    Some((_, kv)) => collect_unknown_fields(&field_path, v, kv, exact, unknown),
+                    None => {
+                        unknown.insert(field_path, v.clone());
+                    }
+                }
+            }
+        }
+        (serde_json::Value::Array(r), serde_json::Value::Array(k)) => {
+            for (i, (rv, kv)) in r.iter().zip(k.iter()).enumerate() {
+                collect_unknown_fields(&format!("{}.{}", path, i), rv, kv, exact, unknown);
+            }
+        }
+        _ => {}
+    }
+}
+
 pub fn make_tx_digest_hash(tx: &xchain::Transaction) -> Result<Vec<u8>> {
     let d = TransactionDef::from(tx);
     let d = d.serialize()?;
@@ -403,3 +505,104 @@ pub fn make_transaction_id(tx: &xchain::Transaction) -> Result<Vec<u8>> {
     let d = d.serialize()?;
     Ok(xchain_crypto::hash::hash::double_sha256(d.as_bytes()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // XuperChain 3.7 背书服务的返回
+    const PRE_EXEC_RESP_V37: &str = r#"{"header":{"logid":"l1","error":0},"bcname":"xuper","response":{"inputs":[{"bucket":"counter","key":"Y291bnRlcg==","ref_txid":"qqo=","ref_offset":1}],"outputs":[{"bucket":"counter","key":"Y291bnRlcg==","value":"MQ=="}],"response":["MQ=="],"gas_used":100,"requests":[{"module_name":"wasm","contract_name":"counter","method_name":"increase","args":{"key":"Y291bnRlcg=="},"resource_limits":[{"limit":1}],"amount":""}],"responses":[{"status":200,"body":"MQ=="}]},"utxoOutput":{"utxoList":[{"amount":"Aw==","toAddr":"YWJj","refTxid":"qqo=","refOffset":1}],"totalSelected":"3"}}"#;
+
+    // 新版本背书服务: 省略header，增加了新字段
+    const PRE_EXEC_RESP_EXTRA_FIELDS: &str = r#"{"bcname":"xuper","fee_hint":"10","response":{"gas_used":100,"trace":{"cost":3},"responses":[{"status":200,"body":"MQ==","event":"e"}]},"utxoOutput":{"utxoList":[{"amount":"Aw==","toAddr":"YWJj","refTxid":"qqo="}],"totalSelected":"3"}}"#;
+
+    // jsonpb风格的背书服务: 字段名是lowerCamelCase
+    const PRE_EXEC_RESP_CAMEL_CASE: &str = r#"{"bcname":"xuper","response":{"inputs":[{"bucket":"counter","key":"Y291bnRlcg==","refTxid":"qqo=","refOffset":1}],"gasUsed":100,"requests":[{"moduleName":"wasm","contractName":"counter","methodName":"increase","args":{"key":"Y291bnRlcg=="}}]},"utxo_output":{"utxo_list":[{"amount":"Aw==","to_addr":"YWJj","ref_txid":"qqo=","ref_offset":1}],"total_selected":"3"}}"#;
+
+    #[test]
+    fn test_tolerant_pre_exec_resp_v37() {
+        let (resp, unknown) = from_slice_tolerant::<xchain::PreExecWithSelectUTXOResponse>(
+            PRE_EXEC_RESP_V37.as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(unknown.is_empty(), true);
+        assert_eq!(resp.get_response().get_gas_used(), 100);
+        assert_eq!(resp.get_response().get_response()[0], b"1".to_vec());
+        assert_eq!(resp.get_response().get_inputs()[0].get_ref_offset(), 1);
+        assert_eq!(resp.get_utxoOutput().get_totalSelected(), "3");
+        assert_eq!(resp.get_utxoOutput().get_utxoList()[0].get_toAddr(), b"abc");
+    }
+
+    #[test]
+    fn test_tolerant_pre_exec_resp_extra_fields() {
+        let (resp, unknown) = from_slice_tolerant::<xchain::PreExecWithSelectUTXOResponse>(
+            PRE_EXEC_RESP_EXTRA_FIELDS.as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(resp.has_header(), false);
+        assert_eq!(resp.get_response().get_gas_used(), 100);
+        assert_eq!(resp.get_utxoOutput().get_utxoList()[0].get_refOffset(), 0);
+        assert_eq!(unknown.len(), 3);
+        assert_eq!(unknown["fee_hint"], serde_json::json!("10"));
+        assert_eq!(unknown["response.trace"], serde_json::json!({"cost": 3}));
+        assert_eq!(
+            unknown["response.responses.0.event"],
+            serde_json::json!("e")
+        );
+    }
+
+    #[test]
+    fn test_tolerant_pre_exec_resp_camel_case() {
+        let (resp, unknown) = from_slice_tolerant::<xchain::PreExecWithSelectUTXOResponse>(
+            PRE_EXEC_RESP_CAMEL_CASE.as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(unknown.is_empty(), true);
+        assert_eq!(resp.get_response().get_gas_used(), 100);
+        assert_eq!(
+            resp.get_response().get_inputs()[0].get_ref_txid().to_vec(),
+            vec![0xaa, 0xaa]
+        );
+        assert_eq!(
+            resp.get_response().get_requests()[0].get_method_name(),
+            "increase"
+        );
+        assert_eq!(resp.get_utxoOutput().get_totalSelected(), "3");
+        assert_eq!(resp.get_utxoOutput().get_utxoList()[0].get_refOffset(), 1);
+    }
+
+    #[test]
+    fn test_strict_pre_exec_resp() {
+        let resp = from_slice_strict::<xchain::PreExecWithSelectUTXOResponse>(
+            PRE_EXEC_RESP_V37.as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(resp.get_response().get_gas_used(), 100);
+
+        let err = from_slice_strict::<xchain::PreExecWithSelectUTXOResponse>(
+            PRE_EXEC_RESP_EXTRA_FIELDS.as_bytes(),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedResponse);
+        // 严格模式不接受不同风格的字段名
+        let err = from_slice_strict::<xchain::PreExecWithSelectUTXOResponse>(
+            PRE_EXEC_RESP_CAMEL_CASE.as_bytes(),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedResponse);
+
+        // 值为默认值的未知字段也不接受
+        for (from, to) in [
+            (r#""bcname":"xuper""#, r#""bcname":"xuper","fee_hint":0"#),
+            (r#""status":200"#, r#""status":200,"event":null"#),
+            (r#""gas_used":100"#, r#""gas_used":100,"trace":{}"#),
+        ]
+        .iter()
+        {
+            let data = PRE_EXEC_RESP_V37.replacen(from, to, 1);
+            let err = from_slice_strict::<xchain::PreExecWithSelectUTXOResponse>(data.as_bytes())
+                .unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::UnexpectedResponse);
+        }
+    }
+}
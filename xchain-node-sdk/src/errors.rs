@@ -1,6 +1,8 @@
 use serde_derive;
 use std::fmt;
 
+use crate::protos::{xchain, xendorser};
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(serde_derive::Serialize, serde_derive::Deserialize)]
@@ -46,6 +48,19 @@ pub enum ErrorKind {
     CryptoError = 3,
     ChainRPCError = 4,
     ContractCodeGT400 = 5,
+    DeadlineExceeded = 6,
+    TransportError = 7,
+    CircuitOpen = 8,
+    PreExecTampered = 9,
+    EndorserSignMissing = 10,
+    FeeTxInvalid = 11,
+    AclNotSatisfied = 12,
+    UnexpectedResponse = 13,
+    AttestationFailed = 14,
+    ChainIdMismatch = 15,
+    EndorserSignInvalid = 16,
+    ComplianceRejected = 17,
+    ShuttingDown = 18,
     Unknown,
 }
 
@@ -57,6 +72,19 @@ impl ErrorKind {
             ErrorKind::CryptoError => "crypto error",
             ErrorKind::ChainRPCError => "rpc to chain node error",
             ErrorKind::ContractCodeGT400 => "contract invoking return code greater than 400",
+            ErrorKind::DeadlineExceeded => "deadline exceeded, tx aborted",
+            ErrorKind::TransportError => "transport to node or endorser failed",
+            ErrorKind::CircuitOpen => "circuit breaker is open, node or endorser unavailable",
+            ErrorKind::PreExecTampered => "pre-exec response does not match the request",
+            ErrorKind::EndorserSignMissing => "endorser returned no signature",
+            ErrorKind::FeeTxInvalid => "compliance check fee tx does not match the expected fee",
+            ErrorKind::AclNotSatisfied => "signatures do not satisfy the account ACL",
+            ErrorKind::UnexpectedResponse => "response has unexpected fields in strict mode",
+            ErrorKind::AttestationFailed => "endorser attestation failed or channel is not attested",
+            ErrorKind::ChainIdMismatch => "transaction is not bound to the expected chain id",
+            ErrorKind::EndorserSignInvalid => "endorser signature does not match the configured public key",
+            ErrorKind::ComplianceRejected => "compliance check rejected by the endorser",
+            ErrorKind::ShuttingDown => "client is shutting down",
             ErrorKind::Unknown => "unknown error",
         }
     }
@@ -80,6 +108,19 @@ impl From<u32> for Error {
             0x0000_0003 => ErrorKind::CryptoError,
             0x0000_0004 => ErrorKind::ChainRPCError,
             0x0000_0005 => ErrorKind::ContractCodeGT400,
+            0x0000_0006 => ErrorKind::DeadlineExceeded,
+            0x0000_0007 => ErrorKind::TransportError,
+            0x0000_0008 => ErrorKind::CircuitOpen,
+            0x0000_0009 => ErrorKind::PreExecTampered,
+            0x0000_000a => ErrorKind::EndorserSignMissing,
+            0x0000_000b => ErrorKind::FeeTxInvalid,
+            0x0000_000c => ErrorKind::AclNotSatisfied,
+            0x0000_000d => ErrorKind::UnexpectedResponse,
+            0x0000_000e => ErrorKind::AttestationFailed,
+            0x0000_000f => ErrorKind::ChainIdMismatch,
+            0x0000_0010 => ErrorKind::EndorserSignInvalid,
+            0x0000_0011 => ErrorKind::ComplianceRejected,
+            0x0000_0012 => ErrorKind::ShuttingDown,
             _ => ErrorKind::Unknown,
         };
 
@@ -96,10 +137,19 @@ impl From<hex::FromHexError> for Error {
     }
 }
 
+/// gRPC的DEADLINE_EXCEEDED状态码
+const GRPC_STATUS_DEADLINE_EXCEEDED: i32 = 4;
+
 impl From<grpc::Error> for Error {
     #[inline]
     fn from(err: grpc::Error) -> Error {
-        Error::new(ErrorKind::ParseError, err)
+        // 服务端按grpc-timeout取消的请求不算传输失败，不计入熔断
+        if let grpc::Error::GrpcMessage(ref m) = err {
+            if m.grpc_status == GRPC_STATUS_DEADLINE_EXCEEDED {
+                return Error::new(ErrorKind::DeadlineExceeded, err);
+            }
+        }
+        Error::new(ErrorKind::TransportError, err)
     }
 }
 
@@ -134,6 +184,19 @@ impl Into<u32> for Error {
             ErrorKind::CryptoError => 0x0000_0003,
             ErrorKind::ChainRPCError => 0x0000_0004,
             ErrorKind::ContractCodeGT400 => 0x0000_0005,
+            ErrorKind::DeadlineExceeded => 0x0000_0006,
+            ErrorKind::TransportError => 0x0000_0007,
+            ErrorKind::CircuitOpen => 0x0000_0008,
+            ErrorKind::PreExecTampered => 0x0000_0009,
+            ErrorKind::EndorserSignMissing => 0x0000_000a,
+            ErrorKind::FeeTxInvalid => 0x0000_000b,
+            ErrorKind::AclNotSatisfied => 0x0000_000c,
+            ErrorKind::UnexpectedResponse => 0x0000_000d,
+            ErrorKind::AttestationFailed => 0x0000_000e,
+            ErrorKind::ChainIdMismatch => 0x0000_000f,
+            ErrorKind::EndorserSignInvalid => 0x0000_0010,
+            ErrorKind::ComplianceRejected => 0x0000_0011,
+            ErrorKind::ShuttingDown => 0x0000_0012,
             ErrorKind::Unknown => 0xffff_ffff,
         }
     }
@@ -191,6 +254,16 @@ impl Error {
     pub fn unknown() -> Error {
         Error::from(ErrorKind::Unknown)
     }
+
+    /// ContractCodeGT400错误里合约返回的status、message和body
+    pub fn contract(&self) -> Option<&ContractError> {
+        self.get_ref().and_then(|e| e.downcast_ref::<ContractError>())
+    }
+
+    /// 节点或背书服务返回的错误码和logid
+    pub fn rpc(&self) -> Option<&RpcError> {
+        self.get_ref().and_then(|e| e.downcast_ref::<RpcError>())
+    }
 }
 
 impl fmt::Debug for Repr {
@@ -219,3 +292,99 @@ impl std::error::Error for Error {
         }
     }
 }
+
+/// 合约调用返回的status大于400
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContractError {
+    pub status: i32,
+    pub message: String,
+    pub body: Vec<u8>,
+}
+
+impl ContractError {
+    pub fn from_response(resp: &xchain::ContractResponse) -> Self {
+        ContractError {
+            status: resp.status,
+            message: resp.message.to_owned(),
+            body: resp.body.clone(),
+        }
+    }
+
+    /// 第一个status大于400的合约返回ContractCodeGT400
+    pub fn check(resp: &[xchain::ContractResponse]) -> Result<()> {
+        match resp.iter().find(|r| r.status > 400) {
+            Some(r) => Err(Error::new(
+                ErrorKind::ContractCodeGT400,
+                ContractError::from_response(r),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for ContractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "contract returned status {}: {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for ContractError {}
+
+/// 节点或背书服务的响应header里的错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcError {
+    pub error: xchain::XChainErrorEnum,
+    pub logid: String,
+    /// 背书服务返回的ResponseData，节点的错误为空
+    pub body: Vec<u8>,
+}
+
+impl RpcError {
+    /// header.error不是SUCCESS时返回ChainRPCError
+    pub fn check(header: &xchain::Header) -> Result<()> {
+        Self::check_as(header, ErrorKind::ChainRPCError)
+    }
+
+    /// 同check，返回kind类型的错误
+    pub fn check_as(header: &xchain::Header, kind: ErrorKind) -> Result<()> {
+        if header.error == xchain::XChainErrorEnum::SUCCESS {
+            return Ok(());
+        }
+        Err(Error::new(
+            kind,
+            RpcError {
+                error: header.error.clone(),
+                logid: header.logid.to_owned(),
+                body: vec![],
+            },
+        ))
+    }
+
+    /// 背书服务的响应header.error不是SUCCESS时返回ChainRPCError，带着ResponseData
+    pub fn check_endorser(resp: &xendorser::EndorserResponse) -> Result<()> {
+        let header = resp.get_header();
+        if header.error == xchain::XChainErrorEnum::SUCCESS {
+            return Ok(());
+        }
+        Err(Error::new(
+            ErrorKind::ChainRPCError,
+            RpcError {
+                error: header.error.clone(),
+                logid: header.logid.to_owned(),
+                body: resp.ResponseData.clone(),
+            },
+        ))
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rpc error {:?}, logid: {}", self.error, self.logid)?;
+        if !self.body.is_empty() {
+            write!(f, ", {}", String::from_utf8_lossy(&self.body))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RpcError {}
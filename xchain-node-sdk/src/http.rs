@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::client::NodeClient;
+use crate::errors::{ContractError, Error, ErrorKind, Result, RpcError};
+use crate::protos::{xchain, xendorser};
+use crate::{auth, deadline, encoder};
+
+// 通过HTTP/JSON网关访问节点和背书服务，用于只开放网关的托管服务。
+// 请求体是protobuf消息的json，响应宽松解析(见encoder::from_slice_tolerant)。
+// SDK不带http客户端，请求由调用方提供的HttpTransport发送。
+// 网关一般有限流: 两次请求之间至少间隔min_interval，返回429时等待之后重试。
+// 设置了deadline(见deadline::scope)时，每次发送前检查并更新HttpRequest::timeout，
+// transport应当用它作为请求的超时。
+
+pub const POST_TX: &str = "post_tx";
+pub const QUERY_TX: &str = "query_tx";
+pub const GET_BALANCE: &str = "get_balance";
+pub const GET_BALANCE_DETAIL: &str = "get_balance_detail";
+pub const QUERY_UTXO_RECORD: &str = "query_utxo_record";
+pub const QUERY_ACL: &str = "query_acl";
+pub const GET_BLOCK_BY_HEIGHT: &str = "get_block_by_height";
+pub const GET_BLOCK: &str = "get_block";
+pub const PRE_EXEC: &str = "pre_exec";
+pub const GET_BLOCK_CHAINS: &str = "get_block_chains";
+pub const GET_BLOCK_CHAIN_STATUS: &str = "get_block_chain_status";
+pub const ENDORSER_CALL: &str = "endorser_call";
+
+/// XuperChain gateway默认的路径，可以用HttpClient::with_path修改
+pub fn default_paths() -> HashMap<&'static str, String> {
+    [
+        (POST_TX, "/v1/post_tx"),
+        (QUERY_TX, "/v1/query_tx"),
+        (GET_BALANCE, "/v1/get_balance"),
+        (GET_BALANCE_DETAIL, "/v1/get_balance_detail"),
+        (QUERY_UTXO_RECORD, "/v1/query_utxo_record"),
+        (QUERY_ACL, "/v1/query_acl"),
+        (GET_BLOCK_BY_HEIGHT, "/v1/get_block_by_height"),
+        (GET_BLOCK, "/v1/get_block"),
+        (PRE_EXEC, "/v1/preexec"),
+        (GET_BLOCK_CHAINS, "/v1/get_bcchains"),
+        (GET_BLOCK_CHAIN_STATUS, "/v1/get_bcstatus"),
+        (ENDORSER_CALL, "/v1/endorsercall"),
+    ]
+    .iter()
+    .map(|(k, v)| (*k, v.to_string()))
+    .collect()
+}
+
+pub struct HttpRequest {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// deadline剩余的时间，None表示不限
+    pub timeout: Option<Duration>,
+}
+
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+/// 发送http POST
+pub type HttpTransport = Box<dyn Fn(&HttpRequest) -> Result<HttpResponse> + Send + Sync>;
+
+pub struct HttpClient {
+    pub chain_name: String,
+    /// 例如https://gateway.example.com，也用于查找查询签名者
+    pub base_url: String,
+    headers: Vec<(String, String)>,
+    paths: HashMap<&'static str, String>,
+    transport: HttpTransport,
+    min_interval: Duration,
+    max_retries: u32,
+    last: Mutex<Option<Instant>>,
+}
+
+impl HttpClient {
+    pub fn new(chain_name: &str, base_url: &str, transport: HttpTransport) -> Self {
+        HttpClient {
+            chain_name: chain_name.to_owned(),
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            headers: vec![],
+            paths: default_paths(),
+            transport: transport,
+            min_interval: Duration::from_millis(0),
+            max_retries: 3,
+            last: Mutex::new(None),
+        }
+    }
+
+    /// 每个请求都带上的header，例如网关的API key
+    pub fn with_header(mut self, key: &str, value: &str) -> Self {
+        self.headers.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub fn with_path(mut self, method: &'static str, path: &str) -> Self {
+        self.paths.insert(method, path.to_owned());
+        self
+    }
+
+    /// 两次请求的最小间隔，以及429时的最大重试次数
+    pub fn with_rate_limit(mut self, min_interval: Duration, max_retries: u32) -> Self {
+        self.min_interval = min_interval;
+        self.max_retries = max_retries;
+        self
+    }
+
+    fn wait_turn(&self) {
+        let mut last = self.last.lock().unwrap();
+        if let Some(t) = *last {
+            let elapsed = t.elapsed();
+            if elapsed < self.min_interval {
+                std::thread::sleep(self.min_interval - elapsed);
+            }
+        }
+        *last = Some(Instant::now());
+    }
+
+    fn post<Req, Resp>(&self, method: &str, req: &Req, signed: bool) -> Result<Resp>
+    where
+        Req: protobuf::Message + serde::Serialize,
+        Resp: serde::de::DeserializeOwned + serde::Serialize,
+    {
+        let path = self
+            .paths
+            .get(method)
+            .ok_or(Error::from(ErrorKind::InvalidArguments))?;
+        let mut headers = self.headers.clone();
+        headers.extend(auth::api_key_headers(&self.base_url)?);
+        headers.push((
+            String::from("Content-Type"),
+            String::from("application/json"),
+        ));
+        if signed {
+            for (key, value) in auth::signed_headers(&self.base_url, req)? {
+                headers.push((key.to_owned(), value));
+            }
+        }
+        let mut request = HttpRequest {
+            url: format!("{}{}", self.base_url, path),
+            headers: headers,
+            body: serde_json::to_vec(req)?,
+            timeout: None,
+        };
+
+        let mut backoff = std::cmp::max(self.min_interval, Duration::from_millis(100));
+        let mut retries = 0;
+        loop {
+            self.wait_turn();
+            request.timeout = deadline::remaining()?;
+            let resp = (self.transport)(&request)?;
+            match resp.status {
+                200..=299 => {
+                    let (resp, _) = encoder::from_slice_tolerant(&resp.body)?;
+                    return Ok(resp);
+                }
+                429 if retries < self.max_retries => {
+                    std::thread::sleep(backoff);
+                    backoff *= 2;
+                    retries += 1;
+                }
+                _ => return Err(Error::from(ErrorKind::TransportError)),
+            }
+        }
+    }
+}
+
+fn check_header(header: &xchain::Header) -> Result<()> {
+    RpcError::check(header)
+}
+
+impl NodeClient for HttpClient {
+    fn call(&self, r: xendorser::EndorserRequest) -> Result<xendorser::EndorserResponse> {
+        self.post(ENDORSER_CALL, &r, false)
+    }
+
+    fn post_tx(&self, tx: &xchain::Transaction) -> Result<()> {
+        let mut tx_status = xchain::TxStatus::new();
+        tx_status.set_bcname(self.chain_name.to_owned());
+        tx_status.set_status(xchain::TransactionStatus::UNCONFIRM);
+        tx_status.set_tx(tx.clone());
+        tx_status.set_txid(tx.txid.clone());
+        let resp: xchain::CommonReply = self.post(POST_TX, &tx_status, false)?;
+        check_header(resp.get_header())
+    }
+
+    fn query_tx(&self, txid: &String) -> Result<xchain::TxStatus> {
+        let mut tx_status = xchain::TxStatus::new();
+        tx_status.set_bcname(self.chain_name.to_owned());
+        tx_status.set_txid(hex::decode(txid)?);
+        let resp: xchain::TxStatus = self.post(QUERY_TX, &tx_status, true)?;
+        check_header(resp.get_header())?;
+        Ok(resp)
+    }
+
+    fn get_balance(&self, address: &String) -> Result<xchain::AddressStatus> {
+        let mut token = xchain::TokenDetail::new();
+        token.set_bcname(self.chain_name.to_owned());
+        let mut addr_status = xchain::AddressStatus::new();
+        addr_status.set_address(address.to_owned());
+        addr_status.set_bcs(protobuf::RepeatedField::from_vec(vec![token]));
+        let resp: xchain::AddressStatus = self.post(GET_BALANCE, &addr_status, true)?;
+        check_header(resp.get_header())?;
+        Ok(resp)
+    }
+
+    fn get_balance_detail(&self, address: &String) -> Result<xchain::AddressBalanceStatus> {
+        let mut tfds = xchain::TokenFrozenDetails::new();
+        tfds.set_bcname(self.chain_name.to_owned());
+        let mut addr_status = xchain::AddressBalanceStatus::new();
+        addr_status.set_address(address.to_owned());
+        addr_status.set_tfds(protobuf::RepeatedField::from_vec(vec![tfds]));
+        let resp: xchain::AddressBalanceStatus =
+            self.post(GET_BALANCE_DETAIL, &addr_status, true)?;
+        check_header(resp.get_header())?;
+        Ok(resp)
+    }
+
+    fn query_utxo_record(
+        &self,
+        account: &String,
+        display_count: i64,
+    ) -> Result<xchain::UtxoRecordDetail> {
+        let mut detail = xchain::UtxoRecordDetail::new();
+        detail.set_bcname(self.chain_name.to_owned());
+        detail.set_accountName(account.to_owned());
+        detail.set_displayCount(display_count);
+        let resp: xchain::UtxoRecordDetail = self.post(QUERY_UTXO_RECORD, &detail, true)?;
+        check_header(resp.get_header())?;
+        Ok(resp)
+    }
+
+    fn query_acl(&self, account_name: &String) -> Result<xchain::AclStatus> {
+        let mut acl_status = xchain::AclStatus::new();
+        acl_status.set_bcname(self.chain_name.to_owned());
+        acl_status.set_accountName(account_name.to_owned());
+        let resp: xchain::AclStatus = self.post(QUERY_ACL, &acl_status, true)?;
+        check_header(resp.get_header())?;
+        Ok(resp)
+    }
+
+    fn get_block_by_height(&self, height: i64) -> Result<xchain::Block> {
+        let mut block_height = xchain::BlockHeight::new();
+        block_height.set_bcname(self.chain_name.to_owned());
+        block_height.set_height(height);
+        let resp: xchain::Block = self.post(GET_BLOCK_BY_HEIGHT, &block_height, true)?;
+        check_header(resp.get_header())?;
+        Ok(resp)
+    }
+
+    fn get_block(&self, blockid: &String) -> Result<xchain::Block> {
+        let mut block_id = xchain::BlockID::new();
+        block_id.set_bcname(self.chain_name.to_owned());
+        block_id.set_blockid(hex::decode(blockid)?);
+        block_id.set_need_content(true);
+        let resp: xchain::Block = self.post(GET_BLOCK, &block_id, true)?;
+        check_header(resp.get_header())?;
+        Ok(resp)
+    }
+
+    fn pre_exec(
+        &self,
+        invoke_rpc_req: xchain::InvokeRPCRequest,
+    ) -> Result<xchain::InvokeRPCResponse> {
+        let resp: xchain::InvokeRPCResponse = self.post(PRE_EXEC, &invoke_rpc_req, false)?;
+        ContractError::check(resp.get_response().get_responses())?;
+        Ok(resp)
+    }
+
+    fn get_block_chains(&self) -> Result<Vec<String>> {
+        let resp: xchain::BlockChains =
+            self.post(GET_BLOCK_CHAINS, &xchain::CommonIn::new(), false)?;
+        check_header(resp.get_header())?;
+        Ok(resp.blockchains.into_vec())
+    }
+
+    fn get_block_chain_status(&self, bcname: &String) -> Result<xchain::BCStatus> {
+        let mut bc_status = xchain::BCStatus::new();
+        bc_status.set_bcname(bcname.to_owned());
+        let resp: xchain::BCStatus = self.post(GET_BLOCK_CHAIN_STATUS, &bc_status, false)?;
+        check_header(resp.get_header())?;
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_http_client() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let c = calls.clone();
+        let transport: HttpTransport = Box::new(move |req| {
+            let n = c.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(req.url, "https://gateway.example.com/v1/get_bcchains");
+            assert_eq!(
+                req.headers
+                    .contains(&(String::from("X-Api-Key"), String::from("k"))),
+                true
+            );
+            // 第一次被限流
+            if n == 0 {
+                return Ok(HttpResponse {
+                    status: 429,
+                    body: vec![],
+                });
+            }
+            let mut resp = xchain::BlockChains::new();
+            resp.mut_blockchains().push(String::from("xuper"));
+            Ok(HttpResponse {
+                status: 200,
+                body: serde_json::to_vec(&resp).unwrap(),
+            })
+        });
+        let cli = HttpClient::new("xuper", "https://gateway.example.com/", transport)
+            .with_header("X-Api-Key", "k")
+            .with_rate_limit(Duration::from_millis(1), 1);
+        assert_eq!(cli.get_block_chains().unwrap(), vec![String::from("xuper")]);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_http_client_errors() {
+        let transport: HttpTransport = Box::new(|req| {
+            if req.url.ends_with("/v1/get_block_by_height") {
+                let mut resp = xchain::Block::new();
+                resp.mut_header()
+                    .set_error(xchain::XChainErrorEnum::BLOCK_EXIST_ERROR);
+                resp.mut_header().set_logid(String::from("log1"));
+                return Ok(HttpResponse {
+                    status: 200,
+                    body: serde_json::to_vec(&resp).unwrap(),
+                });
+            }
+            if req.url.ends_with("/v1/preexec") {
+                let mut r = xchain::ContractResponse::new();
+                r.set_status(500);
+                r.set_message(String::from("insufficient balance"));
+                r.set_body(b"detail".to_vec());
+                let mut resp = xchain::InvokeRPCResponse::new();
+                resp.mut_response().mut_responses().push(r);
+                return Ok(HttpResponse {
+                    status: 200,
+                    body: serde_json::to_vec(&resp).unwrap(),
+                });
+            }
+            Ok(HttpResponse {
+                status: 429,
+                body: vec![],
+            })
+        });
+        let cli = HttpClient::new("xuper", "https://gateway.example.com", transport)
+            .with_rate_limit(Duration::from_millis(1), 0);
+        let e = cli.get_block_by_height(1).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::ChainRPCError);
+        let rpc = e.rpc().unwrap();
+        assert_eq!(rpc.error, xchain::XChainErrorEnum::BLOCK_EXIST_ERROR);
+        assert_eq!(rpc.logid, "log1");
+
+        let e = cli.pre_exec(xchain::InvokeRPCRequest::new()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::ContractCodeGT400);
+        assert_eq!(
+            e.contract(),
+            Some(&ContractError {
+                status: 500,
+                message: String::from("insufficient balance"),
+                body: b"detail".to_vec(),
+            })
+        );
+        assert_eq!(e.rpc(), None);
+        // 超过重试次数
+        assert_eq!(
+            cli.get_block_chains().unwrap_err().kind(),
+            ErrorKind::TransportError
+        );
+    }
+
+    #[test]
+    fn test_http_client_deadline() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let c = calls.clone();
+        let transport: HttpTransport = Box::new(move |req| {
+            c.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(req.timeout.unwrap() <= Duration::from_secs(5), true);
+            Ok(HttpResponse {
+                status: 429,
+                body: vec![],
+            })
+        });
+        let cli = HttpClient::new("xuper", "https://gateway.example.com", transport)
+            .with_rate_limit(Duration::from_millis(20), 100);
+        // 限流重试直到deadline
+        let d = Instant::now() + Duration::from_millis(100);
+        let e = deadline::scope(Some(d), || cli.get_block_chains().unwrap_err());
+        assert_eq!(e.kind(), ErrorKind::DeadlineExceeded);
+        assert_eq!(calls.load(Ordering::SeqCst) < 100, true);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_http_client_async() {
+        // HttpClient没有异步实现，ocall在单独的线程里执行，不阻塞调用方的线程
+        let caller = std::thread::current().id();
+        let transport: HttpTransport = Box::new(move |_| {
+            assert_ne!(std::thread::current().id(), caller);
+            let mut resp = xendorser::EndorserResponse::new();
+            resp.set_ResponseName(String::from("ok"));
+            Ok(HttpResponse {
+                status: 200,
+                body: serde_json::to_vec(&resp).unwrap(),
+            })
+        });
+        let cli = HttpClient::new("xuper", "https://gateway.example.com/", transport);
+        assert_eq!(cli.is_async(), false);
+        crate::ocall::set_endorser("http-async-endorser", Some(Box::new(cli)));
+        let resp = futures::executor::block_on(crate::ocall::ocall_xchain_endorser_call_by_async(
+            &String::from("http-async-endorser"),
+            xendorser::EndorserRequest::new(),
+            None,
+        ))
+        .unwrap();
+        assert_eq!(resp.ResponseName, "ok");
+        crate::ocall::set_endorser("http-async-endorser", None);
+    }
+}
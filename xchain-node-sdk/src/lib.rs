@@ -4,9 +4,18 @@ extern crate serde_yaml;
 #[macro_use]
 extern crate serde_derive;
 
+#[macro_use]
+extern crate lazy_static;
+
 mod xchain;
 
+pub mod auth;
+pub mod canonical;
+pub mod circuit;
+pub mod client;
+pub mod deadline;
 pub mod encoder;
 pub mod errors;
+pub mod http;
 pub mod ocall;
 pub mod protos;
@@ -1,24 +1,204 @@
-use crate::errors::Result;
+use crate::circuit;
+use crate::client::NodeClient;
+use crate::deadline;
+#[cfg(feature = "async")]
+use crate::client::{spawn_blocking, BoxFuture};
+use crate::errors::{Error, ErrorKind, Result};
 use crate::protos::{xchain, xendorser};
 use crate::xchain::XChainClient;
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Arc, RwLock};
+#[cfg(feature = "async")]
+use std::time::Instant;
 
+/// 指向Box<dyn NodeClient>
 pub static CLI: AtomicPtr<()> = AtomicPtr::new(0 as *mut ());
 
+lazy_static! {
+    /// 额外的合规背书服务，背书地址 -> NodeClient
+    static ref ENDORSERS: RwLock<HashMap<String, Arc<dyn NodeClient>>> = RwLock::new(HashMap::new());
+    /// 备用节点，circuit::NODE熔断时访问节点的请求切换到这里
+    static ref FALLBACK_NODE: RwLock<Option<Arc<dyn NodeClient>>> = RwLock::new(None);
+}
+
 #[no_mangle]
 pub extern "C" fn init(
     bcname: &String,
     host: &String,
     port: u16,
 ) -> Result<()> {
-    let ptr = CLI.load(Ordering::SeqCst);
-    if ptr.is_null() {
-        let ptr: *mut XChainClient = Box::into_raw(Box::new(XChainClient::new(&bcname, host, port)));
-        CLI.store(ptr as *mut (), Ordering::SeqCst);
+    if CLI.load(Ordering::SeqCst).is_null() {
+        init_with_client(Box::new(XChainClient::new(&bcname, host, port)))?;
     }
     Ok(())
 }
 
+/// 节点和背书服务在不同的地址时用gRPC连接
+pub fn init_with_endorser(
+    bcname: &String,
+    host: &String,
+    port: u16,
+    endorser_host: &String,
+    endorser_port: u16,
+) -> Result<()> {
+    if CLI.load(Ordering::SeqCst).is_null() {
+        init_with_client(Box::new(XChainClient::with_endorser(
+            bcname,
+            host,
+            port,
+            endorser_host,
+            endorser_port,
+        )))?;
+    }
+    Ok(())
+}
+
+/// 是否已经设置了NodeClient，没有设置时调用ocall_xchain_*是未定义行为
+pub fn initialized() -> bool {
+    !CLI.load(Ordering::SeqCst).is_null()
+}
+
+/// 使用其他的NodeClient实现，例如HTTP网关(crate::http::HttpClient)
+/// 已经初始化过时不替换
+pub fn init_with_client(client: Box<dyn NodeClient>) -> Result<()> {
+    let ptr: *mut Box<dyn NodeClient> = Box::into_raw(Box::new(client));
+    if CLI
+        .compare_exchange(std::ptr::null_mut(), ptr as *mut (), Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        unsafe { drop(Box::from_raw(ptr)) };
+    }
+    Ok(())
+}
+
+/// 配置地址为addr的额外背书服务，client为None时删除
+pub fn set_endorser(addr: &str, client: Option<Box<dyn NodeClient>>) {
+    let mut endorsers = ENDORSERS.write().unwrap();
+    match client {
+        Some(c) => endorsers.insert(addr.to_owned(), Arc::from(c)),
+        None => endorsers.remove(addr),
+    };
+}
+
+/// 用gRPC连接地址为addr的额外背书服务
+pub fn init_endorser(addr: &str, host: &String, port: u16) {
+    set_endorser(addr, Some(Box::new(XChainClient::new(&String::new(), host, port))));
+}
+
+fn endorser(addr: &str) -> Result<Arc<dyn NodeClient>> {
+    ENDORSERS
+        .read()
+        .unwrap()
+        .get(addr)
+        .cloned()
+        .ok_or(Error::from(ErrorKind::InvalidArguments))
+}
+
+/// 是否设置了地址为addr的背书服务
+pub fn has_endorser(addr: &str) -> bool {
+    ENDORSERS.read().unwrap().contains_key(addr)
+}
+
+/// 设置备用节点，client为None时删除
+/// 访问节点的熔断器(circuit::NODE)熔断时请求切换到备用节点，由circuit::FALLBACK_NODE单独熔断
+pub fn set_fallback_node(client: Option<Box<dyn NodeClient>>) {
+    *FALLBACK_NODE.write().unwrap() = client.map(Arc::from);
+}
+
+/// 用gRPC连接备用节点
+pub fn init_fallback_node(bcname: &String, host: &String, port: u16) {
+    set_fallback_node(Some(Box::new(XChainClient::new(bcname, host, port))));
+}
+
+fn fallback_node() -> Option<Arc<dyn NodeClient>> {
+    FALLBACK_NODE.read().unwrap().clone()
+}
+
+/// 访问节点，circuit::NODE熔断并且设置了备用节点时访问备用节点
+fn node_call<T, F>(f: F) -> Result<T>
+where
+    F: Fn(&dyn NodeClient) -> Result<T>,
+{
+    deadline::remaining()?;
+    let ptr: *mut Box<dyn NodeClient> = CLI.load(Ordering::SeqCst) as *mut Box<dyn NodeClient>;
+    let cli = unsafe { &(*ptr) };
+    let res = circuit::NODE.call(|| f(cli.as_ref()));
+    match res {
+        Err(ref e) if e.kind() == ErrorKind::CircuitOpen => match fallback_node() {
+            Some(fallback) => circuit::FALLBACK_NODE.call(|| f(fallback.as_ref())),
+            None => res,
+        },
+        _ => res,
+    }
+}
+
+/// 在单独的线程里以deadline执行f
+#[cfg(feature = "async")]
+fn spawn_blocking_until<T, F>(deadline: Option<Instant>, f: F) -> BoxFuture<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    spawn_blocking(move || deadline::scope(deadline, f))
+}
+
+/// cli有异步实现时调用af，否则在单独的线程里执行同步的f
+#[cfg(feature = "async")]
+fn node_call_on<C, R, T, A, F>(
+    cli: C,
+    deadline: Option<Instant>,
+    req: R,
+    af: A,
+    f: F,
+) -> BoxFuture<T>
+where
+    C: AsRef<dyn NodeClient> + Send + 'static,
+    R: Send + 'static,
+    T: Send + 'static,
+    A: FnOnce(&dyn NodeClient, R) -> BoxFuture<T>,
+    F: FnOnce(&dyn NodeClient, R) -> Result<T> + Send + 'static,
+{
+    if cli.as_ref().is_async() {
+        // 异步实现在返回future之前生成请求的metadata
+        return deadline::scope(deadline, || af(cli.as_ref(), req));
+    }
+    spawn_blocking_until(deadline, move || f(cli.as_ref(), req))
+}
+
+/// 不阻塞线程的node_call，主节点和备用节点都通过node_call_on访问
+#[cfg(feature = "async")]
+async fn node_call_async<R, T, A, F>(
+    req: R,
+    deadline: Option<Instant>,
+    af: A,
+    f: F,
+) -> Result<T>
+where
+    R: Clone + Send + 'static,
+    T: Send + 'static,
+    A: Fn(&dyn NodeClient, R) -> BoxFuture<T> + Send + Sync,
+    F: Fn(&dyn NodeClient, R) -> Result<T> + Clone + Send + Sync + 'static,
+{
+    let cli = static_cli();
+    deadline::remaining_until(deadline)?;
+    let res = circuit::NODE
+        .call_async(|| node_call_on(cli, deadline, req.clone(), &af, f.clone()))
+        .await;
+    match res {
+        Err(ref e) if e.kind() == ErrorKind::CircuitOpen => match fallback_node() {
+            Some(fallback) => {
+                deadline::remaining_until(deadline)?;
+                circuit::FALLBACK_NODE
+                    .call_async(|| node_call_on(fallback, deadline, req, &af, f))
+                    .await
+            }
+            None => res,
+        },
+        _ => res,
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn close(){}
 
@@ -26,38 +206,174 @@ pub extern "C" fn close(){}
 pub extern "C" fn ocall_xchain_endorser_call(
     en_req: xendorser::EndorserRequest,
 ) -> Result<xendorser::EndorserResponse> {
-    let ptr: *mut XChainClient = CLI.load(Ordering::SeqCst) as *mut XChainClient;
+    let ptr: *mut Box<dyn NodeClient> = CLI.load(Ordering::SeqCst) as *mut Box<dyn NodeClient>;
 //    let cli = unsafe { &mut (*ptr) };
     let cli = unsafe { &(*ptr) };
-    cli.call(en_req)
+    deadline::remaining()?;
+    circuit::ENDORSER.call(|| cli.call(en_req))
 }
 
 #[no_mangle]
 pub extern "C" fn ocall_xchain_post_tx(
     req: &xchain::Transaction,
 ) -> Result<()> {
-    let ptr: *mut XChainClient = CLI.load(Ordering::SeqCst) as *mut XChainClient;
-//    let cli = unsafe { &mut (*ptr) };
-    let cli = unsafe { &(*ptr) };
-    cli.post_tx(req)
+    node_call(|cli| cli.post_tx(req))
+}
+
+/// 调用地址为addr的额外背书服务，没有配置时返回InvalidArguments
+#[no_mangle]
+pub extern "C" fn ocall_xchain_endorser_call_by(
+    addr: &String,
+    en_req: xendorser::EndorserRequest,
+) -> Result<xendorser::EndorserResponse> {
+    let cli = endorser(addr)?;
+    deadline::remaining()?;
+    circuit::ENDORSER.call(|| cli.call(en_req))
+}
+
+/// CLI初始化之后不会释放
+#[cfg(feature = "async")]
+fn static_cli() -> &'static Box<dyn NodeClient> {
+    let ptr: *mut Box<dyn NodeClient> = CLI.load(Ordering::SeqCst) as *mut Box<dyn NodeClient>;
+    unsafe { &(*ptr) }
+}
+
+/// cli没有异步实现时在单独的线程里执行同步的call
+#[cfg(feature = "async")]
+fn endorser_call_on<C>(
+    cli: C,
+    en_req: xendorser::EndorserRequest,
+    deadline: Option<Instant>,
+) -> BoxFuture<xendorser::EndorserResponse>
+where
+    C: AsRef<dyn NodeClient> + Send + 'static,
+{
+    node_call_on(cli, deadline, en_req, |c, r| c.call_async(r), |c, r| c.call(r))
+}
+
+/// 不阻塞线程的ocall_xchain_endorser_call，deadline见ocall_xchain_post_tx_async
+#[cfg(feature = "async")]
+pub async fn ocall_xchain_endorser_call_async(
+    en_req: xendorser::EndorserRequest,
+    deadline: Option<Instant>,
+) -> Result<xendorser::EndorserResponse> {
+    let cli = static_cli();
+    deadline::remaining_until(deadline)?;
+    circuit::ENDORSER.call_async(|| endorser_call_on(cli, en_req, deadline)).await
+}
+
+/// 不阻塞线程的ocall_xchain_endorser_call_by
+#[cfg(feature = "async")]
+pub async fn ocall_xchain_endorser_call_by_async(
+    addr: &String,
+    en_req: xendorser::EndorserRequest,
+    deadline: Option<Instant>,
+) -> Result<xendorser::EndorserResponse> {
+    let cli = endorser(addr)?;
+    deadline::remaining_until(deadline)?;
+    circuit::ENDORSER.call_async(|| endorser_call_on(cli, en_req, deadline)).await
+}
+
+/// 不阻塞线程的ocall_xchain_post_tx。异步的ocall不在调用方的线程上执行，
+/// 不使用deadline::scope，deadline由参数传入，None表示不限
+#[cfg(feature = "async")]
+pub async fn ocall_xchain_post_tx_async(
+    req: xchain::Transaction,
+    deadline: Option<Instant>,
+) -> Result<()> {
+    node_call_async(req, deadline, |c, tx| c.post_tx_async(tx), |c, tx| c.post_tx(&tx)).await
+}
+
+/// 不阻塞线程的ocall_xchain_pre_exec
+#[cfg(feature = "async")]
+pub async fn ocall_xchain_pre_exec_async(
+    req: xchain::InvokeRPCRequest,
+    deadline: Option<Instant>,
+) -> Result<xchain::InvokeRPCResponse> {
+    node_call_async(req, deadline, |c, r| c.pre_exec_async(r), |c, r| c.pre_exec(r)).await
+}
+
+/// 不阻塞线程的ocall_xchain_query_utxo_record
+#[cfg(feature = "async")]
+pub async fn ocall_xchain_query_utxo_record_async(
+    account: String,
+    display_count: i64,
+    deadline: Option<Instant>,
+) -> Result<xchain::UtxoRecordDetail> {
+    node_call_async(
+        account,
+        deadline,
+        move |c, a| c.query_utxo_record_async(a, display_count),
+        move |c, a: String| c.query_utxo_record(&a, display_count),
+    )
+    .await
 }
 
 #[no_mangle]
 pub extern "C" fn ocall_xchain_query_tx(
     txid: &String,
 ) -> Result<xchain::TxStatus> {
-    let ptr: *mut XChainClient = CLI.load(Ordering::SeqCst) as *mut XChainClient;
-//    let cli = unsafe { &mut (*ptr) };
-    let cli = unsafe {  &(*ptr) };
-    cli.query_tx(&txid)
+    node_call(|cli| cli.query_tx(&txid))
 }
 
 #[no_mangle]
 pub extern "C" fn ocall_xchain_pre_exec(
     req: xchain::InvokeRPCRequest,
 ) -> Result<xchain::InvokeRPCResponse> {
-    let ptr: *mut XChainClient = CLI.load(Ordering::SeqCst) as *mut XChainClient;
-//    let cli = unsafe { &mut (*ptr) };
-    let cli = unsafe { &(*ptr) };
-    cli.pre_exec(req)
+    node_call(|cli| cli.pre_exec(req.clone()))
+}
+
+#[no_mangle]
+pub extern "C" fn ocall_xchain_get_balance(
+    address: &String,
+) -> Result<xchain::AddressStatus> {
+    node_call(|cli| cli.get_balance(address))
+}
+
+#[no_mangle]
+pub extern "C" fn ocall_xchain_get_balance_detail(
+    address: &String,
+) -> Result<xchain::AddressBalanceStatus> {
+    node_call(|cli| cli.get_balance_detail(address))
+}
+
+#[no_mangle]
+pub extern "C" fn ocall_xchain_query_utxo_record(
+    account: &String,
+    display_count: i64,
+) -> Result<xchain::UtxoRecordDetail> {
+    node_call(|cli| cli.query_utxo_record(account, display_count))
+}
+
+#[no_mangle]
+pub extern "C" fn ocall_xchain_query_acl(
+    account_name: &String,
+) -> Result<xchain::AclStatus> {
+    node_call(|cli| cli.query_acl(account_name))
+}
+
+#[no_mangle]
+pub extern "C" fn ocall_xchain_get_block_by_height(
+    height: i64,
+) -> Result<xchain::Block> {
+    node_call(|cli| cli.get_block_by_height(height))
+}
+
+#[no_mangle]
+pub extern "C" fn ocall_xchain_get_block(
+    blockid: &String,
+) -> Result<xchain::Block> {
+    node_call(|cli| cli.get_block(blockid))
+}
+
+#[no_mangle]
+pub extern "C" fn ocall_xchain_get_block_chains() -> Result<Vec<String>> {
+    node_call(|cli| cli.get_block_chains())
+}
+
+#[no_mangle]
+pub extern "C" fn ocall_xchain_get_block_chain_status(
+    bcname: &String,
+) -> Result<xchain::BCStatus> {
+    node_call(|cli| cli.get_block_chain_status(bcname))
 }
@@ -30,8 +30,9 @@ use protobuf::ProtobufEnum as ProtobufEnum_imported_for_functions;
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct Header {
     // message fields
-    pub logid: ::std::string::String,
     #[serde(default)]
+    pub logid: ::std::string::String,
+    #[serde(default, alias = "fromNode")]
     pub from_node: ::std::string::String,
     #[serde(default)]
     pub error: XChainErrorEnum,
@@ -4331,12 +4332,17 @@ impl ::protobuf::reflect::ProtobufValue for AddressBalanceStatus {
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct TxInput {
     // message fields
+    #[serde(default, alias = "refTxid")]
     pub ref_txid: ::std::vec::Vec<u8>,
+    #[serde(default, alias = "refOffset")]
     pub ref_offset: i32,
+    #[serde(default, alias = "fromAddr")]
     #[serde(serialize_with = "crate::encoder::serialize_bytes")]
     #[serde(deserialize_with = "crate::encoder::deserialize_bytes")]
     pub from_addr: ::std::vec::Vec<u8>,
+    #[serde(default)]
     pub amount: ::std::vec::Vec<u8>,
+    #[serde(default, alias = "frozenHeight")]
     pub frozen_height: i64,
     // special fields
     #[cfg_attr(feature = "with-serde", serde(skip))]
@@ -4653,12 +4659,15 @@ impl ::protobuf::reflect::ProtobufValue for TxInput {
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct TxOutput {
     // message fields
+    #[serde(default)]
     #[serde(serialize_with = "crate::encoder::serialize_bytes")]
     #[serde(deserialize_with = "crate::encoder::deserialize_bytes")]
     pub amount: ::std::vec::Vec<u8>,
+    #[serde(default, alias = "toAddr")]
     #[serde(serialize_with = "crate::encoder::serialize_bytes")]
     #[serde(deserialize_with = "crate::encoder::deserialize_bytes")]
     pub to_addr: ::std::vec::Vec<u8>,
+    #[serde(default, alias = "frozenHeight")]
     pub frozen_height: i64,
     // special fields
     #[cfg_attr(feature = "with-serde", serde(skip))]
@@ -10300,20 +10309,23 @@ impl ::protobuf::reflect::ProtobufValue for RawUrl {
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct Utxo {
     // message fields
+    #[serde(default)]
     #[serde(serialize_with = "crate::encoder::serialize_bytes")]
     #[serde(deserialize_with = "crate::encoder::deserialize_bytes")]
     pub amount: ::std::vec::Vec<u8>,
+    #[serde(default, alias = "to_addr")]
     #[serde(serialize_with = "crate::encoder::serialize_bytes")]
     #[serde(deserialize_with = "crate::encoder::deserialize_bytes")]
     pub toAddr: ::std::vec::Vec<u8>,
-    #[serde(default)]
+    #[serde(default, alias = "to_pubkey")]
     #[serde(serialize_with = "crate::encoder::serialize_bytes")]
     #[serde(deserialize_with = "crate::encoder::deserialize_bytes")]
     pub toPubkey: ::std::vec::Vec<u8>,
+    #[serde(default, alias = "ref_txid")]
     #[serde(serialize_with = "crate::encoder::serialize_bytes")]
     #[serde(deserialize_with = "crate::encoder::deserialize_bytes")]
     pub refTxid: ::std::vec::Vec<u8>,
-    #[serde(default)]
+    #[serde(default, alias = "ref_offset")]
     pub refOffset: i32,
     // special fields
     #[cfg_attr(feature = "with-serde", serde(skip))]
@@ -11063,8 +11075,11 @@ impl ::protobuf::reflect::ProtobufValue for UtxoInput {
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct UtxoOutput {
     // message fields
+    #[serde(default)]
     pub header: ::protobuf::SingularPtrField<Header>,
+    #[serde(default, alias = "utxo_list")]
     pub utxoList: ::protobuf::RepeatedField<Utxo>,
+    #[serde(default, alias = "total_selected")]
     pub totalSelected: ::std::string::String,
     // special fields
     #[cfg_attr(feature = "with-serde", serde(skip))]
@@ -18252,13 +18267,19 @@ impl ::protobuf::reflect::ProtobufValue for InvokeRPCResponse {
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct InvokeRequest {
     // message fields
+    #[serde(default, alias = "moduleName")]
     pub module_name: ::std::string::String,
+    #[serde(default, alias = "contractName")]
     pub contract_name: ::std::string::String,
+    #[serde(default, alias = "methodName")]
     pub method_name: ::std::string::String,
+    #[serde(default)]
     #[serde(serialize_with = "crate::encoder::serialize_ordered_map")]
     #[serde(deserialize_with = "crate::encoder::deserialize_ordered_map")]
     pub args: ::std::collections::HashMap<::std::string::String, ::std::vec::Vec<u8>>,
+    #[serde(default, alias = "resourceLimits")]
     pub resource_limits: ::protobuf::RepeatedField<ResourceLimit>,
+    #[serde(default)]
     pub amount: ::std::string::String,
     // special fields
     #[cfg_attr(feature = "with-serde", serde(skip))]
@@ -18640,15 +18661,15 @@ pub struct InvokeResponse {
     #[serde(serialize_with = "crate::encoder::serialize_bytes_arr")]
     #[serde(deserialize_with = "crate::encoder::deserialize_bytes_arr")]
     pub response: ::protobuf::RepeatedField<::std::vec::Vec<u8>>,
-    #[serde(default)]
+    #[serde(default, alias = "gasUsed")]
     pub gas_used: i64,
     #[serde(default)]
     pub requests: ::protobuf::RepeatedField<InvokeRequest>,
     #[serde(default)]
     pub responses: ::protobuf::RepeatedField<ContractResponse>,
-    #[serde(default)]
+    #[serde(default, alias = "utxo_inputs")]
     pub utxoInputs: ::protobuf::RepeatedField<TxInput>,
-    #[serde(default)]
+    #[serde(default, alias = "utxo_outputs")]
     pub utxoOutputs: ::protobuf::RepeatedField<TxOutput>,
     // special fields
     #[cfg_attr(feature = "with-serde", serde(skip))]
@@ -19136,14 +19157,17 @@ impl ::protobuf::reflect::ProtobufValue for InvokeResponse {
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct TxInputExt {
     // message fields
+    #[serde(default)]
     pub bucket: ::std::string::String,
+    #[serde(default)]
     #[serde(serialize_with = "crate::encoder::serialize_bytes")]
     #[serde(deserialize_with = "crate::encoder::deserialize_bytes")]
     pub key: ::std::vec::Vec<u8>,
+    #[serde(default, alias = "refTxid")]
     #[serde(serialize_with = "crate::encoder::serialize_bytes")]
     #[serde(deserialize_with = "crate::encoder::deserialize_bytes")]
     pub ref_txid: ::std::vec::Vec<u8>,
-    #[serde(default)]
+    #[serde(default, alias = "refOffset")]
     pub ref_offset: i32,
     // special fields
     #[cfg_attr(feature = "with-serde", serde(skip))]
@@ -19426,10 +19450,13 @@ impl ::protobuf::reflect::ProtobufValue for TxInputExt {
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct TxOutputExt {
     // message fields
+    #[serde(default)]
     pub bucket: ::std::string::String,
+    #[serde(default)]
     #[serde(serialize_with = "crate::encoder::serialize_bytes")]
     #[serde(deserialize_with = "crate::encoder::deserialize_bytes")]
     pub key: ::std::vec::Vec<u8>,
+    #[serde(default)]
     #[serde(serialize_with = "crate::encoder::serialize_bytes")]
     #[serde(deserialize_with = "crate::encoder::deserialize_bytes")]
     pub value: ::std::vec::Vec<u8>,
@@ -23623,9 +23650,13 @@ impl ::protobuf::reflect::ProtobufValue for PreExecWithSelectUTXORequest {
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct PreExecWithSelectUTXOResponse {
     // message fields
+    #[serde(default)]
     pub header: ::protobuf::SingularPtrField<Header>,
+    #[serde(default)]
     pub bcname: ::std::string::String,
+    #[serde(default)]
     pub response: ::protobuf::SingularPtrField<InvokeResponse>,
+    #[serde(default, alias = "utxo_output")]
     pub utxoOutput: ::protobuf::SingularPtrField<UtxoOutput>,
     // special fields
     #[cfg_attr(feature = "with-serde", serde(skip))]
@@ -23960,6 +23991,7 @@ impl ::protobuf::reflect::ProtobufValue for PreExecWithSelectUTXOResponse {
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct ContractResponse {
     // message fields
+    #[serde(default)]
     pub status: i32,
     #[serde(default)]
     pub message: ::std::string::String,
@@ -1,12 +1,17 @@
 use futures::executor;
 use grpc::ClientStubExt;
 
-use crate::errors::{Error, ErrorKind, Result};
+use crate::auth;
+use crate::errors::{ContractError, ErrorKind, Result, RpcError};
 use crate::protos::xendorser_grpc;
 use crate::protos::{xchain, xchain_grpc, xendorser};
 
 pub struct XChainClient {
     pub chain_name: String,
+    /// host:port，用于查找查询签名者
+    pub endpoint: String,
+    /// 背书服务的host:port
+    pub endorser_endpoint: String,
     pub endorser: xendorser_grpc::xendorserClient,
     pub xchain: xchain_grpc::XchainClient,
 }
@@ -14,10 +19,22 @@ pub struct XChainClient {
 #[allow(dead_code)]
 impl XChainClient {
     pub fn new(bcname: &String, host: &String, port: u16) -> Self {
+        Self::with_endorser(bcname, host, port, host, port)
+    }
+
+    /// 节点和背书服务在不同的地址
+    pub fn with_endorser(
+        bcname: &String,
+        host: &String,
+        port: u16,
+        endorser_host: &String,
+        endorser_port: u16,
+    ) -> Self {
         //TODO: 设置超时，以及body大小
         let client_conf = Default::default();
-        let client_endorser = xendorser_grpc::xendorserClient::new_plain(host, port, client_conf)
-            .expect("new connection");
+        let client_endorser =
+            xendorser_grpc::xendorserClient::new_plain(endorser_host, endorser_port, client_conf)
+                .expect("new connection");
 
         let client_conf = Default::default();
         let client_xchain =
@@ -25,6 +42,8 @@ impl XChainClient {
 
         XChainClient {
             chain_name: bcname.to_owned(),
+            endpoint: auth::endpoint(host, port),
+            endorser_endpoint: auth::endpoint(endorser_host, endorser_port),
             endorser: client_endorser,
             xchain: client_xchain,
         }
@@ -33,18 +52,13 @@ impl XChainClient {
     pub fn call(&self, r: xendorser::EndorserRequest) -> Result<xendorser::EndorserResponse> {
         let resp = self
             .endorser
-            .endorser_call(grpc::RequestOptions::new(), r)
+            .endorser_call(auth::api_key_options(&self.endorser_endpoint)?, r)
             .drop_metadata();
         Ok(executor::block_on(resp)?)
     }
 
     pub fn check_resp_code(&self, resp: &[xchain::ContractResponse]) -> Result<()> {
-        for i in resp.iter() {
-            if i.status > 400 {
-                return Err(Error::from(ErrorKind::ContractCodeGT400));
-            }
-        }
-        Ok(())
+        ContractError::check(resp)
     }
 
     pub fn post_tx(&self, tx: &xchain::Transaction) -> Result<()> {
@@ -55,14 +69,37 @@ impl XChainClient {
         tx_status.set_txid(tx.txid.clone());
         let resp = self
             .xchain
-            .post_tx(grpc::RequestOptions::new(), tx_status)
+            .post_tx(auth::api_key_options(&self.endpoint)?, tx_status)
             .drop_metadata();
-        let resp = executor::block_on(resp).unwrap();
-        if resp.get_header().error != xchain::XChainErrorEnum::SUCCESS {
-            println!("post tx failed, {:?}", resp);
-            return Err(Error::from(ErrorKind::ParseError));
-        }
-        Ok(())
+        check_post_tx(executor::block_on(resp)?)
+    }
+
+    #[cfg(feature = "async")]
+    pub fn call_async(
+        &self,
+        r: xendorser::EndorserRequest,
+    ) -> crate::client::BoxFuture<xendorser::EndorserResponse> {
+        let options = match auth::api_key_options(&self.endorser_endpoint) {
+            Ok(o) => o,
+            Err(e) => return Box::pin(futures::future::ready(Err(e))),
+        };
+        let resp = self.endorser.endorser_call(options, r).drop_metadata();
+        Box::pin(async move { Ok(resp.await?) })
+    }
+
+    #[cfg(feature = "async")]
+    pub fn post_tx_async(&self, tx: xchain::Transaction) -> crate::client::BoxFuture<()> {
+        let options = match auth::api_key_options(&self.endpoint) {
+            Ok(o) => o,
+            Err(e) => return Box::pin(futures::future::ready(Err(e))),
+        };
+        let mut tx_status = xchain::TxStatus::new();
+        tx_status.set_bcname(self.chain_name.to_owned());
+        tx_status.set_status(xchain::TransactionStatus::UNCONFIRM);
+        tx_status.set_txid(tx.txid.clone());
+        tx_status.set_tx(tx);
+        let resp = self.xchain.post_tx(options, tx_status).drop_metadata();
+        Box::pin(async move { check_post_tx(resp.await?) })
     }
 
     pub fn query_tx(&self, txid: &String) -> Result<xchain::TxStatus> {
@@ -71,27 +108,307 @@ impl XChainClient {
         tx_status.set_txid(hex::decode(txid)?);
         let resp = self
             .xchain
-            .query_tx(grpc::RequestOptions::new(), tx_status)
+            .query_tx(
+                auth::request_options(&self.endpoint, &tx_status)?,
+                tx_status,
+            )
             .drop_metadata();
-        let resp = executor::block_on(resp).unwrap();
+        let resp = executor::block_on(resp)?;
 
-        if resp.get_header().error != xchain::XChainErrorEnum::SUCCESS {
-            return Err(Error::from(ErrorKind::ChainRPCError));
-        }
+        RpcError::check(resp.get_header())?;
         // TODO check txid if null
         Ok(resp)
     }
 
+    pub fn get_balance(&self, address: &String) -> Result<xchain::AddressStatus> {
+        let mut token = xchain::TokenDetail::new();
+        token.set_bcname(self.chain_name.to_owned());
+        let mut addr_status = xchain::AddressStatus::new();
+        addr_status.set_address(address.to_owned());
+        addr_status.set_bcs(protobuf::RepeatedField::from_vec(vec![token]));
+        let resp = self
+            .xchain
+            .get_balance(
+                auth::request_options(&self.endpoint, &addr_status)?,
+                addr_status,
+            )
+            .drop_metadata();
+        let resp = executor::block_on(resp)?;
+
+        RpcError::check(resp.get_header())?;
+        Ok(resp)
+    }
+
+    pub fn get_balance_detail(&self, address: &String) -> Result<xchain::AddressBalanceStatus> {
+        let mut tfds = xchain::TokenFrozenDetails::new();
+        tfds.set_bcname(self.chain_name.to_owned());
+        let mut addr_status = xchain::AddressBalanceStatus::new();
+        addr_status.set_address(address.to_owned());
+        addr_status.set_tfds(protobuf::RepeatedField::from_vec(vec![tfds]));
+        let resp = self
+            .xchain
+            .get_balance_detail(
+                auth::request_options(&self.endpoint, &addr_status)?,
+                addr_status,
+            )
+            .drop_metadata();
+        let resp = executor::block_on(resp)?;
+
+        RpcError::check(resp.get_header())?;
+        Ok(resp)
+    }
+
+    pub fn query_utxo_record(
+        &self,
+        account: &String,
+        display_count: i64,
+    ) -> Result<xchain::UtxoRecordDetail> {
+        let mut detail = xchain::UtxoRecordDetail::new();
+        detail.set_bcname(self.chain_name.to_owned());
+        detail.set_accountName(account.to_owned());
+        detail.set_displayCount(display_count);
+        let resp = self
+            .xchain
+            .query_utxo_record(auth::request_options(&self.endpoint, &detail)?, detail)
+            .drop_metadata();
+        let resp = executor::block_on(resp)?;
+
+        RpcError::check(resp.get_header())?;
+        Ok(resp)
+    }
+
+    pub fn query_acl(&self, account_name: &String) -> Result<xchain::AclStatus> {
+        let mut acl_status = xchain::AclStatus::new();
+        acl_status.set_bcname(self.chain_name.to_owned());
+        acl_status.set_accountName(account_name.to_owned());
+        let resp = self
+            .xchain
+            .query_acl(
+                auth::request_options(&self.endpoint, &acl_status)?,
+                acl_status,
+            )
+            .drop_metadata();
+        let resp = executor::block_on(resp)?;
+
+        RpcError::check(resp.get_header())?;
+        Ok(resp)
+    }
+
+    pub fn get_block_by_height(&self, height: i64) -> Result<xchain::Block> {
+        let mut block_height = xchain::BlockHeight::new();
+        block_height.set_bcname(self.chain_name.to_owned());
+        block_height.set_height(height);
+        let resp = self
+            .xchain
+            .get_block_by_height(
+                auth::request_options(&self.endpoint, &block_height)?,
+                block_height,
+            )
+            .drop_metadata();
+        let resp = executor::block_on(resp)?;
+
+        RpcError::check(resp.get_header())?;
+        Ok(resp)
+    }
+
+    pub fn get_block(&self, blockid: &String) -> Result<xchain::Block> {
+        let mut block_id = xchain::BlockID::new();
+        block_id.set_bcname(self.chain_name.to_owned());
+        block_id.set_blockid(hex::decode(blockid)?);
+        block_id.set_need_content(true);
+        let resp = self
+            .xchain
+            .get_block(auth::request_options(&self.endpoint, &block_id)?, block_id)
+            .drop_metadata();
+        let resp = executor::block_on(resp)?;
+
+        RpcError::check(resp.get_header())?;
+        Ok(resp)
+    }
+
+    /// 节点服务的所有链
+    pub fn get_block_chains(&self) -> Result<Vec<String>> {
+        let resp = self
+            .xchain
+            .get_block_chains(
+                auth::api_key_options(&self.endpoint)?,
+                xchain::CommonIn::new(),
+            )
+            .drop_metadata();
+        let resp = executor::block_on(resp)?;
+
+        RpcError::check(resp.get_header())?;
+        Ok(resp.blockchains.into_vec())
+    }
+
+    pub fn get_block_chain_status(&self, bcname: &String) -> Result<xchain::BCStatus> {
+        let mut bc_status = xchain::BCStatus::new();
+        bc_status.set_bcname(bcname.to_owned());
+        let resp = self
+            .xchain
+            .get_block_chain_status(auth::api_key_options(&self.endpoint)?, bc_status)
+            .drop_metadata();
+        let resp = executor::block_on(resp)?;
+
+        RpcError::check(resp.get_header())?;
+        Ok(resp)
+    }
+
     pub fn pre_exec(
         &self,
         invoke_rpc_req: xchain::InvokeRPCRequest,
     ) -> Result<xchain::InvokeRPCResponse> {
         let resp = self
             .xchain
-            .pre_exec(grpc::RequestOptions::new(), invoke_rpc_req)
+            .pre_exec(auth::api_key_options(&self.endpoint)?, invoke_rpc_req)
             .drop_metadata();
-        let resp = executor::block_on(resp).unwrap();
+        let resp = executor::block_on(resp)?;
         self.check_resp_code(resp.get_response().get_responses())?;
         Ok(resp)
     }
+
+    #[cfg(feature = "async")]
+    pub fn pre_exec_async(
+        &self,
+        invoke_rpc_req: xchain::InvokeRPCRequest,
+    ) -> crate::client::BoxFuture<xchain::InvokeRPCResponse> {
+        let options = match auth::api_key_options(&self.endpoint) {
+            Ok(o) => o,
+            Err(e) => return Box::pin(futures::future::ready(Err(e))),
+        };
+        let resp = self
+            .xchain
+            .pre_exec(options, invoke_rpc_req)
+            .drop_metadata();
+        Box::pin(async move {
+            let resp = resp.await?;
+            ContractError::check(resp.get_response().get_responses())?;
+            Ok(resp)
+        })
+    }
+
+    #[cfg(feature = "async")]
+    pub fn query_utxo_record_async(
+        &self,
+        account: String,
+        display_count: i64,
+    ) -> crate::client::BoxFuture<xchain::UtxoRecordDetail> {
+        let mut detail = xchain::UtxoRecordDetail::new();
+        detail.set_bcname(self.chain_name.to_owned());
+        detail.set_accountName(account);
+        detail.set_displayCount(display_count);
+        let options = match auth::request_options(&self.endpoint, &detail) {
+            Ok(o) => o,
+            Err(e) => return Box::pin(futures::future::ready(Err(e))),
+        };
+        let resp = self
+            .xchain
+            .query_utxo_record(options, detail)
+            .drop_metadata();
+        Box::pin(async move {
+            let resp = resp.await?;
+            RpcError::check(resp.get_header())?;
+            Ok(resp)
+        })
+    }
+}
+
+fn check_post_tx(resp: xchain::CommonReply) -> Result<()> {
+    let res = RpcError::check_as(resp.get_header(), ErrorKind::ParseError);
+    if let Err(ref e) = res {
+        tracing::warn!(error = %e, "post tx failed");
+    }
+    res
+}
+
+impl crate::client::NodeClient for XChainClient {
+    fn call(&self, r: xendorser::EndorserRequest) -> Result<xendorser::EndorserResponse> {
+        XChainClient::call(self, r)
+    }
+
+    fn post_tx(&self, tx: &xchain::Transaction) -> Result<()> {
+        XChainClient::post_tx(self, tx)
+    }
+
+    #[cfg(feature = "async")]
+    fn is_async(&self) -> bool {
+        true
+    }
+
+    #[cfg(feature = "async")]
+    fn call_async(
+        &self,
+        r: xendorser::EndorserRequest,
+    ) -> crate::client::BoxFuture<xendorser::EndorserResponse> {
+        XChainClient::call_async(self, r)
+    }
+
+    #[cfg(feature = "async")]
+    fn post_tx_async(&self, tx: xchain::Transaction) -> crate::client::BoxFuture<()> {
+        XChainClient::post_tx_async(self, tx)
+    }
+
+    #[cfg(feature = "async")]
+    fn pre_exec_async(
+        &self,
+        invoke_rpc_req: xchain::InvokeRPCRequest,
+    ) -> crate::client::BoxFuture<xchain::InvokeRPCResponse> {
+        XChainClient::pre_exec_async(self, invoke_rpc_req)
+    }
+
+    #[cfg(feature = "async")]
+    fn query_utxo_record_async(
+        &self,
+        account: String,
+        display_count: i64,
+    ) -> crate::client::BoxFuture<xchain::UtxoRecordDetail> {
+        XChainClient::query_utxo_record_async(self, account, display_count)
+    }
+
+    fn query_tx(&self, txid: &String) -> Result<xchain::TxStatus> {
+        XChainClient::query_tx(self, txid)
+    }
+
+    fn get_balance(&self, address: &String) -> Result<xchain::AddressStatus> {
+        XChainClient::get_balance(self, address)
+    }
+
+    fn get_balance_detail(&self, address: &String) -> Result<xchain::AddressBalanceStatus> {
+        XChainClient::get_balance_detail(self, address)
+    }
+
+    fn query_utxo_record(
+        &self,
+        account: &String,
+        display_count: i64,
+    ) -> Result<xchain::UtxoRecordDetail> {
+        XChainClient::query_utxo_record(self, account, display_count)
+    }
+
+    fn query_acl(&self, account_name: &String) -> Result<xchain::AclStatus> {
+        XChainClient::query_acl(self, account_name)
+    }
+
+    fn get_block_by_height(&self, height: i64) -> Result<xchain::Block> {
+        XChainClient::get_block_by_height(self, height)
+    }
+
+    fn get_block(&self, blockid: &String) -> Result<xchain::Block> {
+        XChainClient::get_block(self, blockid)
+    }
+
+    fn pre_exec(
+        &self,
+        invoke_rpc_req: xchain::InvokeRPCRequest,
+    ) -> Result<xchain::InvokeRPCResponse> {
+        XChainClient::pre_exec(self, invoke_rpc_req)
+    }
+
+    fn get_block_chains(&self) -> Result<Vec<String>> {
+        XChainClient::get_block_chains(self)
+    }
+
+    fn get_block_chain_status(&self, bcname: &String) -> Result<xchain::BCStatus> {
+        XChainClient::get_block_chain_status(self, bcname)
+    }
 }